@@ -10,14 +10,16 @@ use super::{
 use bytemuck::{Pod, Zeroable};
 use std::mem;
 
-const TILE_WIDTH: u32 = 16;
-const TILE_HEIGHT: u32 = 16;
+/// Width of a tile in pixels.
+pub const TILE_WIDTH: u32 = 16;
+/// Height of a tile in pixels.
+pub const TILE_HEIGHT: u32 = 16;
 
-// TODO: Obtain these from the vello_shaders crate
-pub(crate) const PATH_REDUCE_WG: u32 = 256;
-const PATH_BBOX_WG: u32 = 256;
-const FLATTEN_WG: u32 = 256;
-const CLIP_REDUCE_WG: u32 = 256;
+// Must be kept in sync with shader/shared/ptcl.wgsl
+pub(crate) const PTCL_INITIAL_ALLOC: u32 = 64;
+pub(crate) const PTCL_INCREMENT: u32 = 256;
+
+use crate::workgroup::{CLIP_REDUCE_WG, FLATTEN_WG, PATH_BBOX_WG, PATH_REDUCE_WG};
 
 /// Counters for tracking dynamic allocation on the GPU.
 ///
@@ -37,6 +39,22 @@ pub struct BumpAllocators {
     pub lines: u32,
 }
 
+impl BumpAllocators {
+    /// Returns the larger of each of the allocation sizes of `self` and `other`.
+    pub fn max(&self, other: &Self) -> Self {
+        Self {
+            failed: self.failed | other.failed,
+            binning: self.binning.max(other.binning),
+            ptcl: self.ptcl.max(other.ptcl),
+            tile: self.tile.max(other.tile),
+            seg_counts: self.seg_counts.max(other.seg_counts),
+            segments: self.segments.max(other.segments),
+            blend: self.blend.max(other.blend),
+            lines: self.lines.max(other.lines),
+        }
+    }
+}
+
 /// Storage of indirect dispatch size values.
 ///
 /// The original plan was to reuse BumpAllocators, but the WebGPU compatible
@@ -51,6 +69,33 @@ pub struct IndirectCount {
     pub pad0: u32,
 }
 
+/// Configuration of one pass of the separable Gaussian blur applied to filter layers.
+///
+/// This data structure must be kept in sync with the definition in
+/// shaders/blur.wgsl.
+#[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
+#[repr(C)]
+pub struct BlurConfig {
+    /// Width of the blurred region in pixels.
+    pub width: u32,
+    /// Height of the blurred region in pixels.
+    pub height: u32,
+    /// Horizontal location of the blurred region in the output image.
+    pub dst_x: u32,
+    /// Vertical location of the blurred region in the output image.
+    pub dst_y: u32,
+    /// Non-zero for the vertical pass.
+    pub vertical: u32,
+    /// Number of pixels sampled on either side of each output pixel.
+    pub radius: u32,
+    /// Standard deviation of the blur in pixels.
+    pub std_dev: f32,
+    /// Non-zero if the blurred alpha is filled with `color`.
+    pub use_color: u32,
+    /// Packed premultiplied RGBA color with the alpha component in the low byte.
+    pub color: u32,
+}
+
 /// Uniform render configuration data used by all GPU stages.
 ///
 /// This data structure must be kept in sync with the definition in
@@ -78,6 +123,16 @@ pub struct ConfigUniform {
     pub segments_size: u32,
     /// Size of per-tile command list buffer allocation (in u32s).
     pub ptcl_size: u32,
+    /// Size of the blend stack spill buffer allocation (in u32s).
+    pub blend_size: u32,
+    /// Horizontal offset in the output image of the first column of the target.
+    pub target_x_offset: u32,
+    /// Vertical offset in the output image of the first row of the target.
+    pub target_y_offset: u32,
+    /// Non-zero if fine rasterization blends in linear light and encodes the result to sRGB.
+    pub linear_blending: u32,
+    /// Non-zero if fine rasterization dithers colors written to 8 bit outputs.
+    pub dithering: u32,
 }
 
 /// CPU side setup and configuration.
@@ -112,12 +167,111 @@ impl RenderConfig {
                 tiles_size: buffer_sizes.tiles.len(),
                 segments_size: buffer_sizes.segments.len(),
                 ptcl_size: buffer_sizes.ptcl.len(),
+                blend_size: buffer_sizes.blend_spill.len(),
+                target_x_offset: 0,
+                target_y_offset: 0,
+                linear_blending: 0,
+                dithering: 0,
                 layout: *layout,
             },
             workgroup_counts,
             buffer_sizes,
         }
     }
+
+    /// Dispatches the flattening stage for shaders built with a workgroup size other than
+    /// [`FLATTEN_WG`](crate::FLATTEN_WG).
+    pub fn set_flatten_workgroup_size(&mut self, size: u32) {
+        let n_path_tags = self.gpu.layout.path_tags_size();
+        let flatten_wgs = (n_path_tags + size - 1) / size;
+        self.workgroup_counts.flatten = (flatten_wgs, 1, 1);
+        self.workgroup_counts.path_coarse = (flatten_wgs, 1, 1);
+    }
+
+    /// Sizes the bump allocated buffers to hold the given allocations, such as an estimate
+    /// from [`estimate_bump_sizes`](crate::estimate_bump_sizes).
+    ///
+    /// Unlike [`RenderConfig::grow_to_fit`], this also shrinks buffers that are larger than
+    /// needed.
+    pub fn fit_bump_sizes(&mut self, bump: &BumpAllocators) {
+        let sizes = &mut self.buffer_sizes;
+        let layout = &self.gpu.layout;
+        let ptcl_dyn_start =
+            self.gpu.width_in_tiles * self.gpu.height_in_tiles * PTCL_INITIAL_ALLOC;
+        sizes.bin_data = fit(layout.bin_data_start + bump.binning);
+        sizes.tiles = fit(bump.tile);
+        sizes.lines = fit(bump.lines);
+        sizes.seg_counts = fit(bump.seg_counts);
+        sizes.segments = fit(bump.segments);
+        sizes.ptcl = fit(ptcl_dyn_start + bump.ptcl);
+        sizes.blend_spill = fit(bump.blend);
+        self.update_bump_sizes();
+    }
+
+    /// Grows the bump allocated buffers so that they can hold the allocations reported by a
+    /// previous run of the pipeline.
+    ///
+    /// Returns `true` if any of the buffers had to grow, in which case the run that reported
+    /// `bump` ran out of memory and its output is incomplete.
+    pub fn grow_to_fit(&mut self, bump: &BumpAllocators) -> bool {
+        let sizes = &mut self.buffer_sizes;
+        let layout = &self.gpu.layout;
+        let ptcl_dyn_start =
+            self.gpu.width_in_tiles * self.gpu.height_in_tiles * PTCL_INITIAL_ALLOC;
+        // Stages don't stop bumping their allocators once they run out of space, so the counters
+        // hold the full size that was needed. Stages following a failed stage exit early though,
+        // so it can take more than one attempt until everything fits.
+        let grew = grow(&mut sizes.bin_data, layout.bin_data_start + bump.binning)
+            | grow(&mut sizes.tiles, bump.tile)
+            | grow(&mut sizes.lines, bump.lines)
+            | grow(&mut sizes.seg_counts, bump.seg_counts)
+            | grow(&mut sizes.segments, bump.segments)
+            | grow(&mut sizes.ptcl, ptcl_dyn_start + bump.ptcl)
+            | grow(&mut sizes.blend_spill, bump.blend);
+        self.update_bump_sizes();
+        grew
+    }
+
+    /// Returns the capacity of each bump allocated buffer, in the same units as the counters
+    /// of [`BumpAllocators`].
+    pub fn bump_capacity(&self) -> BumpAllocators {
+        let sizes = &self.buffer_sizes;
+        let ptcl_dyn_start =
+            self.gpu.width_in_tiles * self.gpu.height_in_tiles * PTCL_INITIAL_ALLOC;
+        BumpAllocators {
+            failed: 0,
+            binning: self.gpu.binning_size,
+            ptcl: sizes.ptcl.len().saturating_sub(ptcl_dyn_start),
+            tile: sizes.tiles.len(),
+            seg_counts: sizes.seg_counts.len(),
+            segments: sizes.segments.len(),
+            blend: sizes.blend_spill.len(),
+            lines: sizes.lines.len(),
+        }
+    }
+
+    fn update_bump_sizes(&mut self) {
+        let sizes = &self.buffer_sizes;
+        self.gpu.binning_size = sizes.bin_data.len() - self.gpu.layout.bin_data_start;
+        self.gpu.tiles_size = sizes.tiles.len();
+        self.gpu.segments_size = sizes.segments.len();
+        self.gpu.ptcl_size = sizes.ptcl.len();
+        self.gpu.blend_size = sizes.blend_spill.len();
+    }
+}
+
+/// Returns the next power of two that holds at least `len` elements.
+fn fit<T: Sized>(len: u32) -> BufferSize<T> {
+    BufferSize::new(len.next_power_of_two())
+}
+
+/// Grows `size` to the next power of two that holds at least `len` elements.
+fn grow<T: Sized>(size: &mut BufferSize<T>, len: u32) -> bool {
+    if len <= size.len() {
+        return false;
+    }
+    *size = fit(len);
+    true
 }
 
 /// Type alias for a workgroup size.
@@ -254,6 +408,8 @@ pub struct BufferSizes {
     pub path_reduced: BufferSize<PathMonoid>,
     pub path_reduced2: BufferSize<PathMonoid>,
     pub path_reduced_scan: BufferSize<PathMonoid>,
+    /// Partition counter and published monoid fields of the single pass path tag scan.
+    pub path_scan_state: BufferSize<u32>,
     pub path_monoids: BufferSize<PathMonoid>,
     pub path_bboxes: BufferSize<PathBbox>,
     pub cubics: BufferSize<Cubic>,
@@ -276,6 +432,7 @@ pub struct BufferSizes {
     pub seg_counts: BufferSize<SegmentCount>,
     pub segments: BufferSize<PathSegment>,
     pub ptcl: BufferSize<u32>,
+    pub blend_spill: BufferSize<u32>,
 }
 
 impl BufferSizes {
@@ -292,6 +449,7 @@ impl BufferSizes {
         let path_reduced = BufferSize::new(reduced_size);
         let path_reduced2 = BufferSize::new(PATH_REDUCE_WG);
         let path_reduced_scan = BufferSize::new(path_tag_wgs);
+        let path_scan_state = BufferSize::new(1 + path_tag_wgs * 6);
         let path_monoids = BufferSize::new(path_tag_wgs * PATH_REDUCE_WG);
         let path_bboxes = BufferSize::new(n_paths);
         let cubics = BufferSize::new(n_path_tags);
@@ -311,18 +469,21 @@ impl BufferSizes {
         let paths = BufferSize::new(n_paths_aligned);
 
         // The following buffer sizes have been hand picked to accommodate the vello test scenes as
-        // well as paris-30k. These should instead get derived from the scene layout using
-        // reasonable heuristics.
+        // well as paris-30k. Renders replace them with sizes derived from the scene by
+        // `estimate_bump_sizes`, see `RenderConfig::fit_bump_sizes`.
         let bin_data = BufferSize::new(1 << 18);
         let tiles = BufferSize::new(1 << 21);
         let lines = BufferSize::new(1 << 21);
         let seg_counts = BufferSize::new(1 << 21);
         let segments = BufferSize::new(1 << 21);
         let ptcl = BufferSize::new(1 << 23);
+        // Only tiles with more than `BLEND_STACK_SPLIT` nested layers spill their blend stack.
+        let blend_spill = BufferSize::new(1 << 16);
         Self {
             path_reduced,
             path_reduced2,
             path_reduced_scan,
+            path_scan_state,
             path_monoids,
             path_bboxes,
             cubics,
@@ -344,8 +505,44 @@ impl BufferSizes {
             seg_counts,
             segments,
             ptcl,
+            blend_spill,
         }
     }
+
+    /// Returns the total size of all buffers, in bytes.
+    pub fn total_bytes(&self) -> u64 {
+        [
+            self.path_reduced.size_in_bytes(),
+            self.path_reduced2.size_in_bytes(),
+            self.path_reduced_scan.size_in_bytes(),
+            self.path_scan_state.size_in_bytes(),
+            self.path_monoids.size_in_bytes(),
+            self.path_bboxes.size_in_bytes(),
+            self.cubics.size_in_bytes(),
+            self.draw_reduced.size_in_bytes(),
+            self.draw_monoids.size_in_bytes(),
+            self.info.size_in_bytes(),
+            self.clip_inps.size_in_bytes(),
+            self.clip_els.size_in_bytes(),
+            self.clip_bics.size_in_bytes(),
+            self.clip_bboxes.size_in_bytes(),
+            self.draw_bboxes.size_in_bytes(),
+            self.bump_alloc.size_in_bytes(),
+            self.indirect_count.size_in_bytes(),
+            self.bin_headers.size_in_bytes(),
+            self.paths.size_in_bytes(),
+            self.lines.size_in_bytes(),
+            self.bin_data.size_in_bytes(),
+            self.tiles.size_in_bytes(),
+            self.seg_counts.size_in_bytes(),
+            self.segments.size_in_bytes(),
+            self.ptcl.size_in_bytes(),
+            self.blend_spill.size_in_bytes(),
+        ]
+        .into_iter()
+        .map(u64::from)
+        .sum()
+    }
 }
 
 const fn align_up(len: u32, alignment: u32) -> u32 {
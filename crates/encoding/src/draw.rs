@@ -64,6 +64,31 @@ impl DrawColor {
             rgba: color.to_premul_u32(),
         }
     }
+
+    /// Converts the color from sRGB to linear light, keeping it premultiplied.
+    pub fn to_linear(self) -> Self {
+        let [r, g, b, a] = self.rgba.to_be_bytes();
+        if a == 0 {
+            return self;
+        }
+        let alpha = a as f64 / 255.0;
+        let convert = |c: u8| {
+            let c = (c as f64 / 255.0 / alpha).min(1.0);
+            (srgb_to_linear(c) * alpha * 255.0).round() as u8
+        };
+        Self {
+            rgba: u32::from_be_bytes([convert(r), convert(g), convert(b), a]),
+        }
+    }
+}
+
+/// Converts an sRGB encoded color component in the range 0 to 1 to linear light.
+pub(crate) fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
 }
 
 /// Draw data for a linear gradient.
@@ -98,12 +123,23 @@ pub struct DrawRadialGradient {
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 #[repr(C)]
 pub struct DrawImage {
-    /// Packed atlas coordinates.
+    /// Packed atlas coordinates, or [`Self::EXTERNAL_FLAG`] and the slot of an external
+    /// image.
     pub xy: u32,
     /// Packed image dimensions.
     pub width_height: u32,
 }
 
+impl DrawImage {
+    /// Number of external images a scene can draw, each bound to fine rasterization as its
+    /// own texture.
+    pub const MAX_EXTERNAL_IMAGES: u32 = 4;
+
+    /// Set in `xy` for external images, whose slot is in the low bits instead of their atlas
+    /// coordinates. Atlas coordinates are always below this.
+    pub const EXTERNAL_FLAG: u32 = 0x8000_0000;
+}
+
 /// Draw data for a clip or layer.
 #[derive(Clone, Copy, Debug, Default, Zeroable, Pod)]
 #[repr(C)]
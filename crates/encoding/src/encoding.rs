@@ -1,9 +1,12 @@
 // Copyright 2022 The Vello authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
-use super::{DrawColor, DrawTag, PathEncoder, PathTag, Transform};
+use super::{DrawColor, DrawTag, PathEncoder, PathTag, Style, Transform};
 
-use peniko::{kurbo::Shape, BlendMode, BrushRef, Color, Fill};
+use peniko::{
+    kurbo::{Shape, Stroke},
+    BlendMode, BrushRef, Color, Fill,
+};
 
 #[cfg(feature = "full")]
 use {
@@ -26,7 +29,14 @@ pub struct Encoding {
     /// The transform stream.
     pub transforms: Vec<Transform>,
     /// The line width stream.
-    pub linewidths: Vec<f32>,
+    pub styles: Vec<Style>,
+    /// The stroke width stream, with the widths at the start and end of each path segment.
+    ///
+    /// This is only populated up to the last variable width stroke, and is empty if there
+    /// are none. The widths of other segments are zero.
+    pub widths: Vec<[f32; 2]>,
+    /// Instances of earlier segments, in the order of their instance tags.
+    pub instances: Vec<Instance>,
     /// Late bound resource data.
     #[cfg(feature = "full")]
     pub resources: Resources,
@@ -51,12 +61,78 @@ impl Encoding {
         self.path_tags.is_empty()
     }
 
+    /// Returns statistics about the contents of the encoding.
+    pub fn stats(&self) -> EncodingStats {
+        let mut depth = 0;
+        let mut max_layer_depth = 0;
+        for tag in &self.draw_tags {
+            if *tag == DrawTag::BEGIN_CLIP {
+                depth += 1;
+                max_layer_depth = max_layer_depth.max(depth);
+            } else if *tag == DrawTag::END_CLIP {
+                depth = depth.saturating_sub(1);
+            }
+        }
+        #[cfg(feature = "full")]
+        let glyph_runs = self.resources.glyph_runs.len() as u32;
+        #[cfg(not(feature = "full"))]
+        let glyph_runs = 0;
+        let scene_bytes = self.path_tags.len()
+            + self.path_data.len()
+            + self.draw_tags.len() * std::mem::size_of::<DrawTag>()
+            + self.draw_data.len()
+            + self.transforms.len() * std::mem::size_of::<Transform>()
+            + self.styles.len() * std::mem::size_of::<Style>()
+            + self.widths.len() * std::mem::size_of::<[f32; 2]>();
+        EncodingStats {
+            paths: self.n_paths,
+            segments: self.n_path_segments,
+            draw_objects: self.draw_tags.len() as u32,
+            glyph_runs,
+            max_layer_depth,
+            scene_bytes: scene_bytes as u64,
+            gpu_bytes: None,
+        }
+    }
+
+    /// Returns statistics about the contents of the encoding like [`Encoding::stats`], along
+    /// with an estimate of the GPU memory needed to render it to a `width` by `height` target.
+    ///
+    /// The estimate packs the encoding, resolving its glyph runs, and sizes the buffers of the
+    /// pipeline with [`estimate_bump_sizes`](crate::estimate_bump_sizes), as the renderer does
+    /// before each render, so it costs about as much CPU time as preparing a frame.
+    pub fn stats_for_target(&self, width: u32, height: u32) -> EncodingStats {
+        let mut packed = Vec::new();
+        #[cfg(feature = "full")]
+        let layout = crate::Resolver::new().resolve(self, &mut packed).0;
+        #[cfg(not(feature = "full"))]
+        let layout = crate::resolve_solid_paths_only(self, &mut packed);
+        let mut config = crate::RenderConfig::new(&layout, width, height, &Color::TRANSPARENT);
+        config.fit_bump_sizes(&crate::estimate_bump_sizes(&layout, &packed, width, height));
+        let target_bytes = width as u64 * height as u64 * 4;
+        EncodingStats {
+            gpu_bytes: Some(packed.len() as u64 + config.buffer_sizes.total_bytes() + target_bytes),
+            ..self.stats()
+        }
+    }
+
+    /// Returns true if every draw object of the encoding is a color fill, so that the scene
+    /// has no clips, layers, gradients or images.
+    ///
+    /// Such scenes can be rendered with variants of the draw and coarse stages which skip
+    /// the handling of the other kinds of draw objects.
+    pub fn is_simple(&self) -> bool {
+        self.n_clips == 0 && self.draw_tags.iter().all(|tag| *tag == DrawTag::COLOR)
+    }
+
     /// Clears the encoding.
     pub fn reset(&mut self, is_fragment: bool) {
         self.transforms.clear();
         self.path_tags.clear();
         self.path_data.clear();
-        self.linewidths.clear();
+        self.styles.clear();
+        self.widths.clear();
+        self.instances.clear();
         self.draw_data.clear();
         self.draw_tags.clear();
         self.n_paths = 0;
@@ -67,12 +143,157 @@ impl Encoding {
         self.resources.reset();
         if !is_fragment {
             self.transforms.push(Transform::IDENTITY);
-            self.linewidths.push(-1.0);
+            self.styles.push(Style::from_fill(Fill::NonZero));
         }
     }
 
     /// Appends another encoding to this one with an optional transform.
     pub fn append(&mut self, other: &Self, transform: &Option<Transform>) {
+        self.append_streams(other, transform, true);
+    }
+
+    /// Appends several encodings to this one with an optional transform, reserving space for
+    /// all of them first.
+    ///
+    /// This is the cheap merge step for encodings built independently, for example on
+    /// separate threads.
+    pub fn append_all(&mut self, others: &[&Self], transform: &Option<Transform>) {
+        let mut path_tags = 0;
+        let mut path_data = 0;
+        let mut draw_tags = 0;
+        let mut draw_data = 0;
+        let mut transforms = 0;
+        let mut styles = 0;
+        for other in others {
+            path_tags += other.path_tags.len();
+            path_data += other.path_data.len();
+            draw_tags += other.draw_tags.len();
+            draw_data += other.draw_data.len();
+            transforms += other.transforms.len();
+            styles += other.styles.len();
+        }
+        self.path_tags.reserve(path_tags);
+        self.path_data.reserve(path_data);
+        self.draw_tags.reserve(draw_tags);
+        self.draw_data.reserve(draw_data);
+        self.transforms.reserve(transforms);
+        self.styles.reserve(styles);
+        for other in others {
+            self.append(other, transform);
+        }
+    }
+
+    /// Appends another encoding to this one as an instance of an earlier copy of it, with an
+    /// optional transform.
+    ///
+    /// `source` must be the stream offsets at which `other` was appended before. The segments
+    /// of the instance read the path data of that copy instead of storing their own, while the
+    /// other streams are appended as usual. Encodings with glyph runs or instances of their own
+    /// are appended in full.
+    pub fn append_instance(
+        &mut self,
+        other: &Self,
+        transform: &Option<Transform>,
+        source: &StreamOffsets,
+    ) {
+        #[cfg(feature = "full")]
+        let has_glyphs = !other.resources.glyph_runs.is_empty();
+        #[cfg(not(feature = "full"))]
+        let has_glyphs = false;
+        if has_glyphs || !other.instances.is_empty() || other.path_data.is_empty() {
+            self.append(other, transform);
+            return;
+        }
+        self.instances.push(Instance {
+            source: *source,
+            offsets: self.stream_offsets(),
+            path_data_len: other.path_data.len(),
+        });
+        self.path_tags.push(PathTag::INSTANCE);
+        self.append_streams(other, transform, false);
+        self.path_tags.push(PathTag::INSTANCE);
+    }
+
+    /// Replaces the contents of an encoding appended at `offsets` with those of `other`, with an
+    /// optional transform.
+    ///
+    /// This only succeeds if `other` has the same path and draw tags as the appended encoding,
+    /// with the same amount of path data, draw data, transforms and styles, and if neither has
+    /// late bound resources, instances or variable stroke widths. Returns false and leaves this
+    /// encoding unchanged otherwise.
+    pub fn patch(
+        &mut self,
+        offsets: &StreamOffsets,
+        other: &Self,
+        transform: &Option<Transform>,
+    ) -> bool {
+        let end = offsets.rebase(&other.stream_offsets());
+        let matches = self.path_tags.get(offsets.path_tags..end.path_tags)
+            == Some(&other.path_tags[..])
+            && self.draw_tags.get(offsets.draw_tags..end.draw_tags) == Some(&other.draw_tags[..])
+            && end.path_data <= self.path_data.len()
+            && end.draw_data <= self.draw_data.len()
+            && end.transforms <= self.transforms.len()
+            && end.styles <= self.styles.len();
+        if !matches || !other.instances.is_empty() || !other.widths.is_empty() {
+            return false;
+        }
+        // The widths of the replaced segments would have to be patched as well.
+        let first_segment = self.path_tags[..offsets.path_tags]
+            .iter()
+            .filter(|tag| tag.is_path_segment())
+            .count();
+        if self.widths.len() > first_segment {
+            return false;
+        }
+        #[cfg(feature = "full")]
+        {
+            let resources = &self.resources;
+            let in_region = |draw_data_offset: usize| {
+                (offsets.draw_data..end.draw_data).contains(&draw_data_offset)
+            };
+            let has_patches = resources.patches.iter().any(|patch| match patch {
+                Patch::Ramp {
+                    draw_data_offset, ..
+                }
+                | Patch::Image {
+                    draw_data_offset, ..
+                }
+                | Patch::FilterLayer {
+                    draw_data_offset, ..
+                }
+                | Patch::ExternalImage {
+                    draw_data_offset, ..
+                } => in_region(*draw_data_offset),
+                Patch::GlyphRun { index } => (offsets.draw_tags..end.draw_tags)
+                    .contains(&resources.glyph_runs[*index].stream_offsets.draw_tags),
+            });
+            if has_patches || !other.resources.patches.is_empty() {
+                return false;
+            }
+        }
+        self.path_data[offsets.path_data..end.path_data].copy_from_slice(&other.path_data);
+        self.draw_data[offsets.draw_data..end.draw_data].copy_from_slice(&other.draw_data);
+        let transforms = &mut self.transforms[offsets.transforms..end.transforms];
+        if let Some(transform) = *transform {
+            for (dst, src) in transforms.iter_mut().zip(&other.transforms) {
+                *dst = transform * *src;
+            }
+        } else {
+            transforms.copy_from_slice(&other.transforms);
+        }
+        self.styles[offsets.styles..end.styles].copy_from_slice(&other.styles);
+        true
+    }
+
+    fn append_streams(&mut self, other: &Self, transform: &Option<Transform>, path_data: bool) {
+        let offsets = self.stream_offsets();
+        self.instances
+            .extend(other.instances.iter().map(|instance| Instance {
+                source: offsets.rebase(&instance.source),
+                offsets: offsets.rebase(&instance.offsets),
+                path_data_len: instance.path_data_len,
+            }));
         #[cfg(feature = "full")]
         let glyph_runs_base = {
             let offsets = self.stream_offsets();
@@ -80,6 +301,7 @@ impl Encoding {
             let glyph_runs_base = self.resources.glyph_runs.len();
             let glyphs_base = self.resources.glyphs.len();
             let coords_base = self.resources.normalized_coords.len();
+            let filter_layers_base = self.resources.filter_layers.len();
             self.resources
                 .glyphs
                 .extend_from_slice(&other.resources.glyphs);
@@ -96,7 +318,7 @@ impl Encoding {
                     run.stream_offsets.draw_tags += offsets.draw_tags;
                     run.stream_offsets.draw_data += offsets.draw_data;
                     run.stream_offsets.transforms += offsets.transforms;
-                    run.stream_offsets.linewidths += offsets.linewidths;
+                    run.stream_offsets.styles += offsets.styles;
                     run
                 }));
             self.resources
@@ -124,16 +346,39 @@ impl Encoding {
                         image: image.clone(),
                         draw_data_offset: *draw_data_offset + offsets.draw_data,
                     },
+                    Patch::FilterLayer {
+                        index,
+                        draw_data_offset,
+                    } => Patch::FilterLayer {
+                        index: index + filter_layers_base,
+                        draw_data_offset: *draw_data_offset + offsets.draw_data,
+                    },
+                    Patch::ExternalImage {
+                        image,
+                        draw_data_offset,
+                    } => Patch::ExternalImage {
+                        image: *image,
+                        draw_data_offset: *draw_data_offset + offsets.draw_data,
+                    },
                 }));
+            self.resources
+                .filter_layers
+                .extend_from_slice(&other.resources.filter_layers);
             self.resources
                 .color_stops
                 .extend_from_slice(&other.resources.color_stops);
             glyph_runs_base
         };
         self.path_tags.extend_from_slice(&other.path_tags);
-        self.path_data.extend_from_slice(&other.path_data);
+        if path_data {
+            self.path_data.extend_from_slice(&other.path_data);
+        }
         self.draw_tags.extend_from_slice(&other.draw_tags);
         self.draw_data.extend_from_slice(&other.draw_data);
+        if !other.widths.is_empty() {
+            self.widths.resize(self.n_path_segments as usize, [0.0; 2]);
+            self.widths.extend_from_slice(&other.widths);
+        }
         self.n_paths += other.n_paths;
         self.n_path_segments += other.n_path_segments;
         self.n_clips += other.n_clips;
@@ -148,7 +393,7 @@ impl Encoding {
         } else {
             self.transforms.extend_from_slice(&other.transforms);
         }
-        self.linewidths.extend_from_slice(&other.linewidths);
+        self.styles.extend_from_slice(&other.styles);
     }
 
     /// Returns a snapshot of the current stream offsets.
@@ -159,19 +404,36 @@ impl Encoding {
             draw_tags: self.draw_tags.len(),
             draw_data: self.draw_data.len(),
             transforms: self.transforms.len(),
-            linewidths: self.linewidths.len(),
+            styles: self.styles.len(),
         }
     }
 
     /// Encodes a fill style.
     pub fn encode_fill_style(&mut self, fill: Fill) {
-        let linewidth = match fill {
-            Fill::NonZero => -1.0,
-            Fill::EvenOdd => -2.0,
-        };
-        if self.linewidths.last() != Some(&linewidth) {
-            self.path_tags.push(PathTag::LINEWIDTH);
-            self.linewidths.push(linewidth);
+        self.encode_style(Style::from_fill(fill));
+    }
+
+    /// Encodes a stroke style.
+    ///
+    /// Paths drawn with this style should be encoded with `is_fill` set to false so that
+    /// open subpaths receive caps instead of being closed. The dash pattern is ignored.
+    pub fn encode_stroke_style(&mut self, stroke: &Stroke) {
+        self.encode_style(Style::from_stroke(stroke));
+    }
+
+    /// Encodes the style of a stroke with a width at each point of the path.
+    ///
+    /// Paths drawn with this style should be encoded with
+    /// [`encode_variable_width_path`](Self::encode_variable_width_path). `max_width` is the
+    /// largest of their widths. The dash pattern and the width of `stroke` are ignored.
+    pub fn encode_variable_width_stroke_style(&mut self, stroke: &Stroke, max_width: f32) {
+        self.encode_style(Style::from_variable_width_stroke(stroke, max_width));
+    }
+
+    fn encode_style(&mut self, style: Style) {
+        if self.styles.last() != Some(&style) {
+            self.path_tags.push(PathTag::STYLE);
+            self.styles.push(style);
         }
     }
 
@@ -180,6 +442,15 @@ impl Encoding {
     /// If the given transform is different from the current one, encodes it and
     /// returns true. Otherwise, encodes nothing and returns false.
     pub fn encode_transform(&mut self, transform: Transform) -> bool {
+        #[cfg(feature = "validate")]
+        assert!(
+            transform
+                .matrix
+                .iter()
+                .chain(&transform.translation)
+                .all(|v| v.is_finite()),
+            "transform is not finite: {transform:?}"
+        );
         if self.transforms.last() != Some(&transform) {
             self.path_tags.push(PathTag::TRANSFORM);
             self.transforms.push(transform);
@@ -201,6 +472,20 @@ impl Encoding {
         )
     }
 
+    /// Returns an encoder for encoding a path with a variable width stroke. The width at each
+    /// point is set with [`PathEncoder::set_width`].
+    pub fn encode_variable_width_path(&mut self) -> PathEncoder {
+        self.widths.resize(self.n_path_segments as usize, [0.0; 2]);
+        PathEncoder::new(
+            &mut self.path_tags,
+            &mut self.path_data,
+            &mut self.n_path_segments,
+            &mut self.n_paths,
+            false,
+        )
+        .with_widths(&mut self.widths)
+    }
+
     /// Encodes a shape. If `is_fill` is true, all subpaths will be automatically closed.
     /// Returns true if a non-zero number of segments were encoded.
     pub fn encode_shape(&mut self, shape: &impl Shape, is_fill: bool) -> bool {
@@ -338,6 +623,43 @@ impl Encoding {
             }));
     }
 
+    /// Encodes an image brush for a texture which fine rasterization samples on the GPU.
+    ///
+    /// Unlike [`Self::encode_image`], this takes no alpha multiplier, as image brushes don't
+    /// apply one.
+    #[cfg(feature = "full")]
+    pub fn encode_external_image(&mut self, image: &ExternalImage) {
+        self.resources.patches.push(Patch::ExternalImage {
+            image: *image,
+            draw_data_offset: self.draw_data.len(),
+        });
+        self.draw_tags.push(DrawTag::IMAGE);
+        self.draw_data
+            .extend_from_slice(bytemuck::bytes_of(&DrawImage {
+                xy: 0,
+                width_height: (image.width << 16) | (image.height & 0xFFFF),
+            }));
+    }
+
+    /// Encodes a brush which draws the filtered contents of an offscreen layer.
+    ///
+    /// The layer is drawn like an image of its size, with one pixel per unit of the
+    /// transform of the path.
+    #[cfg(feature = "full")]
+    pub fn encode_filter_layer(&mut self, layer: FilterLayer) {
+        self.resources.patches.push(Patch::FilterLayer {
+            index: self.resources.filter_layers.len(),
+            draw_data_offset: self.draw_data.len(),
+        });
+        self.draw_tags.push(DrawTag::IMAGE);
+        self.draw_data
+            .extend_from_slice(bytemuck::bytes_of(&DrawImage {
+                xy: 0,
+                width_height: (layer.width << 16) | (layer.height & 0xFFFF),
+            }));
+        self.resources.filter_layers.push(layer);
+    }
+
     /// Encodes a begin clip command.
     pub fn encode_begin_clip(&mut self, blend_mode: BlendMode, alpha: f32) {
         use super::DrawBeginClip;
@@ -350,6 +672,11 @@ impl Encoding {
 
     /// Encodes an end clip command.
     pub fn encode_end_clip(&mut self) {
+        #[cfg(feature = "validate")]
+        assert!(
+            self.n_open_clips > 0,
+            "layer popped without a matching push"
+        );
         if self.n_open_clips > 0 {
             self.draw_tags.push(DrawTag::END_CLIP);
             // This is a dummy path, and will go away with the new clip impl.
@@ -423,6 +750,8 @@ pub struct Resources {
     pub glyph_runs: Vec<GlyphRun>,
     /// Normalized coordinate buffer for variable fonts.
     pub normalized_coords: Vec<NormalizedCoord>,
+    /// Layers which are rendered offscreen and filtered before being drawn.
+    pub filter_layers: Vec<FilterLayer>,
 }
 
 #[cfg(feature = "full")]
@@ -433,9 +762,84 @@ impl Resources {
         self.glyphs.clear();
         self.glyph_runs.clear();
         self.normalized_coords.clear();
+        self.filter_layers.clear();
     }
 }
 
+/// Contents of a layer which are rendered into an offscreen image and blurred before they
+/// are drawn.
+#[cfg(feature = "full")]
+#[derive(Clone)]
+pub struct FilterLayer {
+    /// Encoded contents of the layer, in the pixel coordinates of the offscreen image.
+    pub encoding: Encoding,
+    /// Width of the offscreen image in pixels.
+    pub width: u32,
+    /// Height of the offscreen image in pixels.
+    pub height: u32,
+    /// Standard deviation of the Gaussian blur, in pixels.
+    pub std_dev: f32,
+    /// Color which replaces the color of the blurred layer, keeping its alpha, to draw
+    /// shadows.
+    pub color: Option<Color>,
+}
+
+/// Handle of a texture which is drawn as an image without being uploaded from the CPU, such
+/// as a decoded video frame.
+///
+/// Handles are created by the renderer that owns the texture, which binds it to fine
+/// rasterization each time a scene that draws it is rendered. A scene draws at most
+/// [`DrawImage::MAX_EXTERNAL_IMAGES`](crate::DrawImage::MAX_EXTERNAL_IMAGES) different external
+/// images, and the renderer rejects scenes with more. The texture holds colors with separate
+/// alpha, like the data of an [`Image`].
+#[cfg(feature = "full")]
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ExternalImage {
+    /// Identifier assigned by the renderer.
+    pub id: u64,
+    /// Width of the texture in pixels.
+    pub width: u32,
+    /// Height of the texture in pixels.
+    pub height: u32,
+}
+
+/// Statistics about the contents of an encoding, returned by [`Encoding::stats`].
+///
+/// Glyph runs are only turned into paths when the encoding is resolved, so their paths and
+/// segments are not included in the counts.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct EncodingStats {
+    /// Number of paths.
+    pub paths: u32,
+    /// Number of path segments.
+    pub segments: u32,
+    /// Number of draw objects, including the begin and end of each layer.
+    pub draw_objects: u32,
+    /// Number of glyph runs.
+    pub glyph_runs: u32,
+    /// Deepest nesting of layers.
+    pub max_layer_depth: u32,
+    /// Estimated size of the scene buffer uploaded to the GPU, in bytes. The buffers used
+    /// while rendering grow with the segment count and the size of the target.
+    pub scene_bytes: u64,
+    /// Estimated GPU memory needed to render the scene, in bytes: the scene buffer, the
+    /// buffers of the pipeline and an RGBA8 target. Gradient ramps and the image atlas are not
+    /// included. This is only computed by [`Encoding::stats_for_target`].
+    pub gpu_bytes: Option<u64>,
+}
+
+/// Segments encoded as an instance of earlier segments, which are enclosed by a pair of
+/// [`PathTag::INSTANCE`] tags and have no path data of their own.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct Instance {
+    /// Stream offsets of the segments that are repeated.
+    pub source: StreamOffsets,
+    /// Stream offsets of the opening instance tag.
+    pub offsets: StreamOffsets,
+    /// Size of the path data of the repeated segments, in bytes.
+    pub path_data_len: usize,
+}
+
 /// Snapshot of offsets for encoded streams.
 #[derive(Copy, Clone, Default, Debug)]
 pub struct StreamOffsets {
@@ -449,11 +853,23 @@ pub struct StreamOffsets {
     pub draw_data: usize,
     /// Current length of transform stream.
     pub transforms: usize,
-    /// Current length of linewidth stream.
-    pub linewidths: usize,
+    /// Current length of style stream.
+    pub styles: usize,
 }
 
 impl StreamOffsets {
+    /// Returns the offsets of `other` shifted by these offsets.
+    pub(crate) fn rebase(&self, other: &Self) -> Self {
+        Self {
+            path_tags: self.path_tags + other.path_tags,
+            path_data: self.path_data + other.path_data,
+            draw_tags: self.draw_tags + other.draw_tags,
+            draw_data: self.draw_data + other.draw_data,
+            transforms: self.transforms + other.transforms,
+            styles: self.styles + other.styles,
+        }
+    }
+
     #[cfg(feature = "full")]
     pub(crate) fn add(&mut self, other: &Self) {
         self.path_tags += other.path_tags;
@@ -461,6 +877,6 @@ impl StreamOffsets {
         self.draw_tags += other.draw_tags;
         self.draw_data += other.draw_data;
         self.transforms += other.transforms;
-        self.linewidths += other.linewidths;
+        self.styles += other.styles;
     }
 }
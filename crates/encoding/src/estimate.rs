@@ -0,0 +1,281 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! CPU side estimation of the dynamic memory needed to render a scene.
+
+use super::{
+    config::{PTCL_INCREMENT, PTCL_INITIAL_ALLOC, TILE_HEIGHT, TILE_WIDTH},
+    BumpAllocators, DrawTag, Layout, PathTag, Style, Transform,
+};
+use peniko::Fill;
+
+/// Width and height of a bin in tiles.
+const BIN_SIZE: u32 = 16;
+
+/// Depth of the blend stack that fine rasterization keeps in registers.
+///
+/// Must be kept in sync with shader/shared/config.wgsl.
+const BLEND_STACK_SPLIT: usize = 4;
+
+/// Flattening tolerance, in pixels.
+///
+/// This is tighter than the accuracy used by the flattening stage so that the line count it
+/// produces stays below the estimate.
+const TOLERANCE: f32 = 0.1;
+
+/// Estimates the sizes of the bump allocated buffers needed to render a resolved scene.
+///
+/// The scene is walked in the same order as the GPU pipeline: path segments are transformed
+/// and their flattened line counts and tile crossings are bounded, then the bounding box of
+/// each draw object is used to count the bins, tiles and per-tile commands it produces. All
+/// of the sizes are conservative estimates rather than exact counts; in particular clips are
+/// not taken into account when bounding draw objects. The result can be applied with
+/// [`RenderConfig::fit_bump_sizes`](crate::RenderConfig::fit_bump_sizes).
+pub fn estimate_bump_sizes(
+    layout: &Layout,
+    data: &[u8],
+    width: u32,
+    height: u32,
+) -> BumpAllocators {
+    let width_in_tiles = width.div_ceil(TILE_WIDTH);
+    let height_in_tiles = height.div_ceil(TILE_HEIGHT);
+    let transforms = layout.transforms(data);
+    let styles = layout.styles(data);
+    let path_data = layout.path_data(data);
+    let instances = layout.instances(data);
+    let mut estimate = BumpAllocators::default();
+
+    // Path bounding boxes in tiles, as computed by the flattening stage.
+    let mut path_bboxes = Vec::with_capacity(layout.n_paths as usize);
+    let mut bbox = Bbox::EMPTY;
+    let mut trans_ix = 0;
+    let mut style_ix = 0;
+    let mut instance_ix = 0;
+    let mut offset = 0;
+    for tag in layout.path_tags(data) {
+        let tag = *tag;
+        if tag.0 & PathTag::TRANSFORM.0 != 0 {
+            trans_ix += 1;
+        }
+        if tag.0 & PathTag::STYLE.0 != 0 {
+            style_ix += 1;
+        }
+        if tag.0 & PathTag::INSTANCE.0 != 0 {
+            instance_ix += 1;
+        }
+        let n_points = tag.path_segment_type().0 as usize;
+        if n_points != 0 {
+            let transform = transforms
+                .get(trans_ix)
+                .copied()
+                .unwrap_or(Transform::IDENTITY);
+            let point_size = if tag.is_f32() { 8 } else { 4 };
+            let style = styles
+                .get(style_ix)
+                .copied()
+                .unwrap_or_else(|| Style::from_fill(Fill::NonZero));
+            let is_stroke = style.is_stroke();
+            // Strokes extend the bounding box by half the line width in each direction, or
+            // further for miter joins and square caps.
+            let stroke = if is_stroke {
+                let m = &transform.matrix;
+                let hw = 0.5 * style.line_width * stroke_extent(&style);
+                [hw * m[0].hypot(m[2]), hw * m[1].hypot(m[3])]
+            } else {
+                [0.0; 2]
+            };
+            // Segments of instances read the path data of the segments they repeat.
+            let delta = instances
+                .get(instance_ix)
+                .map_or(0, |delta| *delta as i32 as isize);
+            let base = (offset as isize + delta * 4) as usize;
+            let mut points = [[0.0; 2]; 4];
+            for (i, point) in points.iter_mut().take(n_points + 1).enumerate() {
+                *point = apply(
+                    &transform,
+                    read_point(path_data, base + i * point_size, point_size),
+                );
+                bbox.add([point[0] - stroke[0], point[1] - stroke[1]]);
+                bbox.add([point[0] + stroke[0], point[1] + stroke[1]]);
+            }
+            let mut n_lines = estimate_lines(&points[..=n_points]);
+            if is_stroke {
+                // Each flattened line is expanded to a quad, with a bevel between consecutive
+                // lines, and the segment has a join or cap at its end and may have a cap at
+                // its start.
+                n_lines = 7 * n_lines + 2 * MAX_CAP_LINES;
+            }
+            estimate.lines += n_lines;
+            // Each line touches at most its horizontal and vertical extent in tiles, plus the
+            // tiles at both of its ends.
+            let mut extent = [0.0f32; 2];
+            for pair in points[..=n_points].windows(2) {
+                extent[0] += (pair[1][0] - pair[0][0]).abs();
+                extent[1] += (pair[1][1] - pair[0][1]).abs();
+            }
+            let mut crossings =
+                (extent[0] / TILE_WIDTH as f32 + extent[1] / TILE_HEIGHT as f32).ceil() as u32;
+            if is_stroke {
+                // Both sides of the stroke cross the same tiles as the centerline, and the
+                // ends of every quad cross the width of the stroke.
+                let across = (2.0 * stroke[0].max(stroke[1])) as u32 / TILE_WIDTH + 1;
+                crossings = 2 * crossings + 2 * n_lines * across;
+            }
+            crossings += 3 * n_lines;
+            estimate.seg_counts += crossings;
+            offset += n_points * point_size;
+            if tag.is_subpath_end() {
+                offset += point_size;
+            }
+        }
+        if tag.0 & PathTag::PATH.0 != 0 {
+            path_bboxes.push(bbox.to_tiles(width_in_tiles, height_in_tiles));
+            bbox = Bbox::EMPTY;
+        }
+    }
+    estimate.segments = estimate.seg_counts;
+
+    // Per draw object allocations. Each draw object is paired with the path at the same index.
+    let mut clip_stack = vec![];
+    let mut ptcl_words = 0;
+    for (tag, path_bbox) in layout.draw_tags(data).iter().zip(path_bboxes) {
+        let (bbox, cmd_size) = match *tag {
+            DrawTag::BEGIN_CLIP => {
+                clip_stack.push(path_bbox);
+                (path_bbox, 1)
+            }
+            // End clips cover the bounding box of their matching begin clip, but don't
+            // allocate any tiles of their own.
+            DrawTag::END_CLIP => (clip_stack.pop().unwrap_or(path_bbox), 3),
+            DrawTag::COLOR => (path_bbox, 2),
+            DrawTag::LINEAR_GRADIENT => (path_bbox, 6),
+            DrawTag::RADIAL_GRADIENT | DrawTag::IMAGE => (path_bbox, 13),
+            _ => continue,
+        };
+        let (x0, y0, x1, y1) = bbox;
+        let n_tiles = (x1 - x0) * (y1 - y0);
+        if n_tiles == 0 {
+            continue;
+        }
+        let n_bins =
+            (x1.div_ceil(BIN_SIZE) - x0 / BIN_SIZE) * (y1.div_ceil(BIN_SIZE) - y0 / BIN_SIZE);
+        estimate.binning += n_bins;
+        // Tiles inside more than BLEND_STACK_SPLIT layers spill their blend stack, and
+        // allocate room for all of its layers.
+        if *tag == DrawTag::BEGIN_CLIP && clip_stack.len() > BLEND_STACK_SPLIT {
+            let n_layers = if clip_stack.len() == BLEND_STACK_SPLIT + 1 {
+                clip_stack.len() as u32
+            } else {
+                1
+            };
+            estimate.blend += n_tiles * n_layers * TILE_WIDTH * TILE_HEIGHT;
+        }
+        if *tag != DrawTag::END_CLIP {
+            estimate.tile += n_tiles;
+            // A fill command precedes the draw command in every tile.
+            ptcl_words += n_tiles * (4 + cmd_size);
+        } else {
+            ptcl_words += n_tiles * cmd_size;
+        }
+    }
+    // Commands go into the initial allocation of each tile first, and spill into chunks of
+    // PTCL_INCREMENT words. A command that doesn't fit at the end of a chunk leaves a gap, and
+    // every tile that spills may leave most of its last chunk unused.
+    let n_spilling_tiles = (ptcl_words / PTCL_INITIAL_ALLOC).min(width_in_tiles * height_in_tiles);
+    estimate.ptcl = ptcl_words + ptcl_words / 8 + n_spilling_tiles * PTCL_INCREMENT;
+    estimate
+}
+
+/// Maximum number of lines in a join or cap of a stroke, which is reached by round caps made
+/// of two arcs of up to 32 lines each.
+const MAX_CAP_LINES: u32 = 2 * (32 + 2);
+
+/// Returns the distance a stroke can extend from its centerline, in units of half the line width.
+fn stroke_extent(style: &Style) -> f32 {
+    let mut extent = 1.0f32;
+    if style.flags & Style::JOIN_MASK == Style::JOIN_MITER {
+        extent = extent.max(style.miter_limit);
+    }
+    let caps = [
+        (style.flags >> Style::START_CAP_SHIFT) & Style::CAP_MASK,
+        (style.flags >> Style::END_CAP_SHIFT) & Style::CAP_MASK,
+    ];
+    if caps.contains(&Style::CAP_SQUARE) {
+        extent = extent.max(std::f32::consts::SQRT_2);
+    }
+    extent
+}
+
+/// Bounding box in pixels.
+struct Bbox {
+    x0: f32,
+    y0: f32,
+    x1: f32,
+    y1: f32,
+}
+
+impl Bbox {
+    const EMPTY: Self = Self {
+        x0: f32::MAX,
+        y0: f32::MAX,
+        x1: f32::MIN,
+        y1: f32::MIN,
+    };
+
+    fn add(&mut self, p: [f32; 2]) {
+        self.x0 = self.x0.min(p[0]);
+        self.y0 = self.y0.min(p[1]);
+        self.x1 = self.x1.max(p[0]);
+        self.y1 = self.y1.max(p[1]);
+    }
+
+    /// Returns the tiles covered by the bounding box, clamped to the viewport.
+    fn to_tiles(&self, width_in_tiles: u32, height_in_tiles: u32) -> (u32, u32, u32, u32) {
+        if self.x1 < self.x0 || self.y1 < self.y0 {
+            return (0, 0, 0, 0);
+        }
+        let tile = |v: f32, size: u32, max: u32| (v / size as f32).clamp(0.0, max as f32);
+        let x0 = tile(self.x0, TILE_WIDTH, width_in_tiles).floor() as u32;
+        let y0 = tile(self.y0, TILE_HEIGHT, height_in_tiles).floor() as u32;
+        let x1 = tile(self.x1, TILE_WIDTH, width_in_tiles).ceil() as u32;
+        let y1 = tile(self.y1, TILE_HEIGHT, height_in_tiles).ceil() as u32;
+        (x0, y0, x1.max(x0), y1.max(y0))
+    }
+}
+
+/// Bounds the number of lines a segment flattens to, using Wang's formula.
+fn estimate_lines(points: &[[f32; 2]]) -> u32 {
+    let degree = points.len() - 1;
+    if degree < 2 {
+        return 1;
+    }
+    let mut max_dd = 0.0f32;
+    for p in points.windows(3) {
+        let ddx = p[0][0] - 2.0 * p[1][0] + p[2][0];
+        let ddy = p[0][1] - 2.0 * p[1][1] + p[2][1];
+        max_dd = max_dd.max(ddx.hypot(ddy));
+    }
+    let n = (degree * (degree - 1)) as f32 / (8.0 * TOLERANCE) * max_dd;
+    (n.sqrt().ceil() as u32).max(1)
+}
+
+fn read_point(data: &[u8], offset: usize, point_size: usize) -> [f32; 2] {
+    let Some(bytes) = data.get(offset..offset + point_size) else {
+        return [0.0; 2];
+    };
+    if point_size == 8 {
+        bytemuck::pod_read_unaligned(bytes)
+    } else {
+        let xy: [i16; 2] = bytemuck::pod_read_unaligned(bytes);
+        [xy[0] as f32, xy[1] as f32]
+    }
+}
+
+fn apply(transform: &Transform, p: [f32; 2]) -> [f32; 2] {
+    let m = &transform.matrix;
+    let t = &transform.translation;
+    [
+        m[0] * p[0] + m[2] * p[1] + t[0],
+        m[1] * p[0] + m[3] * p[1] + t[1],
+    ]
+}
@@ -7,6 +7,16 @@ use peniko::{Font, Style};
 
 use super::{StreamOffsets, Transform};
 
+/// Returns the font referenced by `font`, which is selected by its index if the data is a
+/// collection.
+pub fn font_ref(font: &Font) -> Option<fello::raw::FontRef<'_>> {
+    use fello::raw::FileRef;
+    match FileRef::new(font.data.as_ref()).ok()? {
+        FileRef::Font(font) => Some(font),
+        FileRef::Collection(collection) => collection.get(font.index).ok(),
+    }
+}
+
 /// Positioned glyph.
 #[derive(Copy, Clone, Default, Debug)]
 pub struct Glyph {
@@ -3,34 +3,205 @@
 
 use std::collections::HashMap;
 
-use super::{Encoding, StreamOffsets};
+use super::{Encoding, PathTag, StreamOffsets};
 
 use fello::scale::{Pen, Scaler};
-use fello::GlyphId;
+use fello::{GlyphId, NormalizedCoord};
 use peniko::{
     kurbo::{BezPath, Shape},
     Fill, Style,
 };
 
+/// Number of frames a cached glyph is kept for after it was last used.
+const RETAINED_FRAMES: u64 = 2;
+
+/// Default size budget of the encoded outlines of cached glyphs, in bytes.
+const DEFAULT_BUDGET: usize = 16 << 20;
+
 #[derive(Copy, Clone, PartialEq, Eq, Hash, Default, Debug)]
 pub struct GlyphKey {
     pub font_id: u64,
     pub font_index: u32,
     pub glyph_id: u32,
+    /// Bits of the size the outline is extracted at, which is a size band for unhinted
+    /// glyphs.
     pub font_size: u32,
     pub hint: bool,
+    /// Identifier of the variation coordinates, from [`GlyphCache::coords_id`].
+    pub coords: u32,
 }
 
-#[derive(Default)]
+/// Returns the size that unhinted outlines are extracted at for `font_size`, which is the
+/// next power of two. The outlines are then scaled down to the font size, so that a glyph
+/// is only extracted once for all of the sizes in its band.
+pub fn size_band(font_size: f32) -> f32 {
+    if font_size.is_normal() {
+        2.0f32.powi(font_size.abs().log2().ceil() as i32)
+    } else {
+        font_size
+    }
+}
+
+/// Encoded glyph outlines, which are kept across frames.
+///
+/// Glyphs are evicted once they haven't been used for a few frames, or least recently used
+/// first when the cached outlines grow beyond the size budget.
 pub struct GlyphCache {
     pub encoding: Encoding,
-    glyphs: HashMap<GlyphKey, CachedRange>,
+    glyphs: HashMap<GlyphKey, CachedGlyph>,
+    /// Identifiers and last frame of use of the variation coordinates of cached glyphs.
+    coords: HashMap<Vec<i16>, (u32, u64)>,
+    next_coords_id: u32,
+    epoch: u64,
+    /// Size budget of the cached outlines, in bytes.
+    budget: usize,
+    /// Size of the cached outlines, in bytes.
+    size: usize,
+    stats: GlyphCacheStats,
+}
+
+/// Statistics of the glyph cache.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct GlyphCacheStats {
+    /// Number of glyphs found in the cache while resolving the last scene.
+    pub hits: u32,
+    /// Number of glyphs encoded while resolving the last scene, including glyphs that can't
+    /// be cached.
+    pub misses: u32,
+    /// Number of glyphs evicted before resolving the last scene.
+    pub evictions: u32,
+    /// Number of cached glyphs.
+    pub glyphs: u32,
+    /// Size of the encoded outlines of the cached glyphs, in bytes.
+    pub bytes: usize,
+}
+
+impl Default for GlyphCache {
+    fn default() -> Self {
+        Self {
+            encoding: Encoding::default(),
+            glyphs: HashMap::default(),
+            coords: HashMap::default(),
+            next_coords_id: 0,
+            epoch: 0,
+            budget: DEFAULT_BUDGET,
+            size: 0,
+            stats: GlyphCacheStats::default(),
+        }
+    }
+}
+
+#[derive(Copy, Clone)]
+struct CachedGlyph {
+    range: CachedRange,
+    /// Last frame the glyph was used in.
+    epoch: u64,
 }
 
 impl GlyphCache {
     pub fn clear(&mut self) {
         self.encoding.reset(true);
         self.glyphs.clear();
+        self.coords.clear();
+        self.size = 0;
+    }
+
+    /// Sets the size budget of the encoded outlines of cached glyphs, in bytes.
+    pub fn set_budget(&mut self, bytes: usize) {
+        self.budget = bytes;
+    }
+
+    /// Returns the statistics of the cache.
+    pub fn stats(&self) -> GlyphCacheStats {
+        GlyphCacheStats {
+            glyphs: self.glyphs.len() as u32,
+            bytes: self.size,
+            ..self.stats
+        }
+    }
+
+    /// Returns the identifier of a set of variation coordinates for use in glyph keys, which
+    /// is 0 for the default instance.
+    pub fn coords_id(&mut self, coords: &[NormalizedCoord]) -> u32 {
+        if coords.iter().all(|coord| coord.to_bits() == 0) {
+            return 0;
+        }
+        let bits = coords
+            .iter()
+            .map(|coord| coord.to_bits())
+            .collect::<Vec<_>>();
+        let epoch = self.epoch;
+        let next_id = &mut self.next_coords_id;
+        let entry = self.coords.entry(bits).or_insert_with(|| {
+            *next_id += 1;
+            (*next_id, epoch)
+        });
+        entry.1 = epoch;
+        entry.0
+    }
+
+    /// Starts a new frame.
+    ///
+    /// Glyphs which haven't been used for a few frames are evicted, and the encoding is
+    /// compacted if it holds any outlines which aren't cached.
+    pub fn advance(&mut self) {
+        self.epoch += 1;
+        let epoch = self.epoch;
+        let n_glyphs = self.glyphs.len();
+        self.glyphs
+            .retain(|_, glyph| glyph.epoch + RETAINED_FRAMES >= epoch);
+        self.size = self.glyphs.values().map(|glyph| glyph.range.size()).sum();
+        if self.size > self.budget {
+            let mut glyphs = self
+                .glyphs
+                .iter()
+                .map(|(key, glyph)| (glyph.epoch, glyph.range.size(), *key))
+                .collect::<Vec<_>>();
+            glyphs.sort_unstable_by_key(|(epoch, ..)| *epoch);
+            for (_, size, key) in glyphs {
+                if self.size <= self.budget {
+                    break;
+                }
+                self.glyphs.remove(&key);
+                self.size -= size;
+            }
+        }
+        self.stats = GlyphCacheStats {
+            evictions: (n_glyphs - self.glyphs.len()) as u32,
+            ..Default::default()
+        };
+        // Coordinates are used whenever their glyphs are, so this doesn't evict any
+        // coordinates of retained glyphs. Animated variations produce new coordinates every
+        // frame, which only stay around for as long as their glyphs.
+        self.coords
+            .retain(|_, (_, coords_epoch)| *coords_epoch + RETAINED_FRAMES >= epoch);
+        let cached_tags: usize = self
+            .glyphs
+            .values()
+            .map(|glyph| glyph.range.len().path_tags)
+            .sum();
+        if cached_tags == self.encoding.path_tags.len() {
+            return;
+        }
+        let mut encoding = Encoding::new();
+        for glyph in self.glyphs.values_mut() {
+            let start = encoding.stream_offsets();
+            let src = glyph.range;
+            encoding.path_tags.extend_from_slice(
+                &self.encoding.path_tags[src.start.path_tags..src.end.path_tags],
+            );
+            encoding.path_data.extend_from_slice(
+                &self.encoding.path_data[src.start.path_data..src.end.path_data],
+            );
+            encoding
+                .styles
+                .extend_from_slice(&self.encoding.styles[src.start.styles..src.end.styles]);
+            glyph.range = CachedRange {
+                start,
+                end: encoding.stream_offsets(),
+            };
+        }
+        self.encoding = encoding;
     }
 
     pub fn get_or_insert(
@@ -39,7 +210,6 @@ impl GlyphCache {
         style: &Style,
         scaler: &mut Scaler,
     ) -> Option<CachedRange> {
-        let is_var = !scaler.normalized_coords().is_empty();
         let encoding_cache = &mut self.encoding;
         let mut encode_glyph = || {
             let start = encoding_cache.stream_offsets();
@@ -47,7 +217,9 @@ impl GlyphCache {
                 Style::Fill(fill) => *fill,
                 Style::Stroke(_) => Fill::NonZero,
             };
-            encoding_cache.encode_fill_style(fill);
+            // Every glyph carries its style, as it may be drawn after paths with any other.
+            encoding_cache.path_tags.push(PathTag::STYLE);
+            encoding_cache.styles.push(crate::Style::from_fill(fill));
             let mut path = encoding_cache.encode_path(true);
             match style {
                 Style::Fill(_) => {
@@ -76,15 +248,26 @@ impl GlyphCache {
             let end = encoding_cache.stream_offsets();
             Some(CachedRange { start, end })
         };
-        // For now, only cache non-zero filled, non-variable glyphs so we don't need to keep style
-        // as part of the key.
-        let range = if matches!(style, Style::Fill(Fill::NonZero)) && !is_var {
+        // For now, only cache non-zero filled glyphs so we don't need to keep style as part of
+        // the key.
+        let range = if matches!(style, Style::Fill(Fill::NonZero)) {
             use std::collections::hash_map::Entry;
+            let epoch = self.epoch;
             match self.glyphs.entry(key) {
-                Entry::Occupied(entry) => *entry.get(),
-                Entry::Vacant(entry) => *entry.insert(encode_glyph()?),
+                Entry::Occupied(mut entry) => {
+                    self.stats.hits += 1;
+                    entry.get_mut().epoch = epoch;
+                    entry.get().range
+                }
+                Entry::Vacant(entry) => {
+                    self.stats.misses += 1;
+                    let range = encode_glyph()?;
+                    self.size += range.size();
+                    entry.insert(CachedGlyph { range, epoch }).range
+                }
             }
         } else {
+            self.stats.misses += 1;
             encode_glyph()?
         };
         Some(range)
@@ -105,9 +288,17 @@ impl CachedRange {
             draw_tags: self.end.draw_tags - self.start.draw_tags,
             draw_data: self.end.draw_data - self.start.draw_data,
             transforms: self.end.transforms - self.start.transforms,
-            linewidths: self.end.linewidths - self.start.linewidths,
+            styles: self.end.styles - self.start.styles,
         }
     }
+
+    /// Returns the size of the encoded data in the range, in bytes.
+    fn size(&self) -> usize {
+        let len = self.len();
+        len.path_tags * std::mem::size_of::<PathTag>()
+            + len.path_data
+            + len.styles * std::mem::size_of::<crate::Style>()
+    }
 }
 
 // A wrapper newtype so we can implement the `Pen` trait. Arguably, this could
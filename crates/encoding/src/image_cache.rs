@@ -3,6 +3,8 @@
 
 use guillotiere::{size2, AtlasAllocator};
 use peniko::Image;
+
+use crate::{DrawImage, ExternalImage};
 use std::collections::{hash_map::Entry, HashMap};
 
 const DEFAULT_ATLAS_SIZE: i32 = 1024;
@@ -13,6 +15,13 @@ pub struct Images<'a> {
     pub width: u32,
     pub height: u32,
     pub images: &'a [(Image, u32, u32)],
+    /// Filter layers rendered into the atlas, as the index of the layer in the encoding and
+    /// its atlas location.
+    pub layers: &'a [(usize, u32, u32)],
+    /// External images, in the order of the slots they are bound to.
+    pub external_images: &'a [ExternalImage],
+    /// Number of external image draws that found all slots taken and are not drawn.
+    pub dropped_external_images: u32,
 }
 
 pub struct ImageCache {
@@ -21,6 +30,13 @@ pub struct ImageCache {
     map: HashMap<u64, (u32, u32)>,
     /// List of all allocated images with associated atlas location.
     images: Vec<(Image, u32, u32)>,
+    /// List of all allocated filter layers with associated atlas location.
+    layers: Vec<(usize, u32, u32)>,
+    /// External images, in the order of the slots they are bound to. These don't take room
+    /// in the atlas.
+    external_images: Vec<ExternalImage>,
+    /// Number of external image draws that found all slots taken.
+    dropped_external_images: u32,
 }
 
 impl Default for ImageCache {
@@ -35,6 +51,9 @@ impl ImageCache {
             atlas: AtlasAllocator::new(size2(DEFAULT_ATLAS_SIZE, DEFAULT_ATLAS_SIZE)),
             map: Default::default(),
             images: Default::default(),
+            layers: Default::default(),
+            external_images: Default::default(),
+            dropped_external_images: 0,
         }
     }
 
@@ -43,6 +62,9 @@ impl ImageCache {
             width: self.atlas.size().width as u32,
             height: self.atlas.size().height as u32,
             images: &self.images,
+            layers: &self.layers,
+            external_images: &self.external_images,
+            dropped_external_images: self.dropped_external_images,
         }
     }
 
@@ -54,6 +76,7 @@ impl ImageCache {
         self.atlas = AtlasAllocator::new(size2(new_size, new_size));
         self.map.clear();
         self.images.clear();
+        self.layers.clear();
         true
     }
 
@@ -61,6 +84,32 @@ impl ImageCache {
         self.atlas.clear();
         self.map.clear();
         self.images.clear();
+        self.layers.clear();
+        self.external_images.clear();
+        self.dropped_external_images = 0;
+    }
+
+    /// Allocates room in the atlas for the filter layer at `index` in the encoding.
+    pub fn allocate_layer(&mut self, index: usize, width: u32, height: u32) -> Option<(u32, u32)> {
+        let alloc = self.atlas.allocate(size2(width as _, height as _))?;
+        let x = alloc.rectangle.min.x as u32;
+        let y = alloc.rectangle.min.y as u32;
+        self.layers.push((index, x, y));
+        Some((x, y))
+    }
+
+    /// Returns the slot of an external image, assigning the next one the first time the
+    /// image is drawn, or `None` if all slots are taken.
+    pub fn get_or_insert_external(&mut self, image: &ExternalImage) -> Option<u32> {
+        if let Some(slot) = self.external_images.iter().position(|ext| ext == image) {
+            return Some(slot as u32);
+        }
+        if self.external_images.len() >= DrawImage::MAX_EXTERNAL_IMAGES as usize {
+            self.dropped_external_images += 1;
+            return None;
+        }
+        self.external_images.push(*image);
+        Some(self.external_images.len() as u32 - 1)
     }
 
     pub fn get_or_insert(&mut self, image: &Image) -> Option<(u32, u32)> {
@@ -8,6 +8,7 @@ mod clip;
 mod config;
 mod draw;
 mod encoding;
+mod estimate;
 #[cfg(feature = "full")]
 mod glyph;
 #[cfg(feature = "full")]
@@ -20,30 +21,38 @@ mod path;
 #[cfg(feature = "full")]
 mod ramp_cache;
 mod resolve;
+mod validate;
+mod workgroup;
 
 pub use binning::BinHeader;
 pub use clip::{Clip, ClipBbox, ClipBic, ClipElement};
 pub use config::{
-    BufferSize, BufferSizes, BumpAllocators, ConfigUniform, IndirectCount, RenderConfig,
-    WorkgroupCounts, WorkgroupSize,
+    BlurConfig, BufferSize, BufferSizes, BumpAllocators, ConfigUniform, IndirectCount,
+    RenderConfig, WorkgroupCounts, WorkgroupSize, TILE_HEIGHT, TILE_WIDTH,
 };
 pub use draw::{
     DrawBbox, DrawBeginClip, DrawColor, DrawImage, DrawLinearGradient, DrawMonoid,
     DrawRadialGradient, DrawTag,
 };
-pub use encoding::{Encoding, StreamOffsets};
+pub use encoding::{Encoding, EncodingStats, Instance, StreamOffsets};
+pub use estimate::estimate_bump_sizes;
 pub use math::Transform;
 pub use monoid::Monoid;
 pub use path::{
     Cubic, LineSoup, Path, PathBbox, PathEncoder, PathMonoid, PathSegment, PathSegmentType,
-    PathTag, SegmentCount, Tile,
+    PathTag, SegmentCount, Style, Tile,
 };
 pub use resolve::{resolve_solid_paths_only, Layout};
+pub use validate::ValidationError;
+pub use workgroup::{
+    CLIP_REDUCE_WG, FLATTEN_WG, PATH_BBOX_WG, PATH_COUNT_WG, PATH_REDUCE_WG, WORKGROUP_SIZES,
+};
 
 #[cfg(feature = "full")]
 pub use {
-    encoding::Resources,
-    glyph::{Glyph, GlyphRun},
+    encoding::{ExternalImage, FilterLayer, Resources},
+    glyph::{font_ref, Glyph, GlyphRun},
+    glyph_cache::GlyphCacheStats,
     ramp_cache::Ramps,
     resolve::{Patch, Resolver},
 };
@@ -2,7 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use bytemuck::{Pod, Zeroable};
-use peniko::kurbo::Shape;
+use peniko::{
+    kurbo::{Cap, Circle, Join, Line, Point, Rect, RoundedRect, Shape, Stroke, Vec2},
+    Fill,
+};
 
 use super::Monoid;
 
@@ -93,8 +96,14 @@ impl PathTag {
     /// Path marker.
     pub const PATH: Self = Self(0x10);
 
-    /// Line width setting.
-    pub const LINEWIDTH: Self = Self(0x40);
+    /// Style setting.
+    pub const STYLE: Self = Self(0x40);
+
+    /// Instance marker.
+    ///
+    /// Pairs of these enclose the segments of an instance, which read their points from an
+    /// earlier copy of the same segments rather than from path data of their own.
+    pub const INSTANCE: Self = Self(0x80);
 
     /// Bit for path segments that are represented as f32 values. If unset
     /// they are represented as i16.
@@ -142,10 +151,12 @@ pub struct PathMonoid {
     pub pathseg_ix: u32,
     /// Offset into path segment stream.
     pub pathseg_offset: u32,
-    /// Index into linewidth stream.
-    pub linewidth_ix: u32,
+    /// Index into style stream.
+    pub style_ix: u32,
     /// Index of containing path.
     pub path_ix: u32,
+    /// Index into instance stream.
+    pub instance_ix: u32,
 }
 
 impl Monoid for PathMonoid {
@@ -163,7 +174,8 @@ impl Monoid for PathMonoid {
         a += a >> 16;
         c.pathseg_offset = a & 0xff;
         c.path_ix = (tag_word & (PathTag::PATH.0 as u32 * 0x1010101)).count_ones();
-        c.linewidth_ix = (tag_word & (PathTag::LINEWIDTH.0 as u32 * 0x1010101)).count_ones();
+        c.style_ix = (tag_word & (PathTag::STYLE.0 as u32 * 0x1010101)).count_ones();
+        c.instance_ix = (tag_word & (PathTag::INSTANCE.0 as u32 * 0x1010101)).count_ones();
         c
     }
 
@@ -173,8 +185,9 @@ impl Monoid for PathMonoid {
             trans_ix: self.trans_ix + other.trans_ix,
             pathseg_ix: self.pathseg_ix + other.pathseg_ix,
             pathseg_offset: self.pathseg_offset + other.pathseg_offset,
-            linewidth_ix: self.linewidth_ix + other.linewidth_ix,
+            style_ix: self.style_ix + other.style_ix,
             path_ix: self.path_ix + other.path_ix,
+            instance_ix: self.instance_ix + other.instance_ix,
         }
     }
 }
@@ -192,6 +205,98 @@ pub struct Cubic {
     pub flags: u32,
 }
 
+/// Fill or stroke style of a path.
+///
+/// Fills have a negative line width: -1.0 for the non-zero fill rule and -2.0 for even-odd.
+/// Strokes have a non-negative line width, and their join and caps packed into `flags`.
+#[derive(Copy, Clone, PartialEq, Pod, Zeroable, Debug)]
+#[repr(C)]
+pub struct Style {
+    /// Line width of a stroke, or a negative value encoding the fill rule.
+    pub line_width: f32,
+    /// Miter limit, used by strokes with a miter join.
+    pub miter_limit: f32,
+    /// Join in bits 0-1, start cap in bits 2-3, end cap in bits 4-5 and the variable width
+    /// flag in bit 6.
+    pub flags: u32,
+}
+
+impl Style {
+    /// Mask for the join bits.
+    pub const JOIN_MASK: u32 = 0x3;
+    /// Bevel join.
+    pub const JOIN_BEVEL: u32 = 0;
+    /// Miter join.
+    pub const JOIN_MITER: u32 = 1;
+    /// Round join.
+    pub const JOIN_ROUND: u32 = 2;
+
+    /// Shift of the start cap bits.
+    pub const START_CAP_SHIFT: u32 = 2;
+    /// Shift of the end cap bits.
+    pub const END_CAP_SHIFT: u32 = 4;
+    /// Mask for the cap bits, after shifting.
+    pub const CAP_MASK: u32 = 0x3;
+    /// Butt cap.
+    pub const CAP_BUTT: u32 = 0;
+    /// Square cap.
+    pub const CAP_SQUARE: u32 = 1;
+    /// Round cap.
+    pub const CAP_ROUND: u32 = 2;
+
+    /// Set for strokes whose width varies along the path. The widths at the ends of each
+    /// segment are read from the width stream, and the line width bounds them.
+    pub const VARIABLE_WIDTH: u32 = 0x40;
+
+    /// Creates a style for a fill with the given rule.
+    pub fn from_fill(fill: Fill) -> Self {
+        let line_width = match fill {
+            Fill::NonZero => -1.0,
+            Fill::EvenOdd => -2.0,
+        };
+        Self {
+            line_width,
+            miter_limit: 0.0,
+            flags: 0,
+        }
+    }
+
+    /// Creates a style for a stroke. The dash pattern is ignored.
+    pub fn from_stroke(stroke: &Stroke) -> Self {
+        let join = match stroke.join {
+            Join::Bevel => Self::JOIN_BEVEL,
+            Join::Miter => Self::JOIN_MITER,
+            Join::Round => Self::JOIN_ROUND,
+        };
+        let cap = |cap: Cap| match cap {
+            Cap::Butt => Self::CAP_BUTT,
+            Cap::Square => Self::CAP_SQUARE,
+            Cap::Round => Self::CAP_ROUND,
+        };
+        Self {
+            line_width: stroke.width as f32,
+            miter_limit: stroke.miter_limit as f32,
+            flags: join
+                | cap(stroke.start_cap) << Self::START_CAP_SHIFT
+                | cap(stroke.end_cap) << Self::END_CAP_SHIFT,
+        }
+    }
+
+    /// Creates a style for a stroke with a width at each point of the path, which is encoded
+    /// in the width stream. `max_width` is the largest of the widths.
+    pub fn from_variable_width_stroke(stroke: &Stroke, max_width: f32) -> Self {
+        let mut style = Self::from_stroke(stroke);
+        style.line_width = max_width;
+        style.flags |= Self::VARIABLE_WIDTH;
+        style
+    }
+
+    /// Returns true if this style is a stroke.
+    pub fn is_stroke(&self) -> bool {
+        self.line_width >= 0.0
+    }
+}
+
 /// Path bounding box.
 #[derive(Copy, Clone, Pod, Zeroable, Default, Debug)]
 #[repr(C)]
@@ -244,6 +349,13 @@ pub struct PathEncoder<'a> {
     state: PathState,
     n_encoded_segments: u32,
     is_fill: bool,
+    widths: Option<&'a mut Vec<[f32; 2]>>,
+    /// Stroke width at the current point.
+    width: f32,
+    /// Stroke width at the first point of the subpath.
+    first_width: f32,
+    /// Stroke width at the next encoded point.
+    next_width: f32,
 }
 
 #[derive(PartialEq)]
@@ -272,9 +384,25 @@ impl<'a> PathEncoder<'a> {
             state: PathState::Start,
             n_encoded_segments: 0,
             is_fill,
+            widths: None,
+            width: 0.0,
+            first_width: 0.0,
+            next_width: 0.0,
         }
     }
 
+    /// Records the stroke widths at the ends of each encoded segment in `widths`.
+    pub(crate) fn with_widths(mut self, widths: &'a mut Vec<[f32; 2]>) -> Self {
+        self.widths = Some(widths);
+        self
+    }
+
+    /// Sets the stroke width at the points encoded after this call, for paths with a
+    /// variable width stroke.
+    pub fn set_width(&mut self, width: f32) {
+        self.next_width = width;
+    }
+
     /// Encodes a move, starting a new subpath.
     pub fn move_to(&mut self, x: f32, y: f32) {
         if self.is_fill {
@@ -283,6 +411,8 @@ impl<'a> PathEncoder<'a> {
         let buf = [x, y];
         let bytes = bytemuck::bytes_of(&buf);
         self.first_point = buf;
+        self.width = self.next_width;
+        self.first_width = self.next_width;
         if self.state == PathState::MoveTo {
             let new_len = self.data.len() - 8;
             self.data.truncate(new_len);
@@ -304,12 +434,13 @@ impl<'a> PathEncoder<'a> {
                 self.move_to(x, y);
                 return;
             }
-            self.move_to(self.first_point[0], self.first_point[1]);
+            self.move_to_first_point();
         }
         let buf = [x, y];
         let bytes = bytemuck::bytes_of(&buf);
         self.data.extend_from_slice(bytes);
         self.tags.push(PathTag::LINE_TO_F32);
+        self.push_widths(self.next_width);
         self.state = PathState::NonemptySubpath;
         self.n_encoded_segments += 1;
     }
@@ -321,12 +452,13 @@ impl<'a> PathEncoder<'a> {
                 self.move_to(x2, y2);
                 return;
             }
-            self.move_to(self.first_point[0], self.first_point[1]);
+            self.move_to_first_point();
         }
         let buf = [x1, y1, x2, y2];
         let bytes = bytemuck::bytes_of(&buf);
         self.data.extend_from_slice(bytes);
         self.tags.push(PathTag::QUAD_TO_F32);
+        self.push_widths(self.next_width);
         self.state = PathState::NonemptySubpath;
         self.n_encoded_segments += 1;
     }
@@ -338,12 +470,13 @@ impl<'a> PathEncoder<'a> {
                 self.move_to(x3, y3);
                 return;
             }
-            self.move_to(self.first_point[0], self.first_point[1]);
+            self.move_to_first_point();
         }
         let buf = [x1, y1, x2, y2, x3, y3];
         let bytes = bytemuck::bytes_of(&buf);
         self.data.extend_from_slice(bytes);
         self.tags.push(PathTag::CUBIC_TO_F32);
+        self.push_widths(self.next_width);
         self.state = PathState::NonemptySubpath;
         self.n_encoded_segments += 1;
     }
@@ -371,6 +504,7 @@ impl<'a> PathEncoder<'a> {
             let mut tag = PathTag::LINE_TO_F32;
             tag.set_subpath_end();
             self.tags.push(tag);
+            self.push_widths(self.first_width);
             self.n_encoded_segments += 1;
         } else if let Some(tag) = self.tags.last_mut() {
             tag.set_subpath_end();
@@ -378,26 +512,175 @@ impl<'a> PathEncoder<'a> {
         self.state = PathState::Start;
     }
 
+    /// Starts a new subpath at the first point of the previous one, with its width.
+    fn move_to_first_point(&mut self) {
+        let next_width = self.next_width;
+        self.next_width = self.first_width;
+        self.move_to(self.first_point[0], self.first_point[1]);
+        self.next_width = next_width;
+    }
+
+    fn push_widths(&mut self, end_width: f32) {
+        if let Some(widths) = &mut self.widths {
+            widths.push([self.width, end_width]);
+        }
+        self.width = end_width;
+    }
+
     /// Encodes a shape.
+    ///
+    /// Rectangles, rounded rectangles, circles and lines are encoded directly rather than from
+    /// their path elements.
     pub fn shape(&mut self, shape: &impl Shape) {
+        if let Some(rect) = shape.as_rect() {
+            self.rect(&rect);
+        } else if let Some(rect) = shape.as_rounded_rect() {
+            self.rounded_rect(&rect);
+        } else if let Some(circle) = shape.as_circle() {
+            self.circle(&circle);
+        } else if let Some(line) = shape.as_line() {
+            self.line(&line);
+        } else {
+            for el in shape.path_elements(0.1) {
+                self.path_el(el);
+            }
+        }
+    }
+
+    /// Encodes a rectangle as a closed subpath of four lines.
+    pub fn rect(&mut self, rect: &Rect) {
+        let [x0, y0, x1, y1] = [rect.x0, rect.y0, rect.x1, rect.y1].map(|v| v as f32);
+        self.move_to(x0, y0);
+        self.line_to(x1, y0);
+        self.line_to(x1, y1);
+        self.line_to(x0, y1);
+        self.close();
+    }
+
+    /// Encodes a rounded rectangle as a closed subpath of lines along its sides and cubic
+    /// beziers around its corners.
+    pub fn rounded_rect(&mut self, rect: &RoundedRect) {
+        let radii = rect.radii();
+        let rect = rect.rect();
+        let (x0, y0, x1, y1) = (rect.x0, rect.y0, rect.x1, rect.y1);
+        let (tl, tr, br, bl) = (
+            radii.top_left,
+            radii.top_right,
+            radii.bottom_right,
+            radii.bottom_left,
+        );
+        // Sides are skipped when the corners meet.
+        self.move_to((x0 + tl) as f32, y0 as f32);
+        if x1 - tr > x0 + tl {
+            self.line_to((x1 - tr) as f32, y0 as f32);
+        }
+        self.quarter_arc(Point::new(x1 - tr, y0 + tr), tr, Vec2::new(0.0, -1.0));
+        if y1 - br > y0 + tr {
+            self.line_to(x1 as f32, (y1 - br) as f32);
+        }
+        self.quarter_arc(Point::new(x1 - br, y1 - br), br, Vec2::new(1.0, 0.0));
+        if x0 + bl < x1 - br {
+            self.line_to((x0 + bl) as f32, y1 as f32);
+        }
+        self.quarter_arc(Point::new(x0 + bl, y1 - bl), bl, Vec2::new(0.0, 1.0));
+        if y0 + tl < y1 - bl {
+            self.line_to(x0 as f32, (y0 + tl) as f32);
+        }
+        self.quarter_arc(Point::new(x0 + tl, y0 + tl), tl, Vec2::new(-1.0, 0.0));
+        self.close();
+    }
+
+    /// Encodes a circle as a closed subpath of cubic beziers.
+    pub fn circle(&mut self, circle: &Circle) {
+        let (center, radius) = (circle.center, circle.radius);
+        self.move_to((center.x + radius) as f32, center.y as f32);
+        for u in [(1.0, 0.0), (0.0, 1.0), (-1.0, 0.0), (0.0, -1.0)] {
+            self.quarter_arc(center, radius, u.into());
+        }
+        self.close();
+    }
+
+    /// Encodes a line as an open subpath with a single segment.
+    pub fn line(&mut self, line: &Line) {
+        self.move_to(line.p0.x as f32, line.p0.y as f32);
+        self.line_to(line.p1.x as f32, line.p1.y as f32);
+    }
+
+    /// Encodes a quarter of a circle from `center + radius * u` to `center + radius * v`,
+    /// where `v` is `u` turned a quarter in the direction of positive angles. Does nothing if
+    /// the radius is zero.
+    fn quarter_arc(&mut self, center: Point, radius: f64, u: Vec2) {
+        if radius <= 0.0 {
+            return;
+        }
+        let v = Vec2::new(-u.y, u.x);
+        // The error of a cubic approximation of a quarter circle is 2.7e-4 times its radius,
+        // and grows with the sixth power of the angle. Arcs are split to keep the error within
+        // the tolerance of 0.1 used for other shapes.
+        let n = (radius * 2.7e-3).powf(1.0 / 6.0).ceil().max(1.0) as usize;
+        let step = std::f64::consts::FRAC_PI_2 / n as f64;
+        let k = 4.0 / 3.0 * (step / 4.0).tan() * radius;
+        // Unit direction and tangent at the end of the ith part, with the end of the quarter
+        // exact so that the arcs of a circle meet.
+        let direction = |i: usize| {
+            if i == n {
+                (v, -u)
+            } else {
+                let (sin, cos) = (step * i as f64).sin_cos();
+                (u * cos + v * sin, v * cos - u * sin)
+            }
+        };
+        for i in 0..n {
+            let (d0, t0) = direction(i);
+            let (d1, t1) = direction(i + 1);
+            let p0 = center + d0 * radius;
+            let p3 = center + d1 * radius;
+            let p1 = p0 + t0 * k;
+            let p2 = p3 - t1 * k;
+            self.cubic_to(
+                p1.x as f32,
+                p1.y as f32,
+                p2.x as f32,
+                p2.y as f32,
+                p3.x as f32,
+                p3.y as f32,
+            );
+        }
+    }
+
+    /// Encodes a shape with a variable width stroke. `widths` yields the width at the end
+    /// point of each element of the path other than a close. Once it runs out, the last
+    /// width is used for the rest of the path.
+    pub fn shape_with_widths(&mut self, shape: &impl Shape, widths: impl IntoIterator<Item = f32>) {
         use peniko::kurbo::PathEl;
+        let mut widths = widths.into_iter();
         for el in shape.path_elements(0.1) {
-            match el {
-                PathEl::MoveTo(p0) => self.move_to(p0.x as f32, p0.y as f32),
-                PathEl::LineTo(p0) => self.line_to(p0.x as f32, p0.y as f32),
-                PathEl::QuadTo(p0, p1) => {
-                    self.quad_to(p0.x as f32, p0.y as f32, p1.x as f32, p1.y as f32)
+            if !matches!(el, PathEl::ClosePath) {
+                if let Some(width) = widths.next() {
+                    self.set_width(width);
                 }
-                PathEl::CurveTo(p0, p1, p2) => self.cubic_to(
-                    p0.x as f32,
-                    p0.y as f32,
-                    p1.x as f32,
-                    p1.y as f32,
-                    p2.x as f32,
-                    p2.y as f32,
-                ),
-                PathEl::ClosePath => self.close(),
             }
+            self.path_el(el);
+        }
+    }
+
+    fn path_el(&mut self, el: peniko::kurbo::PathEl) {
+        use peniko::kurbo::PathEl;
+        match el {
+            PathEl::MoveTo(p0) => self.move_to(p0.x as f32, p0.y as f32),
+            PathEl::LineTo(p0) => self.line_to(p0.x as f32, p0.y as f32),
+            PathEl::QuadTo(p0, p1) => {
+                self.quad_to(p0.x as f32, p0.y as f32, p1.x as f32, p1.y as f32)
+            }
+            PathEl::CurveTo(p0, p1, p2) => self.cubic_to(
+                p0.x as f32,
+                p0.y as f32,
+                p1.x as f32,
+                p1.y as f32,
+                p2.x as f32,
+                p2.y as f32,
+            ),
+            PathEl::ClosePath => self.close(),
         }
     }
 
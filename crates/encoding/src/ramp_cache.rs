@@ -5,6 +5,8 @@ use std::collections::HashMap;
 
 use peniko::{Color, ColorStop, ColorStops};
 
+use super::draw::srgb_to_linear;
+
 const N_SAMPLES: usize = 512;
 const RETAINED_COUNT: usize = 64;
 
@@ -21,9 +23,20 @@ pub struct RampCache {
     epoch: u64,
     map: HashMap<ColorStops, (u32, u64)>,
     data: Vec<u32>,
+    /// Whether ramps are interpolated and stored in linear light rather than sRGB.
+    linear: bool,
 }
 
 impl RampCache {
+    /// Sets whether ramps are made in linear light, dropping the cached ramps if this changes.
+    pub fn set_linear(&mut self, linear: bool) {
+        if self.linear != linear {
+            self.linear = linear;
+            self.map.clear();
+            self.data.clear();
+        }
+    }
+
     pub fn advance(&mut self) {
         self.epoch += 1;
         if self.map.len() > RETAINED_COUNT {
@@ -39,7 +52,7 @@ impl RampCache {
             entry.0
         } else if self.map.len() < RETAINED_COUNT {
             let id = (self.data.len() / N_SAMPLES) as u32;
-            self.data.extend(make_ramp(stops));
+            self.data.extend(make_ramp(stops, self.linear));
             self.map.insert(stops.into(), (id, self.epoch));
             id
         } else {
@@ -55,7 +68,7 @@ impl RampCache {
                 let start = id as usize * N_SAMPLES;
                 for (dst, src) in self.data[start..start + N_SAMPLES]
                     .iter_mut()
-                    .zip(make_ramp(stops))
+                    .zip(make_ramp(stops, self.linear))
                 {
                     *dst = src;
                 }
@@ -63,7 +76,7 @@ impl RampCache {
                 id
             } else {
                 let id = (self.data.len() / N_SAMPLES) as u32;
-                self.data.extend(make_ramp(stops));
+                self.data.extend(make_ramp(stops, self.linear));
                 self.map.insert(stops.into(), (id, self.epoch));
                 id
             }
@@ -79,9 +92,9 @@ impl RampCache {
     }
 }
 
-fn make_ramp(stops: &[ColorStop]) -> impl Iterator<Item = u32> + '_ {
+fn make_ramp(stops: &[ColorStop], linear: bool) -> impl Iterator<Item = u32> + '_ {
     let mut last_u = 0.0;
-    let mut last_c = ColorF64::from_color(stops[0].color);
+    let mut last_c = ColorF64::from_color(stops[0].color, linear);
     let mut this_u = last_u;
     let mut this_c = last_c;
     let mut j = 0;
@@ -92,7 +105,7 @@ fn make_ramp(stops: &[ColorStop]) -> impl Iterator<Item = u32> + '_ {
             last_c = this_c;
             if let Some(s) = stops.get(j + 1) {
                 this_u = s.offset as f64;
-                this_c = ColorF64::from_color(s.color);
+                this_c = ColorF64::from_color(s.color, linear);
                 j += 1;
             } else {
                 break;
@@ -112,13 +125,23 @@ fn make_ramp(stops: &[ColorStop]) -> impl Iterator<Item = u32> + '_ {
 struct ColorF64([f64; 4]);
 
 impl ColorF64 {
-    fn from_color(color: Color) -> Self {
-        Self([
+    fn from_color(color: Color, linear: bool) -> Self {
+        let c = Self([
             color.r as f64 / 255.0,
             color.g as f64 / 255.0,
             color.b as f64 / 255.0,
             color.a as f64 / 255.0,
-        ])
+        ]);
+        if linear {
+            Self([
+                srgb_to_linear(c.0[0]),
+                srgb_to_linear(c.0[1]),
+                srgb_to_linear(c.0[2]),
+                c.0[3],
+            ])
+        } else {
+            c
+        }
     }
 
     fn lerp(&self, other: &Self, a: f64) -> Self {
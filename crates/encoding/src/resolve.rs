@@ -3,14 +3,16 @@
 
 use bytemuck::{Pod, Zeroable};
 
-use super::{DrawTag, Encoding, PathTag, StreamOffsets, Transform};
+use super::{DrawTag, Encoding, Instance, PathTag, StreamOffsets, Style, Transform};
 
 #[cfg(feature = "full")]
 use {
     super::{
-        glyph_cache::{CachedRange, GlyphCache, GlyphKey},
+        glyph::font_ref,
+        glyph_cache::{size_band, CachedRange, GlyphCache, GlyphCacheStats, GlyphKey},
         image_cache::{ImageCache, Images},
         ramp_cache::{RampCache, Ramps},
+        DrawColor, DrawImage, DrawMonoid, ExternalImage, Monoid,
     },
     peniko::{Extend, Image},
     std::ops::Range,
@@ -38,8 +40,12 @@ pub struct Layout {
     pub draw_data_base: u32,
     /// Start of transform stream.
     pub transform_base: u32,
-    /// Start of linewidth stream.
-    pub linewidth_base: u32,
+    /// Start of style stream.
+    pub style_base: u32,
+    /// Start of stroke width stream.
+    pub width_base: u32,
+    /// Start of instance stream.
+    pub instance_base: u32,
 }
 
 impl Layout {
@@ -92,13 +98,28 @@ impl Layout {
     /// Returns the transform stream.
     pub fn transforms<'a>(&self, data: &'a [u8]) -> &'a [Transform] {
         let start = self.transform_base as usize * 4;
-        let end = self.linewidth_base as usize * 4;
+        let end = self.style_base as usize * 4;
         bytemuck::cast_slice(&data[start..end])
     }
 
-    /// Returns the linewidth stream.
-    pub fn linewidths<'a>(&self, data: &'a [u8]) -> &'a [f32] {
-        let start = self.linewidth_base as usize * 4;
+    /// Returns the style stream.
+    pub fn styles<'a>(&self, data: &'a [u8]) -> &'a [Style] {
+        let start = self.style_base as usize * 4;
+        let end = self.width_base as usize * 4;
+        bytemuck::cast_slice(&data[start..end])
+    }
+
+    /// Returns the stroke width stream, which is empty if there are no variable width
+    /// strokes.
+    pub fn widths<'a>(&self, data: &'a [u8]) -> &'a [[f32; 2]] {
+        let start = self.width_base as usize * 4;
+        let end = self.instance_base as usize * 4;
+        bytemuck::cast_slice(&data[start..end])
+    }
+
+    /// Returns the instance stream.
+    pub fn instances<'a>(&self, data: &'a [u8]) -> &'a [u32] {
+        let start = self.instance_base as usize * 4;
         bytemuck::cast_slice(&data[start..])
     }
 }
@@ -124,7 +145,7 @@ pub fn resolve_solid_paths_only(encoding: &Encoding, packed: &mut Vec<u8>) -> La
     let SceneBufferSizes {
         buffer_size,
         path_tag_padded,
-    } = SceneBufferSizes::new(encoding, &StreamOffsets::default());
+    } = SceneBufferSizes::new(encoding, &StreamOffsets::default(), 0);
     data.reserve(buffer_size);
     // Path tag stream
     layout.path_tag_base = size_to_words(data.len());
@@ -150,9 +171,17 @@ pub fn resolve_solid_paths_only(encoding: &Encoding, packed: &mut Vec<u8>) -> La
     // Transform stream
     layout.transform_base = size_to_words(data.len());
     data.extend_from_slice(bytemuck::cast_slice(&encoding.transforms));
-    // Linewidth stream
-    layout.linewidth_base = size_to_words(data.len());
-    data.extend_from_slice(bytemuck::cast_slice(&encoding.linewidths));
+    // Style stream
+    layout.style_base = size_to_words(data.len());
+    data.extend_from_slice(bytemuck::cast_slice(&encoding.styles));
+    // Stroke width stream
+    layout.width_base = size_to_words(data.len());
+    if !encoding.widths.is_empty() {
+        pack_widths(data, &encoding.widths, 0..encoding.n_path_segments as usize);
+    }
+    // Instance stream
+    layout.instance_base = size_to_words(data.len());
+    pack_instances(data, &encoding.instances, |_| 0);
     layout.n_draw_objects = layout.n_paths;
     assert_eq!(buffer_size, data.len());
     layout
@@ -168,7 +197,10 @@ pub struct Resolver {
     ramp_cache: RampCache,
     image_cache: ImageCache,
     pending_images: Vec<PendingImage>,
+    pending_layers: Vec<PendingLayer>,
+    pending_external_images: Vec<PendingExternalImage>,
     patches: Vec<ResolvedPatch>,
+    linear_colors: bool,
 }
 
 #[cfg(feature = "full")]
@@ -178,6 +210,23 @@ impl Resolver {
         Self::default()
     }
 
+    /// Sets whether solid colors and gradient ramps are converted from sRGB to linear light
+    /// when packing, for rendering that blends in linear light.
+    pub fn set_linear_colors(&mut self, linear: bool) {
+        self.linear_colors = linear;
+        self.ramp_cache.set_linear(linear);
+    }
+
+    /// Sets the size budget of the glyph outlines cached across frames, in bytes.
+    pub fn set_glyph_cache_budget(&mut self, bytes: usize) {
+        self.glyph_cache.set_budget(bytes);
+    }
+
+    /// Returns the statistics of the glyph cache.
+    pub fn glyph_cache_stats(&self) -> GlyphCacheStats {
+        self.glyph_cache.stats()
+    }
+
     /// Resolves late bound resources and packs an encoding. Returns the packed
     /// layout and computed ramp data.
     pub fn resolve<'a>(
@@ -188,10 +237,22 @@ impl Resolver {
         let resources = &encoding.resources;
         if resources.patches.is_empty() {
             let layout = resolve_solid_paths_only(encoding, packed);
+            if self.linear_colors {
+                convert_colors_to_linear(encoding, &layout, packed);
+            }
             return (layout, Ramps::default(), Images::default());
         }
         let patch_sizes = self.resolve_patches(encoding);
         self.resolve_pending_images();
+        // Glyphs add segments which aren't in the width stream of the encoding.
+        let n_glyph_segments = if encoding.widths.is_empty() {
+            0
+        } else {
+            self.glyph_ranges
+                .iter()
+                .map(|glyph| self.glyph_segment_count(glyph))
+                .sum()
+        };
         let data = packed;
         data.clear();
         let mut layout = Layout {
@@ -202,7 +263,7 @@ impl Resolver {
         let SceneBufferSizes {
             buffer_size,
             path_tag_padded,
-        } = SceneBufferSizes::new(encoding, &patch_sizes);
+        } = SceneBufferSizes::new(encoding, &patch_sizes, n_glyph_segments);
         data.reserve(buffer_size);
         // Path tag stream
         layout.path_tag_base = size_to_words(data.len());
@@ -292,11 +353,27 @@ impl Resolver {
                     ResolvedPatch::Image {
                         index,
                         draw_data_offset,
+                    }
+                    | ResolvedPatch::FilterLayer {
+                        index,
+                        draw_data_offset,
+                    }
+                    | ResolvedPatch::ExternalImage {
+                        index,
+                        draw_data_offset,
                     } => {
                         if pos < *draw_data_offset {
                             data.extend_from_slice(&encoding.draw_data[pos..*draw_data_offset]);
                         }
-                        if let Some((x, y)) = self.pending_images[*index].xy {
+                        let xy = match patch {
+                            ResolvedPatch::Image { .. } => self.pending_images[*index].xy,
+                            ResolvedPatch::ExternalImage { .. } => {
+                                let slot = self.pending_external_images[*index].slot;
+                                slot.map(|slot| (DrawImage::EXTERNAL_FLAG >> 16, slot))
+                            }
+                            _ => self.pending_layers[*index].xy,
+                        };
+                        if let Some((x, y)) = xy {
                             let xy = (x << 16) | y;
                             data.extend_from_slice(bytemuck::bytes_of(&xy));
                             pos = *draw_data_offset + 4;
@@ -315,6 +392,9 @@ impl Resolver {
                 data.extend_from_slice(bytemuck::cast_slice(&stream[pos..]));
             }
         }
+        if self.linear_colors {
+            convert_colors_to_linear(encoding, &layout, data);
+        }
         // Transform stream
         layout.transform_base = size_to_words(data.len());
         {
@@ -325,6 +405,8 @@ impl Resolver {
                     index,
                     glyphs: _,
                     transform,
+                    outline_scale,
+                    hint,
                 } = patch
                 {
                     let run = &resources.glyph_runs[*index];
@@ -333,25 +415,26 @@ impl Resolver {
                         data.extend_from_slice(bytemuck::cast_slice(&stream[pos..stream_offset]));
                         pos = stream_offset;
                     }
-                    if let Some(glyph_transform) = run.glyph_transform {
-                        for glyph in &resources.glyphs[run.glyphs.clone()] {
-                            let xform = *transform
-                                * Transform {
-                                    matrix: [1.0, 0.0, 0.0, -1.0],
-                                    translation: [glyph.x, glyph.y],
-                                }
-                                * glyph_transform;
-                            data.extend_from_slice(bytemuck::bytes_of(&xform));
-                        }
-                    } else {
-                        for glyph in &resources.glyphs[run.glyphs.clone()] {
-                            let xform = *transform
-                                * Transform {
-                                    matrix: [1.0, 0.0, 0.0, -1.0],
-                                    translation: [glyph.x, glyph.y],
-                                };
-                            data.extend_from_slice(bytemuck::bytes_of(&xform));
+                    // Outlines are scaled from the size they were extracted at to the font size
+                    // before any other transform.
+                    let outline_transform = run.glyph_transform.unwrap_or(Transform::IDENTITY)
+                        * Transform {
+                            matrix: [*outline_scale, 0.0, 0.0, *outline_scale],
+                            translation: [0.0, 0.0],
+                        };
+                    for glyph in &resources.glyphs[run.glyphs.clone()] {
+                        let mut xform = *transform
+                            * Transform {
+                                matrix: [1.0, 0.0, 0.0, -1.0],
+                                translation: [glyph.x, glyph.y],
+                            }
+                            * outline_transform;
+                        if *hint {
+                            // Hinted outlines are fitted to the pixel grid vertically, which
+                            // only holds if their baseline is too.
+                            xform.translation[1] = xform.translation[1].round();
                         }
+                        data.extend_from_slice(bytemuck::bytes_of(&xform));
                     }
                 }
             }
@@ -359,21 +442,21 @@ impl Resolver {
                 data.extend_from_slice(bytemuck::cast_slice(&stream[pos..]));
             }
         }
-        // Linewidth stream
-        layout.linewidth_base = size_to_words(data.len());
+        // Style stream
+        layout.style_base = size_to_words(data.len());
         {
             let mut pos = 0;
-            let stream = &encoding.linewidths;
+            let stream = &encoding.styles;
             for patch in &self.patches {
                 if let ResolvedPatch::GlyphRun { index, glyphs, .. } = patch {
-                    let stream_offset = resources.glyph_runs[*index].stream_offsets.linewidths;
+                    let stream_offset = resources.glyph_runs[*index].stream_offsets.styles;
                     if pos < stream_offset {
                         data.extend_from_slice(bytemuck::cast_slice(&stream[pos..stream_offset]));
                         pos = stream_offset;
                     }
                     for glyph in &self.glyph_ranges[glyphs.clone()] {
-                        let glyph_data = &self.glyph_cache.encoding.linewidths
-                            [glyph.start.linewidths..glyph.end.linewidths];
+                        let glyph_data =
+                            &self.glyph_cache.encoding.styles[glyph.start.styles..glyph.end.styles];
                         data.extend_from_slice(bytemuck::cast_slice(glyph_data));
                     }
                 }
@@ -382,17 +465,68 @@ impl Resolver {
                 data.extend_from_slice(bytemuck::cast_slice(&stream[pos..]));
             }
         }
+        // Stroke width stream
+        layout.width_base = size_to_words(data.len());
+        if !encoding.widths.is_empty() {
+            let mut pos = 0;
+            let mut tag_pos = 0;
+            let stream = &encoding.widths;
+            for patch in &self.patches {
+                if let ResolvedPatch::GlyphRun { index, glyphs, .. } = patch {
+                    let tag_offset = resources.glyph_runs[*index].stream_offsets.path_tags;
+                    let stream_offset =
+                        pos + segment_count(&encoding.path_tags[tag_pos..tag_offset]);
+                    pack_widths(data, stream, pos..stream_offset);
+                    pos = stream_offset;
+                    tag_pos = tag_offset;
+                    for glyph in &self.glyph_ranges[glyphs.clone()] {
+                        let n_segments = self.glyph_segment_count(glyph);
+                        data.resize(data.len() + n_segments * 8, 0);
+                    }
+                }
+            }
+            pack_widths(data, stream, pos..encoding.n_path_segments as usize);
+        }
+        // Instance stream
+        layout.instance_base = size_to_words(data.len());
+        {
+            // Size of the glyph path data inserted up to each glyph run.
+            let mut glyph_data = vec![];
+            let mut size = 0;
+            for patch in &self.patches {
+                if let ResolvedPatch::GlyphRun { index, glyphs, .. } = patch {
+                    size += self.glyph_ranges[glyphs.clone()]
+                        .iter()
+                        .map(|glyph| glyph.end.path_data - glyph.start.path_data)
+                        .sum::<usize>();
+                    let tag_offset = resources.glyph_runs[*index].stream_offsets.path_tags;
+                    glyph_data.push((tag_offset, size));
+                }
+            }
+            pack_instances(data, &encoding.instances, |tag_offset| {
+                let n = glyph_data.partition_point(|(offset, _)| *offset <= tag_offset);
+                n.checked_sub(1).map_or(0, |i| glyph_data[i].1)
+            });
+        }
         layout.n_draw_objects = layout.n_paths;
         assert_eq!(buffer_size, data.len());
         (layout, self.ramp_cache.ramps(), self.image_cache.images())
     }
 
+    fn glyph_segment_count(&self, glyph: &CachedRange) -> usize {
+        segment_count(
+            &self.glyph_cache.encoding.path_tags[glyph.start.path_tags..glyph.end.path_tags],
+        )
+    }
+
     fn resolve_patches(&mut self, encoding: &Encoding) -> StreamOffsets {
         self.ramp_cache.advance();
-        self.glyph_cache.clear();
+        self.glyph_cache.advance();
         self.glyph_ranges.clear();
         self.image_cache.clear();
         self.pending_images.clear();
+        self.pending_layers.clear();
+        self.pending_external_images.clear();
         self.patches.clear();
         let mut sizes = StreamOffsets::default();
         let resources = &encoding.resources;
@@ -414,17 +548,9 @@ impl Resolver {
                     let mut run_sizes = StreamOffsets::default();
                     let run = &resources.glyph_runs[*index];
                     let font_id = run.font.data.id();
-                    let font_size_u32 = run.font_size.to_bits();
-                    let Ok(font_file) = fello::raw::FileRef::new(run.font.data.as_ref()) else {
+                    let Some(font) = font_ref(&run.font) else {
                         continue;
                     };
-                    let font = match font_file {
-                        fello::raw::FileRef::Font(font) => Some(font),
-                        fello::raw::FileRef::Collection(collection) => {
-                            collection.get(run.font.index).ok()
-                        }
-                    };
-                    let Some(font) = font else { continue };
                     let glyphs = &resources.glyphs[run.glyphs.clone()];
                     let coords = &resources.normalized_coords[run.normalized_coords.clone()];
                     let key = fello::FontKey {
@@ -448,22 +574,37 @@ impl Resolver {
                             hint = false;
                         }
                     }
+                    // Unhinted fills are extracted at a size band and scaled to the font size,
+                    // so that they are shared between nearby sizes. Strokes are expanded at
+                    // the font size as scaling would change their width.
+                    let extract_size = if !hint && matches!(run.style, peniko::Style::Fill(_)) {
+                        size_band(font_size)
+                    } else {
+                        font_size
+                    };
+                    let outline_scale = if extract_size != 0.0 {
+                        font_size / extract_size
+                    } else {
+                        1.0
+                    };
                     let mut scaler = self
                         .glyph_cx
                         .new_scaler()
                         .key(Some(key))
                         .hint(hint.then_some(fello::scale::Hinting::VerticalSubpixel))
                         .coords(coords)
-                        .size(fello::Size::new(font_size))
+                        .size(fello::Size::new(extract_size))
                         .build(&font);
                     let glyph_start = self.glyph_ranges.len();
+                    let coords_id = self.glyph_cache.coords_id(coords);
                     for glyph in glyphs {
                         let key = GlyphKey {
                             font_id,
                             font_index: run.font.index,
-                            font_size: font_size_u32,
+                            font_size: extract_size.to_bits(),
                             glyph_id: glyph.id,
-                            hint: run.hint,
+                            hint,
+                            coords: coords_id,
                         };
                         let encoding_range = self
                             .glyph_cache
@@ -480,6 +621,8 @@ impl Resolver {
                         index: *index,
                         glyphs: glyph_start..glyph_end,
                         transform,
+                        outline_scale,
+                        hint,
                     });
                 }
                 Patch::Image {
@@ -496,6 +639,35 @@ impl Resolver {
                         draw_data_offset: *draw_data_offset + sizes.draw_data,
                     });
                 }
+                Patch::FilterLayer {
+                    index,
+                    draw_data_offset,
+                } => {
+                    let layer = &resources.filter_layers[*index];
+                    self.patches.push(ResolvedPatch::FilterLayer {
+                        index: self.pending_layers.len(),
+                        draw_data_offset: *draw_data_offset + sizes.draw_data,
+                    });
+                    self.pending_layers.push(PendingLayer {
+                        index: *index,
+                        width: layer.width,
+                        height: layer.height,
+                        xy: None,
+                    });
+                }
+                Patch::ExternalImage {
+                    image,
+                    draw_data_offset,
+                } => {
+                    self.patches.push(ResolvedPatch::ExternalImage {
+                        index: self.pending_external_images.len(),
+                        draw_data_offset: *draw_data_offset + sizes.draw_data,
+                    });
+                    self.pending_external_images.push(PendingExternalImage {
+                        image: *image,
+                        slot: None,
+                    });
+                }
             }
         }
         sizes
@@ -521,10 +693,41 @@ impl Resolver {
                     }
                 }
             }
+            for pending_layer in &mut self.pending_layers {
+                pending_layer.xy = self.image_cache.allocate_layer(
+                    pending_layer.index,
+                    pending_layer.width,
+                    pending_layer.height,
+                );
+                if pending_layer.xy.is_none() && self.image_cache.bump_size() {
+                    continue 'outer;
+                }
+            }
             // If we made it here, we've either successfully allocated all images or we reached
             // the maximum atlas size.
             break;
         }
+        // External images are bound as their own textures rather than placed in the atlas.
+        // Those beyond the last slot aren't drawn, and are counted so the render can fail.
+        for pending_image in &mut self.pending_external_images {
+            pending_image.slot = self
+                .image_cache
+                .get_or_insert_external(&pending_image.image);
+        }
+    }
+}
+
+/// Converts the solid colors in the packed draw data stream from sRGB to linear light.
+#[cfg(feature = "full")]
+fn convert_colors_to_linear(encoding: &Encoding, layout: &Layout, data: &mut [u8]) {
+    let mut offset = layout.draw_data_base as usize * 4;
+    for tag in &encoding.draw_tags {
+        if *tag == DrawTag::COLOR {
+            let bytes = &mut data[offset..offset + 4];
+            let color: DrawColor = bytemuck::pod_read_unaligned(bytes);
+            bytes.copy_from_slice(bytemuck::bytes_of(&color.to_linear()));
+        }
+        offset += DrawMonoid::new(*tag).scene_offset as usize * 4;
     }
 }
 
@@ -553,6 +756,20 @@ pub enum Patch {
         /// Underlying image data.
         image: Image,
     },
+    /// Filtered layer, which is rendered into the atlas.
+    FilterLayer {
+        /// Index in the filter layer buffer.
+        index: usize,
+        /// Offset to the atlas coordinates in the draw data stream.
+        draw_data_offset: usize,
+    },
+    /// Texture owned by the renderer, which is bound to fine rasterization.
+    ExternalImage {
+        /// Offset to the image slot in the draw data stream.
+        draw_data_offset: usize,
+        /// Handle of the texture.
+        image: ExternalImage,
+    },
 }
 
 /// Image to be allocated in the atlas.
@@ -563,6 +780,25 @@ struct PendingImage {
     xy: Option<(u32, u32)>,
 }
 
+/// Filter layer to be allocated in the atlas.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug)]
+struct PendingLayer {
+    /// Index of the layer in the encoding.
+    index: usize,
+    width: u32,
+    height: u32,
+    xy: Option<(u32, u32)>,
+}
+
+/// External image to be assigned a slot.
+#[cfg(feature = "full")]
+#[derive(Clone, Debug)]
+struct PendingExternalImage {
+    image: ExternalImage,
+    slot: Option<u32>,
+}
+
 #[cfg(feature = "full")]
 #[derive(Clone, Debug)]
 enum ResolvedPatch {
@@ -581,6 +817,10 @@ enum ResolvedPatch {
         glyphs: Range<usize>,
         /// Global transform.
         transform: Transform,
+        /// Scale from the size the outlines were extracted at to the font size.
+        outline_scale: f32,
+        /// True if the outlines are hinted.
+        hint: bool,
     },
     Image {
         /// Index of pending image element.
@@ -588,6 +828,18 @@ enum ResolvedPatch {
         /// Offset to the atlas location in the draw data stream.
         draw_data_offset: usize,
     },
+    FilterLayer {
+        /// Index of pending layer element.
+        index: usize,
+        /// Offset to the atlas location in the draw data stream.
+        draw_data_offset: usize,
+    },
+    ExternalImage {
+        /// Index of pending external image element.
+        index: usize,
+        /// Offset to the image slot in the draw data stream.
+        draw_data_offset: usize,
+    },
 }
 
 struct SceneBufferSizes {
@@ -600,10 +852,10 @@ struct SceneBufferSizes {
 impl SceneBufferSizes {
     /// Computes common scene buffer sizes for the given encoding and patch
     /// stream sizes.
-    fn new(encoding: &Encoding, patch_sizes: &StreamOffsets) -> Self {
+    fn new(encoding: &Encoding, patch_sizes: &StreamOffsets, n_glyph_segments: usize) -> Self {
         let n_path_tags =
             encoding.path_tags.len() + patch_sizes.path_tags + encoding.n_open_clips as usize;
-        let path_tag_padded = align_up(n_path_tags, 4 * crate::config::PATH_REDUCE_WG);
+        let path_tag_padded = align_up(n_path_tags, 4 * crate::PATH_REDUCE_WG);
         let buffer_size = path_tag_padded
             + slice_size_in_bytes(&encoding.path_data, patch_sizes.path_data)
             + slice_size_in_bytes(
@@ -612,7 +864,13 @@ impl SceneBufferSizes {
             )
             + slice_size_in_bytes(&encoding.draw_data, patch_sizes.draw_data)
             + slice_size_in_bytes(&encoding.transforms, patch_sizes.transforms)
-            + slice_size_in_bytes(&encoding.linewidths, patch_sizes.linewidths);
+            + slice_size_in_bytes(&encoding.styles, patch_sizes.styles)
+            + if encoding.widths.is_empty() {
+                0
+            } else {
+                (encoding.n_path_segments as usize + n_glyph_segments) * 8
+            }
+            + (1 + 2 * encoding.instances.len()) * 4;
         Self {
             buffer_size,
             path_tag_padded,
@@ -620,6 +878,42 @@ impl SceneBufferSizes {
     }
 }
 
+/// Packs the instance stream, which holds the offset in words added to the path data offset of
+/// the segments in each region delimited by instance tags.
+///
+/// `glyph_data` returns the size of the glyph path data inserted before a path tag offset of the
+/// encoding.
+fn pack_instances(data: &mut Vec<u8>, instances: &[Instance], glyph_data: impl Fn(usize) -> usize) {
+    let words = |size: usize| (size / 4) as u32;
+    data.extend_from_slice(bytemuck::bytes_of(&0u32));
+    // Size of the path data skipped by the preceding instances, which the path data offsets
+    // of the following segments include.
+    let mut skipped = 0;
+    for instance in instances {
+        let source = instance.source.path_data + glyph_data(instance.source.path_tags);
+        let start = instance.offsets.path_data + glyph_data(instance.offsets.path_tags) + skipped;
+        let delta = words(source).wrapping_sub(words(start));
+        data.extend_from_slice(bytemuck::bytes_of(&delta));
+        skipped += instance.path_data_len;
+        data.extend_from_slice(bytemuck::bytes_of(&0u32.wrapping_sub(words(skipped))));
+    }
+}
+
+/// Packs the widths of a range of path segments, with zeros for the segments past the end of
+/// the width stream.
+fn pack_widths(data: &mut Vec<u8>, widths: &[[f32; 2]], range: std::ops::Range<usize>) {
+    let start = range.start.min(widths.len());
+    let end = range.end.min(widths.len());
+    data.extend_from_slice(bytemuck::cast_slice(&widths[start..end]));
+    data.resize(data.len() + (range.len() - (end - start)) * 8, 0);
+}
+
+fn segment_count(tags: &[PathTag]) -> usize {
+    tags.iter()
+        .filter(|tag| tag.path_segment_type().0 != 0)
+        .count()
+}
+
 fn slice_size_in_bytes<T: Sized>(slice: &[T], extra: usize) -> usize {
     (slice.len() + extra) * std::mem::size_of::<T>()
 }
@@ -0,0 +1,228 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Consistency checks of encodings.
+
+use std::fmt;
+
+use super::{DrawMonoid, DrawTag, Encoding, Monoid, PathTag};
+
+/// Inconsistency found by [`Encoding::validate`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ValidationError {
+    /// A stream is not the length implied by the tags which refer to it, in elements.
+    StreamLength {
+        stream: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// A count kept by the encoding doesn't match its tags.
+    Count {
+        count: &'static str,
+        expected: usize,
+        actual: usize,
+    },
+    /// The path segment at this index of the path tag stream isn't followed by a path marker.
+    UnterminatedPath { path_tag: usize },
+    /// The instance marker at this index of the path tag stream isn't closed.
+    UnterminatedInstance { path_tag: usize },
+    /// The end clip at this index of the draw tag stream has no matching begin clip.
+    UnbalancedEndClip { draw_tag: usize },
+    /// This many layers are pushed but not popped.
+    UnclosedLayers { count: usize },
+    /// The transform at this index has a component that is infinite or NaN.
+    NonFiniteTransform { index: usize },
+    /// The point at this byte offset of the path data has a coordinate that is infinite or NaN.
+    NonFinitePoint { offset: usize },
+    /// The style at this index has a line width that is negative, infinite or NaN.
+    InvalidLineWidth { index: usize },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::StreamLength {
+                stream,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{stream} stream has {actual} elements, but its tags refer to {expected}"
+            ),
+            Self::Count {
+                count,
+                expected,
+                actual,
+            } => write!(f, "encoding counts {actual} {count}, but has {expected}"),
+            Self::UnterminatedPath { path_tag } => {
+                write!(f, "path segment at path tag {path_tag} is not in a path")
+            }
+            Self::UnterminatedInstance { path_tag } => {
+                write!(f, "instance at path tag {path_tag} is not closed")
+            }
+            Self::UnbalancedEndClip { draw_tag } => {
+                write!(f, "end clip at draw tag {draw_tag} has no begin clip")
+            }
+            Self::UnclosedLayers { count } => write!(f, "{count} layers are not popped"),
+            Self::NonFiniteTransform { index } => {
+                write!(f, "transform {index} is not finite")
+            }
+            Self::NonFinitePoint { offset } => {
+                write!(f, "point at path data offset {offset} is not finite")
+            }
+            Self::InvalidLineWidth { index } => {
+                write!(f, "style {index} has an invalid line width")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+impl Encoding {
+    /// Checks that the streams of the encoding are consistent with each other and its counts,
+    /// that layers are balanced, and that its transforms and points are finite.
+    ///
+    /// Encodings which fail these checks render incorrectly or not at all, in ways that are
+    /// hard to trace back to the code that encoded them.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        // Path tags
+        let mut n_paths = 0;
+        let mut n_segments = 0;
+        let mut n_transforms = 0;
+        let mut n_styles = 0;
+        let mut path_data_size = 0;
+        let mut open_segment = None;
+        let mut open_instance = None;
+        for (ix, tag) in self.path_tags.iter().enumerate() {
+            if tag.0 & PathTag::TRANSFORM.0 != 0 {
+                n_transforms += 1;
+            }
+            if tag.0 & PathTag::STYLE.0 != 0 {
+                n_styles += 1;
+            }
+            if tag.0 & PathTag::INSTANCE.0 != 0 {
+                open_instance = match open_instance {
+                    Some(_) => None,
+                    None => Some(ix),
+                };
+            }
+            if tag.is_path_segment() {
+                n_segments += 1;
+                open_segment = open_segment.or(Some(ix));
+                // Segments of instances have no path data of their own.
+                if open_instance.is_none() {
+                    let point_size = if tag.is_f32() { 8 } else { 4 };
+                    let n_points = tag.path_segment_type().0 as usize;
+                    let size = (n_points + tag.is_subpath_end() as usize) * point_size;
+                    if tag.is_f32() {
+                        self.validate_points(path_data_size, size)?;
+                    }
+                    path_data_size += size;
+                }
+            }
+            if tag.0 & PathTag::PATH.0 != 0 {
+                n_paths += 1;
+                open_segment = None;
+            }
+        }
+        if let Some(path_tag) = open_segment {
+            return Err(ValidationError::UnterminatedPath { path_tag });
+        }
+        if let Some(path_tag) = open_instance {
+            return Err(ValidationError::UnterminatedInstance { path_tag });
+        }
+        check_count("paths", n_paths, self.n_paths as usize)?;
+        check_count("path segments", n_segments, self.n_path_segments as usize)?;
+        check_length("path data", path_data_size, self.path_data.len())?;
+        check_length("transform", n_transforms, self.transforms.len())?;
+        check_length("style", n_styles, self.styles.len())?;
+        // Draw tags
+        let mut draw_data_size = 0;
+        let mut n_clips = 0;
+        let mut depth = 0;
+        for (ix, tag) in self.draw_tags.iter().enumerate() {
+            draw_data_size += DrawMonoid::new(*tag).scene_offset as usize * 4;
+            if *tag == DrawTag::BEGIN_CLIP {
+                depth += 1;
+                n_clips += 1;
+            } else if *tag == DrawTag::END_CLIP {
+                if depth == 0 {
+                    return Err(ValidationError::UnbalancedEndClip { draw_tag: ix });
+                }
+                depth -= 1;
+                n_clips += 1;
+            }
+        }
+        // Glyph runs are drawn with a path which is only added when they are resolved.
+        #[cfg(feature = "full")]
+        let n_draw_paths = n_paths + self.resources.glyph_runs.len();
+        #[cfg(not(feature = "full"))]
+        let n_draw_paths = n_paths;
+        check_length("draw tag", n_draw_paths, self.draw_tags.len())?;
+        check_length("draw data", draw_data_size, self.draw_data.len())?;
+        check_count("clips", n_clips, self.n_clips as usize)?;
+        check_count("open clips", depth, self.n_open_clips as usize)?;
+        if depth != 0 {
+            return Err(ValidationError::UnclosedLayers { count: depth });
+        }
+        // Values
+        for (index, transform) in self.transforms.iter().enumerate() {
+            let values = transform.matrix.iter().chain(&transform.translation);
+            if !values.copied().all(f32::is_finite) {
+                return Err(ValidationError::NonFiniteTransform { index });
+            }
+        }
+        for (index, style) in self.styles.iter().enumerate() {
+            if style.is_stroke() && !(style.line_width >= 0.0 && style.line_width.is_finite()) {
+                return Err(ValidationError::InvalidLineWidth { index });
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks the f32 points in `size` bytes of path data at `offset`.
+    fn validate_points(&self, offset: usize, size: usize) -> Result<(), ValidationError> {
+        let Some(data) = self.path_data.get(offset..offset + size) else {
+            // The length of the stream is checked once all tags are read.
+            return Ok(());
+        };
+        for (i, point) in data.chunks_exact(8).enumerate() {
+            let point: [f32; 2] = bytemuck::pod_read_unaligned(point);
+            if !point.iter().copied().all(f32::is_finite) {
+                return Err(ValidationError::NonFinitePoint {
+                    offset: offset + i * 8,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn check_length(
+    stream: &'static str,
+    expected: usize,
+    actual: usize,
+) -> Result<(), ValidationError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(ValidationError::StreamLength {
+            stream,
+            expected,
+            actual,
+        })
+    }
+}
+
+fn check_count(count: &'static str, expected: usize, actual: usize) -> Result<(), ValidationError> {
+    if expected == actual {
+        Ok(())
+    } else {
+        Err(ValidationError::Count {
+            count,
+            expected,
+            actual,
+        })
+    }
+}
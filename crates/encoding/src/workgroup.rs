@@ -0,0 +1,35 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Workgroup sizes of the stages whose dispatches are sized on the CPU, which are shared with
+//! the shaders.
+
+/// Workgroup size of the path tag reduction and scan stages.
+pub const PATH_REDUCE_WG: u32 = 256;
+/// Workgroup size of the stages that process one draw object or path per invocation.
+///
+/// The binning stage assumes that this is equal to the number of tiles in a bin (256).
+pub const PATH_BBOX_WG: u32 = 256;
+/// Workgroup size of the flattening stage.
+pub const FLATTEN_WG: u32 = 256;
+/// Workgroup size of the path count and path tiling stages, which process one line or tile
+/// segment per invocation.
+pub const PATH_COUNT_WG: u32 = 256;
+/// Workgroup size of the clip reduction and leaf stages.
+pub const CLIP_REDUCE_WG: u32 = 256;
+
+/// Constants that are substituted into the shader sources by the preprocessor.
+///
+/// Every occurrence of one of these names as an identifier in WGSL is replaced with the value
+/// as a `u32` literal, which allows them to appear in `@workgroup_size` attributes and array
+/// lengths. This keeps the workgroup sizes in one place for the shaders and the CPU side
+/// dispatch computations.
+pub const WORKGROUP_SIZES: &[(&str, u32)] = &[
+    ("PATH_REDUCE_WG", PATH_REDUCE_WG),
+    ("PATH_BBOX_WG", PATH_BBOX_WG),
+    ("FLATTEN_WG", FLATTEN_WG),
+    ("PATH_COUNT_WG", PATH_COUNT_WG),
+    ("CLIP_REDUCE_WG", CLIP_REDUCE_WG),
+    // Size of the workgroup array used to reduce clip bicyclic monoids in clip_leaf.
+    ("CLIP_BIC_SIZE", 2 * CLIP_REDUCE_WG - 2),
+];
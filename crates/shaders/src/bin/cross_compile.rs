@@ -0,0 +1,47 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Translates every shader permutation to SPIR-V, HLSL and (with the `msl` feature) MSL.
+//!
+//! Usage: `cargo run -p vello_shaders --bin cross_compile OUT_DIR [SHADER_DIR]`
+//!
+//! For each permutation `name`, this writes `name.spv`, `name.hlsl` and `name.metal` to
+//! `OUT_DIR`. `SHADER_DIR` defaults to the `shader` directory at the root of the workspace.
+
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+use vello_shaders::compile::{hlsl, spirv, ShaderInfo};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args_os().skip(1);
+    let out_dir = PathBuf::from(args.next().ok_or("missing output directory argument")?);
+    let shader_dir = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../shader")));
+    fs::create_dir_all(&out_dir)?;
+    let mut shaders = ShaderInfo::from_dir(&shader_dir)
+        .into_iter()
+        .collect::<Vec<_>>();
+    shaders.sort_by(|x, y| x.0.cmp(&y.0));
+    for (name, info) in &shaders {
+        let spv = spirv::translate(info).map_err(|e| format!("{name}: {e}"))?;
+        let spv = spv
+            .iter()
+            .flat_map(|word| word.to_le_bytes())
+            .collect::<Vec<_>>();
+        fs::write(out_dir.join(format!("{name}.spv")), spv)?;
+        let hlsl = hlsl::translate(info).map_err(|e| format!("{name}: {e}"))?;
+        fs::write(out_dir.join(format!("{name}.hlsl")), hlsl)?;
+        #[cfg(feature = "msl")]
+        {
+            let msl =
+                vello_shaders::compile::msl::translate(info).map_err(|e| format!("{name}: {e}"))?;
+            fs::write(out_dir.join(format!("{name}.metal")), msl)?;
+        }
+        println!("Translated {name}");
+    }
+    Ok(())
+}
@@ -0,0 +1,28 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Runs every shader permutation through naga's front end and validator, reporting all errors.
+//!
+//! Usage: `cargo run -p vello_shaders --bin validate_shaders [SHADER_DIR]`
+//!
+//! `SHADER_DIR` defaults to the `shader` directory at the root of the workspace.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let shader_dir = std::env::args_os()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../shader")));
+    let failures = vello_shaders::compile::validate_dir(&shader_dir);
+    if failures.is_empty() {
+        println!("All shaders in {} are valid", shader_dir.display());
+        return ExitCode::SUCCESS;
+    }
+    for (name, report) in &failures {
+        eprintln!("error in {name}:\n{report}");
+    }
+    eprintln!("{} shader(s) failed to validate", failures.len());
+    ExitCode::FAILURE
+}
@@ -0,0 +1,46 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use naga::back::hlsl as naga_hlsl;
+
+use super::{BindType, ShaderInfo};
+
+/// Translates a shader to HLSL for shader model 5.1.
+///
+/// Each binding is assigned the next free register of the class implied by its [`BindType`],
+/// in binding order: `b` for uniforms, `t` for read only buffers and images, and `u` for
+/// everything that is written to. The entry point is named `main`.
+pub fn translate(shader: &ShaderInfo) -> Result<String, naga_hlsl::Error> {
+    let mut binding_map = naga_hlsl::BindingMap::default();
+    let (mut cbv_idx, mut srv_idx, mut uav_idx) = (0, 0, 0);
+    for resource in &shader.bindings {
+        let binding = naga::ResourceBinding {
+            group: resource.location.0,
+            binding: resource.location.1,
+        };
+        let idx = match resource.ty {
+            BindType::Uniform => &mut cbv_idx,
+            BindType::BufReadOnly | BindType::ImageRead => &mut srv_idx,
+            BindType::Buffer | BindType::Image => &mut uav_idx,
+        };
+        let target = naga_hlsl::BindTarget {
+            register: *idx,
+            ..Default::default()
+        };
+        *idx += 1;
+        binding_map.insert(binding, target);
+    }
+    let options = naga_hlsl::Options {
+        shader_model: naga_hlsl::ShaderModel::V5_1,
+        binding_map,
+        // Unused resources are still declared in the output, so give them a binding rather
+        // than failing.
+        fake_missing_bindings: true,
+        zero_initialize_workgroup_memory: true,
+        ..Default::default()
+    };
+    let mut source = String::new();
+    let mut writer = naga_hlsl::Writer::new(&mut source, &options);
+    writer.write(&shader.module, &shader.module_info)?;
+    Ok(source)
+}
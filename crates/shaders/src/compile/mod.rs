@@ -20,7 +20,15 @@ pub mod preprocess;
 #[cfg(feature = "msl")]
 pub mod msl;
 
+// These are only used by the cross_compile binary, not the build script which includes this
+// module directly.
+#[allow(dead_code)]
+pub mod hlsl;
+#[allow(dead_code)]
+pub mod spirv;
+
 use crate::types::{BindType, BindingInfo, WorkgroupBufferInfo};
+use vello_encoding::WORKGROUP_SIZES;
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -136,50 +144,86 @@ impl ShaderInfo {
     }
 
     pub fn from_dir(shader_dir: impl AsRef<Path>) -> HashMap<String, Self> {
-        use std::fs;
-        let shader_dir = shader_dir.as_ref();
-        let permutation_map = if let Ok(permutations_source) =
-            std::fs::read_to_string(shader_dir.join("permutations"))
-        {
+        preprocess_dir(shader_dir)
+            .into_iter()
+            .map(|(name, source)| {
+                let shader_info = Self::new(source, "main").unwrap();
+                (name, shader_info)
+            })
+            .collect()
+    }
+}
+
+/// Preprocesses and validates every shader permutation in `shader_dir`.
+///
+/// Unlike [`ShaderInfo::from_dir`], this doesn't stop at the first invalid shader. Instead it
+/// returns the name of each permutation that failed along with a rendered diagnostic, so that
+/// all errors can be reported at once without needing a device.
+// Only used by the validate_shaders binary, not the build script which includes this module
+// directly.
+#[allow(dead_code)]
+pub fn validate_dir(shader_dir: impl AsRef<Path>) -> Vec<(String, String)> {
+    let mut shaders = preprocess_dir(shader_dir);
+    shaders.sort_by(|x, y| x.0.cmp(&y.0));
+    shaders
+        .into_iter()
+        .filter_map(|(name, source)| {
+            let report = match ShaderInfo::new(source.clone(), "main") {
+                Ok(_) => return None,
+                Err(Error::Parse(e)) => e.emit_to_string(&source),
+                Err(Error::Validate(e)) => e.emit_to_string(&source),
+                Err(e) => e.to_string(),
+            };
+            Some((name, report))
+        })
+        .collect()
+}
+
+/// Returns the preprocessed source of every shader permutation in `shader_dir`, keyed by the
+/// permutation name.
+fn preprocess_dir(shader_dir: impl AsRef<Path>) -> Vec<(String, String)> {
+    use std::fs;
+    let shader_dir = shader_dir.as_ref();
+    let permutation_map =
+        if let Ok(permutations_source) = std::fs::read_to_string(shader_dir.join("permutations")) {
             permutations::parse(&permutations_source)
         } else {
             Default::default()
         };
-        println!("{:?}", permutation_map);
-        let imports = preprocess::get_imports(shader_dir);
-        let mut info = HashMap::default();
-        let mut defines = HashSet::default();
-        defines.insert("full".to_string());
-        for entry in shader_dir
-            .read_dir()
-            .expect("Can read shader import directory")
-            .filter_map(move |e| {
-                e.ok()
-                    .filter(|e| e.path().extension().map(|e| e == "wgsl").unwrap_or(false))
-            })
-        {
-            let file_name = entry.file_name();
-            if let Some(name) = file_name.to_str() {
-                let suffix = ".wgsl";
-                if let Some(shader_name) = name.strip_suffix(suffix) {
-                    let contents = fs::read_to_string(shader_dir.join(&file_name))
-                        .expect("Could read shader {shader_name} contents");
-                    if let Some(permutations) = permutation_map.get(shader_name) {
-                        for permutation in permutations {
-                            let mut defines = defines.clone();
-                            defines.extend(permutation.defines.iter().cloned());
-                            let source = preprocess::preprocess(&contents, &defines, &imports);
-                            let shader_info = Self::new(source.clone(), "main").unwrap();
-                            info.insert(permutation.name.clone(), shader_info);
-                        }
-                    } else {
+    println!("{:?}", permutation_map);
+    let imports = preprocess::get_imports(shader_dir);
+    let mut shaders = vec![];
+    let mut defines = HashSet::default();
+    defines.insert("full".to_string());
+    for entry in shader_dir
+        .read_dir()
+        .expect("Can read shader import directory")
+        .filter_map(move |e| {
+            e.ok()
+                .filter(|e| e.path().extension().map(|e| e == "wgsl").unwrap_or(false))
+        })
+    {
+        let file_name = entry.file_name();
+        if let Some(name) = file_name.to_str() {
+            let suffix = ".wgsl";
+            if let Some(shader_name) = name.strip_suffix(suffix) {
+                let contents = fs::read_to_string(shader_dir.join(&file_name))
+                    .expect("Could read shader {shader_name} contents");
+                if let Some(permutations) = permutation_map.get(shader_name) {
+                    for permutation in permutations {
+                        let mut defines = defines.clone();
+                        defines.extend(permutation.defines.iter().cloned());
                         let source = preprocess::preprocess(&contents, &defines, &imports);
-                        let shader_info = Self::new(source.clone(), "main").unwrap();
-                        info.insert(shader_name.to_string(), shader_info);
+                        let source = preprocess::substitute_constants(&source, WORKGROUP_SIZES);
+                        shaders.push((permutation.name.clone(), source));
                     }
+                } else {
+                    let source = preprocess::preprocess(&contents, &defines, &imports);
+                    let source = preprocess::substitute_constants(&source, WORKGROUP_SIZES);
+                    shaders.push((shader_name.to_string(), source));
                 }
             }
         }
-        info
     }
+    shaders
 }
@@ -2,6 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0 OR MIT
 
 use std::{
+    borrow::Cow,
     collections::{HashMap, HashSet},
     fs,
     path::Path,
@@ -35,15 +36,36 @@ pub fn get_imports(shader_dir: &Path) -> HashMap<String, String> {
 pub struct StackItem {
     active: bool,
     else_passed: bool,
+    /// Whether any branch of this conditional has been active so far, for `#elif` and `#else`.
+    taken: bool,
 }
 
+/// Preprocesses a shader.
+///
+/// Each of `defines` is defined without a value, which makes it usable with `#ifdef` and
+/// evaluate to 1 in `#if` expressions. Shaders can add their own with `#define NAME VALUE`,
+/// after which `NAME` is replaced by `VALUE` in the rest of the output.
 pub fn preprocess(
     input: &str,
     defines: &HashSet<String>,
     imports: &HashMap<String, String>,
 ) -> String {
     let mut output = String::with_capacity(input.len());
-    let mut stack = vec![];
+    let mut defines = defines
+        .iter()
+        .map(|define| (define.clone(), String::new()))
+        .collect();
+    preprocess_into(input, &mut defines, imports, &mut output);
+    output
+}
+
+fn preprocess_into(
+    input: &str,
+    defines: &mut HashMap<String, String>,
+    imports: &HashMap<String, String>,
+    output: &mut String,
+) {
+    let mut stack: Vec<StackItem> = vec![];
     'all_lines: for (line_number, mut line) in input.lines().enumerate() {
         loop {
             if line.is_empty() {
@@ -66,9 +88,16 @@ pub fn preprocess(
                 .unwrap_or(directive_start.len());
             let directive = &directive_start[..directive_len];
             let directive_is_at_start = line.trim_start().starts_with('#');
+            // In theory, we can cache this until the top item of the stack changes
+            // However, in practise there will only ever be a few stack items, so
+            // it's reasonable to just recompute it every time
+            let is_active = stack.iter().all(|item| item.active);
 
             match directive {
-                if_item @ ("ifdef" | "ifndef" | "else" | "endif") if !directive_is_at_start => {
+                if_item @ ("ifdef" | "ifndef" | "if" | "elif" | "else" | "endif" | "define"
+                | "undef")
+                    if !directive_is_at_start =>
+                {
                     eprintln!(
                         "#{if_item} directives must be the first non_whitespace items on \
                                their line, ignoring (line {line_number})"
@@ -77,27 +106,55 @@ pub fn preprocess(
                 }
                 def_test @ ("ifdef" | "ifndef") => {
                     let def = directive_start[directive_len..].trim();
-                    let exists = defines.contains(def);
+                    let exists = defines.contains_key(def);
                     let mode = def_test == "ifdef";
                     stack.push(StackItem {
                         active: mode == exists,
                         else_passed: false,
+                        taken: mode == exists,
                     });
                     // Don't add this line to the output; instead process the next line
                     continue 'all_lines;
                 }
+                "if" => {
+                    let condition = directive_start[directive_len..].trim();
+                    let active = evaluate_condition(condition, defines, line_number);
+                    stack.push(StackItem {
+                        active,
+                        else_passed: false,
+                        taken: active,
+                    });
+                    continue 'all_lines;
+                }
+                "elif" => {
+                    let condition = directive_start[directive_len..].trim();
+                    match stack.last_mut() {
+                        Some(item) if item.else_passed => {
+                            eprintln!("#elif after #else (line {line_number}); ignoring")
+                        }
+                        Some(item) => {
+                            item.active =
+                                !item.taken && evaluate_condition(condition, defines, line_number);
+                            item.taken |= item.active;
+                        }
+                        None => eprintln!("Mismatched elif (line {line_number})"),
+                    }
+                    continue 'all_lines;
+                }
                 "else" => {
-                    let item = stack.last_mut();
-                    if let Some(item) = item {
-                        if item.else_passed {
+                    match stack.last_mut() {
+                        Some(item) if item.else_passed => {
                             eprintln!(
                                 "Second else for same ifdef/ifndef (line {line_number}); \
                                        ignoring second else"
                             )
-                        } else {
+                        }
+                        Some(item) => {
                             item.else_passed = true;
-                            item.active = !item.active;
+                            item.active = !item.taken;
+                            item.taken = true;
                         }
+                        None => eprintln!("Mismatched else (line {line_number})"),
                     }
                     let remainder = directive_start[directive_len..].trim();
                     if !remainder.is_empty() {
@@ -123,6 +180,18 @@ pub fn preprocess(
                     // Don't add this line to the output; it should be empty (see warning above)
                     continue 'all_lines;
                 }
+                def_item @ ("define" | "undef") => {
+                    let args = directive_start[directive_len..].trim();
+                    let (def, value) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                    if !is_identifier(def) {
+                        eprintln!("#{def_item} needs an identifier argument (line {line_number})");
+                    } else if is_active && def_item == "define" {
+                        defines.insert(def.to_owned(), value.trim().to_owned());
+                    } else if is_active {
+                        defines.remove(def);
+                    }
+                    continue 'all_lines;
+                }
                 "import" => {
                     output.push_str(&line[..hash_index]);
                     let directive_end = &directive_start[directive_len..];
@@ -143,11 +212,8 @@ pub fn preprocess(
                     line = &import_name_start[import_name_end_index..];
                     let import = imports.get(import_name);
                     if let Some(import) = import {
-                        // In theory, we can cache this until the top item of the stack changes
-                        // However, in practise there will only ever be at most 2 stack items, so
-                        // it's reasonable to just recompute it every time
-                        if stack.iter().all(|item| item.active) {
-                            output.push_str(&preprocess(import, defines, imports));
+                        if is_active {
+                            preprocess_into(import, defines, imports, output);
                         }
                     } else {
                         eprintln!("Unknown import `{import_name}` (line {line_number})");
@@ -160,6 +226,9 @@ pub fn preprocess(
             }
         }
         if stack.iter().all(|item| item.active) {
+            let line = substitute_defines(line, defines);
+            // `__LINE__` is the line of the output, as in the runtime preprocessor.
+            let line = substitute_line(line, || output.matches('\n').count() + 1);
             // Naga does not yet recognize `const` but web does not allow global `let`. We
             // use `let` in our canonical sources to satisfy wgsl-analyzer but replace with
             // `const` when targeting web.
@@ -172,13 +241,265 @@ pub fn preprocess(
                     line.replace_range(idx..(idx + 12), "var<storage, read_write>");
                     output.push_str(&line);
                 } else {
-                    output.push_str(line);
+                    output.push_str(&line);
                 }
             } else {
-                output.push_str(line);
+                output.push_str(&line);
             }
             output.push('\n');
         }
     }
+}
+
+fn is_identifier(s: &str) -> bool {
+    s.starts_with(|c: char| c == '_' || c.is_alphabetic())
+        && s.chars().all(|c| c == '_' || c.is_alphanumeric())
+}
+
+/// Replaces `__LINE__` in `line` with the result of `line_number`, as a `u32` literal.
+fn substitute_line(line: Cow<str>, line_number: impl FnOnce() -> usize) -> Cow<str> {
+    if line.contains("__LINE__") {
+        Cow::Owned(line.replace("__LINE__", &format!("{}u", line_number())))
+    } else {
+        line
+    }
+}
+
+/// Replaces identifiers in `line` which were given a value with `#define`.
+fn substitute_defines<'a>(line: &'a str, defines: &HashMap<String, String>) -> Cow<'a, str> {
+    if defines.values().all(String::is_empty) {
+        return Cow::Borrowed(line);
+    }
+    let mut output = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c == '_' || c.is_alphabetic()) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c == '_' || c.is_alphanumeric()))
+            .unwrap_or(rest.len());
+        match defines.get(&rest[..end]) {
+            Some(value) if !value.is_empty() => output.push_str(value),
+            _ => output.push_str(&rest[..end]),
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    Cow::Owned(output)
+}
+
+/// Evaluates the condition of an `#if` or `#elif` directive, treating errors as false.
+fn evaluate_condition(condition: &str, defines: &HashMap<String, String>, line: usize) -> bool {
+    let result = tokenize(condition).and_then(|tokens| {
+        let mut parser = ExprParser {
+            tokens: &tokens,
+            defines,
+        };
+        let value = parser.parse_or()?;
+        match parser.tokens.first() {
+            Some(token) => Err(format!("unexpected `{token}`")),
+            None => Ok(value),
+        }
+    });
+    match result {
+        Ok(value) => value != 0,
+        Err(e) => {
+            eprintln!("Invalid #if condition `{condition}`: {e} (line {line})");
+            false
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<&str>, String> {
+    const OPERATORS: [&str; 16] = [
+        "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "(", ")", "+", "-", "*", "/", "%",
+    ];
+    let mut tokens = vec![];
+    let mut rest = expr.trim_start();
+    while !rest.is_empty() {
+        let len = if rest.starts_with(|c: char| c == '_' || c.is_alphanumeric()) {
+            rest.find(|c: char| !(c == '_' || c.is_alphanumeric()))
+                .unwrap_or(rest.len())
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            op.len()
+        } else {
+            return Err(format!("unexpected character in `{rest}`"));
+        };
+        tokens.push(&rest[..len]);
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// A recursive descent parser for the C-like integer expressions accepted by `#if`.
+struct ExprParser<'a> {
+    tokens: &'a [&'a str],
+    defines: &'a HashMap<String, String>,
+}
+
+impl<'a> ExprParser<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let (first, rest) = self.tokens.split_first()?;
+        self.tokens = rest;
+        Some(first)
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let matches = self.tokens.first() == Some(&token);
+        if matches {
+            self.tokens = &self.tokens[1..];
+        }
+        matches
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(format!("expected `{token}`"))
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<i64, String> {
+        let mut value = self.parse_and()?;
+        while self.eat("||") {
+            let rhs = self.parse_and()?;
+            value = (value != 0 || rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn parse_and(&mut self) -> Result<i64, String> {
+        let mut value = self.parse_comparison()?;
+        while self.eat("&&") {
+            let rhs = self.parse_comparison()?;
+            value = (value != 0 && rhs != 0) as i64;
+        }
+        Ok(value)
+    }
+
+    fn parse_comparison(&mut self) -> Result<i64, String> {
+        let lhs = self.parse_sum()?;
+        let op = match self.tokens.first() {
+            Some(op @ (&"==" | &"!=" | &"<" | &"<=" | &">" | &">=")) => *op,
+            _ => return Ok(lhs),
+        };
+        self.next();
+        let rhs = self.parse_sum()?;
+        let result = match op {
+            "==" => lhs == rhs,
+            "!=" => lhs != rhs,
+            "<" => lhs < rhs,
+            "<=" => lhs <= rhs,
+            ">" => lhs > rhs,
+            _ => lhs >= rhs,
+        };
+        Ok(result as i64)
+    }
+
+    fn parse_sum(&mut self) -> Result<i64, String> {
+        let mut value = self.parse_product()?;
+        loop {
+            if self.eat("+") {
+                value = value.wrapping_add(self.parse_product()?);
+            } else if self.eat("-") {
+                value = value.wrapping_sub(self.parse_product()?);
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn parse_product(&mut self) -> Result<i64, String> {
+        let mut value = self.parse_unary()?;
+        loop {
+            let op = match self.tokens.first() {
+                Some(op @ (&"*" | &"/" | &"%")) => *op,
+                _ => return Ok(value),
+            };
+            self.next();
+            let rhs = self.parse_unary()?;
+            value = match op {
+                "*" => value.wrapping_mul(rhs),
+                _ if rhs == 0 => return Err("division by zero".into()),
+                "/" => value / rhs,
+                _ => value % rhs,
+            };
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<i64, String> {
+        if self.eat("!") {
+            Ok((self.parse_unary()? == 0) as i64)
+        } else if self.eat("-") {
+            Ok(self.parse_unary()?.wrapping_neg())
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<i64, String> {
+        match self.next() {
+            Some("(") => {
+                let value = self.parse_or()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            Some("defined") => {
+                let parenthesized = self.eat("(");
+                let name = self.next().ok_or("expected identifier after `defined`")?;
+                if parenthesized {
+                    self.expect(")")?;
+                }
+                Ok(self.defines.contains_key(name) as i64)
+            }
+            Some(token) if token.starts_with(|c: char| c.is_ascii_digit()) => parse_int(token),
+            Some(name) if is_identifier(name) => match self.defines.get(name) {
+                // Defines without a value act as flags
+                Some(value) if value.is_empty() => Ok(1),
+                Some(value) => parse_int(value),
+                None => Ok(0),
+            },
+            Some(token) => Err(format!("unexpected `{token}`")),
+            None => Err("unexpected end of expression".into()),
+        }
+    }
+}
+
+/// Parses an integer literal, allowing the `u` and `i` suffixes used in WGSL.
+fn parse_int(literal: &str) -> Result<i64, String> {
+    let digits = literal.trim_end_matches(|c| c == 'u' || c == 'i');
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else {
+        digits.parse()
+    };
+    value.map_err(|_| format!("`{literal}` is not an integer"))
+}
+
+/// Replaces each identifier in `source` which names one of `constants` with its value as a
+/// `u32` literal.
+pub fn substitute_constants(source: &str, constants: &[(&str, u32)]) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+    while let Some(start) = rest.find(|c: char| c == '_' || c.is_alphabetic()) {
+        // Identifiers can't start inside a number literal such as `1e5`
+        let in_number = rest[..start].ends_with(|c: char| c.is_ascii_digit());
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = rest
+            .find(|c: char| !(c == '_' || c.is_alphanumeric()))
+            .unwrap_or(rest.len());
+        let ident = &rest[..end];
+        match constants.iter().find(|(name, _)| *name == ident) {
+            Some((_, value)) if !in_number => {
+                output.push_str(&value.to_string());
+                output.push('u');
+            }
+            _ => output.push_str(ident),
+        }
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
     output
 }
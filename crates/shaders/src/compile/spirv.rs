@@ -0,0 +1,27 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+use naga::back::spv as naga_spv;
+
+use super::ShaderInfo;
+
+/// Translates a shader to SPIR-V.
+///
+/// Resources keep the group and binding indices declared in the WGSL source, and the entry
+/// point is named `main`.
+pub fn translate(shader: &ShaderInfo) -> Result<Vec<u32>, naga_spv::Error> {
+    let options = naga_spv::Options {
+        lang_version: (1, 3),
+        ..Default::default()
+    };
+    let pipeline_options = naga_spv::PipelineOptions {
+        shader_stage: naga::ShaderStage::Compute,
+        entry_point: "main".to_string(),
+    };
+    naga_spv::write_vec(
+        &shader.module,
+        &shader.module_info,
+        &options,
+        Some(&pipeline_options),
+    )
+}
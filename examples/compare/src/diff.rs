@@ -0,0 +1,108 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Pixel and perceptual differences between two images.
+
+/// The just noticeable difference in CIE76 ΔE.
+pub const JND: f64 = 2.3;
+
+/// Channel differences at or below this are treated as rounding, rather than divergence.
+const CHANNEL_TOLERANCE: u8 = 2;
+
+pub struct Diff {
+    /// The mean absolute difference of the color channels, from 0 to 255.
+    pub mean_channel: f64,
+    /// The largest difference of any channel.
+    pub max_channel: u8,
+    /// The fraction of pixels which have a channel differing by more than rounding.
+    pub differing_pixels: f64,
+    /// The mean CIE76 ΔE of the pixels.
+    pub mean_delta_e: f64,
+    pub max_delta_e: f64,
+    /// The fraction of pixels which differ noticeably, by more than [`JND`].
+    pub noticeable_pixels: f64,
+    /// An image of the differences, from black where the pixels are equal to red where they
+    /// differ most, as RGBA8.
+    pub image: Vec<u8>,
+}
+
+/// Compares two opaque RGBA8 images of the same size.
+pub fn diff(a: &[u8], b: &[u8]) -> Diff {
+    assert_eq!(a.len(), b.len());
+    let n_pixels = a.len() / 4;
+    let mut sum_channel = 0_u64;
+    let mut max_channel = 0;
+    let mut differing = 0;
+    let mut sum_delta_e = 0.0;
+    let mut max_delta_e = 0.0_f64;
+    let mut noticeable = 0;
+    let mut delta_es = Vec::with_capacity(n_pixels);
+    for (pa, pb) in a.chunks_exact(4).zip(b.chunks_exact(4)) {
+        let mut pixel_max = 0;
+        for (ca, cb) in pa[..3].iter().zip(&pb[..3]) {
+            let d = ca.abs_diff(*cb);
+            sum_channel += d as u64;
+            pixel_max = pixel_max.max(d);
+        }
+        max_channel = max_channel.max(pixel_max);
+        if pixel_max > CHANNEL_TOLERANCE {
+            differing += 1;
+        }
+        let delta_e = delta_e(to_lab(pa), to_lab(pb));
+        sum_delta_e += delta_e;
+        max_delta_e = max_delta_e.max(delta_e);
+        if delta_e > JND {
+            noticeable += 1;
+        }
+        delta_es.push(delta_e);
+    }
+    let image = delta_es
+        .iter()
+        .flat_map(|delta_e| {
+            let level = if max_delta_e > 0.0 {
+                (delta_e / max_delta_e * 255.0).round() as u8
+            } else {
+                0
+            };
+            [level, 0, 0, 255]
+        })
+        .collect();
+    let n = n_pixels.max(1) as f64;
+    Diff {
+        mean_channel: sum_channel as f64 / (n * 3.0),
+        max_channel,
+        differing_pixels: differing as f64 / n,
+        mean_delta_e: sum_delta_e / n,
+        max_delta_e,
+        noticeable_pixels: noticeable as f64 / n,
+        image,
+    }
+}
+
+fn delta_e(a: [f64; 3], b: [f64; 3]) -> f64 {
+    let [l, a_, b_] = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    (l * l + a_ * a_ + b_ * b_).sqrt()
+}
+
+/// Converts an sRGB pixel to CIE L*a*b*, with a D65 white point.
+fn to_lab(pixel: &[u8]) -> [f64; 3] {
+    let [r, g, b] = [pixel[0], pixel[1], pixel[2]].map(|c| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let [fx, fy, fz] = [x, y, z].map(|t| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    });
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
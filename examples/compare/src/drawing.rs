@@ -0,0 +1,467 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Scenes described as kurbo shapes and peniko brushes, which can be drawn both by Vello and
+//! by tiny-skia.
+
+use std::f64::consts::{PI, TAU};
+
+use tiny_skia::{
+    FillRule, GradientStop, LinearGradient, Mask, Paint, PathBuilder, Pixmap, PixmapPaint, Shader,
+    SpreadMode,
+};
+use vello::kurbo::{Affine, BezPath, Cap, Circle, Join, PathEl, Point, Rect, Shape, Stroke};
+use vello::peniko::{BlendMode, Color, Fill, Gradient};
+use vello::SceneBuilder;
+
+/// Brushes supported by both renderers.
+#[derive(Clone)]
+pub enum Brush {
+    Solid(Color),
+    /// Linear gradient between two points, padded at both ends.
+    Linear(Point, Point, Vec<(f32, Color)>),
+}
+
+pub enum Op {
+    Fill {
+        fill: Fill,
+        transform: Affine,
+        brush: Brush,
+        path: BezPath,
+    },
+    Stroke {
+        stroke: Stroke,
+        transform: Affine,
+        brush: Brush,
+        path: BezPath,
+    },
+    /// Starts a layer which is clipped to `clip` and composited with `alpha` when popped.
+    PushLayer {
+        alpha: f32,
+        transform: Affine,
+        clip: BezPath,
+    },
+    PopLayer,
+}
+
+/// A named list of drawing operations.
+pub struct Drawing {
+    pub name: &'static str,
+    pub ops: Vec<Op>,
+}
+
+impl Drawing {
+    fn new(name: &'static str) -> Self {
+        Self { name, ops: vec![] }
+    }
+
+    fn fill(&mut self, fill: Fill, transform: Affine, brush: Brush, shape: &impl Shape) {
+        self.ops.push(Op::Fill {
+            fill,
+            transform,
+            brush,
+            path: shape.to_path(0.1),
+        });
+    }
+
+    fn stroke(&mut self, stroke: Stroke, transform: Affine, brush: Brush, shape: &impl Shape) {
+        self.ops.push(Op::Stroke {
+            stroke,
+            transform,
+            brush,
+            path: shape.to_path(0.1),
+        });
+    }
+
+    /// Draws the operations with `builder`.
+    pub fn draw_vello(&self, builder: &mut SceneBuilder) {
+        for op in &self.ops {
+            match op {
+                Op::Fill {
+                    fill,
+                    transform,
+                    brush,
+                    path,
+                } => builder.fill(*fill, *transform, &vello_brush(brush), None, path),
+                Op::Stroke {
+                    stroke,
+                    transform,
+                    brush,
+                    path,
+                } => builder.stroke(stroke, *transform, &vello_brush(brush), None, path),
+                Op::PushLayer {
+                    alpha,
+                    transform,
+                    clip,
+                } => builder.push_layer(BlendMode::default(), *alpha, *transform, clip),
+                Op::PopLayer => builder.pop_layer(),
+            }
+        }
+    }
+
+    /// Draws the operations into `pixmap`.
+    ///
+    /// Layers are drawn into pixmaps of their own, which are composited through a mask of
+    /// their clip, as tiny-skia has no layers.
+    pub fn draw_tiny_skia(&self, pixmap: &mut Pixmap) {
+        let mut layers: Vec<(Pixmap, Mask, f32)> = vec![];
+        for op in &self.ops {
+            let target = layers.last_mut().map_or(&mut *pixmap, |(layer, ..)| layer);
+            match op {
+                Op::Fill {
+                    fill,
+                    transform,
+                    brush,
+                    path,
+                } => {
+                    let (Some(path), Some(paint)) = (skia_path(path), skia_paint(brush)) else {
+                        continue;
+                    };
+                    let rule = match fill {
+                        Fill::NonZero => FillRule::Winding,
+                        Fill::EvenOdd => FillRule::EvenOdd,
+                    };
+                    target.fill_path(&path, &paint, rule, skia_transform(*transform), None);
+                }
+                Op::Stroke {
+                    stroke,
+                    transform,
+                    brush,
+                    path,
+                } => {
+                    let (Some(path), Some(paint)) = (skia_path(path), skia_paint(brush)) else {
+                        continue;
+                    };
+                    let stroke = skia_stroke(stroke);
+                    target.stroke_path(&path, &paint, &stroke, skia_transform(*transform), None);
+                }
+                Op::PushLayer {
+                    alpha,
+                    transform,
+                    clip,
+                } => {
+                    let (width, height) = (pixmap.width(), pixmap.height());
+                    let layer = Pixmap::new(width, height).unwrap();
+                    let mut mask = Mask::new(width, height).unwrap();
+                    if let Some(clip) = skia_path(clip) {
+                        let transform = skia_transform(*transform);
+                        mask.fill_path(&clip, FillRule::Winding, true, transform);
+                    }
+                    layers.push((layer, mask, *alpha));
+                }
+                Op::PopLayer => {
+                    let Some((layer, mask, alpha)) = layers.pop() else {
+                        continue;
+                    };
+                    let paint = PixmapPaint {
+                        opacity: alpha,
+                        ..Default::default()
+                    };
+                    let target = layers.last_mut().map_or(&mut *pixmap, |(layer, ..)| layer);
+                    let identity = tiny_skia::Transform::identity();
+                    target.draw_pixmap(0, 0, layer.as_ref(), &paint, identity, Some(&mask));
+                }
+            }
+        }
+    }
+}
+
+fn vello_brush(brush: &Brush) -> vello::peniko::Brush {
+    match brush {
+        Brush::Solid(color) => (*color).into(),
+        Brush::Linear(start, end, stops) => Gradient::new_linear(*start, *end)
+            .with_stops(stops.as_slice())
+            .into(),
+    }
+}
+
+fn skia_paint(brush: &Brush) -> Option<Paint<'static>> {
+    let shader = match brush {
+        Brush::Solid(color) => Shader::SolidColor(skia_color(*color)),
+        Brush::Linear(start, end, stops) => LinearGradient::new(
+            tiny_skia::Point::from_xy(start.x as f32, start.y as f32),
+            tiny_skia::Point::from_xy(end.x as f32, end.y as f32),
+            stops
+                .iter()
+                .map(|(offset, color)| GradientStop::new(*offset, skia_color(*color)))
+                .collect(),
+            SpreadMode::Pad,
+            tiny_skia::Transform::identity(),
+        )?,
+    };
+    Some(Paint {
+        shader,
+        anti_alias: true,
+        ..Default::default()
+    })
+}
+
+fn skia_color(color: Color) -> tiny_skia::Color {
+    tiny_skia::Color::from_rgba8(color.r, color.g, color.b, color.a)
+}
+
+fn skia_transform(transform: Affine) -> tiny_skia::Transform {
+    let [a, b, c, d, e, f] = transform.as_coeffs().map(|coeff| coeff as f32);
+    tiny_skia::Transform::from_row(a, b, c, d, e, f)
+}
+
+fn skia_path(path: &BezPath) -> Option<tiny_skia::Path> {
+    let mut builder = PathBuilder::new();
+    for el in path.elements() {
+        match *el {
+            PathEl::MoveTo(p) => builder.move_to(p.x as f32, p.y as f32),
+            PathEl::LineTo(p) => builder.line_to(p.x as f32, p.y as f32),
+            PathEl::QuadTo(p1, p2) => {
+                builder.quad_to(p1.x as f32, p1.y as f32, p2.x as f32, p2.y as f32)
+            }
+            PathEl::CurveTo(p1, p2, p3) => builder.cubic_to(
+                p1.x as f32,
+                p1.y as f32,
+                p2.x as f32,
+                p2.y as f32,
+                p3.x as f32,
+                p3.y as f32,
+            ),
+            PathEl::ClosePath => builder.close(),
+        }
+    }
+    builder.finish()
+}
+
+fn skia_stroke(stroke: &Stroke) -> tiny_skia::Stroke {
+    tiny_skia::Stroke {
+        width: stroke.width as f32,
+        miter_limit: stroke.miter_limit as f32,
+        // tiny-skia has a single cap for both ends.
+        line_cap: match stroke.start_cap {
+            Cap::Butt => tiny_skia::LineCap::Butt,
+            Cap::Square => tiny_skia::LineCap::Square,
+            Cap::Round => tiny_skia::LineCap::Round,
+        },
+        line_join: match stroke.join {
+            Join::Bevel => tiny_skia::LineJoin::Bevel,
+            Join::Miter => tiny_skia::LineJoin::Miter,
+            Join::Round => tiny_skia::LineJoin::Round,
+        },
+        dash: None,
+    }
+}
+
+/// Returns the built-in drawings, which cover `size` by `size` pixels.
+pub fn drawings(size: f64) -> Vec<Drawing> {
+    vec![
+        circles(size),
+        thin_lines(size),
+        curves(size),
+        fill_rules(size),
+        subpixel_rects(size),
+        layers(size),
+        gradients(size),
+        transforms(size),
+        many_clips(size),
+    ]
+}
+
+fn palette(ix: usize) -> Color {
+    const COLORS: [Color; 6] = [
+        Color::rgb8(220, 38, 127),
+        Color::rgb8(100, 143, 255),
+        Color::rgb8(255, 176, 0),
+        Color::rgb8(120, 94, 240),
+        Color::rgb8(254, 97, 0),
+        Color::rgb8(20, 160, 90),
+    ];
+    COLORS[ix % COLORS.len()]
+}
+
+/// Concentric circles at fractional positions, which exercise the coverage of curved edges.
+fn circles(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("circles");
+    let center = Point::new(size * 0.5 + 0.3, size * 0.5 + 0.7);
+    for ix in 0..12 {
+        let radius = size * 0.45 * (1.0 - ix as f64 / 12.0);
+        let circle = Circle::new(center, radius);
+        let brush = Brush::Solid(palette(ix));
+        drawing.fill(Fill::NonZero, Affine::IDENTITY, brush, &circle);
+    }
+    drawing
+}
+
+/// Lines fanning out from a corner with widths from a quarter pixel to a few pixels, which
+/// show the differences in antialiasing most clearly.
+fn thin_lines(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("thin_lines");
+    let origin = Point::new(size * 0.05, size * 0.05);
+    for ix in 0..24 {
+        let angle = ix as f64 / 23.0 * PI * 0.5;
+        let end = origin + (angle.cos() * size * 0.9, angle.sin() * size * 0.9);
+        let stroke = Stroke::new(0.25 + ix as f64 * 0.125);
+        let line = vello::kurbo::Line::new(origin, end);
+        drawing.stroke(stroke, Affine::IDENTITY, Brush::Solid(Color::BLACK), &line);
+    }
+    drawing
+}
+
+/// Cubic strokes with each join and cap.
+fn curves(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("curves");
+    let styles = [
+        (Join::Bevel, Cap::Butt),
+        (Join::Miter, Cap::Square),
+        (Join::Round, Cap::Round),
+    ];
+    for (ix, (join, cap)) in styles.into_iter().enumerate() {
+        let y = size * (0.2 + ix as f64 * 0.3);
+        let mut path = BezPath::new();
+        path.move_to((size * 0.1, y));
+        path.curve_to(
+            (size * 0.3, y - size * 0.2),
+            (size * 0.5, y + size * 0.2),
+            (size * 0.6, y),
+        );
+        path.line_to((size * 0.9, y - size * 0.1));
+        path.line_to((size * 0.75, y + size * 0.05));
+        let stroke = Stroke::new(size * 0.03).with_join(join).with_caps(cap);
+        drawing.stroke(stroke, Affine::IDENTITY, Brush::Solid(palette(ix)), &path);
+    }
+    drawing
+}
+
+/// Self-intersecting stars with each fill rule.
+fn fill_rules(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("fill_rules");
+    for (ix, fill) in [Fill::NonZero, Fill::EvenOdd].into_iter().enumerate() {
+        let center = Point::new(size * (0.27 + ix as f64 * 0.46), size * 0.5);
+        let mut star = BezPath::new();
+        for point in 0..7 {
+            let angle = point as f64 * TAU * 3.0 / 7.0 - PI * 0.5;
+            let p = center + (angle.cos() * size * 0.22, angle.sin() * size * 0.22);
+            if point == 0 {
+                star.move_to(p);
+            } else {
+                star.line_to(p);
+            }
+        }
+        star.close_path();
+        drawing.fill(fill, Affine::IDENTITY, Brush::Solid(palette(ix)), &star);
+    }
+    drawing
+}
+
+/// Small rectangles offset by fractions of a pixel, like the stems of small text.
+fn subpixel_rects(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("subpixel_rects");
+    let cell = size / 16.0;
+    for row in 0..16 {
+        for col in 0..16 {
+            let x = col as f64 * cell + col as f64 / 16.0;
+            let y = row as f64 * cell + row as f64 / 16.0;
+            let rect = Rect::new(x, y, x + 1.0 + col as f64 * 0.1, y + cell * 0.6);
+            drawing.fill(
+                Fill::NonZero,
+                Affine::IDENTITY,
+                Brush::Solid(Color::BLACK),
+                &rect,
+            );
+        }
+    }
+    drawing
+}
+
+/// Overlapping translucent layers, clipped to circles.
+fn layers(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("layers");
+    for ix in 0..3 {
+        let angle = ix as f64 * TAU / 3.0;
+        let center = Point::new(size * 0.5, size * 0.5) + (angle.cos(), angle.sin());
+        let center = center + (angle.cos() * size * 0.15, angle.sin() * size * 0.15);
+        drawing.ops.push(Op::PushLayer {
+            alpha: 0.6,
+            transform: Affine::IDENTITY,
+            clip: Circle::new(center, size * 0.3).to_path(0.1),
+        });
+        let rect = Rect::new(0.0, 0.0, size, size);
+        drawing.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            Brush::Solid(palette(ix)),
+            &rect,
+        );
+        drawing.ops.push(Op::PopLayer);
+    }
+    drawing
+}
+
+/// Linear gradients with two and several stops.
+fn gradients(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("gradients");
+    let top = Rect::new(size * 0.05, size * 0.05, size * 0.95, size * 0.45);
+    let brush = Brush::Linear(
+        Point::new(top.x0, 0.0),
+        Point::new(top.x1, 0.0),
+        vec![(0.0, Color::BLACK), (1.0, Color::WHITE)],
+    );
+    drawing.fill(Fill::NonZero, Affine::IDENTITY, brush, &top);
+    let bottom = Rect::new(size * 0.05, size * 0.55, size * 0.95, size * 0.95);
+    let brush = Brush::Linear(
+        Point::new(bottom.x0, bottom.y0),
+        Point::new(bottom.x1, bottom.y1),
+        (0..5).map(|ix| (ix as f32 / 4.0, palette(ix))).collect(),
+    );
+    drawing.fill(Fill::NonZero, Affine::IDENTITY, brush, &bottom);
+    drawing
+}
+
+/// Rotated and skewed squares and their outlines.
+fn transforms(size: f64) -> Drawing {
+    let mut drawing = Drawing::new("transforms");
+    let square = Rect::new(-0.1, -0.1, 0.1, 0.1).scale_from_origin(size);
+    for ix in 0..4 {
+        let center = Point::new(
+            size * (0.27 + (ix % 2) as f64 * 0.46),
+            size * (0.27 + (ix / 2) as f64 * 0.46),
+        );
+        let transform = Affine::translate(center.to_vec2())
+            * Affine::rotate(ix as f64 * 0.3)
+            * Affine::skew(ix as f64 * 0.2, 0.0);
+        drawing.fill(Fill::NonZero, transform, Brush::Solid(palette(ix)), &square);
+        let stroke = Stroke::new(1.5);
+        drawing.stroke(stroke, transform, Brush::Solid(Color::BLACK), &square);
+    }
+    drawing
+}
+
+/// A grid of clipped cells inside a clip of the whole drawing, with enough clips that the
+/// clip stages run more than 256 workgroups. The cells are offset by the outer clip, so the
+/// push and pop of a cell straddle the boundaries between workgroups.
+fn many_clips(size: f64) -> Drawing {
+    const N: usize = 190;
+    let mut drawing = Drawing::new("many_clips");
+    drawing.ops.push(Op::PushLayer {
+        alpha: 1.0,
+        transform: Affine::IDENTITY,
+        clip: Rect::new(0.0, 0.0, size, size).to_path(0.1),
+    });
+    let cell = size / N as f64;
+    for row in 0..N {
+        for col in 0..N {
+            let (x, y) = (col as f64 * cell, row as f64 * cell);
+            let rect = Rect::new(x, y, x + cell, y + cell);
+            drawing.ops.push(Op::PushLayer {
+                alpha: 1.0,
+                transform: Affine::IDENTITY,
+                clip: rect.to_path(0.1),
+            });
+            let brush = Brush::Solid(palette(row + col));
+            drawing.fill(
+                Fill::NonZero,
+                Affine::IDENTITY,
+                brush,
+                &rect.inflate(1.0, 1.0),
+            );
+            drawing.ops.push(Op::PopLayer);
+        }
+    }
+    drawing.ops.push(Op::PopLayer);
+    drawing
+}
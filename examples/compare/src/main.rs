@@ -0,0 +1,212 @@
+//! Renders the same scenes with Vello on the GPU and with tiny-skia on the CPU, and reports how
+//! much they differ.
+//!
+//! This is useful both for validating Vello against an established renderer, and for
+//! quantifying the differences between Vello's antialiasing methods and analytic coverage.
+
+mod diff;
+mod drawing;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::{Parser, ValueEnum};
+use vello::{
+    block_on_wgpu,
+    peniko::Color,
+    util::{read_texture, DeviceHandle, RenderContext},
+    AaConfig, RenderParams, Renderer, RendererOptions, Scene, SceneBuilder,
+};
+use wgpu::{Extent3d, TextureDescriptor, TextureFormat, TextureUsages};
+
+use drawing::Drawing;
+
+#[derive(Parser, Debug)]
+#[command(about, long_about = None, bin_name = "cargo run -p compare --")]
+struct Args {
+    #[arg(long, default_value_t = 256)]
+    /// The width and height of the images
+    size: u32,
+    #[arg(long, value_enum, default_value_t = Aa::Area)]
+    /// The antialiasing method to render with in Vello
+    aa: Aa,
+    #[arg(long)]
+    /// Only compare the scenes whose name contains this
+    scene: Option<String>,
+    #[arg(long)]
+    /// Directory to write the images of each renderer and their differences to
+    out_directory: Option<PathBuf>,
+    #[arg(long)]
+    /// Exit with an error if the mean ΔE of any scene is above this
+    fail_above: Option<f64>,
+    #[arg(long)]
+    /// Whether to use CPU shaders
+    use_cpu: bool,
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Aa {
+    Area,
+    Msaa8,
+    Msaa16,
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+    let drawings: Vec<_> = drawing::drawings(args.size as f64)
+        .into_iter()
+        .filter(|drawing| {
+            args.scene
+                .as_ref()
+                .map_or(true, |filter| drawing.name.contains(filter.as_str()))
+        })
+        .collect();
+    if drawings.is_empty() {
+        bail!("No scenes match {:?}", args.scene.as_deref().unwrap_or(""));
+    }
+    if let Some(out_directory) = &args.out_directory {
+        std::fs::create_dir_all(out_directory)
+            .with_context(|| format!("Failed to create {out_directory:?}"))?;
+    }
+    pollster::block_on(compare(&drawings, &args))
+}
+
+async fn compare(drawings: &[Drawing], args: &Args) -> Result<()> {
+    let mut context = RenderContext::new()
+        .or_else(|_| bail!("Got non-Send/Sync error from creating render context"))?;
+    let device_id = context
+        .device(None)
+        .await
+        .ok_or_else(|| anyhow!("No compatible device found"))?;
+    let device_handle = &mut context.devices[device_id];
+    let mut renderer = Renderer::new(
+        &device_handle.device,
+        &RendererOptions {
+            surface_format: None,
+            timestamp_period: device_handle.queue.get_timestamp_period(),
+            use_cpu: args.use_cpu,
+            num_init_threads: None,
+            memory_budget_bytes: None,
+            compact_tiles: false,
+            antialiasing_method: match args.aa {
+                Aa::Area => AaConfig::Area,
+                Aa::Msaa8 => AaConfig::Msaa8,
+                Aa::Msaa16 => AaConfig::Msaa16,
+            },
+            linear_blending: false,
+            dithering: false,
+            output_format: TextureFormat::Rgba8Unorm,
+            split_submissions: false,
+            single_pass_scan: false,
+            shader_source: None,
+        },
+    )
+    .or_else(|_| bail!("Got non-Send/Sync error from creating renderer"))?;
+    println!(
+        "{:<16} {:>9} {:>9} {:>10} {:>9} {:>9} {:>10}",
+        "scene", "mean |d|", "max |d|", "differing", "mean ΔE", "max ΔE", "noticeable"
+    );
+    let mut failed = vec![];
+    for drawing in drawings {
+        let size = args.size;
+        let vello = render_vello(device_handle, &mut renderer, drawing, size)?;
+        let tiny_skia = render_tiny_skia(drawing, size)?;
+        let diff = diff::diff(&vello, &tiny_skia);
+        println!(
+            "{:<16} {:>9.3} {:>9} {:>9.2}% {:>9.3} {:>9.3} {:>9.2}%",
+            drawing.name,
+            diff.mean_channel,
+            diff.max_channel,
+            diff.differing_pixels * 100.0,
+            diff.mean_delta_e,
+            diff.max_delta_e,
+            diff.noticeable_pixels * 100.0,
+        );
+        if let Some(out_directory) = &args.out_directory {
+            let images = [
+                ("vello", &vello),
+                ("tiny_skia", &tiny_skia),
+                ("diff", &diff.image),
+            ];
+            for (renderer, data) in images {
+                let path = out_directory.join(format!("{}_{renderer}.png", drawing.name));
+                write_png(&path, size, data)?;
+            }
+        }
+        if args
+            .fail_above
+            .map_or(false, |limit| diff.mean_delta_e > limit)
+        {
+            failed.push(drawing.name);
+        }
+    }
+    println!(
+        "Differing pixels have a channel differing by more than rounding; noticeable pixels have a ΔE above {}",
+        diff::JND
+    );
+    if !failed.is_empty() {
+        bail!("The mean ΔE of {} is above the limit", failed.join(", "));
+    }
+    Ok(())
+}
+
+fn render_vello(
+    device_handle: &DeviceHandle,
+    renderer: &mut Renderer,
+    drawing: &Drawing,
+    size: u32,
+) -> Result<Vec<u8>> {
+    let device = &device_handle.device;
+    let queue = &device_handle.queue;
+    let mut scene = Scene::new();
+    let mut builder = SceneBuilder::for_scene(&mut scene);
+    drawing.draw_vello(&mut builder);
+    let target = device.create_texture(&TextureDescriptor {
+        label: Some("Target texture"),
+        size: Extent3d {
+            width: size,
+            height: size,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::STORAGE_BINDING | TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    let params = RenderParams {
+        base_color: Color::WHITE,
+        width: size,
+        height: size,
+    };
+    block_on_wgpu(
+        device,
+        renderer.render_to_texture_async(device, queue, &scene, &view, &params),
+    )
+    .or_else(|_| bail!("Got non-Send/Sync error from rendering"))?;
+    read_texture(device, queue, &target).map_err(|e| anyhow!("Failed to read back the target: {e}"))
+}
+
+fn render_tiny_skia(drawing: &Drawing, size: u32) -> Result<Vec<u8>> {
+    let mut pixmap =
+        tiny_skia::Pixmap::new(size, size).ok_or_else(|| anyhow!("Invalid size {size}"))?;
+    pixmap.fill(tiny_skia::Color::WHITE);
+    drawing.draw_tiny_skia(&mut pixmap);
+    // The pixmap is premultiplied, but opaque over the white background.
+    Ok(pixmap.take())
+}
+
+fn write_png(path: &Path, size: u32, data: &[u8]) -> Result<()> {
+    let mut file = File::create(path).with_context(|| format!("Failed to create {path:?}"))?;
+    let mut encoder = png::Encoder::new(&mut file, size, size);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(data)?;
+    writer.finish()?;
+    Ok(())
+}
@@ -10,7 +10,7 @@ use vello::{
     block_on_wgpu,
     kurbo::{Affine, Vec2},
     util::RenderContext,
-    RendererOptions, Scene, SceneBuilder, SceneFragment,
+    AaConfig, RendererOptions, Scene, SceneBuilder, SceneFragment,
 };
 use wgpu::{
     BufferDescriptor, BufferUsages, CommandEncoderDescriptor, Extent3d, ImageCopyBuffer,
@@ -91,6 +91,16 @@ async fn render(mut scenes: SceneSet, index: usize, args: &Args) -> Result<()> {
             surface_format: None,
             timestamp_period: queue.get_timestamp_period(),
             use_cpu: false,
+            num_init_threads: None,
+            memory_budget_bytes: None,
+            compact_tiles: false,
+            antialiasing_method: AaConfig::Area,
+            linear_blending: false,
+            dithering: false,
+            output_format: wgpu::TextureFormat::Rgba8Unorm,
+            split_submissions: false,
+            single_pass_scan: false,
+            shader_source: None,
         },
     )
     .or_else(|_| bail!("Got non-Send/Sync error from creating renderer"))?;
@@ -163,6 +173,35 @@ async fn render(mut scenes: SceneSet, index: usize, args: &Args) -> Result<()> {
     renderer
         .render_to_texture(device, queue, &scene, &view, &render_params)
         .or_else(|_| bail!("Got non-Send/Sync error from rendering"))?;
+    if args.capture {
+        let capture = block_on_wgpu(
+            device,
+            renderer.capture_frame(device, queue, &scene, &view, &render_params),
+        )
+        .or_else(|_| bail!("Got non-Send/Sync error from capturing"))?;
+        let capture_dir = args
+            .out_directory
+            .join(format!("{}_capture", example_scene.config.name));
+        capture.write_to_dir(&capture_dir)?;
+        println!("Wrote intermediate buffers to {capture_dir:?}");
+    }
+    if args.step {
+        let capture = block_on_wgpu(
+            device,
+            renderer.step_frame(device, queue, &scene, &view, &render_params, |step| {
+                println!("{}: {} ({})", step.index, step.stage, step.bound.join(", "));
+                Ok(())
+            }),
+        )
+        .or_else(|_| bail!("Got non-Send/Sync error from stepping through the frame"))?;
+        let mismatches = capture
+            .compare_with_cpu()
+            .or_else(|_| bail!("Got non-Send/Sync error from comparing with the CPU stages"))?;
+        for mismatch in &mismatches {
+            println!("{mismatch}");
+        }
+        println!("{} buffers differ from the CPU stages", mismatches.len());
+    }
     // (width * 4).next_multiple_of(256)
     let padded_byte_width = {
         let w = width * 4;
@@ -245,6 +284,14 @@ struct Args {
     #[arg(long, short, global(false))]
     /// Display a list of all scene names
     print_scenes: bool,
+    #[arg(long, global(false))]
+    /// Also write the intermediate buffers of the pipeline for the frame, each as raw data
+    /// and as text, to a directory next to the result
+    capture: bool,
+    #[arg(long, global(false))]
+    /// Also render the frame one dispatch at a time, printing the buffers bound to each, then
+    /// compare the buffers with the CPU implementations of the stages
+    step: bool,
     #[command(flatten)]
     args: scenes::Arguments,
 }
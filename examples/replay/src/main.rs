@@ -0,0 +1,148 @@
+//! Replays a trace of scenes recorded with `vello::TraceWriter`, such as by the `--trace`
+//! option of the with_winit example.
+//!
+//! Each frame is rendered offscreen as fast as possible, and waited for before the next one,
+//! so that the workload of an application can be profiled without running it.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context, Result};
+use clap::Parser;
+use vello::{
+    block_on_wgpu, util::RenderContext, AaConfig, RendererOptions, TraceFrame, TraceReader,
+};
+use wgpu::{Device, Extent3d, TextureDescriptor, TextureFormat, TextureUsages, TextureView};
+
+#[derive(Parser, Debug)]
+#[command(about, long_about = None, bin_name = "cargo run -p replay --")]
+struct Args {
+    /// The trace to replay
+    trace: PathBuf,
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    /// How many times to replay the trace
+    iterations: u32,
+    #[arg(long)]
+    /// Whether to use CPU shaders
+    use_cpu: bool,
+}
+
+fn main() -> Result<()> {
+    env_logger::init();
+    let args = Args::parse();
+    let file =
+        File::open(&args.trace).with_context(|| format!("Failed to open {:?}", args.trace))?;
+    // The whole trace is read up front, so that reading it isn't timed.
+    let frames = TraceReader::new(BufReader::new(file))?
+        .collect::<std::io::Result<Vec<_>>>()
+        .context("Failed to read the trace")?;
+    // Frames of a minimized window have nothing to render to.
+    let frames: Vec<_> = frames
+        .into_iter()
+        .filter(|frame| frame.params.width != 0 && frame.params.height != 0)
+        .collect();
+    if frames.is_empty() {
+        bail!("The trace has no frames to replay");
+    }
+    pollster::block_on(replay(&frames, &args))
+}
+
+async fn replay(frames: &[TraceFrame], args: &Args) -> Result<()> {
+    let mut context = RenderContext::new()
+        .or_else(|_| bail!("Got non-Send/Sync error from creating render context"))?;
+    let device_id = context
+        .device(None)
+        .await
+        .ok_or_else(|| anyhow!("No compatible device found"))?;
+    let device_handle = &mut context.devices[device_id];
+    let device = &device_handle.device;
+    let queue = &device_handle.queue;
+    let mut renderer = vello::Renderer::new(
+        device,
+        &RendererOptions {
+            surface_format: None,
+            timestamp_period: queue.get_timestamp_period(),
+            use_cpu: args.use_cpu,
+            num_init_threads: None,
+            memory_budget_bytes: None,
+            compact_tiles: false,
+            antialiasing_method: AaConfig::Area,
+            linear_blending: false,
+            dithering: false,
+            output_format: TextureFormat::Rgba8Unorm,
+            split_submissions: false,
+            single_pass_scan: false,
+            shader_source: None,
+        },
+    )
+    .or_else(|_| bail!("Got non-Send/Sync error from creating renderer"))?;
+    // The window may have been resized while recording, so there is a target for each size.
+    let mut targets = HashMap::new();
+    for frame in frames {
+        let size = (frame.params.width, frame.params.height);
+        targets
+            .entry(size)
+            .or_insert_with(|| create_target(device, size));
+    }
+    let mut frame_times = vec![];
+    let start = Instant::now();
+    for _ in 0..args.iterations {
+        for frame in frames {
+            let frame_start = Instant::now();
+            let target = &targets[&(frame.params.width, frame.params.height)];
+            block_on_wgpu(
+                device,
+                renderer.render_to_texture_async(
+                    device,
+                    queue,
+                    &frame.scene,
+                    target,
+                    &frame.params,
+                ),
+            )
+            .or_else(|_| bail!("Got non-Send/Sync error from rendering"))?;
+            device.poll(wgpu::Maintain::Wait);
+            frame_times.push(frame_start.elapsed());
+        }
+    }
+    let total = start.elapsed();
+    frame_times.sort();
+    println!(
+        "Replayed {} frames {} times in {total:.2?}",
+        frames.len(),
+        args.iterations
+    );
+    println!(
+        "Frame time: mean {:.2?}, median {:.2?}, min {:.2?}, max {:.2?}",
+        total / frame_times.len() as u32,
+        frame_times[frame_times.len() / 2],
+        frame_times[0],
+        frame_times[frame_times.len() - 1],
+    );
+    println!(
+        "{:.1} frames per second",
+        frame_times.len() as f64 / total.as_secs_f64()
+    );
+    Ok(())
+}
+
+fn create_target(device: &Device, (width, height): (u32, u32)) -> TextureView {
+    let target = device.create_texture(&TextureDescriptor {
+        label: Some("Replay target"),
+        size: Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: TextureUsages::STORAGE_BINDING,
+        view_formats: &[],
+    });
+    target.create_view(&wgpu::TextureViewDescriptor::default())
+}
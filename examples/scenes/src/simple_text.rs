@@ -19,7 +19,7 @@ use std::sync::Arc;
 use vello::{
     fello::meta::MetadataProvider,
     fello::raw::FontRef,
-    glyph::{Glyph, GlyphContext},
+    glyph::{font_ref, Glyph, GlyphContext},
     kurbo::Affine,
     peniko::{Blob, Brush, BrushRef, Font, StyleRef},
     SceneBuilder,
@@ -90,7 +90,7 @@ impl SimpleText {
             &self.inconsolata
         };
         let font = font.unwrap_or(default_font);
-        let font_ref = to_font_ref(font).unwrap();
+        let font_ref = font_ref(font).unwrap();
         let brush = brush.into();
         let style = style.into();
         let axes = font_ref.axes();
@@ -142,7 +142,7 @@ impl SimpleText {
         text: &str,
     ) {
         let default_font = FontRef::new(ROBOTO_FONT).unwrap();
-        let font = font.and_then(to_font_ref).unwrap_or(default_font);
+        let font = font.and_then(font_ref).unwrap_or(default_font);
         let fello_size = vello::fello::Size::new(size);
         let charmap = font.charmap();
         let metrics = font.metrics(fello_size, Default::default());
@@ -170,12 +170,3 @@ impl SimpleText {
         }
     }
 }
-
-fn to_font_ref(font: &Font) -> Option<FontRef<'_>> {
-    use vello::fello::raw::FileRef;
-    let file_ref = FileRef::new(font.data.as_ref()).ok()?;
-    match file_ref {
-        FileRef::Font(font) => Some(font),
-        FileRef::Collection(collection) => collection.get(font.index).ok(),
-    }
-}
@@ -43,6 +43,9 @@ pub fn test_scenes() -> SceneSet {
         scene!(clip_test: animated),
         scene!(longpathdash(Cap::Butt), "longpathdash (butt caps)", false),
         scene!(longpathdash(Cap::Round), "longpathdash (round caps)", false),
+        scene!(many_draw_objects),
+        scene!(filters),
+        scene!(tapered_strokes),
     ];
 
     SceneSet { scenes }
@@ -858,6 +861,106 @@ fn base_color_test(sb: &mut SceneBuilder, params: &mut SceneParams) {
     );
 }
 
+fn many_draw_objects(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    // Enough draw objects to need more than 256 workgroups in the draw stages, which
+    // exercises the workgroup prefix reduction beyond a single reduction step.
+    const N: usize = 320;
+    const SIZE: f64 = 3.0;
+    for y in 0..N {
+        for x in 0..N {
+            let color = Color::rgb8((x * 255 / N) as u8, (y * 255 / N) as u8, 128);
+            let (x0, y0) = (x as f64 * SIZE, y as f64 * SIZE);
+            sb.fill(
+                Fill::NonZero,
+                Affine::IDENTITY,
+                color,
+                None,
+                &Rect::new(x0, y0, x0 + SIZE - 1.0, y0 + SIZE - 1.0),
+            );
+        }
+    }
+}
+
+fn filters(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    // A card casting a shadow, as drawn by UI toolkits.
+    let card = kurbo::RoundedRect::new(0.0, 0.0, 300.0, 200.0, 16.0);
+    let mut fragment = SceneFragment::new();
+    let mut builder = SceneBuilder::for_fragment(&mut fragment);
+    builder.fill(Fill::NonZero, Affine::IDENTITY, Color::WHITE, None, &card);
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(0xe0, 0xe0, 0xe8),
+        None,
+        &Rect::new(0.0, 0.0, 1000.0, 600.0),
+    );
+    sb.draw_filtered(
+        vello::Filter::DropShadow {
+            std_dev: 8.0,
+            offset: kurbo::Vec2::new(0.0, 6.0),
+            color: Color::rgba8(0, 0, 0, 96),
+        },
+        Affine::translate((100.0, 100.0)),
+        card.rect(),
+        &fragment,
+    );
+    // Blurred shapes of increasing standard deviation.
+    let mut fragment = SceneFragment::new();
+    let mut builder = SceneBuilder::for_fragment(&mut fragment);
+    builder.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(0x40, 0x80, 0xff),
+        None,
+        &Ellipse::new((50.0, 50.0), (50.0, 50.0), 0.0),
+    );
+    for (i, std_dev) in [0.0, 2.0, 5.0, 10.0].into_iter().enumerate() {
+        sb.draw_filtered(
+            vello::Filter::Blur(std_dev),
+            Affine::translate((
+                500.0 + 120.0 * (i % 2) as f64,
+                100.0 + 150.0 * (i / 2) as f64,
+            )),
+            Rect::new(0.0, 0.0, 100.0, 100.0),
+            &fragment,
+        );
+    }
+}
+
+fn tapered_strokes(sb: &mut SceneBuilder, _: &mut SceneParams) {
+    // A brush stroke which swells in the middle and tapers to a point at both ends.
+    let mut path = BezPath::new();
+    path.move_to((100.0, 300.0));
+    path.curve_to((200.0, 100.0), (300.0, 100.0), (400.0, 300.0));
+    path.curve_to((500.0, 500.0), (600.0, 500.0), (700.0, 300.0));
+    sb.stroke_with_widths(
+        &Stroke::new(1.0).with_caps(Cap::Round),
+        Affine::IDENTITY,
+        Color::rgb8(0x20, 0x20, 0x40),
+        None,
+        &path,
+        &[0.0, 40.0, 0.0],
+    );
+    // A closed zigzag with miter joins, which taper from one corner to the next.
+    let mut zigzag = BezPath::new();
+    zigzag.move_to((100.0, 600.0));
+    let mut widths = vec![2.0];
+    for i in 1..8 {
+        let y = if i % 2 == 0 { 600.0 } else { 500.0 };
+        zigzag.line_to((100.0 + 80.0 * i as f64, y));
+        widths.push(2.0 + 4.0 * i as f64);
+    }
+    zigzag.close_path();
+    sb.stroke_with_widths(
+        &Stroke::new(1.0).with_join(kurbo::Join::Miter),
+        Affine::translate((0.0, 150.0)),
+        Color::rgb8(0xc0, 0x40, 0x40),
+        None,
+        &zigzag,
+        &widths,
+    );
+}
+
 fn clip_test(sb: &mut SceneBuilder, params: &mut SceneParams) {
     let clip = {
         const X0: f64 = 50.0;
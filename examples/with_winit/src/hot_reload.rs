@@ -1,4 +1,4 @@
-use std::{path::Path, time::Duration};
+use std::time::Duration;
 
 use anyhow::Result;
 use notify_debouncer_mini::{new_debouncer, notify::*, DebounceEventResult};
@@ -14,11 +14,9 @@ pub(crate) fn hot_reload(mut f: impl FnMut() -> Option<()> + Send + 'static) ->
     )?;
 
     debouncer.watcher().watch(
-        &Path::new(env!("CARGO_MANIFEST_DIR"))
-            .join("../../shader")
-            .canonicalize()?,
-        // We currently don't support hot reloading the imports, so don't recurse into there
-        RecursiveMode::NonRecursive,
+        &vello::shader_dir().canonicalize()?,
+        // Recurse so that changes to the shared imports also trigger a reload
+        RecursiveMode::Recursive,
     )?;
     Ok(debouncer)
 }
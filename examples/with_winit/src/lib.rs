@@ -14,20 +14,19 @@
 //
 // Also licensed under MIT license, at your choice.
 
-use instant::{Duration, Instant};
+use instant::Instant;
 use std::collections::HashSet;
 
 use anyhow::Result;
 use clap::{CommandFactory, Parser};
 use scenes::{ImageCache, SceneParams, SceneSet, SimpleText};
 use vello::peniko::Color;
-use vello::util::RenderSurface;
 use vello::{
     kurbo::{Affine, Vec2},
+    pacing::{FramePacer, Vsync},
     util::RenderContext,
-    Renderer, Scene, SceneBuilder,
+    Renderer, Scene, SceneBuilder, SceneFragment,
 };
-use vello::{BumpAllocators, RendererOptions, SceneFragment};
 
 use winit::{
     event_loop::{EventLoop, EventLoopBuilder},
@@ -37,7 +36,10 @@ use winit::{
 #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
 mod hot_reload;
 mod multi_touch;
-mod stats;
+pub mod stats;
+pub mod util;
+
+use util::{RenderState, StatsOverlay};
 
 #[derive(Parser, Debug)]
 #[command(about, long_about = None, bin_name="cargo run -p with_winit --")]
@@ -51,13 +53,10 @@ struct Args {
     #[arg(long)]
     /// Whether to use CPU shaders
     use_cpu: bool,
-}
-
-struct RenderState {
-    // SAFETY: We MUST drop the surface before the `window`, so the fields
-    // must be in this order
-    surface: RenderSurface,
-    window: Window,
+    #[arg(long)]
+    /// Record the scene of every frame to a trace at this path, which can be replayed with
+    /// the replay example
+    trace: Option<std::path::PathBuf>,
 }
 
 fn run(
@@ -83,15 +82,8 @@ fn run(
         renderers.resize_with(render_cx.devices.len(), || None);
         let id = render_state.surface.dev_id;
         renderers[id] = Some(
-            Renderer::new(
-                &render_cx.devices[id].device,
-                &RendererOptions {
-                    surface_format: Some(render_state.surface.format),
-                    timestamp_period: render_cx.devices[id].queue.get_timestamp_period(),
-                    use_cpu: use_cpu,
-                },
-            )
-            .expect("Could create renderer"),
+            util::create_renderer(&render_cx, &render_state.surface, use_cpu)
+                .expect("Could create renderer"),
         );
         Some(render_state)
     };
@@ -104,15 +96,15 @@ fn run(
     let mut fragment = SceneFragment::new();
     let mut simple_text = SimpleText::new();
     let mut images = ImageCache::new();
-    let mut stats = stats::Stats::new();
-    let mut stats_shown = true;
-    // Currently not updated in wasm builds
-    #[allow(unused_mut)]
-    let mut scene_complexity: Option<BumpAllocators> = None;
-    let mut complexity_shown = false;
-    let mut vsync_on = true;
-    let mut frame_start_time = Instant::now();
+    // The buffer occupancy is currently not updated in wasm builds
+    let mut stats = StatsOverlay::new();
+    let mut frame_pacer = FramePacer::new(Vsync::On);
     let start = Instant::now();
+    #[cfg(not(target_arch = "wasm32"))]
+    let mut trace = args.trace.as_ref().map(|path| {
+        let file = std::fs::File::create(path).expect("failed to create trace file");
+        vello::TraceWriter::new(std::io::BufWriter::new(file)).expect("failed to write trace")
+    });
 
     let mut touch_state = multi_touch::TouchState::new();
     // navigation_fingers are fingers which are used in the navigation 'zone' at the bottom
@@ -127,9 +119,7 @@ fn run(
     if let Some(set_scene) = args.scene {
         scene_ix = set_scene;
     }
-    let mut profile_stored = None;
     let mut prev_scene_ix = scene_ix - 1;
-    let mut profile_taken = Instant::now();
     // _event_loop is used on non-wasm platforms to create new windows
     event_loop.run(move |event, _event_loop, control_flow| match event {
         Event::WindowEvent {
@@ -165,21 +155,17 @@ fn run(
                                 transform = Affine::IDENTITY;
                             }
                             Some(VirtualKeyCode::S) => {
-                                stats_shown = !stats_shown;
+                                stats.shown = !stats.shown;
                             }
                             Some(VirtualKeyCode::D) => {
-                                complexity_shown = !complexity_shown;
+                                stats.complexity_shown = !stats.complexity_shown;
                             }
                             Some(VirtualKeyCode::C) => {
-                                stats.clear_min_and_max();
+                                stats.stats.clear_min_and_max();
                             }
                             Some(VirtualKeyCode::P) => {
                                 if let Some(renderer) = &renderers[render_state.surface.dev_id] {
-                                    if let Some(profile_result) = &renderer
-                                        .profile_result
-                                        .as_ref()
-                                        .or(profile_stored.as_ref())
-                                    {
+                                    if let Some(profile_result) = stats.profile_result(renderer) {
                                         // There can be empty results if the required features aren't supported
                                         if !profile_result.is_empty() {
                                             let path = std::path::Path::new("trace.json");
@@ -196,16 +182,24 @@ fn run(
                                     }
                                 }
                             }
+                            Some(VirtualKeyCode::T) => {
+                                let dev_id = render_state.surface.dev_id;
+                                if let Some(renderer) = &mut renderers[dev_id] {
+                                    let enabled = !renderer.debug_overlay();
+                                    let device = &render_cx.devices[dev_id].device;
+                                    if let Err(e) = renderer.set_debug_overlay(device, enabled) {
+                                        eprintln!("Failed to toggle the debug overlay: {e}");
+                                    }
+                                }
+                            }
                             Some(VirtualKeyCode::V) => {
-                                vsync_on = !vsync_on;
-                                render_cx.set_present_mode(
-                                    &mut render_state.surface,
-                                    if vsync_on {
-                                        wgpu::PresentMode::AutoVsync
-                                    } else {
-                                        wgpu::PresentMode::AutoNoVsync
-                                    },
-                                );
+                                let vsync = if frame_pacer.vsync() == Vsync::Off {
+                                    Vsync::On
+                                } else {
+                                    Vsync::Off
+                                };
+                                let surface = &mut render_state.surface;
+                                frame_pacer.set_vsync(&render_cx, surface, vsync);
                             }
                             Some(VirtualKeyCode::Escape) => {
                                 *control_flow = ControlFlow::Exit;
@@ -226,17 +220,12 @@ fn run(
                             // in a touch context (i.e. Windows/Linux/MacOS with a touch screen could
                             // also be using mouse/keyboard controls)
                             // Note that winit's rendering is y-down
-                            if touch.location.y
-                                > render_state.surface.config.height as f64 * 2. / 3.
-                            {
+                            if touch.location.y > render_state.height() as f64 * 2. / 3. {
                                 navigation_fingers.insert(touch.id);
                                 // The left third of the navigation zone navigates backwards
-                                if touch.location.x < render_state.surface.config.width as f64 / 3.
-                                {
+                                if touch.location.x < render_state.width() as f64 / 3. {
                                     scene_ix = scene_ix.saturating_sub(1);
-                                } else if touch.location.x
-                                    > 2. * render_state.surface.config.width as f64 / 3.
-                                {
+                                } else if touch.location.x > 2. * render_state.width() as f64 / 3. {
                                     scene_ix = scene_ix.saturating_add(1);
                                 }
                             }
@@ -253,8 +242,7 @@ fn run(
                     }
                 }
                 WindowEvent::Resized(size) => {
-                    render_cx.resize_surface(&mut render_state.surface, size.width, size.height);
-                    render_state.window.request_redraw();
+                    render_state.resize(&render_cx, *size);
                 }
                 WindowEvent::MouseInput { state, button, .. } => {
                     if button == &MouseButton::Left {
@@ -317,10 +305,13 @@ fn run(
             let Some(render_state) = &mut render_state else {
                 return;
             };
-            let width = render_state.surface.config.width;
-            let height = render_state.surface.config.height;
-            let device_handle = &render_cx.devices[render_state.surface.dev_id];
-            let snapshot = stats.snapshot();
+            let width = render_state.width();
+            let height = render_state.height();
+            // Bound the frames queued on the GPU, so that the scene reflects recent input.
+            if !frame_pacer.begin_frame(&render_cx.devices[render_state.surface.dev_id].device) {
+                render_state.window.request_redraw();
+                return;
+            }
 
             // Allow looping forever
             scene_ix = scene_ix.rem_euclid(scenes.scenes.len() as i32);
@@ -332,6 +323,7 @@ fn run(
                     .window
                     .set_title(&format!("Vello demo - {}", example_scene.config.name));
             }
+            let encode_start = Instant::now();
             let mut builder = SceneBuilder::for_fragment(&mut fragment);
             let mut scene_params = SceneParams {
                 time: start.elapsed().as_secs_f64(),
@@ -367,44 +359,33 @@ fn run(
                 transform *= Affine::scale(scale_factor);
             }
             builder.append(&fragment, Some(transform));
-            if stats_shown {
-                snapshot.draw_layer(
-                    &mut builder,
-                    scene_params.text,
-                    width as f64,
-                    height as f64,
-                    stats.samples(),
-                    complexity_shown.then_some(scene_complexity).flatten(),
-                    vsync_on,
-                );
-                if let Some(profiling_result) = renderers[render_state.surface.dev_id]
-                    .as_mut()
-                    .and_then(|it| it.profile_result.take())
-                {
-                    if profile_stored.is_none() || profile_taken.elapsed() > Duration::from_secs(1)
-                    {
-                        profile_stored = Some(profiling_result);
-                        profile_taken = Instant::now();
-                    }
-                }
-                if let Some(profiling_result) = profile_stored.as_ref() {
-                    stats::draw_gpu_profiling(
-                        &mut builder,
-                        scene_params.text,
-                        width as f64,
-                        height as f64,
-                        profiling_result,
-                    )
+            stats.encode_time = encode_start.elapsed();
+            stats.draw(
+                &mut builder,
+                scene_params.text,
+                width as f64,
+                height as f64,
+                renderers[render_state.surface.dev_id].as_mut(),
+                frame_pacer.vsync() != Vsync::Off,
+            );
+            // The trace is flushed after each frame, as the event loop exits without dropping it.
+            #[cfg(not(target_arch = "wasm32"))]
+            if let Some(writer) = &mut trace {
+                let result = writer
+                    .write_frame(&scene, &render_params)
+                    .and_then(|_| writer.flush());
+                if let Err(err) = result {
+                    eprintln!("Stopped recording the trace: {err}");
+                    trace = None;
                 }
             }
-            let surface_texture = render_state
-                .surface
-                .surface
-                .get_current_texture()
-                .expect("failed to get surface texture");
+            let Some(surface_texture) = render_state.current_texture(&render_cx) else {
+                return;
+            };
+            let device_handle = &render_cx.devices[render_state.surface.dev_id];
             #[cfg(not(target_arch = "wasm32"))]
             {
-                scene_complexity = vello::block_on_wgpu(
+                vello::block_on_wgpu(
                     &device_handle.device,
                     renderers[render_state.surface.dev_id]
                         .as_mut()
@@ -436,11 +417,10 @@ fn run(
             surface_texture.present();
             device_handle.device.poll(wgpu::Maintain::Poll);
 
-            let new_time = Instant::now();
-            stats.add_sample(stats::Sample {
-                frame_time_us: (new_time - frame_start_time).as_micros() as u64,
+            let frame_time = frame_pacer.end_frame(&device_handle.queue);
+            stats.stats.add_sample(stats::Sample {
+                frame_time_us: frame_time.as_micros() as u64,
             });
-            frame_start_time = new_time;
         }
         Event::UserEvent(event) => match event {
             #[cfg(not(any(target_arch = "wasm32", target_os = "android")))]
@@ -480,27 +460,17 @@ fn run(
                 let window = cached_window
                     .take()
                     .unwrap_or_else(|| create_window(_event_loop));
-                let size = window.inner_size();
-                let surface_future = render_cx.create_surface(&window, size.width, size.height);
                 // We need to block here, in case a Suspended event appeared
-                let surface = pollster::block_on(surface_future).expect("Error creating surface");
+                let render_state_future = RenderState::new(&mut render_cx, window);
                 render_state = {
-                    let render_state = RenderState { window, surface };
+                    let render_state =
+                        pollster::block_on(render_state_future).expect("Error creating surface");
                     renderers.resize_with(render_cx.devices.len(), || None);
                     let id = render_state.surface.dev_id;
                     renderers[id].get_or_insert_with(|| {
                         eprintln!("Creating renderer {id}");
-                        Renderer::new(
-                            &render_cx.devices[id].device,
-                            &RendererOptions {
-                                surface_format: Some(render_state.surface.format),
-                                timestamp_period: render_cx.devices[id]
-                                    .queue
-                                    .get_timestamp_period(),
-                                use_cpu,
-                            },
-                        )
-                        .expect("Could create renderer")
+                        util::create_renderer(&render_cx, &render_state.surface, use_cpu)
+                            .expect("Could create renderer")
                     });
                     Some(render_state)
                 };
@@ -588,12 +558,8 @@ pub fn main() -> Result<()> {
                 .expect("couldn't append canvas to document body");
             _ = web_sys::HtmlElement::from(canvas).focus();
             wasm_bindgen_futures::spawn_local(async move {
-                let size = window.inner_size();
-                let surface = render_cx
-                    .create_surface(&window, size.width, size.height)
-                    .await;
-                if let Ok(surface) = surface {
-                    let render_state = RenderState { window, surface };
+                let render_state = RenderState::new(&mut render_cx, window).await;
+                if let Ok(render_state) = render_state {
                     // No error handling here; if the event loop has finished, we don't need to send them the surface
                     run(event_loop, args, scenes, render_cx, render_state);
                 } else {
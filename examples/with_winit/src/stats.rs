@@ -19,7 +19,7 @@ use std::{collections::VecDeque, time::Duration};
 use vello::{
     kurbo::{Affine, Line, PathEl, Rect, Stroke},
     peniko::{Brush, Color, Fill},
-    BumpAllocators, SceneBuilder,
+    FrameStats, SceneBuilder,
 };
 use wgpu_profiler::GpuTimerScopeResult;
 
@@ -42,7 +42,8 @@ impl Snapshot {
         viewport_width: f64,
         viewport_height: f64,
         samples: T,
-        bump: Option<BumpAllocators>,
+        encode_time: Duration,
+        frame_stats: Option<FrameStats>,
         vsync: bool,
     ) where
         T: Iterator<Item = &'a u64>,
@@ -66,18 +67,28 @@ impl Snapshot {
             format!("Frame Time: {:.2} ms", self.frame_time_ms),
             format!("Frame Time (min): {:.2} ms", self.frame_time_min_ms),
             format!("Frame Time (max): {:.2} ms", self.frame_time_max_ms),
+            format!("Encode Time: {:.2} ms", encode_time.as_secs_f64() * 1000.),
             format!("VSync: {}", if vsync { "on" } else { "off" }),
             format!("Resolution: {viewport_width}x{viewport_height}"),
         ];
-        if let Some(bump) = &bump {
-            if bump.failed >= 1 {
+        if let Some(frame_stats) = &frame_stats {
+            if frame_stats.overflowed() {
                 labels.push("Allocation Failed!".into());
             }
-            labels.push(format!("binning: {}", bump.binning));
-            labels.push(format!("ptcl: {}", bump.ptcl));
-            labels.push(format!("tile: {}", bump.tile));
-            labels.push(format!("segments: {}", bump.segments));
-            labels.push(format!("blend: {}", bump.blend));
+            let (used, capacity) = (&frame_stats.used, &frame_stats.capacity);
+            let buffers = [
+                ("binning", used.binning, capacity.binning),
+                ("ptcl", used.ptcl, capacity.ptcl),
+                ("tile", used.tile, capacity.tile),
+                ("seg_counts", used.seg_counts, capacity.seg_counts),
+                ("segments", used.segments, capacity.segments),
+                ("lines", used.lines, capacity.lines),
+                ("blend", used.blend, capacity.blend),
+            ];
+            for (name, used, capacity) in buffers {
+                let occupancy = used as f64 * 100. / capacity.max(1) as f64;
+                labels.push(format!("{name}: {used} / {capacity} ({occupancy:.0}%)"));
+            }
         }
 
         // height / 2 is dedicated to the text labels and the rest is filled by the bar graph.
@@ -245,6 +256,12 @@ impl Stats {
     }
 }
 
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 fn round_up(n: usize, f: usize) -> usize {
     n - 1 - (n - 1) % f + f
 }
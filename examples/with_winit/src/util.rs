@@ -0,0 +1,186 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Reusable parts of a winit application which renders with Vello: the surface and renderer
+//! of a window, resizing and the statistics overlay. Frame pacing is in `vello::pacing`.
+
+use instant::{Duration, Instant};
+use scenes::SimpleText;
+use vello::util::{RenderContext, RenderSurface};
+use vello::{AaConfig, Renderer, RendererOptions, SceneBuilder};
+use wgpu_profiler::GpuTimerScopeResult;
+use winit::{dpi::PhysicalSize, window::Window};
+
+use crate::stats::{self, Stats};
+
+/// A window and the surface which is presented to it.
+pub struct RenderState {
+    // SAFETY: We MUST drop the surface before the `window`, so the fields
+    // must be in this order
+    pub surface: RenderSurface,
+    pub window: Window,
+}
+
+impl RenderState {
+    /// Creates the surface of `window` at its current size.
+    pub async fn new(render_cx: &mut RenderContext, window: Window) -> vello::Result<Self> {
+        let size = window.inner_size();
+        let surface = render_cx
+            .create_surface(&window, size.width, size.height)
+            .await?;
+        Ok(Self { surface, window })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.surface.config.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.surface.config.height
+    }
+
+    /// Resizes the surface to the new size of the window and requests a redraw.
+    ///
+    /// Minimized windows are resized to zero, which a surface can't be configured with, so
+    /// the surface keeps its previous size until the window is restored.
+    pub fn resize(&mut self, render_cx: &RenderContext, size: PhysicalSize<u32>) {
+        if size.width == 0 || size.height == 0 {
+            return;
+        }
+        if (size.width, size.height) != (self.width(), self.height()) {
+            render_cx.resize_surface(&mut self.surface, size.width, size.height);
+        }
+        self.window.request_redraw();
+    }
+
+    /// Returns the next texture of the surface, or `None` if this frame should be skipped.
+    ///
+    /// A surface which is outdated or lost, as happens when the window is resized between
+    /// the resize event and the redraw, is reconfigured to the size of the window.
+    pub fn current_texture(&mut self, render_cx: &RenderContext) -> Option<wgpu::SurfaceTexture> {
+        match self.surface.surface.get_current_texture() {
+            Ok(texture) => Some(texture),
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                let size = self.window.inner_size();
+                if size.width != 0 && size.height != 0 {
+                    render_cx.resize_surface(&mut self.surface, size.width, size.height);
+                }
+                self.window.request_redraw();
+                None
+            }
+            Err(wgpu::SurfaceError::Timeout) => None,
+            Err(e) => panic!("failed to get surface texture: {e}"),
+        }
+    }
+}
+
+/// Creates a renderer for the device of `surface`.
+pub fn create_renderer(
+    render_cx: &RenderContext,
+    surface: &RenderSurface,
+    use_cpu: bool,
+) -> vello::Result<Renderer> {
+    let device_handle = &render_cx.devices[surface.dev_id];
+    Renderer::new(
+        &device_handle.device,
+        &RendererOptions {
+            surface_format: Some(surface.format),
+            timestamp_period: device_handle.queue.get_timestamp_period(),
+            use_cpu,
+            num_init_threads: None,
+            memory_budget_bytes: None,
+            compact_tiles: false,
+            antialiasing_method: AaConfig::Area,
+            linear_blending: false,
+            dithering: false,
+            output_format: wgpu::TextureFormat::Rgba8Unorm,
+            split_submissions: false,
+            single_pass_scan: device_handle.supports_single_pass_scan(),
+            shader_source: None,
+        },
+    )
+}
+
+/// Overlay of frame and encode times, buffer occupancy and GPU profiling results, drawn over
+/// the scene.
+pub struct StatsOverlay {
+    pub stats: Stats,
+    pub shown: bool,
+    /// Whether the occupancy of the bump allocated buffers is shown, when it is read back.
+    pub complexity_shown: bool,
+    /// Time taken to build the scene of the current frame.
+    pub encode_time: Duration,
+    profile_stored: Option<Vec<GpuTimerScopeResult>>,
+    profile_taken: Instant,
+}
+
+impl StatsOverlay {
+    pub fn new() -> Self {
+        Self {
+            stats: Stats::new(),
+            shown: true,
+            complexity_shown: false,
+            encode_time: Duration::ZERO,
+            profile_stored: None,
+            profile_taken: Instant::now(),
+        }
+    }
+
+    /// Returns the latest GPU profiling result of `renderer`, or the one shown in the overlay.
+    pub fn profile_result<'a>(
+        &'a self,
+        renderer: &'a Renderer,
+    ) -> Option<&'a Vec<GpuTimerScopeResult>> {
+        renderer
+            .profile_result
+            .as_ref()
+            .or(self.profile_stored.as_ref())
+    }
+
+    /// Draws the overlay in a viewport of `width` by `height`, if it is shown.
+    ///
+    /// The buffer occupancy shown is that of the last frame `renderer` read back the
+    /// allocations of.
+    /// The GPU profiling result shown is taken from `renderer` at most once per second.
+    pub fn draw(
+        &mut self,
+        builder: &mut SceneBuilder,
+        text: &mut SimpleText,
+        width: f64,
+        height: f64,
+        renderer: Option<&mut Renderer>,
+        vsync_on: bool,
+    ) {
+        if !self.shown {
+            return;
+        }
+        let frame_stats = renderer.as_ref().and_then(|it| it.last_frame_stats());
+        self.stats.snapshot().draw_layer(
+            builder,
+            text,
+            width,
+            height,
+            self.stats.samples(),
+            self.encode_time,
+            frame_stats.filter(|_| self.complexity_shown),
+            vsync_on,
+        );
+        if let Some(profiling_result) = renderer.and_then(|it| it.profile_result.take()) {
+            if self.profile_stored.is_none()
+                || self.profile_taken.elapsed() > Duration::from_secs(1)
+            {
+                self.profile_stored = Some(profiling_result);
+                self.profile_taken = Instant::now();
+            }
+        }
+        if let Some(profiling_result) = self.profile_stored.as_ref() {
+            stats::draw_gpu_profiling(builder, text, width, height, profiling_result);
+        }
+    }
+}
+
+impl Default for StatsOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,36 @@
+//! Encodes scenes on the main thread of a web page, and renders them in a web worker.
+//!
+//! The page transfers its canvas to the worker with `transferControlToOffscreen`, so that the
+//! worker owns the GPU device and the surface, and the main thread only builds scenes. Each
+//! frame is serialized with `vello::TraceWriter` and posted to the worker, which reads it back
+//! with `vello::TraceReader` and renders it. A new frame is only posted once the worker has
+//! rendered the previous one.
+//!
+//! Both threads run this binary: the worker loads the same wasm-bindgen output as the page, and
+//! `main` decides what to do based on the global scope it runs in.
+
+#[cfg(target_arch = "wasm32")]
+mod page;
+#[cfg(target_arch = "wasm32")]
+mod worker;
+
+#[cfg(target_arch = "wasm32")]
+fn main() {
+    use wasm_bindgen::JsCast;
+
+    std::panic::set_hook(Box::new(console_error_panic_hook::hook));
+    console_log::init().expect("could not initialize logger");
+    let global = js_sys::global();
+    if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+        if let Err(e) = page::run(window) {
+            log::error!("Failed to start the page: {e:?}");
+        }
+    } else if let Some(scope) = global.dyn_ref::<web_sys::DedicatedWorkerGlobalScope>() {
+        worker::run(scope);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    eprintln!("This example only runs on the web, with `cargo run_wasm -p with_worker`");
+}
@@ -0,0 +1,177 @@
+//! The main thread, which builds a scene for each animation frame and posts it to the worker.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::{Array, Uint8Array};
+use vello::kurbo::{Affine, Circle, Point, RoundedRect, Vec2};
+use vello::peniko::{Color, Fill};
+use vello::{RenderParams, Scene, SceneBuilder, TraceWriter};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{
+    Blob, BlobPropertyBag, HtmlCanvasElement, MessageEvent, OffscreenCanvas, Url, Window, Worker,
+    WorkerOptions, WorkerType,
+};
+
+#[wasm_bindgen]
+extern "C" {
+    /// The URL of the JavaScript glue generated by wasm-bindgen, which is the module this
+    /// accessor is generated in, wherever the glue is served from.
+    #[wasm_bindgen(js_namespace = ["import", "meta"], js_name = url)]
+    static GLUE_URL: String;
+}
+
+struct Page {
+    worker: Worker,
+    /// The canvas to transfer to the worker once it has started.
+    canvas: Option<OffscreenCanvas>,
+    width: u32,
+    height: u32,
+    /// Whether the worker has finished with the last frame posted to it.
+    idle: bool,
+    scene: Scene,
+}
+
+pub fn run(window: &Window) -> Result<(), JsValue> {
+    let document = window.document().ok_or("no document")?;
+    let canvas: HtmlCanvasElement = document.create_element("canvas")?.dyn_into()?;
+    let width = window.inner_width()?.as_f64().unwrap_or(800.0) as u32;
+    let height = window.inner_height()?.as_f64().unwrap_or(600.0) as u32;
+    canvas.set_width(width);
+    canvas.set_height(height);
+    document
+        .body()
+        .ok_or("no document body")?
+        .append_child(&canvas)?;
+    let canvas = canvas.transfer_control_to_offscreen()?;
+    let worker = spawn_worker()?;
+    let page = Rc::new(RefCell::new(Page {
+        worker: worker.clone(),
+        canvas: Some(canvas),
+        width,
+        height,
+        idle: false,
+        scene: Scene::new(),
+    }));
+
+    let onmessage = {
+        let page = page.clone();
+        Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let message = event.data().as_string().unwrap_or_default();
+            if let Err(e) = page.borrow_mut().on_message(&message) {
+                log::error!("Failed to transfer the canvas: {e:?}");
+            }
+        })
+    };
+    worker.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    vello::pacing::run_animation_frames(move |time| {
+        let mut page = page.borrow_mut();
+        if page.idle {
+            if let Err(e) = page.post_frame(time / 1000.0) {
+                log::error!("Failed to post a frame: {e:?}");
+            }
+        }
+        true
+    });
+    Ok(())
+}
+
+/// Starts a module worker which runs this binary, from the same glue as the page.
+///
+/// The worker is loaded from a blob, as the glue can't be started as a worker script directly.
+fn spawn_worker() -> Result<Worker, JsValue> {
+    let glue = GLUE_URL.as_str();
+    let script = format!("import init from \"{glue}\";\nawait init();\n");
+    let mut properties = BlobPropertyBag::new();
+    properties.type_("text/javascript");
+    let blob = Blob::new_with_str_sequence_and_options(
+        &Array::of1(&JsValue::from_str(&script)),
+        &properties,
+    )?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+    let mut options = WorkerOptions::new();
+    options.type_(WorkerType::Module);
+    Worker::new_with_options(&url, &options)
+}
+
+impl Page {
+    fn on_message(&mut self, message: &str) -> Result<(), JsValue> {
+        match message {
+            // The worker only listens for messages once it has loaded, so the canvas is only
+            // transferred then.
+            "started" => {
+                if let Some(canvas) = self.canvas.take() {
+                    self.worker
+                        .post_message_with_transfer(&canvas, &Array::of1(&canvas))?;
+                }
+            }
+            "ready" | "rendered" => self.idle = true,
+            // Errors also end the frame, so that the next one is still posted.
+            error => {
+                log::error!("{error}");
+                self.idle = true;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the scene at `time`, in seconds, and posts it to the worker.
+    fn post_frame(&mut self, time: f64) -> Result<(), JsValue> {
+        let mut sb = SceneBuilder::for_scene(&mut self.scene);
+        add_shapes(&mut sb, time, self.width, self.height);
+        let params = RenderParams {
+            base_color: Color::BLACK,
+            width: self.width,
+            height: self.height,
+        };
+        let mut bytes = Vec::new();
+        let mut writer = TraceWriter::new(&mut bytes).map_err(to_js)?;
+        writer.write_frame(&self.scene, &params).map_err(to_js)?;
+        writer.flush().map_err(to_js)?;
+        drop(writer);
+        let frame = Uint8Array::from(&bytes[..]);
+        self.worker
+            .post_message_with_transfer(&frame, &Array::of1(&frame.buffer()))?;
+        self.idle = false;
+        Ok(())
+    }
+}
+
+/// Draws rounded rectangles orbiting the center of the canvas.
+fn add_shapes(sb: &mut SceneBuilder, time: f64, width: u32, height: u32) {
+    let center = Point::new(width as f64 / 2.0, height as f64 / 2.0);
+    let radius = center.x.min(center.y) * 0.6;
+    sb.fill(
+        Fill::NonZero,
+        Affine::IDENTITY,
+        Color::rgb8(0x20, 0x20, 0x30),
+        None,
+        &Circle::new(center, radius * 1.4),
+    );
+    const COUNT: usize = 12;
+    for i in 0..COUNT {
+        let angle = time + i as f64 * std::f64::consts::TAU / COUNT as f64;
+        let position = center + Vec2::from_angle(angle) * radius;
+        let transform = Affine::translate(position.to_vec2()) * Affine::rotate(angle * 2.0);
+        let color = Color::rgba8(
+            (255.0 * i as f64 / COUNT as f64) as u8,
+            0x80,
+            (255.0 * (1.0 - i as f64 / COUNT as f64)) as u8,
+            0xc0,
+        );
+        sb.fill(
+            Fill::NonZero,
+            transform,
+            color,
+            None,
+            &RoundedRect::new(-30.0, -30.0, 30.0, 30.0, 10.0),
+        );
+    }
+}
+
+fn to_js(e: std::io::Error) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
@@ -0,0 +1,111 @@
+//! The worker, which owns the GPU device and renders the frames posted by the page.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use js_sys::Uint8Array;
+use vello::util::{RenderContext, RenderSurface};
+use vello::{AaConfig, Renderer, RendererOptions, TraceReader};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use web_sys::{DedicatedWorkerGlobalScope, MessageEvent, OffscreenCanvas};
+
+struct WorkerRenderer {
+    context: RenderContext,
+    surface: RenderSurface,
+    renderer: Renderer,
+}
+
+pub fn run(scope: &DedicatedWorkerGlobalScope) {
+    let state = Rc::new(RefCell::new(None::<WorkerRenderer>));
+    let onmessage = {
+        let scope = scope.clone();
+        Closure::<dyn FnMut(MessageEvent)>::new(move |event: MessageEvent| {
+            let data = event.data();
+            if let Some(canvas) = data.dyn_ref::<OffscreenCanvas>() {
+                let canvas = canvas.clone();
+                let state = state.clone();
+                let scope = scope.clone();
+                wasm_bindgen_futures::spawn_local(async move {
+                    match WorkerRenderer::new(canvas).await {
+                        Ok(renderer) => {
+                            *state.borrow_mut() = Some(renderer);
+                            post(&scope, "ready");
+                        }
+                        Err(e) => post(&scope, &format!("Failed to create the renderer: {e}")),
+                    }
+                });
+            } else if let Some(frame) = data.dyn_ref::<Uint8Array>() {
+                let result = match state.borrow_mut().as_mut() {
+                    Some(renderer) => renderer.render(&frame.to_vec()),
+                    None => Err("Received a frame before the canvas".into()),
+                };
+                match result {
+                    Ok(()) => post(&scope, "rendered"),
+                    Err(e) => post(&scope, &format!("Failed to render a frame: {e}")),
+                }
+            }
+        })
+    };
+    scope.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+    post(scope, "started");
+}
+
+fn post(scope: &DedicatedWorkerGlobalScope, message: &str) {
+    if let Err(e) = scope.post_message(&JsValue::from_str(message)) {
+        log::error!("Failed to post {message:?} to the page: {e:?}");
+    }
+}
+
+impl WorkerRenderer {
+    async fn new(canvas: OffscreenCanvas) -> vello::Result<Self> {
+        let mut context = RenderContext::new()?;
+        let (width, height) = (canvas.width(), canvas.height());
+        let surface = context
+            .create_surface_from_offscreen_canvas(canvas, width, height)
+            .await?;
+        let device_handle = &context.devices[surface.dev_id];
+        let renderer = Renderer::new(
+            &device_handle.device,
+            &RendererOptions {
+                surface_format: Some(surface.format),
+                timestamp_period: device_handle.queue.get_timestamp_period(),
+                use_cpu: false,
+                num_init_threads: None,
+                memory_budget_bytes: None,
+                compact_tiles: false,
+                antialiasing_method: AaConfig::Area,
+                linear_blending: false,
+                dithering: false,
+                output_format: wgpu::TextureFormat::Rgba8Unorm,
+                split_submissions: false,
+                single_pass_scan: device_handle.supports_single_pass_scan(),
+                shader_source: None,
+            },
+        )?;
+        Ok(Self {
+            context,
+            surface,
+            renderer,
+        })
+    }
+
+    /// Renders the frame serialized in `trace`, which the worker presents once it yields.
+    fn render(&mut self, trace: &[u8]) -> vello::Result<()> {
+        let frame = TraceReader::new(trace)?
+            .read_frame()?
+            .ok_or("The trace has no frame")?;
+        let device_handle = &self.context.devices[self.surface.dev_id];
+        let surface_texture = self.surface.surface.get_current_texture()?;
+        self.renderer.render_to_surface(
+            &device_handle.device,
+            &device_handle.queue,
+            &frame.scene,
+            &surface_texture,
+            &frame.params,
+        )?;
+        surface_texture.present();
+        Ok(())
+    }
+}
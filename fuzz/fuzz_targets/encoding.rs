@@ -0,0 +1,30 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Encodes generated scenes and lays them out for the GPU, without rendering them.
+//!
+//! Scenes built through the scene builder must pass validation, and resolving them and
+//! sizing the buffers of the pipeline for them must not panic.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use vello::peniko::Color;
+use vello_encoding::{estimate_bump_sizes, RenderConfig, Resolver};
+use vello_fuzz::{build_scene, Op};
+
+fuzz_target!(|ops: Vec<Op>| {
+    let scene = build_scene(&ops);
+    let encoding = scene.data();
+    if let Err(err) = encoding.validate() {
+        panic!("scene builder produced an invalid encoding: {err}");
+    }
+    let mut resolver = Resolver::new();
+    let mut packed = vec![];
+    let (layout, _, _) = resolver.resolve(encoding, &mut packed);
+    assert_eq!(layout.n_paths, encoding.n_paths);
+    assert_eq!(layout.n_clips, encoding.n_clips);
+    let mut config = RenderConfig::new(&layout, 256, 256, &Color::BLACK);
+    let estimate = estimate_bump_sizes(&layout, &packed, 256, 256);
+    config.fit_bump_sizes(&estimate);
+});
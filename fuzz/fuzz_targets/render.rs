@@ -0,0 +1,108 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Renders generated scenes on the default adapter.
+//!
+//! Each render must return without an error or a wgpu validation error. The shaders are built
+//! with bounds checks, so writes past the end of the bump allocated buffers are reported to
+//! the renderer, which retries with larger buffers, rather than corrupting memory.
+
+#![no_main]
+
+use std::cell::RefCell;
+
+use libfuzzer_sys::fuzz_target;
+use vello::peniko::Color;
+use vello::util::{block_on_wgpu, DeviceHandle, RenderContext};
+use vello::{AaConfig, RenderParams, Renderer, RendererOptions};
+use vello_fuzz::{build_scene, Op};
+
+const TARGET_SIZE: u32 = 256;
+
+/// Device and renderer, kept across inputs as creating them dominates the time of a render.
+struct Target {
+    context: RenderContext,
+    device_id: usize,
+    renderer: Renderer,
+    view: wgpu::TextureView,
+}
+
+impl Target {
+    fn new() -> Self {
+        let mut context = RenderContext::new().expect("failed to create render context");
+        let device_id =
+            pollster::block_on(context.device(None)).expect("no compatible device found");
+        let DeviceHandle { device, queue, .. } = &context.devices[device_id];
+        let renderer = Renderer::new(
+            device,
+            &RendererOptions {
+                surface_format: None,
+                timestamp_period: queue.get_timestamp_period(),
+                use_cpu: false,
+                num_init_threads: None,
+                memory_budget_bytes: None,
+                compact_tiles: false,
+                antialiasing_method: AaConfig::Area,
+                linear_blending: false,
+                dithering: false,
+                output_format: wgpu::TextureFormat::Rgba8Unorm,
+                split_submissions: false,
+                single_pass_scan: false,
+                shader_source: None,
+            },
+        )
+        .expect("failed to create renderer");
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("fuzz target"),
+            size: wgpu::Extent3d {
+                width: TARGET_SIZE,
+                height: TARGET_SIZE,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsages::STORAGE_BINDING,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Self {
+            context,
+            device_id,
+            renderer,
+            view,
+        }
+    }
+}
+
+thread_local! {
+    static TARGET: RefCell<Option<Target>> = RefCell::new(None);
+}
+
+fuzz_target!(|ops: Vec<Op>| {
+    let scene = build_scene(&ops);
+    TARGET.with(|target| {
+        let mut target = target.borrow_mut();
+        let target = target.get_or_insert_with(Target::new);
+        let DeviceHandle { device, queue, .. } = &target.context.devices[target.device_id];
+        let params = RenderParams {
+            base_color: Color::BLACK,
+            width: TARGET_SIZE,
+            height: TARGET_SIZE,
+        };
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = block_on_wgpu(
+            device,
+            target
+                .renderer
+                .render_to_texture_async(device, queue, &scene, &target.view, &params),
+        );
+        if let Err(err) = result {
+            panic!("render failed: {err}");
+        }
+        if let Some(err) = pollster::block_on(device.pop_error_scope()) {
+            panic!("render raised a validation error: {err}");
+        }
+    });
+});
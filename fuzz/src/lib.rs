@@ -0,0 +1,199 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Scenes generated from fuzzer input, shared by the fuzz targets.
+//!
+//! The scenes are structurally valid, as built through [`SceneBuilder`], but their contents
+//! are hostile: coordinates and transforms reach the extremes of `f32`, paths can be empty or
+//! degenerate, and layers nest as deeply as the input allows.
+
+use arbitrary::Arbitrary;
+use vello::kurbo::{Affine, BezPath, Point, Stroke};
+use vello::peniko::{Color, Fill, Mix};
+use vello::{Scene, SceneBuilder};
+
+/// Blend modes of layers, indexed by the `mix` of [`Op::PushLayer`].
+const MIXES: &[Mix] = &[
+    Mix::Normal,
+    Mix::Multiply,
+    Mix::Screen,
+    Mix::Overlay,
+    Mix::Darken,
+    Mix::Lighten,
+    Mix::ColorDodge,
+    Mix::ColorBurn,
+    Mix::HardLight,
+    Mix::SoftLight,
+    Mix::Difference,
+    Mix::Exclusion,
+    Mix::Hue,
+    Mix::Saturation,
+    Mix::Color,
+    Mix::Luminosity,
+    Mix::Clip,
+];
+
+/// A coordinate, which is either in the range of the target or anywhere in `f32`.
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum Coord {
+    Zero,
+    /// Quarter pixels from the origin.
+    Near(i16),
+    /// Any finite value, with non-finite values replaced by the largest magnitude.
+    Far(f32),
+}
+
+impl Coord {
+    pub fn value(self) -> f64 {
+        match self {
+            Self::Zero => 0.0,
+            Self::Near(quarters) => quarters as f64 * 0.25,
+            Self::Far(value) if value.is_nan() => f32::MAX as f64,
+            Self::Far(value) => value.clamp(f32::MIN, f32::MAX) as f64,
+        }
+    }
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub struct FuzzPoint(pub Coord, pub Coord);
+
+impl FuzzPoint {
+    pub fn point(self) -> Point {
+        Point::new(self.0.value(), self.1.value())
+    }
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum FuzzTransform {
+    Identity,
+    Translate(FuzzPoint),
+    Scale(Coord),
+    /// Any affine transform, including singular ones.
+    Affine([Coord; 6]),
+}
+
+impl FuzzTransform {
+    pub fn affine(self) -> Affine {
+        match self {
+            Self::Identity => Affine::IDENTITY,
+            Self::Translate(offset) => Affine::translate(offset.point().to_vec2()),
+            Self::Scale(scale) => Affine::scale(scale.value()),
+            Self::Affine(coeffs) => Affine::new(coeffs.map(Coord::value)),
+        }
+    }
+}
+
+#[derive(Arbitrary, Clone, Copy, Debug)]
+pub enum Segment {
+    MoveTo(FuzzPoint),
+    LineTo(FuzzPoint),
+    QuadTo(FuzzPoint, FuzzPoint),
+    CurveTo(FuzzPoint, FuzzPoint, FuzzPoint),
+    ClosePath,
+}
+
+/// Builds a path from `segments`, which may be empty or start without a move.
+pub fn path(segments: &[Segment]) -> BezPath {
+    let mut path = BezPath::new();
+    for segment in segments {
+        // Kurbo requires paths to start with a move.
+        if path.elements().is_empty() && !matches!(segment, Segment::MoveTo(_)) {
+            path.move_to(Point::ZERO);
+        }
+        match *segment {
+            Segment::MoveTo(p) => path.move_to(p.point()),
+            Segment::LineTo(p) => path.line_to(p.point()),
+            Segment::QuadTo(p1, p2) => path.quad_to(p1.point(), p2.point()),
+            Segment::CurveTo(p1, p2, p3) => path.curve_to(p1.point(), p2.point(), p3.point()),
+            Segment::ClosePath => path.close_path(),
+        }
+    }
+    path
+}
+
+/// One call to a [`SceneBuilder`].
+#[derive(Arbitrary, Clone, Debug)]
+pub enum Op {
+    Fill {
+        even_odd: bool,
+        transform: FuzzTransform,
+        color: u32,
+        segments: Vec<Segment>,
+    },
+    Stroke {
+        width: Coord,
+        transform: FuzzTransform,
+        color: u32,
+        segments: Vec<Segment>,
+    },
+    PushLayer {
+        mix: u8,
+        alpha: u8,
+        transform: FuzzTransform,
+        segments: Vec<Segment>,
+    },
+    PopLayer,
+}
+
+/// Builds a scene from `ops`, ignoring pops without a layer and closing the layers which are
+/// left open, so that the scene is balanced.
+pub fn build_scene(ops: &[Op]) -> Scene {
+    let mut scene = Scene::new();
+    let mut builder = SceneBuilder::for_scene(&mut scene);
+    let mut depth = 0_usize;
+    for op in ops {
+        match op {
+            Op::Fill {
+                even_odd,
+                transform,
+                color,
+                segments,
+            } => {
+                let style = if *even_odd {
+                    Fill::EvenOdd
+                } else {
+                    Fill::NonZero
+                };
+                let color = color_from_u32(*color);
+                builder.fill(style, transform.affine(), color, None, &path(segments));
+            }
+            Op::Stroke {
+                width,
+                transform,
+                color,
+                segments,
+            } => {
+                // Negative widths are rejected by the encoding, so the magnitude is used.
+                let stroke = Stroke::new(width.value().abs());
+                let color = color_from_u32(*color);
+                builder.stroke(&stroke, transform.affine(), color, None, &path(segments));
+            }
+            Op::PushLayer {
+                mix,
+                alpha,
+                transform,
+                segments,
+            } => {
+                let mix = MIXES[*mix as usize % MIXES.len()];
+                let alpha = *alpha as f32 / 255.0;
+                builder.push_layer(mix, alpha, transform.affine(), &path(segments));
+                depth += 1;
+            }
+            Op::PopLayer => {
+                if depth > 0 {
+                    builder.pop_layer();
+                    depth -= 1;
+                }
+            }
+        }
+    }
+    for _ in 0..depth {
+        builder.pop_layer();
+    }
+    scene
+}
+
+fn color_from_u32(rgba: u32) -> Color {
+    let [r, g, b, a] = rgba.to_be_bytes();
+    Color::rgba8(r, g, b, a)
+}
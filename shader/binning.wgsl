@@ -43,7 +43,7 @@ let SY = 0.00390625;
 //let SX = 1.0 / f32(N_TILE_X * TILE_WIDTH);
 //let SY = 1.0 / f32(N_TILE_Y * TILE_HEIGHT);
 
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_BBOX_WG;
 let N_SLICE = 8u;
 //let N_SLICE = WG_SIZE / 32u;
 let N_SUBSLICE = 4u;
@@ -53,7 +53,7 @@ var<workgroup> sh_bitmaps: array<array<atomic<u32>, N_TILE>, N_SLICE>;
 var<workgroup> sh_count: array<array<u32, N_TILE>, N_SUBSLICE>;
 var<workgroup> sh_chunk_offset: array<u32, N_TILE>;
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_BBOX_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT OR Unlicense
+
+// One pass of a separable Gaussian blur, used to filter layers which were rendered offscreen.
+// The horizontal pass blurs the rendered layer into an intermediate image, and the vertical
+// pass blurs that into the image atlas, where fine rasterization draws it from.
+
+// This must be kept in sync with BlurConfig in crates/encoding/src/config.rs.
+struct BlurConfig {
+    // Size of the blurred region in pixels.
+    width: u32,
+    height: u32,
+    // Location of the blurred region in the output image.
+    dst_x: u32,
+    dst_y: u32,
+    // Direction of the pass: 0 for horizontal and 1 for vertical.
+    vertical: u32,
+    // Number of pixels sampled on either side of each output pixel.
+    radius: u32,
+    std_dev: f32,
+    // If non-zero, the blurred alpha is filled with color, which is packed RGBA8 in MSB
+    // order and premultiplied.
+    use_color: u32,
+    color: u32,
+}
+
+@group(0) @binding(0)
+var<uniform> config: BlurConfig;
+
+@group(0) @binding(1)
+var input: texture_2d<f32>;
+
+// Both the intermediate image and the image atlas are rgba8.
+@group(0) @binding(2)
+var output: texture_storage_2d<rgba8unorm, write>;
+
+@compute @workgroup_size(16, 16)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+) {
+    let size = vec2(i32(config.width), i32(config.height));
+    let xy = vec2<i32>(global_id.xy);
+    if any(xy >= size) {
+        return;
+    }
+    let step = select(vec2(1, 0), vec2(0, 1), config.vertical != 0u);
+    let scale = -0.5 / max(config.std_dev * config.std_dev, 1e-6);
+    let radius = i32(config.radius);
+    var sum = vec4(0.0);
+    var weight_sum = 0.0;
+    for (var i = -radius; i <= radius; i += 1) {
+        let weight = exp(f32(i * i) * scale);
+        weight_sum += weight;
+        // Pixels outside of the region are transparent.
+        let sample_xy = xy + step * i;
+        if all(sample_xy >= vec2(0)) && all(sample_xy < size) {
+            let rgba_sep = textureLoad(input, sample_xy, 0);
+            sum += weight * vec4(rgba_sep.rgb * rgba_sep.a, rgba_sep.a);
+        }
+    }
+    var rgba = sum / weight_sum;
+    if config.use_color != 0u {
+        rgba = unpack4x8unorm(config.color).wzyx * rgba.a;
+    }
+    // The images hold separate alpha, like the output of fine rasterization.
+    let a_inv = 1.0 / max(rgba.a, 1e-6);
+    let rgba_sep = vec4(rgba.rgb * a_inv, rgba.a);
+    textureStore(output, xy + vec2(i32(config.dst_x), i32(config.dst_y)), rgba_sep);
+}
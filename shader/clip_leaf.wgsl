@@ -26,8 +26,8 @@ var<storage, read_write> draw_monoids: array<DrawMonoid>;
 @group(0) @binding(6)
 var<storage, read_write> clip_bboxes: array<vec4<f32>>;
 
-let WG_SIZE = 256u;
-var<workgroup> sh_bic: array<Bic, 510 >;
+let WG_SIZE = CLIP_REDUCE_WG;
+var<workgroup> sh_bic: array<Bic, CLIP_BIC_SIZE>;
 var<workgroup> sh_stack: array<u32, WG_SIZE>;
 var<workgroup> sh_stack_bbox: array<vec4<f32>, WG_SIZE>;
 var<workgroup> sh_bbox: array<vec4<f32>, WG_SIZE>;
@@ -76,15 +76,21 @@ fn load_clip_path(ix: u32) -> i32 {
     }
 }
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(CLIP_REDUCE_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
     @builtin(workgroup_id) wg_id: vec3<u32>,
 ) {
+    // Reduce prefix of workgroups up to this one. Each thread combines a contiguous block
+    // of the preceding workgroups so that scenes with more than WG_SIZE workgroups of clips
+    // are handled; the blocks keep the workgroups in order, as the bicyclic monoid isn't
+    // commutative.
+    let block_size = (wg_id.x + WG_SIZE - 1u) / WG_SIZE;
+    let block_end = min((local_id.x + 1u) * block_size, wg_id.x);
     var bic: Bic;
-    if local_id.x < wg_id.x {
-        bic = reduced[local_id.x];
+    for (var i = local_id.x * block_size; i < block_end; i += 1u) {
+        bic = bic_combine(bic, reduced[i]);
     }
     sh_bic[local_id.x] = bic;
     for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
@@ -109,10 +115,23 @@ fn main(
             ix = probe;
         }
     }
-    let b = sh_bic[ix].b;
     var bbox = vec4(-1e9, -1e9, 1e9, 1e9);
-    if sp < b {
-        let el = clip_els[ix * WG_SIZE + b - sp - 1u];
+    if sp < sh_bic[ix].b {
+        // Find the workgroup of the block which pushed the element, combining the
+        // workgroups of the block from its end.
+        var suffix = Bic();
+        if ix + 1u < WG_SIZE {
+            suffix = sh_bic[ix + 1u];
+        }
+        var wg = min((ix + 1u) * block_size, wg_id.x);
+        while wg > ix * block_size {
+            wg -= 1u;
+            suffix = bic_combine(reduced[wg], suffix);
+            if sp < suffix.b {
+                break;
+            }
+        }
+        let el = clip_els[wg * WG_SIZE + suffix.b - sp - 1u];
         sh_stack[local_id.x] = el.parent_ix;
         bbox = el.bbox;
     }
@@ -15,12 +15,12 @@ var<storage, read_write> reduced: array<Bic>;
 @group(0) @binding(3)
 var<storage, read_write> clip_out: array<ClipEl>;
 
-let WG_SIZE = 256u;
+let WG_SIZE = CLIP_REDUCE_WG;
 var<workgroup> sh_bic: array<Bic, WG_SIZE>;
 var<workgroup> sh_parent: array<u32, WG_SIZE>;
 var<workgroup> sh_path_ix: array<u32, WG_SIZE>;
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(CLIP_REDUCE_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
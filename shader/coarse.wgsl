@@ -4,6 +4,7 @@
 
 #import config
 #import bump
+#import debug
 #import drawtag
 #import ptcl
 #import tile
@@ -110,6 +111,14 @@ fn write_path(tile: Tile, tile_ix: u32, linewidth: f32) -> bool {
     return true;
 }
 
+// Returns true if a fill with this color covers the whole tile opaquely, which hides
+// everything drawn in the tile before it.
+fn is_opaque_cover(tile: Tile, linewidth: f32, rgba_color: u32) -> bool {
+    let even_odd = linewidth < -1.0;
+    let covered = select(tile.backdrop != 0, (abs(tile.backdrop) & 1) != 0, even_odd);
+    return tile.segment_count_or_ix == 0u && covered && (rgba_color & 0xffu) == 0xffu;
+}
+
 fn write_color(color: CmdColor) {
     alloc_cmd(2u);
     ptcl[cmd_offset] = CMD_COLOR;
@@ -117,6 +126,13 @@ fn write_color(color: CmdColor) {
     cmd_offset += 2u;
 }
 
+fn write_solid_color(color: CmdColor) {
+    alloc_cmd(2u);
+    ptcl[cmd_offset] = CMD_SOLID_COLOR;
+    ptcl[cmd_offset + 1u] = color.rgba_color;
+    cmd_offset += 2u;
+}
+
 fn write_grad(ty: u32, index: u32, info_offset: u32) {
     alloc_cmd(3u);
     ptcl[cmd_offset] = ty;
@@ -192,6 +208,9 @@ fn main(
 
     let blend_offset = cmd_offset;
     cmd_offset += 1u;
+    // Commands are dropped by rewinding to the start of the list.
+    let cmd_start = cmd_offset;
+    let cmd_start_limit = cmd_limit;
 
     while true {
         for (var i = 0u; i < N_SLICE; i += 1u) {
@@ -301,6 +320,10 @@ fn main(
             let y = (x0y0 >> 16u) + seq_ix / width;
             let tile_ix = sh_tile_base[el_ix] + sh_tile_stride[el_ix] * y + x;
             let tile = tiles[tile_ix];
+#ifdef simple
+            // Scenes without clips or layers only need the tiles which are covered.
+            let include_tile = tile.segment_count_or_ix != 0u || tile.backdrop != 0;
+#else
             let is_clip = (tag & 1u) != 0u;
             var is_blend = false;
             if is_clip {
@@ -311,6 +334,7 @@ fn main(
                 is_blend = blend != BLEND_CLIP;
             }
             let include_tile = tile.segment_count_or_ix != 0u || (tile.backdrop == 0) == is_clip || is_blend;
+#endif
             if include_tile {
                 let el_slice = el_ix / 32u;
                 let el_mask = 1u << (el_ix & 31u);
@@ -344,6 +368,20 @@ fn main(
             let dm = draw_monoids[drawobj_ix];
             let dd = config.drawdata_base + dm.scene_offset;
             let di = dm.info_offset;
+#ifdef simple
+            // Every draw object of a simple scene is a color fill.
+            let tile_ix = sh_tile_base[el_ix] + sh_tile_stride[el_ix] * tile_y + tile_x;
+            let tile = tiles[tile_ix];
+            let linewidth = bitcast<f32>(info_bin_data[di]);
+            let rgba_color = scene[dd];
+            if is_opaque_cover(tile, linewidth, rgba_color) {
+                cmd_offset = cmd_start;
+                cmd_limit = cmd_start_limit;
+                write_solid_color(CmdColor(rgba_color));
+            } else if write_path(tile, tile_ix, linewidth) {
+                write_color(CmdColor(rgba_color));
+            }
+#else
             if clip_zero_depth == 0u {
                 let tile_ix = sh_tile_base[el_ix] + sh_tile_stride[el_ix] * tile_y + tile_x;
                 let tile = tiles[tile_ix];
@@ -351,8 +389,17 @@ fn main(
                     // DRAWTAG_FILL_COLOR
                     case 0x44u: {
                         let linewidth = bitcast<f32>(info_bin_data[di]);
-                        if write_path(tile, tile_ix, linewidth) {
-                            let rgba_color = scene[dd];
+                        let rgba_color = scene[dd];
+                        // Commands inside a clip can't be dropped, as the clip has to be
+                        // closed.
+                        let is_cover = is_opaque_cover(tile, linewidth, rgba_color);
+                        if is_cover && clip_depth == 0u {
+                            cmd_offset = cmd_start;
+                            cmd_limit = cmd_start_limit;
+                        }
+                        if is_cover {
+                            write_solid_color(CmdColor(rgba_color));
+                        } else if write_path(tile, tile_ix, linewidth) {
                             write_color(CmdColor(rgba_color));
                         }
                     }
@@ -420,6 +467,7 @@ fn main(
                     default: {}
                 }
             }
+#endif
         }
 
         rd_ix += N_TILE;
@@ -432,7 +480,12 @@ fn main(
         ptcl[cmd_offset] = CMD_END;
         if max_blend_depth > BLEND_STACK_SPLIT {
             let scratch_size = max_blend_depth * TILE_WIDTH * TILE_HEIGHT;
-            ptcl[blend_offset] = atomicAdd(&bump.blend, scratch_size);
+            var blend_ix = atomicAdd(&bump.blend, scratch_size);
+            if blend_ix + scratch_size > config.blend_size {
+                blend_ix = 0u;
+                atomicOr(&bump.failed, STAGE_COARSE);
+            }
+            ptcl[blend_offset] = blend_ix;
         }
     }
 }
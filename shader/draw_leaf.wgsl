@@ -31,7 +31,7 @@ var<storage, read_write> clip_inp: array<ClipInp>;
 
 #import util
 
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_BBOX_WG;
 
 fn read_transform(transform_base: u32, ix: u32) -> Transform {
     let base = transform_base + ix * 6u;
@@ -48,17 +48,20 @@ fn read_transform(transform_base: u32, ix: u32) -> Transform {
 
 var<workgroup> sh_scratch: array<DrawMonoid, WG_SIZE>;
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_BBOX_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
     @builtin(workgroup_id) wg_id: vec3<u32>,
 ) {
     let ix = global_id.x;
-    // Reduce prefix of workgroups up to this one
+    // Reduce prefix of workgroups up to this one. Each thread accumulates a
+    // strided subset of the preceding workgroups so that scenes with more than
+    // WG_SIZE workgroups of draw objects are handled; this relies on the draw
+    // monoid being commutative.
     var agg = draw_monoid_identity();
-    if local_id.x < wg_id.x {
-        agg = reduced[local_id.x];
+    for (var i = local_id.x; i < wg_id.x; i += WG_SIZE) {
+        agg = combine_draw_monoid(agg, reduced[i]);
     }
     sh_scratch[local_id.x] = agg;
     for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
@@ -120,6 +123,10 @@ fn main(
             let matrx = transform.matrx;
             linewidth *= sqrt(abs(matrx.x * matrx.w - matrx.y * matrx.z));
         }
+#ifdef simple
+        // Every draw object of a simple scene is a color fill.
+        info[di] = bitcast<u32>(linewidth);
+#else
         switch tag_word {
             // DRAWTAG_FILL_COLOR
             case 0x44u: {
@@ -239,7 +246,9 @@ fn main(
             }
             default: {}
         }
+#endif
     }
+#ifndef simple
     if tag_word == DRAWTAG_BEGIN_CLIP || tag_word == DRAWTAG_END_CLIP {
         var path_ix = ~ix;
         if tag_word == DRAWTAG_BEGIN_CLIP {
@@ -247,6 +256,7 @@ fn main(
         }
         clip_inp[m.clip_ix] = ClipInp(ix, i32(path_ix));
     }
+#endif
 }
 
 fn two_point_to_unit_line(p0: vec2<f32>, p1: vec2<f32>) -> Transform {
@@ -12,13 +12,13 @@ var<storage> scene: array<u32>;
 @group(0) @binding(2)
 var<storage, read_write> reduced: array<DrawMonoid>;
 
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_BBOX_WG;
 
 var<workgroup> sh_scratch: array<DrawMonoid, WG_SIZE>;
 
 #import util
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_BBOX_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
@@ -4,7 +4,13 @@
 // modes, controllable by #define.
 //
 // To enable multisampled rendering, turn on both the msaa ifdef and one of msaa8
-// or msaa16.
+// or msaa16. The aliased ifdef rounds the analytic area coverage instead, so that
+// pixels are either fully covered or uncovered. The msaa_resolve ifdef, together with
+// msaa and msaa8, keeps a color for each sample, so that paths sharing an edge don't
+// show a seam along it. The subpixel ifdef computes the area coverage of each color
+// channel separately, for LCD panels. The debug_overlay ifdef draws the boundaries of tiles
+// and bins, the number of commands of each tile and the number of draw objects in each bin
+// over the output.
 
 struct Tile {
     backdrop: i32,
@@ -13,6 +19,7 @@ struct Tile {
 
 #import segment
 #import config
+#import debug
 
 @group(0) @binding(0)
 var<uniform> config: Config;
@@ -31,8 +38,17 @@ var<storage> ptcl: array<u32>;
 @group(0) @binding(3)
 var<storage> info: array<u32>;
 
+// The output format is selected with one of the output_* definitions, and defaults to rgba8.
+#if defined(output_rgba16float)
+@group(0) @binding(4)
+var output: texture_storage_2d<rgba16float, write>;
+#elif defined(output_bgra8)
+@group(0) @binding(4)
+var output: texture_storage_2d<bgra8unorm, write>;
+#else
 @group(0) @binding(4)
 var output: texture_storage_2d<rgba8unorm, write>;
+#endif
 
 @group(0) @binding(5)
 var gradients: texture_2d<f32>;
@@ -40,13 +56,111 @@ var gradients: texture_2d<f32>;
 @group(0) @binding(6)
 var image_atlas: texture_2d<f32>;
 
+// Blend stack entries beyond BLEND_STACK_SPLIT, allocated per tile by coarse.
+@group(0) @binding(7)
+var<storage, read_write> blend_spill: array<u32>;
+
+// Textures of the external images drawn by the scene, by slot. Unused slots are bound to
+// placeholders.
+@group(0) @binding(8)
+var external_image0: texture_2d<f32>;
+
+@group(0) @binding(9)
+var external_image1: texture_2d<f32>;
+
+@group(0) @binding(10)
+var external_image2: texture_2d<f32>;
+
+@group(0) @binding(11)
+var external_image3: texture_2d<f32>;
+
+// Loads a texel of the atlas, or of the external image in slot `source - 1`.
+fn load_image(source: u32, coords: vec2<i32>) -> vec4<f32> {
+    switch source {
+        case 1u: {
+            return textureLoad(external_image0, coords, 0);
+        }
+        case 2u: {
+            return textureLoad(external_image1, coords, 0);
+        }
+        case 3u: {
+            return textureLoad(external_image2, coords, 0);
+        }
+        case 4u: {
+            return textureLoad(external_image3, coords, 0);
+        }
+        default: {
+            return textureLoad(image_atlas, coords, 0);
+        }
+    }
+}
+
+#ifdef compact
+// The tiles which have commands, listed by tile_compact. There is one workgroup per listed
+// tile, rather than one per tile of the target.
+@group(0) @binding(12)
+var<storage> tile_list: array<u32>;
+#endif
+
+#ifdef debug_overlay
+struct BinHeader {
+    element_count: u32,
+    chunk_offset: u32,
+}
+
+// The draw objects of each partition of binning in each bin.
+@group(0) @binding(12)
+var<storage> bin_headers: array<BinHeader>;
+
+// Number of commands at which the heatmap of a tile is fully red.
+let DEBUG_MAX_CMDS = 32.0;
+// Number of draw objects at which the border of a bin is fully bright.
+let DEBUG_MAX_BIN_ELEMENTS = 256.0;
+
+// Returns the number of draw objects binning assigned to the bin of a tile.
+fn bin_element_count(tile_xy: vec2<u32>) -> u32 {
+    let width_in_bins = (config.width_in_tiles + N_TILE_X - 1u) / N_TILE_X;
+    let bin_ix = (tile_xy.y / N_TILE_Y) * width_in_bins + tile_xy.x / N_TILE_X;
+    let n_partitions = (config.n_drawobj + N_TILE - 1u) / N_TILE;
+    var count = 0u;
+    for (var part_ix = 0u; part_ix < n_partitions; part_ix += 1u) {
+        count += bin_headers[part_ix * N_TILE + bin_ix].element_count;
+    }
+    return count;
+}
+
+// Composites the overlay over the premultiplied color `rgba` of the pixel at `coords`.
+//
+// The tile is tinted from blue to red by its number of commands, tile boundaries are drawn
+// in gray, and bin boundaries in green, brighter for bins with more draw objects.
+fn debug_overlay(rgba: vec4<f32>, coords: vec2<u32>, n_cmds: u32, n_bin_elements: u32) -> vec4<f32> {
+    var result = rgba;
+    if n_cmds > 0u {
+        let heat = min(f32(n_cmds) / DEBUG_MAX_CMDS, 1.0);
+        let tint = vec4(heat, 0.0, 1.0 - heat, 1.0) * 0.4;
+        result = tint + result * (1.0 - tint.a);
+    }
+    let tile_local = coords % vec2(TILE_WIDTH, TILE_HEIGHT);
+    if tile_local.x == 0u || tile_local.y == 0u {
+        let line = vec4(0.5, 0.5, 0.5, 1.0) * 0.5;
+        result = line + result * (1.0 - line.a);
+    }
+    let bin_local = coords % vec2(TILE_WIDTH * N_TILE_X, TILE_HEIGHT * N_TILE_Y);
+    if bin_local.x < 2u || bin_local.y < 2u {
+        let occupancy = min(f32(n_bin_elements) / DEBUG_MAX_BIN_ELEMENTS, 1.0);
+        result = vec4(0.0, 0.25 + 0.75 * occupancy, 0.0, 1.0);
+    }
+    return result;
+}
+#endif
+
 #ifdef msaa8
 let MASK_WIDTH = 32u;
 let MASK_HEIGHT = 32u;
 let SH_SAMPLES_SIZE = 256u;
 let SAMPLE_WORDS_PER_PIXEL = 1u;
 // This might be better in uniform, but that has 16 byte alignment
-@group(0) @binding(7)
+@group(0) @binding(12)
 var<storage> mask_lut: array<u32, 256u>;
 #endif
 
@@ -55,7 +169,7 @@ let MASK_WIDTH = 64u;
 let MASK_HEIGHT = 64u;
 let SH_SAMPLES_SIZE = 512u;
 let SAMPLE_WORDS_PER_PIXEL = 2u;
-@group(0) @binding(7)
+@group(0) @binding(12)
 var<storage> mask_lut: array<u32, 2048u>;
 #endif
 
@@ -81,6 +195,51 @@ let SEG_SIZE = 5u;
 let ONE_MINUS_ULP: f32 = 0.99999994;
 let ROBUST_EPSILON: f32 = 2e-7;
 
+#ifdef msaa_resolve
+let N_SAMPLES = 8u;
+
+// Samples covered by the last fill, one bit per sample, written by fill_path_ms.
+var<private> sample_masks: array<u32, PIXELS_PER_THREAD>;
+// Premultiplied color of each sample, packed as 16 bit floats.
+var<private> sample_colors: array<array<vec2<u32>, N_SAMPLES>, PIXELS_PER_THREAD>;
+
+fn load_sample(i: u32, s: u32) -> vec4<f32> {
+    let packed = sample_colors[i][s];
+    return vec4(unpack2x16float(packed.x), unpack2x16float(packed.y));
+}
+
+fn store_sample(i: u32, s: u32, rgba: vec4<f32>) {
+    sample_colors[i][s] = vec2(pack2x16float(rgba.xy), pack2x16float(rgba.zw));
+}
+
+// Sets all of the samples of pixel `i` to `rgba`.
+fn fill_samples(i: u32, rgba: vec4<f32>) {
+    for (var s = 0u; s < N_SAMPLES; s += 1u) {
+        store_sample(i, s, rgba);
+    }
+}
+
+// Returns the average color of the samples of pixel `i`.
+fn resolve_samples(i: u32) -> vec4<f32> {
+    var sum = vec4(0.0);
+    for (var s = 0u; s < N_SAMPLES; s += 1u) {
+        sum += load_sample(i, s);
+    }
+    return sum * (1.0 / f32(N_SAMPLES));
+}
+
+// Blends a layer, whose content is in the samples of pixel `i`, over the background `bg`
+// in the samples covered by the clip path.
+fn end_clip_samples(i: u32, bg: vec4<f32>, alpha: f32, blend: u32) {
+    let mask = sample_masks[i];
+    for (var s = 0u; s < N_SAMPLES; s += 1u) {
+        let covered = (mask & (1u << s)) != 0u;
+        let fg = select(vec4(0.0), load_sample(i, s) * alpha, covered);
+        store_sample(i, s, blend_mix_compose(bg, fg, blend));
+    }
+}
+#endif
+
 // New multisampled algorithm.
 fn fill_path_ms(fill: CmdFill, wg_id: vec2<u32>, local_id: vec2<u32>) -> array<f32, PIXELS_PER_THREAD> {
     let n_segs = fill.size_and_rule >> 1u;
@@ -321,6 +480,9 @@ fn fill_path_ms(fill: CmdFill, wg_id: vec2<u32>, local_id: vec2<u32>) -> array<f
         let expected_zero = (((packed_w >> (minor * 4u)) + wind_y) & 0xfu) - u32(fill.backdrop);
         if expected_zero >= 16u {
             area[i] = 1.0;
+#ifdef msaa_resolve
+            sample_masks[i] = 0xffu;
+#endif
         } else {
 #ifdef msaa8
             let samples = atomicLoad(&sh_samples[pix_ix]);
@@ -329,6 +491,13 @@ fn fill_path_ms(fill: CmdFill, wg_id: vec2<u32>, local_id: vec2<u32>) -> array<f
             let xored2 = xored | (xored * 2u);
             let xored4 = xored2 | (xored2 * 4u);
             area[i] = f32(countOneBits(xored4 & 0x88888888u)) * 0.125;
+#ifdef msaa_resolve
+            // Gather the top bit of each nibble into one bit per sample.
+            var mask = (xored4 >> 3u) & 0x11111111u;
+            mask = (mask | (mask >> 3u)) & 0x03030303u;
+            mask = (mask | (mask >> 6u)) & 0x000f000fu;
+            sample_masks[i] = (mask | (mask >> 12u)) & 0xffu;
+#endif
 #endif
 #ifdef msaa16
             let samples0 = atomicLoad(&sh_samples[pix_ix * 2u]);
@@ -397,14 +566,20 @@ fn read_image(cmd_ix: u32) -> CmdImage {
     let m3 = bitcast<f32>(info[info_offset + 3u]);
     let matrx = vec4(m0, m1, m2, m3);
     let xlat = vec2(bitcast<f32>(info[info_offset + 4u]), bitcast<f32>(info[info_offset + 5u]));
-    let xy = info[info_offset + 6u];
+    var xy = info[info_offset + 6u];
     let width_height = info[info_offset + 7u];
+    // External images are sampled from their own texture, from the origin.
+    var source = 0u;
+    if (xy & EXTERNAL_IMAGE_FLAG) != 0u {
+        source = (xy & 0xffffu) + 1u;
+        xy = 0u;
+    }
     // The following are not intended to be bitcasts
     let x = f32(xy >> 16u);
     let y = f32(xy & 0xffffu);
     let width = f32(width_height >> 16u);
     let height = f32(width_height & 0xffffu);
-    return CmdImage(matrx, xlat, vec2(x, y), vec2(width, height));
+    return CmdImage(matrx, xlat, vec2(x, y), vec2(width, height), source);
 }
 
 fn read_end_clip(cmd_ix: u32) -> CmdEndClip {
@@ -491,41 +666,139 @@ fn fill_path(fill: CmdFill, xy: vec2<f32>) -> array<f32, PIXELS_PER_THREAD> {
             area[i] = min(abs(area[i]), 1.0);
         }
     }
+#ifdef aliased
+    // Treat pixels as covered when at least half of their area is, which matches sampling
+    // at the pixel center for edges that are straight across the pixel.
+    for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+        area[i] = select(0.0, 1.0, area[i] >= 0.5);
+    }
+#endif
     return area;
 }
 
+#ifdef subpixel
+// Number of subpixels in the pixels of a thread, which is 3 * PIXELS_PER_THREAD.
+let SUBPIXELS_PER_THREAD = 12u;
+
+// Coverage of the red, green and blue subpixels of each pixel, for panels with horizontal
+// RGB stripes, written by fill_path_subpixel.
+var<private> subpixel_area: array<vec3<f32>, PIXELS_PER_THREAD>;
+
+// Computes the coverage of each third of the pixels in the same way as fill_path, with the
+// x axis scaled by 3, and filters it across the pixel to reduce color fringes.
+fn fill_path_subpixel(fill: CmdFill, xy: vec2<f32>) {
+    let n_segs = fill.size_and_rule >> 1u;
+    let even_odd = (fill.size_and_rule & 1u) != 0u;
+    var area: array<f32, SUBPIXELS_PER_THREAD>;
+    let backdrop_f = f32(fill.backdrop);
+    for (var j = 0u; j < SUBPIXELS_PER_THREAD; j += 1u) {
+        area[j] = backdrop_f;
+    }
+    for (var i = 0u; i < n_segs; i++) {
+        let seg_off = fill.seg_data + i;
+        let segment = segments[seg_off];
+        let y = segment.origin.y - xy.y;
+        let y0 = clamp(y, 0.0, 1.0);
+        let y1 = clamp(y + segment.delta.y, 0.0, 1.0);
+        let dy = y0 - y1;
+        if dy != 0.0 {
+            let vec_y_recip = 1.0 / segment.delta.y;
+            let t0 = (y0 - y) * vec_y_recip;
+            let t1 = (y1 - y) * vec_y_recip;
+            let startx = 3.0 * (segment.origin.x - xy.x);
+            let x0 = startx + t0 * 3.0 * segment.delta.x;
+            let x1 = startx + t1 * 3.0 * segment.delta.x;
+            let xmin0 = min(x0, x1);
+            let xmax0 = max(x0, x1);
+            for (var j = 0u; j < SUBPIXELS_PER_THREAD; j += 1u) {
+                let j_f = f32(j);
+                let xmin = min(xmin0 - j_f, 1.0) - 1.0e-6;
+                let xmax = xmax0 - j_f;
+                let b = min(xmax, 1.0);
+                let c = max(b, 0.0);
+                let d = max(xmin, 0.0);
+                let a = (b + 0.5 * (d * d - c * c) - xmin) / (xmax - xmin);
+                area[j] += a * dy;
+            }
+        }
+        let y_edge = sign(segment.delta.x) * clamp(xy.y - segment.y_edge + 1.0, 0.0, 1.0);
+        for (var j = 0u; j < SUBPIXELS_PER_THREAD; j += 1u) {
+            area[j] += y_edge;
+        }
+    }
+    for (var j = 0u; j < SUBPIXELS_PER_THREAD; j += 1u) {
+        let a = area[j];
+        if even_odd {
+            area[j] = abs(a - 2.0 * round(0.5 * a));
+        } else {
+            area[j] = min(abs(a), 1.0);
+        }
+    }
+    // Each channel is the average of its third and the adjacent thirds of the same pixel,
+    // which keeps the total coverage of the pixel.
+    for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+        let c = vec3(area[3u * i], area[3u * i + 1u], area[3u * i + 2u]);
+        subpixel_area[i] = vec3(2.0 * c.x + c.y, c.x + c.y + c.z, c.y + 2.0 * c.z) * (1.0 / 3.0);
+    }
+}
+#endif
+
 // The X size should be 16 / PIXELS_PER_THREAD
 @compute @workgroup_size(4, 16)
 fn main(
-    @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
     @builtin(workgroup_id) wg_id: vec3<u32>,
 ) {
+#ifdef compact
+    let tile_ix = tile_list[wg_id.x];
+    let tile_xy = vec2(tile_ix % config.width_in_tiles, tile_ix / config.width_in_tiles);
+#else
     let tile_ix = wg_id.y * config.width_in_tiles + wg_id.x;
-    let xy = vec2(f32(global_id.x * PIXELS_PER_THREAD), f32(global_id.y));
+    let tile_xy = wg_id.xy;
+#endif
+    let pixel_xy = tile_xy * vec2(TILE_WIDTH, TILE_HEIGHT) + local_id.xy * vec2(PIXELS_PER_THREAD, 1u);
+    let xy = vec2(f32(pixel_xy.x), f32(pixel_xy.y));
 #ifdef full
     var rgba: array<vec4<f32>, PIXELS_PER_THREAD>;
     for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
-        rgba[i] = unpack4x8unorm(config.base_color).wzyx;
+        rgba[i] = input_color(unpack4x8unorm(config.base_color).wzyx);
+#ifdef msaa_resolve
+        fill_samples(i, rgba[i]);
+#endif
     }
     var blend_stack: array<array<u32, PIXELS_PER_THREAD>, BLEND_STACK_SPLIT>;
+    // Index of this thread's first pixel within a tile sized slice of the blend spill buffer.
+    let blend_pixel_ix = local_id.y * TILE_WIDTH + local_id.x * PIXELS_PER_THREAD;
     var clip_depth = 0u;
     var area: array<f32, PIXELS_PER_THREAD>;
     var cmd_ix = tile_ix * PTCL_INITIAL_ALLOC;
     let blend_offset = ptcl[cmd_ix];
     cmd_ix += 1u;
+#ifdef debug_overlay
+    var n_cmds = 0u;
+#endif
     // main interpretation loop
     while true {
         let tag = ptcl[cmd_ix];
         if tag == CMD_END {
             break;
         }
+#ifdef debug_overlay
+        if tag != CMD_JUMP {
+            n_cmds += 1u;
+        }
+#endif
         switch tag {
             // CMD_FILL
             case 1u: {
                 let fill = read_fill(cmd_ix);
-#ifdef msaa
-                area = fill_path_ms(fill, wg_id.xy, local_id.xy);
+#if defined(msaa)
+                area = fill_path_ms(fill, tile_xy, local_id.xy);
+#elif defined(subpixel)
+                fill_path_subpixel(fill, xy);
+                for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+                    area[i] = dot(subpixel_area[i], vec3(1.0 / 3.0));
+                }
 #else
                 area = fill_path(fill, xy);
 #endif
@@ -538,6 +811,12 @@ fn main(
                 // keep the shader from crashing.
                 for (var i = 0u; i < PIXELS_PER_THREAD; i++) {
                     area[i] = 0.0;
+#ifdef msaa_resolve
+                    sample_masks[i] = 0u;
+#endif
+#ifdef subpixel
+                    subpixel_area[i] = vec3(0.0);
+#endif
                 }
                 cmd_ix += 3u;
             }
@@ -545,6 +824,12 @@ fn main(
             case 3u: {
                 for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
                     area[i] = 1.0;
+#ifdef msaa_resolve
+                    sample_masks[i] = 0xffu;
+#endif
+#ifdef subpixel
+                    subpixel_area[i] = vec3(1.0);
+#endif
                 }
                 cmd_ix += 1u;
             }
@@ -553,8 +838,20 @@ fn main(
                 let color = read_color(cmd_ix);
                 let fg = unpack4x8unorm(color.rgba_color).wzyx;
                 for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
-                    let fg_i = fg * area[i];
-                    rgba[i] = rgba[i] * (1.0 - fg_i.a) + fg_i;
+                    rgba[i] = paint(i, rgba[i], fg, area[i]);
+                }
+                cmd_ix += 2u;
+            }
+            // CMD_SOLID_COLOR
+            case 12u: {
+                // The color is opaque and covers the tile, so it replaces what is below it.
+                let color = read_color(cmd_ix);
+                let fg = unpack4x8unorm(color.rgba_color).wzyx;
+                for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+                    rgba[i] = fg;
+#ifdef msaa_resolve
+                    fill_samples(i, fg);
+#endif
                 }
                 cmd_ix += 2u;
             }
@@ -566,8 +863,7 @@ fn main(
                     let my_d = d + lin.line_x * f32(i);
                     let x = i32(round(extend_mode(my_d, lin.extend_mode) * f32(GRADIENT_WIDTH - 1)));
                     let fg_rgba = textureLoad(gradients, vec2(x, i32(lin.index)), 0);
-                    let fg_i = fg_rgba * area[i];
-                    rgba[i] = rgba[i] * (1.0 - fg_i.a) + fg_i;
+                    rgba[i] = paint(i, rgba[i], fg_rgba, area[i]);
                 }
                 cmd_ix += 3u;
             }
@@ -611,8 +907,7 @@ fn main(
                         t = select(t, 1.0 - t, is_swapped);
                         let x = i32(round(t * f32(GRADIENT_WIDTH - 1)));
                         let fg_rgba = textureLoad(gradients, vec2(x, i32(rad.index)), 0);
-                        let fg_i = fg_rgba * area[i];
-                        rgba[i] = rgba[i] * (1.0 - fg_i.a) + fg_i;
+                        rgba[i] = paint(i, rgba[i], fg_rgba, area[i]);
                     }
                 }
                 cmd_ix += 3u;
@@ -628,26 +923,37 @@ fn main(
                     if all(atlas_uv < atlas_extents) && area[i] != 0.0 {
                         let uv_quad = vec4(max(floor(atlas_uv), image.atlas_offset), min(ceil(atlas_uv), atlas_extents));
                         let uv_frac = fract(atlas_uv);
-                        let a = premul_alpha(textureLoad(image_atlas, vec2<i32>(uv_quad.xy), 0));
-                        let b = premul_alpha(textureLoad(image_atlas, vec2<i32>(uv_quad.xw), 0));
-                        let c = premul_alpha(textureLoad(image_atlas, vec2<i32>(uv_quad.zy), 0));
-                        let d = premul_alpha(textureLoad(image_atlas, vec2<i32>(uv_quad.zw), 0));
+                        let a = image_color(load_image(image.source, vec2<i32>(uv_quad.xy)));
+                        let b = image_color(load_image(image.source, vec2<i32>(uv_quad.xw)));
+                        let c = image_color(load_image(image.source, vec2<i32>(uv_quad.zy)));
+                        let d = image_color(load_image(image.source, vec2<i32>(uv_quad.zw)));
                         let fg_rgba = mix(mix(a, b, uv_frac.y), mix(c, d, uv_frac.y), uv_frac.x);
-                        let fg_i = fg_rgba * area[i];
-                        rgba[i] = rgba[i] * (1.0 - fg_i.a) + fg_i;
+                        rgba[i] = paint(i, rgba[i], fg_rgba, area[i]);
                     }
                 }
                 cmd_ix += 2u;
             }
             // CMD_BEGIN_CLIP
             case 9u: {
+#ifdef msaa_resolve
+                // The blend stack holds one color per pixel, so the samples are averaged
+                // before they are saved, and the layer starts out transparent.
+                for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+                    rgba[i] = resolve_samples(i);
+                    fill_samples(i, vec4(0.0));
+                }
+#endif
                 if clip_depth < BLEND_STACK_SPLIT {
                     for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
                         blend_stack[clip_depth][i] = pack4x8unorm(rgba[i]);
                         rgba[i] = vec4(0.0);
                     }
                 } else {
-                    // TODO: spill to memory
+                    let spill_ix = blend_offset + (clip_depth - BLEND_STACK_SPLIT) * TILE_WIDTH * TILE_HEIGHT + blend_pixel_ix;
+                    for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+                        blend_spill[spill_ix + i] = pack4x8unorm(rgba[i]);
+                        rgba[i] = vec4(0.0);
+                    }
                 }
                 clip_depth += 1u;
                 cmd_ix += 1u;
@@ -661,11 +967,16 @@ fn main(
                     if clip_depth < BLEND_STACK_SPLIT {
                         bg_rgba = blend_stack[clip_depth][i];
                     } else {
-                        // load from memory
+                        let spill_ix = blend_offset + (clip_depth - BLEND_STACK_SPLIT) * TILE_WIDTH * TILE_HEIGHT + blend_pixel_ix;
+                        bg_rgba = blend_spill[spill_ix + i];
                     }
                     let bg = unpack4x8unorm(bg_rgba);
+#ifdef msaa_resolve
+                    end_clip_samples(i, bg, end_clip.alpha, end_clip.blend);
+#else
                     let fg = rgba[i] * area[i] * end_clip.alpha;
                     rgba[i] = blend_mix_compose(bg, fg, end_clip.blend);
+#endif
                 }
                 cmd_ix += 3u;
             }
@@ -677,14 +988,32 @@ fn main(
         }
     }
     let xy_uint = vec2<u32>(xy);
+#ifdef debug_overlay
+    let n_bin_elements = bin_element_count(tile_xy);
+    for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
+        rgba[i] = debug_overlay(rgba[i], xy_uint + vec2(i, 0u), n_cmds, n_bin_elements);
+    }
+#endif
     for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
         let coords = xy_uint + vec2(i, 0u);
         if coords.x < config.target_width && coords.y < config.target_height {
+#ifdef msaa_resolve
+            let fg = resolve_samples(i);
+#else
             let fg = rgba[i];
+#endif
             // Max with a small epsilon to avoid NaNs
             let a_inv = 1.0 / max(fg.a, 1e-6);
-            let rgba_sep = vec4(fg.rgb * a_inv, fg.a);
-            textureStore(output, vec2<i32>(coords), rgba_sep);
+            var rgba_sep = vec4(fg.rgb * a_inv, fg.a);
+            if config.linear_blending != 0u {
+                rgba_sep = vec4(linear_to_srgb(rgba_sep.rgb), rgba_sep.a);
+            }
+#ifndef output_rgba16float
+            if config.dithering != 0u {
+                rgba_sep = dither(rgba_sep, coords);
+            }
+#endif
+            textureStore(output, vec2<i32>(coords + vec2(config.target_x_offset, config.target_y_offset)), rgba_sep);
         }
     } 
 #else
@@ -695,12 +1024,78 @@ fn main(
     for (var i = 0u; i < PIXELS_PER_THREAD; i += 1u) {
         let coords = xy_uint + vec2(i, 0u);
         if coords.x < config.target_width && coords.y < config.target_height {
-            textureStore(output, vec2<i32>(coords), vec4(area[i]));
+            textureStore(output, vec2<i32>(coords + vec2(config.target_x_offset, config.target_y_offset)), vec4(area[i]));
         }
     }
 #endif
 }
 
+// Composites the paint `fg` over `rgba`, the color of pixel `i`, with the coverage `area`
+// of the last fill. When blending into samples, the covered samples are painted instead
+// and `rgba` is returned unchanged. With subpixel coverage, each color channel is blended
+// with the coverage of its subpixel.
+fn paint(i: u32, rgba: vec4<f32>, fg: vec4<f32>, area: f32) -> vec4<f32> {
+#if defined(msaa_resolve)
+    let mask = sample_masks[i];
+    for (var s = 0u; s < N_SAMPLES; s += 1u) {
+        if (mask & (1u << s)) != 0u {
+            store_sample(i, s, fg + load_sample(i, s) * (1.0 - fg.a));
+        }
+    }
+    return rgba;
+#elif defined(subpixel)
+    let cov = subpixel_area[i];
+    let rgb = rgba.rgb * (1.0 - fg.a * cov) + fg.rgb * cov;
+    return vec4(rgb, rgba.a * (1.0 - fg.a * area) + fg.a * area);
+#else
+    let fg_i = fg * area;
+    return rgba * (1.0 - fg_i.a) + fg_i;
+#endif
+}
+
 fn premul_alpha(rgba: vec4<f32>) -> vec4<f32> {
     return vec4(rgba.rgb * rgba.a, rgba.a);
 }
+
+fn srgb_to_linear(rgb: vec3<f32>) -> vec3<f32> {
+    let low = rgb * (1.0 / 12.92);
+    let high = pow((rgb + 0.055) * (1.0 / 1.055), vec3(2.4));
+    return select(high, low, rgb <= vec3(0.04045));
+}
+
+fn linear_to_srgb(rgb: vec3<f32>) -> vec3<f32> {
+    let low = rgb * 12.92;
+    let high = 1.055 * pow(rgb, vec3(1.0 / 2.4)) - 0.055;
+    return select(high, low, rgb <= vec3(0.0031308));
+}
+
+// Ordered dithering with an 8x8 Bayer matrix. Each pixel is offset by less than one step of
+// an 8 bit channel, so that smooth gradients are rounded to alternating levels rather than
+// into bands.
+fn dither(rgba: vec4<f32>, xy: vec2<u32>) -> vec4<f32> {
+    // The index in the matrix is the bit reversal of the interleaved bits of x ^ y and y.
+    let y = xy.y & 7u;
+    let z = (xy.x & 7u) ^ y;
+    let index = ((z & 1u) << 5u) | ((y & 1u) << 4u) | ((z & 2u) << 2u) | ((y & 2u) << 1u) | ((z & 4u) >> 1u) | ((y & 4u) >> 2u);
+    let offset = (f32(index) + 0.5) / 64.0 - 0.5;
+    return vec4(clamp(rgba.rgb + offset / 255.0, vec3(0.0), vec3(1.0)), rgba.a);
+}
+
+// Converts a premultiplied sRGB color which wasn't converted when the scene was packed to
+// the color space used for blending.
+fn input_color(rgba: vec4<f32>) -> vec4<f32> {
+    if config.linear_blending == 0u {
+        return rgba;
+    }
+    let a_inv = 1.0 / max(rgba.a, 1e-6);
+    return vec4(srgb_to_linear(rgba.rgb * a_inv) * rgba.a, rgba.a);
+}
+
+// Converts a texel of the image atlas, which has separate alpha, to a premultiplied color
+// in the color space used for blending.
+fn image_color(rgba: vec4<f32>) -> vec4<f32> {
+    if config.linear_blending == 0u {
+        return premul_alpha(rgba);
+    }
+    return premul_alpha(vec4(srgb_to_linear(rgba.rgb), rgba.a));
+}
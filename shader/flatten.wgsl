@@ -90,16 +90,21 @@ fn eval_cubic(p0: vec2<f32>, p1: vec2<f32>, p2: vec2<f32>, p3: vec2<f32>, t: f32
 
 let MAX_QUADS = 16u;
 
-fn flatten_cubic(cubic: Cubic) {
+// Flattening accuracy, in pixels.
+let ACCURACY = 0.25;
+
+// Flattens a cubic into lines, within `accuracy` of the curve. Fills are flattened in device
+// coordinates, while strokes are flattened in the local coordinates of the path and each line
+// is expanded with the stroke state below.
+fn flatten_cubic(cubic: Cubic, accuracy: f32) {
     let p0 = cubic.p0;
     let p1 = cubic.p1;
     let p2 = cubic.p2;
     let p3 = cubic.p3;
     let err_v = 3.0 * (p2 - p1) + p0 - p3;
     let err = dot(err_v, err_v);
-    let ACCURACY = 0.25;
-    let Q_ACCURACY = ACCURACY * 0.1;
-    let REM_ACCURACY = ACCURACY - Q_ACCURACY;
+    let Q_ACCURACY = accuracy * 0.1;
+    let REM_ACCURACY = accuracy - Q_ACCURACY;
     let MAX_HYPOT2 = 432.0 * Q_ACCURACY * Q_ACCURACY;
     var n_quads = max(u32(ceil(pow(err * (1.0 / MAX_HYPOT2), 1.0 / 6.0))), 1u);
     n_quads = min(n_quads, MAX_QUADS);
@@ -123,6 +128,8 @@ fn flatten_cubic(cubic: Cubic) {
     let v_step = val / f32(n);
     var n_out = 1u;
     var val_sum = 0.0;
+    var last_tangent = vec2(0.0);
+    var lhw0 = stroke_hw0;
     for (var i = 0u; i < n_quads; i += 1u) {
         let t = f32(i + 1u) * step;
         let qp2 = eval_cubic(p0, p1, p2, p3, t);
@@ -135,6 +142,9 @@ fn flatten_cubic(cubic: Cubic) {
         var val_target = f32(n_out) * v_step;
         while n_out == n || val_target < val_sum + params.val {
             var lp1: vec2<f32>;
+            // Parameter of the end of the line along the cubic, which is approximated by
+            // the parameter along the quad.
+            var t_cubic = 1.0;
             if n_out == n {
                 lp1 = p3;
             } else {
@@ -143,12 +153,24 @@ fn flatten_cubic(cubic: Cubic) {
                 let au = approx_parabola_inv_integral(a);
                 let t = (au - u0) * uscale;
                 lp1 = eval_quad(qp0, qp1, qp2, t);
+                t_cubic = (f32(i) + t) * step;
             }
 
             // Output line segment lp0..lp1
-            let line_ix = atomicAdd(&bump.lines, 1u);
-            // TODO: check failure
-            lines[line_ix] = LineSoup(cubic.path_ix, lp0, lp1);
+            if (cubic.flags & CUBIC_IS_STROKE) != 0u {
+                // Lines within a segment are joined with bevels, which are close to the offset
+                // curve as long as the angles between consecutive lines are small.
+                // The width of variable width strokes is interpolated along the segment.
+                let lhw1 = mix(stroke_hw0, stroke_hw1, t_cubic);
+                output_join(lp0, last_tangent, lp1 - lp0, STYLE_JOIN_BEVEL, lhw0);
+                output_stroke_line(lp0, lp1, lhw0, lhw1);
+                if any(lp1 != lp0) {
+                    last_tangent = lp1 - lp0;
+                }
+                lhw0 = lhw1;
+            } else {
+                write_line(cubic.path_ix, lp0, lp1);
+            }
             n_out += 1u;
             val_target += v_step;
             lp0 = lp1;
@@ -158,6 +180,204 @@ fn flatten_cubic(cubic: Cubic) {
     }
 }
 
+fn write_line(path_ix: u32, p0: vec2<f32>, p1: vec2<f32>) {
+    let line_ix = atomicAdd(&bump.lines, 1u);
+    // TODO: check failure
+#ifdef bounds_checks
+    if line_ix < arrayLength(&lines) {
+        lines[line_ix] = LineSoup(path_ix, p0, p1);
+    } else {
+        atomicOr(&bump.failed, STAGE_FLATTEN);
+    }
+#else
+    lines[line_ix] = LineSoup(path_ix, p0, p1);
+#endif
+}
+
+// Stroke style, see `Style` in the encoding crate.
+let STYLE_JOIN_MASK = 3u;
+let STYLE_JOIN_BEVEL = 0u;
+let STYLE_JOIN_MITER = 1u;
+let STYLE_JOIN_ROUND = 2u;
+let STYLE_START_CAP_SHIFT = 2u;
+let STYLE_END_CAP_SHIFT = 4u;
+let STYLE_CAP_MASK = 3u;
+let STYLE_CAP_BUTT = 0u;
+let STYLE_CAP_SQUARE = 1u;
+let STYLE_CAP_ROUND = 2u;
+let STYLE_VARIABLE_WIDTH = 0x40u;
+
+// Maximum number of lines along the arc of a round join, or of half of a round cap.
+let MAX_ARC_LINES = 32u;
+
+// Strokes are expanded into convex polygons in the local coordinates of the path, which are
+// transformed as their lines are written. All of the polygons are written with the same
+// orientation, so that they add up under the non-zero fill rule wherever they overlap.
+var<private> stroke_path_ix: u32;
+var<private> stroke_transform: Transform;
+// True if the transform flips the orientation of the polygons.
+var<private> stroke_flip: bool;
+// Half the line width.
+var<private> stroke_hw: f32;
+// Half the line width at the start and end of the segment, which differ from `stroke_hw` for
+// variable width strokes.
+var<private> stroke_hw0: f32;
+var<private> stroke_hw1: f32;
+var<private> stroke_miter_limit: f32;
+// Flattening accuracy, in local coordinates.
+var<private> stroke_accuracy: f32;
+
+fn cross_2d(a: vec2<f32>, b: vec2<f32>) -> f32 {
+    return a.x * b.y - a.y * b.x;
+}
+
+fn output_line(p0: vec2<f32>, p1: vec2<f32>) {
+    let q0 = transform_apply(stroke_transform, p0);
+    let q1 = transform_apply(stroke_transform, p1);
+    if stroke_flip {
+        write_line(stroke_path_ix, q1, q0);
+    } else {
+        write_line(stroke_path_ix, q0, q1);
+    }
+}
+
+fn output_triangle(p0: vec2<f32>, p1: vec2<f32>, p2: vec2<f32>) {
+    if cross_2d(p1 - p0, p2 - p0) <= 0.0 {
+        output_line(p0, p1);
+        output_line(p1, p2);
+        output_line(p2, p0);
+    } else {
+        output_line(p0, p2);
+        output_line(p2, p1);
+        output_line(p1, p0);
+    }
+}
+
+// The quad must be convex.
+fn output_quad(p0: vec2<f32>, p1: vec2<f32>, p2: vec2<f32>, p3: vec2<f32>) {
+    if cross_2d(p1 - p0, p2 - p0) <= 0.0 {
+        output_line(p0, p1);
+        output_line(p1, p2);
+        output_line(p2, p3);
+        output_line(p3, p0);
+    } else {
+        output_line(p0, p3);
+        output_line(p3, p2);
+        output_line(p2, p1);
+        output_line(p1, p0);
+    }
+}
+
+// Outputs the sector of a circle around `center` between the offsets `n0` and `n1`, which
+// have the same length and are at most a quarter turn apart.
+fn output_arc(center: vec2<f32>, n0_in: vec2<f32>, n1_in: vec2<f32>) {
+    let radius = length(n0_in);
+    if radius == 0.0 {
+        return;
+    }
+    var n0 = n0_in;
+    var n1 = n1_in;
+    var angle = atan2(cross_2d(n0, n1), dot(n0, n1));
+    if angle > 0.0 {
+        n0 = n1_in;
+        n1 = n0_in;
+        angle = -angle;
+    }
+    // Largest angle between the points of the arc that keeps the chords within the accuracy.
+    let max_angle = 2.0 * acos(max(1.0 - stroke_accuracy / radius, 0.0));
+    let n_lines = clamp(u32(ceil(-angle / max_angle)), 1u, MAX_ARC_LINES);
+    let step = angle / f32(n_lines);
+    let rot = vec2(cos(step), sin(step));
+    output_line(center, center + n0);
+    var n = n0;
+    for (var i = 1u; i <= n_lines; i += 1u) {
+        var next = n1;
+        if i < n_lines {
+            next = vec2(n.x * rot.x - n.y * rot.y, n.x * rot.y + n.y * rot.x);
+        }
+        output_line(center + n, center + next);
+        n = next;
+    }
+    output_line(center + n1, center);
+}
+
+// Outputs a line of a stroke, expanded to the half widths `hw0` at its start and `hw1` at
+// its end.
+fn output_stroke_line(p0: vec2<f32>, p1: vec2<f32>, hw0: f32, hw1: f32) {
+    let d = p1 - p0;
+    let len = length(d);
+    if len == 0.0 {
+        return;
+    }
+    let n = vec2(-d.y, d.x) / len;
+    output_quad(p0 + hw0 * n, p1 + hw1 * n, p1 - hw1 * n, p0 - hw0 * n);
+}
+
+// Outputs the join at `p` between a curve ending with tangent `t0` and a curve starting with
+// tangent `t1`, with the half width `half_width`. Only the outer side of the join needs to be
+// filled, as the inner side is covered by the expanded curves.
+fn output_join(p: vec2<f32>, t0: vec2<f32>, t1: vec2<f32>, join: u32, half_width: f32) {
+    let len0 = length(t0);
+    let len1 = length(t1);
+    if len0 == 0.0 || len1 == 0.0 {
+        return;
+    }
+    let d0 = t0 / len0;
+    let d1 = t1 / len1;
+    let cr = cross_2d(d0, d1);
+    let d = dot(d0, d1);
+    if d > 0.0 && abs(cr) < 1e-6 {
+        return;
+    }
+    // The outer side of the join is opposite the direction the curve turns to.
+    let hw = select(half_width, -half_width, cr > 0.0);
+    let n0 = hw * vec2(-d0.y, d0.x);
+    let n1 = hw * vec2(-d1.y, d1.x);
+    if join == STYLE_JOIN_ROUND {
+        // Split the arc where it points away from both curves, so that each half is well
+        // defined even when the curve turns back on itself.
+        let mid = half_width * normalize(d0 - d1);
+        output_arc(p, n0, mid);
+        output_arc(p, mid, n1);
+    } else if join == STYLE_JOIN_MITER && sqrt(0.5 * (1.0 + d)) * stroke_miter_limit >= 1.0 {
+        let tip = p + (n0 + n1) / (1.0 + d);
+        output_quad(p, p + n0, tip, p + n1);
+    } else {
+        output_triangle(p, p + n0, p + n1);
+    }
+}
+
+// Outputs the cap at `p` of a curve leaving `p` in the direction `t`, with the half width
+// `half_width`.
+fn output_cap(p: vec2<f32>, t: vec2<f32>, cap: u32, half_width: f32) {
+    let len = length(t);
+    if cap == STYLE_CAP_BUTT || len == 0.0 {
+        return;
+    }
+    let d = (half_width / len) * t;
+    let n = vec2(-d.y, d.x);
+    if cap == STYLE_CAP_SQUARE {
+        output_quad(p + n, p + n + d, p - n + d, p - n);
+    } else {
+        output_arc(p, n, d);
+        output_arc(p, d, -n);
+    }
+}
+
+fn start_tangent(c: CubicPoints) -> vec2<f32> {
+    let d01 = c.p1 - c.p0;
+    let d02 = c.p2 - c.p0;
+    let d03 = c.p3 - c.p0;
+    return select(select(d03, d02, any(d02 != vec2(0.0))), d01, any(d01 != vec2(0.0)));
+}
+
+fn end_tangent(c: CubicPoints) -> vec2<f32> {
+    let d23 = c.p3 - c.p2;
+    let d13 = c.p3 - c.p1;
+    let d03 = c.p3 - c.p0;
+    return select(select(d03, d13, any(d13 != vec2(0.0))), d23, any(d23 != vec2(0.0)));
+}
+
 var<private> pathdata_base: u32;
 
 fn read_f32_point(ix: u32) -> vec2<f32> {
@@ -195,6 +415,121 @@ fn transform_apply(transform: Transform, p: vec2<f32>) -> vec2<f32> {
     return transform.mat.xy * p.x + transform.mat.zw * p.y + transform.translate;
 }
 
+// Returns the largest factor the transform scales a distance by.
+fn transform_max_scale(transform: Transform) -> f32 {
+    let m = transform.mat;
+    let det = m.x * m.w - m.y * m.z;
+    let s = dot(m, m);
+    return sqrt(0.5 * (s + sqrt(max(s * s - 4.0 * det * det, 0.0))));
+}
+
+fn read_tag_byte(ix: u32) -> u32 {
+    let tag_word = scene[config.pathtag_base + (ix >> 2u)];
+    return (tag_word >> ((ix & 3u) * 8u)) & 0xffu;
+}
+
+fn tag_monoid_at(ix: u32) -> TagMonoid {
+    let tag_word = scene[config.pathtag_base + (ix >> 2u)];
+    let shift = (ix & 3u) * 8u;
+    let tm = reduce_tag(tag_word & ((1u << shift) - 1u));
+    return combine_tag_monoid(tag_monoids[ix >> 2u], tm);
+}
+
+struct CubicPoints {
+    p0: vec2<f32>,
+    p1: vec2<f32>,
+    p2: vec2<f32>,
+    p3: vec2<f32>,
+}
+
+// Reads the path segment with the given tag, raised to a cubic, in the local coordinates of
+// the path.
+fn read_path_segment(tm_in: TagMonoid, tag_byte: u32) -> CubicPoints {
+    var tm = tm_in;
+    // The instance stream offsets the path data of each run of segments between instance
+    // tags, so that instances read the points of the segments they repeat.
+    tm.pathseg_offset += scene[config.instance_base + tm.instance_ix];
+    let seg_type = tag_byte & PATH_TAG_SEG_TYPE;
+    var p0: vec2<f32>;
+    var p1: vec2<f32>;
+    var p2: vec2<f32>;
+    var p3: vec2<f32>;
+    if (tag_byte & PATH_TAG_F32) != 0u {
+        p0 = read_f32_point(tm.pathseg_offset);
+        p1 = read_f32_point(tm.pathseg_offset + 2u);
+        if seg_type >= PATH_TAG_QUADTO {
+            p2 = read_f32_point(tm.pathseg_offset + 4u);
+            if seg_type == PATH_TAG_CUBICTO {
+                p3 = read_f32_point(tm.pathseg_offset + 6u);
+            }
+        }
+    } else {
+        p0 = read_i16_point(tm.pathseg_offset);
+        p1 = read_i16_point(tm.pathseg_offset + 1u);
+        if seg_type >= PATH_TAG_QUADTO {
+            p2 = read_i16_point(tm.pathseg_offset + 2u);
+            if seg_type == PATH_TAG_CUBICTO {
+                p3 = read_i16_point(tm.pathseg_offset + 3u);
+            }
+        }
+    }
+    // Degree-raise
+    if seg_type == PATH_TAG_LINETO {
+        p3 = p1;
+        p2 = mix(p3, p0, 1.0 / 3.0);
+        p1 = mix(p0, p3, 1.0 / 3.0);
+    } else if seg_type == PATH_TAG_QUADTO {
+        p3 = p2;
+        p2 = mix(p1, p2, 1.0 / 3.0);
+        p1 = mix(p1, p0, 1.0 / 3.0);
+    }
+    return CubicPoints(p0, p1, p2, p3);
+}
+
+// Returns true if the tag starts a subpath, which is the case when the previous tag is not a
+// segment or ends a subpath.
+fn is_subpath_start(ix: u32) -> bool {
+    if ix == 0u {
+        return true;
+    }
+    let prev = read_tag_byte(ix - 1u);
+    return (prev & PATH_TAG_SEG_TYPE) == 0u || (prev & PATH_TAG_SUBPATH_END) != 0u;
+}
+
+// Outputs the join or cap at the end of the segment, and the cap at its start if the segment
+// starts an open subpath. A subpath is considered closed if it ends where it starts.
+fn stroke_segment_ends(ix: u32, tag_byte: u32, seg: CubicPoints, style_flags: u32) {
+    let join = style_flags & STYLE_JOIN_MASK;
+    if (tag_byte & PATH_TAG_SUBPATH_END) == 0u {
+        let next = read_path_segment(tag_monoid_at(ix + 1u), read_tag_byte(ix + 1u));
+        output_join(seg.p3, end_tangent(seg), start_tangent(next), join, stroke_hw1);
+    } else {
+        var first_ix = ix;
+        while !is_subpath_start(first_ix) {
+            first_ix -= 1u;
+        }
+        let first = read_path_segment(tag_monoid_at(first_ix), read_tag_byte(first_ix));
+        if all(first.p0 == seg.p3) {
+            output_join(seg.p3, end_tangent(seg), start_tangent(first), join, stroke_hw1);
+        } else {
+            let cap = (style_flags >> STYLE_END_CAP_SHIFT) & STYLE_CAP_MASK;
+            output_cap(seg.p3, end_tangent(seg), cap, stroke_hw1);
+        }
+    }
+    if is_subpath_start(ix) {
+        let n_tags = (config.pathdata_base - config.pathtag_base) * 4u;
+        var last_ix = ix;
+        while (read_tag_byte(last_ix) & PATH_TAG_SUBPATH_END) == 0u && last_ix + 1u < n_tags {
+            last_ix += 1u;
+        }
+        let last = read_path_segment(tag_monoid_at(last_ix), read_tag_byte(last_ix));
+        if any(last.p3 != seg.p0) {
+            let cap = (style_flags >> STYLE_START_CAP_SHIFT) & STYLE_CAP_MASK;
+            output_cap(seg.p0, -start_tangent(seg), cap, stroke_hw0);
+        }
+    }
+}
+
 fn round_down(x: f32) -> i32 {
     return i32(floor(x));
 }
@@ -203,7 +538,14 @@ fn round_up(x: f32) -> i32 {
     return i32(ceil(x));
 }
 
-@compute @workgroup_size(256)
+// Returns whether lines within `bbox` can affect the target. Lines above, below or to the
+// right of the target are culled, but those to its left are kept, as they contribute to the
+// backdrop of the tiles to their right.
+fn is_visible(bbox: vec4<f32>) -> bool {
+    return bbox.x < f32(config.target_width) && bbox.y < f32(config.target_height) && bbox.w > 0.0;
+}
+
+@compute @workgroup_size(FLATTEN_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
@@ -218,7 +560,8 @@ fn main(
     var tag_byte = (tag_word >> shift) & 0xffu;
 
     let out = &path_bboxes[tm.path_ix];
-    let linewidth = bitcast<f32>(scene[config.linewidth_base + tm.linewidth_ix]);
+    let style_base = config.style_base + tm.style_ix * 3u;
+    let linewidth = bitcast<f32>(scene[style_base]);
     if (tag_byte & PATH_TAG_PATH) != 0u {
         (*out).linewidth = linewidth;
         (*out).trans_ix = tm.trans_ix;
@@ -226,60 +569,52 @@ fn main(
     // Decode path data
     let seg_type = tag_byte & PATH_TAG_SEG_TYPE;
     if seg_type != 0u {
-        var p0: vec2<f32>;
-        var p1: vec2<f32>;
-        var p2: vec2<f32>;
-        var p3: vec2<f32>;
-        if (tag_byte & PATH_TAG_F32) != 0u {
-            p0 = read_f32_point(tm.pathseg_offset);
-            p1 = read_f32_point(tm.pathseg_offset + 2u);
-            if seg_type >= PATH_TAG_QUADTO {
-                p2 = read_f32_point(tm.pathseg_offset + 4u);
-                if seg_type == PATH_TAG_CUBICTO {
-                    p3 = read_f32_point(tm.pathseg_offset + 6u);
-                }
+        let seg = read_path_segment(tm, tag_byte);
+        let transform = read_transform(config.transform_base, tm.trans_ix);
+        let p0 = transform_apply(transform, seg.p0);
+        let p1 = transform_apply(transform, seg.p1);
+        let p2 = transform_apply(transform, seg.p2);
+        let p3 = transform_apply(transform, seg.p3);
+        var bbox = vec4(min(min(p0, p1), min(p2, p3)), max(max(p0, p1), max(p2, p3)));
+        if linewidth >= 0.0 {
+            let style_flags = scene[style_base + 2u];
+            stroke_path_ix = tm.path_ix;
+            stroke_transform = transform;
+            let det = transform.mat.x * transform.mat.w - transform.mat.y * transform.mat.z;
+            stroke_flip = det < 0.0;
+            stroke_hw = 0.5 * linewidth;
+            stroke_hw0 = stroke_hw;
+            stroke_hw1 = stroke_hw;
+            if (style_flags & STYLE_VARIABLE_WIDTH) != 0u {
+                let width_base = config.width_base + tm.pathseg_ix * 2u;
+                stroke_hw0 = 0.5 * bitcast<f32>(scene[width_base]);
+                stroke_hw1 = 0.5 * bitcast<f32>(scene[width_base + 1u]);
             }
-        } else {
-            p0 = read_i16_point(tm.pathseg_offset);
-            p1 = read_i16_point(tm.pathseg_offset + 1u);
-            if seg_type >= PATH_TAG_QUADTO {
-                p2 = read_i16_point(tm.pathseg_offset + 2u);
-                if seg_type == PATH_TAG_CUBICTO {
-                    p3 = read_i16_point(tm.pathseg_offset + 3u);
-                }
+            stroke_miter_limit = bitcast<f32>(scene[style_base + 1u]);
+            stroke_accuracy = ACCURACY / max(transform_max_scale(transform), 1e-6);
+            // Miter joins and square caps extend further than half the line width.
+            var extent = 1.0;
+            if (style_flags & STYLE_JOIN_MASK) == STYLE_JOIN_MITER {
+                extent = max(extent, stroke_miter_limit);
             }
-        }
-        let transform = read_transform(config.transform_base, tm.trans_ix);
-        p0 = transform_apply(transform, p0);
-        p1 = transform_apply(transform, p1);
-        var bbox = vec4(min(p0, p1), max(p0, p1));
-        // Degree-raise
-        if seg_type == PATH_TAG_LINETO {
-            p3 = p1;
-            p2 = mix(p3, p0, 1.0 / 3.0);
-            p1 = mix(p0, p3, 1.0 / 3.0);
-        } else if seg_type >= PATH_TAG_QUADTO {
-            p2 = transform_apply(transform, p2);
-            bbox = vec4(min(bbox.xy, p2), max(bbox.zw, p2));
-            if seg_type == PATH_TAG_CUBICTO {
-                p3 = transform_apply(transform, p3);
-                bbox = vec4(min(bbox.xy, p3), max(bbox.zw, p3));
-            } else {
-                p3 = p2;
-                p2 = mix(p1, p2, 1.0 / 3.0);
-                p1 = mix(p1, p0, 1.0 / 3.0);
+            let start_cap = (style_flags >> STYLE_START_CAP_SHIFT) & STYLE_CAP_MASK;
+            let end_cap = (style_flags >> STYLE_END_CAP_SHIFT) & STYLE_CAP_MASK;
+            if start_cap == STYLE_CAP_SQUARE || end_cap == STYLE_CAP_SQUARE {
+                extent = max(extent, sqrt(2.0));
             }
-        }
-        var stroke = vec2(0.0, 0.0);
-        if linewidth >= 0.0 {
             // See https://www.iquilezles.org/www/articles/ellipses/ellipses.htm
-            // This is the correct bounding box, but we're not handling rendering
-            // in the isotropic case, so it may mismatch.
-            stroke = 0.5 * linewidth * vec2(length(transform.mat.xz), length(transform.mat.yw));
+            // This is the correct bounding box of the transformed circle around each point.
+            let radius = stroke_hw * extent;
+            let stroke = radius * vec2(length(transform.mat.xz), length(transform.mat.yw));
             bbox += vec4(-stroke, stroke);
+            if is_visible(bbox) {
+                let cubic = Cubic(seg.p0, seg.p1, seg.p2, seg.p3, vec2(stroke_hw), tm.path_ix, CUBIC_IS_STROKE);
+                flatten_cubic(cubic, stroke_accuracy);
+                stroke_segment_ends(ix, tag_byte, seg, style_flags);
+            }
+        } else if is_visible(bbox) {
+            flatten_cubic(Cubic(p0, p1, p2, p3, vec2(0.0), tm.path_ix, 0u), ACCURACY);
         }
-        let flags = u32(linewidth >= 0.0);
-        flatten_cubic(Cubic(p0, p1, p2, p3, stroke, tm.path_ix, flags));
         // Update bounding box using atomics only. Computing a monoid is a
         // potential future optimization.
         if bbox.z > bbox.x || bbox.w > bbox.y {
@@ -47,7 +47,7 @@ let ROBUST_EPSILON: f32 = 2e-7;
 // robustness issue.
 
 // This shader is dispatched with one thread for each line.
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_COUNT_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
@@ -189,7 +189,15 @@ fn main(
             // Pack two count values into a single u32
             let counts = (seg_within_slice << 16u) | subix;
             let seg_count = SegmentCount(line_ix, counts);
+#ifdef bounds_checks
+            if seg_base + i - imin < arrayLength(&seg_counts) {
+                seg_counts[seg_base + i - imin] = seg_count;
+            } else {
+                atomicOr(&bump.failed, STAGE_PATH_COUNT);
+            }
+#else
             seg_counts[seg_base + i - imin] = seg_count;
+#endif
             // Note: since we're iterating, we have a reliable value for
             // last_z.
             last_z = z;
@@ -11,7 +11,7 @@ var<storage> bump: BumpAllocators;
 var<storage, read_write> indirect: IndirectCount;
 
 // Partition size for path count stage
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_COUNT_WG;
 
 @compute @workgroup_size(1)
 fn main() {
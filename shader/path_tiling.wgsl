@@ -35,7 +35,7 @@ let ROBUST_EPSILON: f32 = 2e-7;
 
 // One invocation for each tile that is to be written.
 // Total number of invocations = bump.seg_counts
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_COUNT_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
@@ -133,6 +133,13 @@ fn main(
             xy1 = tmp;
         }
         let segment = Segment(xy0, xy1 - xy0, y_edge);
+#ifdef bounds_checks
+        // The bump allocators are read-only here, so an overflow can't be reported.
+        if seg_start + seg_within_slice < arrayLength(&segments) {
+            segments[seg_start + seg_within_slice] = segment;
+        }
+#else
         segments[seg_start + seg_within_slice] = segment;
+#endif
     }
 }
@@ -12,12 +12,11 @@ var<storage> scene: array<u32>;
 @group(0) @binding(2)
 var<storage, read_write> reduced: array<TagMonoid>;
 
-let LG_WG_SIZE = 8u;
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_REDUCE_WG;
 
 var<workgroup> sh_scratch: array<TagMonoid, WG_SIZE>;
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_REDUCE_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
@@ -36,6 +35,6 @@ fn main(
         sh_scratch[local_id.x] = agg;
     }
     if local_id.x == 0u {
-        reduced[ix >> LG_WG_SIZE] = agg;
+        reduced[ix >> firstTrailingBit(WG_SIZE)] = agg;
     }
 }
@@ -9,14 +9,20 @@ var<uniform> config: Config;
 @group(0) @binding(1)
 var<storage> scene: array<u32>;
 
+#ifdef lookback
+// The first word is the counter partitions are allocated from, followed by the published
+// state of each field of the monoid of each partition.
+@group(0) @binding(2)
+var<storage, read_write> scan_state: array<atomic<u32>>;
+#else
 @group(0) @binding(2)
 var<storage> reduced: array<TagMonoid>;
+#endif
 
 @group(0) @binding(3)
 var<storage, read_write> tag_monoids: array<TagMonoid>;
 
-let LG_WG_SIZE = 8u;
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_REDUCE_WG;
 
 #ifdef small
 var<workgroup> sh_parent: array<TagMonoid, WG_SIZE>;
@@ -24,19 +30,65 @@ var<workgroup> sh_parent: array<TagMonoid, WG_SIZE>;
 // These could be combined?
 var<workgroup> sh_monoid: array<TagMonoid, WG_SIZE>;
 
-@compute @workgroup_size(256)
+#ifdef lookback
+let N_FIELDS = 6u;
+// Each field is published with a flag in its top two bits, so that a value and whether it
+// is ready are read in one atomic load. This limits the fields to 30 bits.
+let FLAG_AGGREGATE = 0x40000000u;
+let FLAG_PREFIX = 0x80000000u;
+let FLAG_MASK = 0xc0000000u;
+
+var<workgroup> sh_part_ix: u32;
+var<workgroup> sh_prefix: array<u32, N_FIELDS>;
+
+fn tag_monoid_field(m: TagMonoid, i: u32) -> u32 {
+    switch i {
+        case 0u: {
+            return m.trans_ix;
+        }
+        case 1u: {
+            return m.pathseg_ix;
+        }
+        case 2u: {
+            return m.pathseg_offset;
+        }
+        case 3u: {
+            return m.style_ix;
+        }
+        case 4u: {
+            return m.path_ix;
+        }
+        default: {
+            return m.instance_ix;
+        }
+    }
+}
+#endif
+
+@compute @workgroup_size(PATH_REDUCE_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
     @builtin(workgroup_id) wg_id: vec3<u32>,
 ) {
+#ifdef lookback
+    // Partitions are numbered in the order workgroups start rather than by workgroup id, so
+    // that a partition only waits on partitions whose workgroups are already running.
+    if local_id.x == 0u {
+        sh_part_ix = atomicAdd(&scan_state[0], 1u);
+    }
+    let part_ix = workgroupUniformLoad(&sh_part_ix);
+    let ix = part_ix * WG_SIZE + local_id.x;
+#else
+    let ix = global_id.x;
+#endif
 #ifdef small
     var agg = tag_monoid_identity();
     if local_id.x < wg_id.x {
         agg = reduced[local_id.x];
     }
     sh_parent[local_id.x] = agg;
-    for (var i = 0u; i < LG_WG_SIZE; i += 1u) {
+    for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
         workgroupBarrier();
         if local_id.x + (1u << i) < WG_SIZE {
             let other = sh_parent[local_id.x + (1u << i)];
@@ -47,11 +99,10 @@ fn main(
     }
 #endif
 
-    let ix = global_id.x;
     let tag_word = scene[config.pathtag_base + ix];
     var agg_part = reduce_tag(tag_word);
     sh_monoid[local_id.x] = agg_part;
-    for (var i = 0u; i < LG_WG_SIZE; i += 1u) {
+    for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
         workgroupBarrier();
         if local_id.x >= 1u << i {
             let other = sh_monoid[local_id.x - (1u << i)];
@@ -61,11 +112,47 @@ fn main(
         sh_monoid[local_id.x] = agg_part;
     }
     workgroupBarrier();
+#ifdef lookback
+    // Publish the aggregate of this partition, then look back through the preceding
+    // partitions until one has published its inclusive prefix, one thread per field.
+    if local_id.x < N_FIELDS {
+        let field = local_id.x;
+        let state_ix = 1u + part_ix * N_FIELDS + field;
+        let agg = tag_monoid_field(sh_monoid[WG_SIZE - 1u], field);
+        var prefix = 0u;
+        if part_ix == 0u {
+            atomicStore(&scan_state[state_ix], FLAG_PREFIX | agg);
+        } else {
+            atomicStore(&scan_state[state_ix], FLAG_AGGREGATE | agg);
+            var look_ix = part_ix - 1u;
+            loop {
+                let state = atomicLoad(&scan_state[1u + look_ix * N_FIELDS + field]);
+                let flag = state & FLAG_MASK;
+                if flag == 0u {
+                    // Not published yet
+                    continue;
+                }
+                prefix += state & ~FLAG_MASK;
+                if flag == FLAG_PREFIX {
+                    break;
+                }
+                look_ix -= 1u;
+            }
+            atomicStore(&scan_state[state_ix], FLAG_PREFIX | (prefix + agg));
+        }
+        sh_prefix[field] = prefix;
+    }
+    workgroupBarrier();
+    // prefix up to this partition
+    var tm = TagMonoid(sh_prefix[0], sh_prefix[1], sh_prefix[2],
+        sh_prefix[3], sh_prefix[4], sh_prefix[5]);
+#else
     // prefix up to this workgroup
 #ifdef small
     var tm = sh_parent[0];
 #else
     var tm = reduced[wg_id.x];
+#endif
 #endif
     if local_id.x > 0u {
         tm = combine_tag_monoid(tm, sh_monoid[local_id.x - 1u]);
@@ -15,14 +15,13 @@ var<storage> reduced2: array<TagMonoid>;
 @group(0) @binding(2)
 var<storage, read_write> tag_monoids: array<TagMonoid>;
 
-let LG_WG_SIZE = 8u;
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_REDUCE_WG;
 
 var<workgroup> sh_parent: array<TagMonoid, WG_SIZE>;
 // These could be combined?
 var<workgroup> sh_monoid: array<TagMonoid, WG_SIZE>;
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_REDUCE_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
@@ -33,7 +32,7 @@ fn main(
         agg = reduced2[local_id.x];
     }
     sh_parent[local_id.x] = agg;
-    for (var i = 0u; i < LG_WG_SIZE; i += 1u) {
+    for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
         workgroupBarrier();
         if local_id.x + (1u << i) < WG_SIZE {
             let other = sh_parent[local_id.x + (1u << i)];
@@ -46,7 +45,7 @@ fn main(
     let ix = global_id.x;
     agg = reduced[ix];
     sh_monoid[local_id.x] = agg;
-    for (var i = 0u; i < LG_WG_SIZE; i += 1u) {
+    for (var i = 0u; i < firstTrailingBit(WG_SIZE); i += 1u) {
         workgroupBarrier();
         if local_id.x >= 1u << i {
             let other = sh_monoid[local_id.x - (1u << i)];
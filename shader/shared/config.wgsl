@@ -29,13 +29,28 @@ struct Config {
     drawdata_base: u32,
 
     transform_base: u32,
-    linewidth_base: u32,
+    style_base: u32,
+    width_base: u32,
+    instance_base: u32,
 
     // Sizes of bump allocated buffers (in element size units)
     binning_size: u32,
     tiles_size: u32,
     segments_size: u32,    
     ptcl_size: u32,
+    blend_size: u32,
+
+    // Offset in the output image of the top left corner of the target, used when the
+    // target is rendered in bands or regions.
+    target_x_offset: u32,
+    target_y_offset: u32,
+
+    // Non-zero if fine rasterization blends in linear light. Input colors are converted
+    // to linear when the scene is packed, and the output is encoded to sRGB.
+    linear_blending: u32,
+
+    // Non-zero if the colors written to 8 bit outputs are dithered to avoid banding.
+    dithering: u32,
 }
 
 // Geometry of tiles and bins
@@ -0,0 +1,80 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT OR Unlicense
+
+// Assertions and prints for debugging shaders without a graphics debugger. In shaders built
+// with the debug_log define, they record into a log which the engine reads back and prints
+// after each recording, with the file and line of each call. Otherwise they do nothing, so
+// calls can be left in place.
+//
+// Calls pass __LINE__, which the preprocessor replaces with the line of the preprocessed
+// output, for example `debug_assert(ix < n_tiles, __LINE__);`.
+//
+// The log is bound after the other bindings of the shader, at DEBUG_LOG_BINDING, and the
+// engine identifies the shader by DEBUG_LOG_SHADER; both are substituted by the engine. It
+// holds the number of words written, followed by the records. Each record is a header, with
+// the kind in the top 4 bits, the shader in the next 8, the line in the next 16 and the number
+// of values in the low 4, followed by the values. Records past the end of the log are dropped,
+// but still counted.
+
+// This must be kept in sync with debug_log.rs
+let DEBUG_ASSERT = 1u;
+let DEBUG_PRINT_U32 = 2u;
+let DEBUG_PRINT_I32 = 3u;
+let DEBUG_PRINT_F32 = 4u;
+
+#ifdef debug_log
+struct DebugLog {
+    n_words: atomic<u32>,
+    words: array<u32>,
+}
+
+@group(0) @binding(DEBUG_LOG_BINDING)
+var<storage, read_write> debug_log: DebugLog;
+
+fn debug_record(kind: u32, line: u32, values: vec4<u32>, n_values: u32) {
+    let ix = atomicAdd(&debug_log.n_words, n_values + 1u);
+    if ix + n_values + 1u > arrayLength(&debug_log.words) {
+        return;
+    }
+    debug_log.words[ix] = (kind << 28u) | (DEBUG_LOG_SHADER << 20u) | ((line & 0xffffu) << 4u)
+        | n_values;
+    for (var i = 0u; i < n_values; i += 1u) {
+        debug_log.words[ix + 1u + i] = values[i];
+    }
+}
+#else
+fn debug_record(kind: u32, line: u32, values: vec4<u32>, n_values: u32) {}
+#endif
+
+fn debug_assert(condition: bool, line: u32) {
+    if !condition {
+        debug_record(DEBUG_ASSERT, line, vec4(0u), 0u);
+    }
+}
+
+fn debug_print(line: u32, value: u32) {
+    debug_record(DEBUG_PRINT_U32, line, vec4(value, 0u, 0u, 0u), 1u);
+}
+
+fn debug_print2(line: u32, a: u32, b: u32) {
+    debug_record(DEBUG_PRINT_U32, line, vec4(a, b, 0u, 0u), 2u);
+}
+
+fn debug_print4(line: u32, values: vec4<u32>) {
+    debug_record(DEBUG_PRINT_U32, line, values, 4u);
+}
+
+fn debug_print_i32(line: u32, value: i32) {
+    debug_record(DEBUG_PRINT_I32, line, vec4(bitcast<u32>(value), 0u, 0u, 0u), 1u);
+}
+
+fn debug_print_f32(line: u32, value: f32) {
+    debug_record(DEBUG_PRINT_F32, line, vec4(bitcast<u32>(value), 0u, 0u, 0u), 1u);
+}
+
+fn debug_print_vec2f(line: u32, value: vec2<f32>) {
+    debug_record(DEBUG_PRINT_F32, line, vec4(bitcast<vec2<u32>>(value), 0u, 0u), 2u);
+}
+
+fn debug_print_vec4f(line: u32, value: vec4<f32>) {
+    debug_record(DEBUG_PRINT_F32, line, bitcast<vec4<u32>>(value), 4u);
+}
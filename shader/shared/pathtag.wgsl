@@ -6,8 +6,9 @@ struct TagMonoid {
     pathseg_ix: u32,
     pathseg_offset: u32,
 #ifdef full
-    linewidth_ix: u32,
+    style_ix: u32,
     path_ix: u32,
+    instance_ix: u32,
 #endif
 }
 
@@ -15,11 +16,13 @@ let PATH_TAG_SEG_TYPE = 3u;
 let PATH_TAG_LINETO = 1u;
 let PATH_TAG_QUADTO = 2u;
 let PATH_TAG_CUBICTO = 3u;
+let PATH_TAG_SUBPATH_END = 4u;
 let PATH_TAG_F32 = 8u;
 let PATH_TAG_TRANSFORM = 0x20u;
 #ifdef full
 let PATH_TAG_PATH = 0x10u;
-let PATH_TAG_LINEWIDTH = 0x40u;
+let PATH_TAG_STYLE = 0x40u;
+let PATH_TAG_INSTANCE = 0x80u;
 #endif
 
 fn tag_monoid_identity() -> TagMonoid {
@@ -32,8 +35,9 @@ fn combine_tag_monoid(a: TagMonoid, b: TagMonoid) -> TagMonoid {
     c.pathseg_ix = a.pathseg_ix + b.pathseg_ix;
     c.pathseg_offset = a.pathseg_offset + b.pathseg_offset;
 #ifdef full
-    c.linewidth_ix = a.linewidth_ix + b.linewidth_ix;
+    c.style_ix = a.style_ix + b.style_ix;
     c.path_ix = a.path_ix + b.path_ix;
+    c.instance_ix = a.instance_ix + b.instance_ix;
 #endif
     return c;
 }
@@ -50,7 +54,8 @@ fn reduce_tag(tag_word: u32) -> TagMonoid {
     c.pathseg_offset = a & 0xffu;
 #ifdef full
     c.path_ix = countOneBits(tag_word & (PATH_TAG_PATH * 0x1010101u));
-    c.linewidth_ix = countOneBits(tag_word & (PATH_TAG_LINEWIDTH * 0x1010101u));
+    c.style_ix = countOneBits(tag_word & (PATH_TAG_STYLE * 0x1010101u));
+    c.instance_ix = countOneBits(tag_word & (PATH_TAG_INSTANCE * 0x1010101u));
 #endif
     return c;
 }
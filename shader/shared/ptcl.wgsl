@@ -20,6 +20,8 @@ let CMD_IMAGE = 8u;
 let CMD_BEGIN_CLIP = 9u;
 let CMD_END_CLIP = 10u;
 let CMD_JUMP = 11u;
+// A color which covers the whole tile opaquely.
+let CMD_SOLID_COLOR = 12u;
 
 // The individual PTCL structs are written here, but read/write is by
 // hand in the relevant shaders
@@ -62,11 +64,16 @@ struct CmdRadGrad {
     flags: u32,
 }
 
+// Set in the packed atlas coordinates of external images, whose slot is in the low bits.
+let EXTERNAL_IMAGE_FLAG = 0x80000000u;
+
 struct CmdImage {
     matrx: vec4<f32>,
     xlat: vec2<f32>,
     atlas_offset: vec2<f32>,
     extents: vec2<f32>,
+    // 0 for the atlas, or 1 plus the slot of an external image.
+    source: u32,
 }
 
 struct CmdEndClip {
@@ -25,13 +25,13 @@ var<storage, read_write> paths: array<Path>;
 @group(0) @binding(5)
 var<storage, read_write> tiles: array<Tile>;
 
-let WG_SIZE = 256u;
+let WG_SIZE = PATH_BBOX_WG;
 
 var<workgroup> sh_tile_count: array<u32, WG_SIZE>;
 var<workgroup> sh_tile_offset: u32;
 var<workgroup> sh_atomic_failed: u32;
 
-@compute @workgroup_size(256)
+@compute @workgroup_size(PATH_BBOX_WG)
 fn main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
     @builtin(local_invocation_id) local_id: vec3<u32>,
@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: Apache-2.0 OR MIT OR Unlicense
+
+// List the tiles which have commands for an indirect dispatch of the fine stage, and fill the
+// tiles without any with the background color directly.
+
+#import config
+#import ptcl
+
+@group(0) @binding(0)
+var<uniform> config: Config;
+
+@group(0) @binding(1)
+var<storage> ptcl: array<u32>;
+
+// Same layout as IndirectCount, with an atomic to append tiles.
+struct TileListCount {
+    count_x: atomic<u32>,
+    count_y: u32,
+    count_z: u32,
+}
+
+@group(0) @binding(2)
+var<storage, read_write> indirect: TileListCount;
+
+@group(0) @binding(3)
+var<storage, read_write> tile_list: array<u32>;
+
+// The output format is selected with one of the output_* definitions, and defaults to rgba8.
+#if defined(output_rgba16float)
+@group(0) @binding(4)
+var output: texture_storage_2d<rgba16float, write>;
+#elif defined(output_bgra8)
+@group(0) @binding(4)
+var output: texture_storage_2d<bgra8unorm, write>;
+#else
+@group(0) @binding(4)
+var output: texture_storage_2d<rgba8unorm, write>;
+#endif
+
+// One workgroup per tile, and one thread per pixel.
+@compute @workgroup_size(16, 16)
+fn main(
+    @builtin(global_invocation_id) global_id: vec3<u32>,
+    @builtin(local_invocation_index) local_ix: u32,
+    @builtin(workgroup_id) wg_id: vec3<u32>,
+) {
+    let tile_ix = wg_id.y * config.width_in_tiles + wg_id.x;
+    // The first word of the command list is the blend offset.
+    let is_empty = ptcl[tile_ix * PTCL_INITIAL_ALLOC + 1u] == CMD_END;
+    if is_empty {
+        let coords = global_id.xy;
+        if coords.x < config.target_width && coords.y < config.target_height {
+            let fg = unpack4x8unorm(config.base_color).wzyx;
+            // Same conversion as at the end of fine
+            let a_inv = 1.0 / max(fg.a, 1e-6);
+            let rgba_sep = vec4(fg.rgb * a_inv, fg.a);
+            textureStore(output, vec2<i32>(coords + vec2(config.target_x_offset, config.target_y_offset)), rgba_sep);
+        }
+    } else if local_ix == 0u {
+        let ix = atomicAdd(&indirect.count_x, 1u);
+        tile_list[ix] = tile_ix;
+    }
+    if tile_ix == 0u && local_ix == 0u {
+        indirect.count_y = 1u;
+        indirect.count_z = 1u;
+    }
+}
@@ -0,0 +1,290 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Drawing of scenes over Bevy cameras, through a node of the render graph.
+//!
+//! [`VelloPlugin`] creates a renderer with the render device of Bevy, and adds a node after
+//! the post processing of the 2D and 3D core graphs. For each camera with a [`VelloScene`],
+//! the node renders the scene to a texture the size of the viewport and draws that texture
+//! over the main texture of the camera, before it is upscaled to the output, so user
+//! interfaces and debug overlays aren't affected by tonemapping.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use bevy::core_pipeline::{core_2d, core_3d};
+use bevy::ecs::query::QueryItem;
+use bevy::prelude::*;
+use bevy::render::camera::ExtractedCamera;
+use bevy::render::extract_component::{ExtractComponent, ExtractComponentPlugin};
+use bevy::render::render_graph::{
+    NodeRunError, RenderGraphApp, RenderGraphContext, ViewNode, ViewNodeRunner,
+};
+use bevy::render::renderer::{RenderContext, RenderDevice, RenderQueue};
+use bevy::render::view::ViewTarget;
+use bevy::render::{Render, RenderApp, RenderSet};
+use peniko::Color;
+
+use crate::composite::CompositePipeline;
+use crate::{
+    AaConfig, RenderParams, Renderer, RendererOptions, Scene, SceneBuilder, SceneFragment,
+};
+
+/// Name of the node in the 2D and 3D core graphs.
+pub const VELLO_NODE: &str = "vello";
+
+/// Plugin which draws the [`VelloScene`] of each camera over the camera.
+pub struct VelloPlugin;
+
+impl Plugin for VelloPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugins(ExtractComponentPlugin::<VelloScene>::default());
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app
+            .add_systems(Render, prune_targets.in_set(RenderSet::Prepare))
+            .add_render_graph_node::<ViewNodeRunner<VelloNode>>(core_2d::graph::NAME, VELLO_NODE)
+            .add_render_graph_edges(
+                core_2d::graph::NAME,
+                &[
+                    core_2d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+                    VELLO_NODE,
+                    core_2d::graph::node::UPSCALING,
+                ],
+            )
+            .add_render_graph_node::<ViewNodeRunner<VelloNode>>(core_3d::graph::NAME, VELLO_NODE)
+            .add_render_graph_edges(
+                core_3d::graph::NAME,
+                &[
+                    core_3d::graph::node::END_MAIN_PASS_POST_PROCESSING,
+                    VELLO_NODE,
+                    core_3d::graph::node::UPSCALING,
+                ],
+            );
+    }
+
+    fn finish(&self, app: &mut App) {
+        let Ok(render_app) = app.get_sub_app_mut(RenderApp) else {
+            return;
+        };
+        render_app.init_resource::<VelloRenderer>();
+    }
+}
+
+/// Scene drawn over a camera, with the origin of the scene at the top left corner of its
+/// viewport and one unit of the scene per physical pixel.
+#[derive(Component, Default)]
+pub struct VelloScene(pub SceneFragment);
+
+/// Scene of a camera in the render world.
+#[derive(Component)]
+pub struct ExtractedVelloScene(Scene);
+
+impl ExtractComponent for VelloScene {
+    type Query = &'static Self;
+
+    type Filter = ();
+
+    type Out = ExtractedVelloScene;
+
+    fn extract_component(fragment: QueryItem<'_, Self::Query>) -> Option<Self::Out> {
+        let mut scene = Scene::default();
+        let mut builder = SceneBuilder::for_scene(&mut scene);
+        builder.append(&fragment.0, None);
+        Some(ExtractedVelloScene(scene))
+    }
+}
+
+/// Renderer of the scenes of cameras, in the render world.
+///
+/// The renderer is locked while a node renders, as nodes only have shared access to the
+/// world.
+#[derive(Resource)]
+pub struct VelloRenderer(Mutex<RendererState>);
+
+struct RendererState {
+    renderer: Renderer,
+    /// Textures scenes are rendered to, by camera.
+    targets: HashMap<Entity, CameraTarget>,
+    /// Composite pipelines, by format of the main textures they draw to.
+    composites: HashMap<wgpu::TextureFormat, CompositePipeline>,
+}
+
+/// Texture the scene of a camera is rendered to, kept between frames.
+struct CameraTarget {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    format: wgpu::TextureFormat,
+    width: u32,
+    height: u32,
+}
+
+impl FromWorld for VelloRenderer {
+    fn from_world(world: &mut World) -> Self {
+        let device = world.resource::<RenderDevice>();
+        let queue = world.resource::<RenderQueue>();
+        let renderer = Renderer::new(
+            device.wgpu_device(),
+            &RendererOptions {
+                surface_format: None,
+                timestamp_period: queue.get_timestamp_period(),
+                use_cpu: false,
+                num_init_threads: None,
+                memory_budget_bytes: None,
+                compact_tiles: false,
+                antialiasing_method: AaConfig::Area,
+                linear_blending: false,
+                dithering: false,
+                output_format: wgpu::TextureFormat::Rgba8Unorm,
+                split_submissions: false,
+                single_pass_scan: false,
+                shader_source: None,
+            },
+        )
+        .expect("failed to create Vello renderer");
+        Self(Mutex::new(RendererState {
+            renderer,
+            targets: HashMap::new(),
+            composites: HashMap::new(),
+        }))
+    }
+}
+
+impl RendererState {
+    /// Creates the texture for `entity`, unless it exists with the same size and main
+    /// texture format.
+    fn prepare_target(
+        &mut self,
+        device: &wgpu::Device,
+        entity: Entity,
+        format: wgpu::TextureFormat,
+        width: u32,
+        height: u32,
+    ) {
+        let target = self.targets.get(&entity);
+        if target.map_or(false, |target| {
+            (target.format, target.width, target.height) == (format, width, height)
+        }) {
+            return;
+        }
+        // Main textures hold linear colors, in sRGB or HDR formats.
+        let composite = self
+            .composites
+            .entry(format)
+            .or_insert_with(|| CompositePipeline::new(device, format, 1, true));
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("bevy scene target"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = composite.bind_group(device, &view);
+        self.targets.insert(
+            entity,
+            CameraTarget {
+                view,
+                bind_group,
+                format,
+                width,
+                height,
+            },
+        );
+    }
+}
+
+/// Drops the textures of cameras which no longer have a scene.
+fn prune_targets(renderer: Res<VelloRenderer>, views: Query<Entity, With<ExtractedVelloScene>>) {
+    let mut state = renderer.0.lock().unwrap();
+    state.targets.retain(|entity, _| views.contains(*entity));
+}
+
+/// Render graph node which draws the scene of a camera over its main texture.
+#[derive(Default)]
+pub struct VelloNode;
+
+impl ViewNode for VelloNode {
+    type ViewQuery = (
+        &'static ViewTarget,
+        &'static ExtractedCamera,
+        &'static ExtractedVelloScene,
+    );
+
+    fn run(
+        &self,
+        graph: &mut RenderGraphContext,
+        render_context: &mut RenderContext,
+        (view_target, camera, scene): QueryItem<Self::ViewQuery>,
+        world: &World,
+    ) -> Result<(), NodeRunError> {
+        let Some(size) = camera.physical_viewport_size else {
+            return Ok(());
+        };
+        if size.x == 0 || size.y == 0 {
+            return Ok(());
+        }
+        let entity = graph.view_entity();
+        let render_device = render_context.render_device().clone();
+        let device = render_device.wgpu_device();
+        let queue = world.resource::<RenderQueue>();
+        let mut state = world.resource::<VelloRenderer>().0.lock().unwrap();
+        let state = &mut *state;
+        state.prepare_target(
+            device,
+            entity,
+            view_target.main_texture_format(),
+            size.x,
+            size.y,
+        );
+        let target = &state.targets[&entity];
+        let params = RenderParams {
+            base_color: Color::TRANSPARENT,
+            width: size.x,
+            height: size.y,
+        };
+        // The renderer submits its own work, which runs before the commands of the graph.
+        if let Err(e) =
+            state
+                .renderer
+                .render_to_texture(device, queue, &scene.0, &target.view, &params)
+        {
+            error!("Failed to render Vello scene: {e}");
+            return Ok(());
+        }
+        let composite = &state.composites[&target.format];
+        let mut render_pass =
+            render_context
+                .command_encoder()
+                .begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("vello composite pass"),
+                    color_attachments: &[Some(view_target.get_unsampled_color_attachment(
+                        wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    ))],
+                    depth_stencil_attachment: None,
+                });
+        if let Some(viewport) = &camera.viewport {
+            render_pass.set_viewport(
+                viewport.physical_position.x as f32,
+                viewport.physical_position.y as f32,
+                viewport.physical_size.x as f32,
+                viewport.physical_size.y as f32,
+                viewport.depth.start,
+                viewport.depth.end,
+            );
+        }
+        composite.draw(&mut render_pass, &target.bind_group);
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Encoding of bitmap glyphs from the CBLC/CBDT and sbix tables.
+//!
+//! Fonts that only have bitmap strikes, such as most emoji fonts, are drawn by decoding the PNG
+//! bitmap of each glyph and drawing it as an image, which is placed in the image atlas when the
+//! scene is resolved. Bitmaps in formats other than PNG are skipped, and without the
+//! `bitmap_glyphs` feature, which decodes them with the `png` crate, so are all bitmaps.
+//!
+//! Decoded bitmaps are cached for the process, keyed by font, glyph and strike, so a glyph is
+//! decoded once and its image keeps the same blob, and so the same atlas slot, across scenes.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use fello::raw::{types::Tag, FontRef, TableProvider};
+use peniko::{
+    kurbo::{Affine, Rect},
+    Blob, Fill, Font, Format, Image,
+};
+use vello_encoding::{Encoding, Glyph, Transform};
+
+/// Number of decoded bitmaps above which the cache is cleared.
+const MAX_CACHED_BITMAPS: usize = 1024;
+
+/// Identifies a decoded bitmap: the font data and index, the glyph id and the size of the
+/// strike in pixels per em.
+type BitmapKey = (u64, u32, u16, u16);
+
+fn bitmap_cache() -> &'static Mutex<HashMap<BitmapKey, Option<Arc<Bitmap>>>> {
+    static CACHE: OnceLock<Mutex<HashMap<BitmapKey, Option<Arc<Bitmap>>>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// Bitmap strikes of a font.
+pub struct BitmapGlyphs<'a> {
+    /// Id of the font data and index of the font in it.
+    font: (u64, u32),
+    strikes: Strikes<'a>,
+}
+
+enum Strikes<'a> {
+    Cbdt { cblc: &'a [u8], cbdt: &'a [u8] },
+    Sbix { sbix: &'a [u8], num_glyphs: u16 },
+}
+
+/// Decoded bitmap of a glyph.
+struct Bitmap {
+    image: Image,
+    /// Size of the strike in pixels per em.
+    ppem: f32,
+    /// Offset from the glyph origin to the top left corner of the bitmap, in pixels of the
+    /// strike with y up.
+    left: f32,
+    top: f32,
+}
+
+/// PNG data of a glyph in a strike, before decoding.
+struct BitmapSource<'a> {
+    png: &'a [u8],
+    ppem: u16,
+    left: f32,
+    /// Offset to the top of the bitmap for CBDT, or to its bottom for sbix, whose metrics
+    /// don't include the height.
+    vertical: Vertical,
+}
+
+enum Vertical {
+    Top(f32),
+    Bottom(f32),
+}
+
+impl<'a> BitmapGlyphs<'a> {
+    /// Returns the bitmap strikes of `font`, parsed as `font_ref`, if it has no outlines to
+    /// draw instead.
+    pub fn new(font: &Font, font_ref: FontRef<'a>) -> Option<Self> {
+        let table = |tag: &[u8; 4]| {
+            font_ref
+                .table_data(Tag::new(tag))
+                .map(|data| data.as_bytes())
+        };
+        if !cfg!(feature = "bitmap_glyphs") {
+            return None;
+        }
+        if table(b"glyf").is_some() || table(b"CFF ").is_some() || table(b"CFF2").is_some() {
+            return None;
+        }
+        let strikes = if let (Some(cblc), Some(cbdt)) = (table(b"CBLC"), table(b"CBDT")) {
+            Strikes::Cbdt { cblc, cbdt }
+        } else {
+            let sbix = table(b"sbix")?;
+            let num_glyphs = font_ref.maxp().ok()?.num_glyphs();
+            Strikes::Sbix { sbix, num_glyphs }
+        };
+        Some(Self {
+            font: (font.data.id(), font.index),
+            strikes,
+        })
+    }
+
+    /// Encodes the glyphs as images.
+    ///
+    /// The glyphs are placed the same way as the outlines of a glyph run with the given
+    /// transforms and size. Each glyph is drawn from the smallest strike at least as large as
+    /// the font size in the scene, or the largest strike if there is none.
+    pub fn encode(
+        &self,
+        encoding: &mut Encoding,
+        transform: Transform,
+        glyph_transform: Option<Transform>,
+        font_size: f32,
+        glyphs: &[Glyph],
+        alpha: f32,
+    ) {
+        let run_transform = transform.to_kurbo();
+        let glyph_transform = glyph_transform.map_or(Affine::IDENTITY, |xform| xform.to_kurbo());
+        let scene_ppem = font_size * run_transform.determinant().abs().sqrt() as f32;
+        let mut bitmaps: HashMap<u32, Option<Arc<Bitmap>>> = HashMap::new();
+        for glyph in glyphs {
+            let bitmap = bitmaps
+                .entry(glyph.id)
+                .or_insert_with(|| self.bitmap(glyph.id as u16, scene_ppem));
+            let Some(bitmap) = bitmap else { continue };
+            let scale = font_size as f64 / bitmap.ppem as f64;
+            let xform = run_transform
+                * Affine::new([1.0, 0.0, 0.0, -1.0, glyph.x as f64, glyph.y as f64])
+                * glyph_transform
+                * Affine::scale(scale)
+                * Affine::new([1.0, 0.0, 0.0, -1.0, bitmap.left as f64, bitmap.top as f64]);
+            let image = &bitmap.image;
+            encoding.encode_transform(Transform::from_kurbo(&xform));
+            encoding.encode_fill_style(Fill::NonZero);
+            let rect = Rect::new(0.0, 0.0, image.width as f64, image.height as f64);
+            if encoding.encode_shape(&rect, true) {
+                encoding.encode_brush(image, alpha);
+            }
+        }
+    }
+
+    /// Returns the bitmap of the glyph in the strike selected for `ppem`, decoding it unless
+    /// it is in the cache.
+    fn bitmap(&self, glyph_id: u16, ppem: f32) -> Option<Arc<Bitmap>> {
+        let source = match self.strikes {
+            Strikes::Cbdt { cblc, cbdt } => cbdt_source(cblc, cbdt, glyph_id, ppem),
+            Strikes::Sbix { sbix, num_glyphs } => sbix_source(sbix, num_glyphs, glyph_id, ppem),
+        }?;
+        let key = (self.font.0, self.font.1, glyph_id, source.ppem);
+        if let Some(bitmap) = bitmap_cache().lock().unwrap().get(&key) {
+            return bitmap.clone();
+        }
+        // Decoding happens outside the lock, so another thread may decode the same bitmap;
+        // the first one inserted is kept.
+        let bitmap = decode_png(source.png).map(|image| {
+            let top = match source.vertical {
+                Vertical::Top(top) => top,
+                Vertical::Bottom(bottom) => bottom + image.height as f32,
+            };
+            Arc::new(Bitmap {
+                image,
+                ppem: source.ppem as f32,
+                left: source.left,
+                top,
+            })
+        });
+        let mut cache = bitmap_cache().lock().unwrap();
+        if cache.len() >= MAX_CACHED_BITMAPS {
+            cache.clear();
+        }
+        cache.entry(key).or_insert(bitmap).clone()
+    }
+}
+
+/// Returns the index of the strike to use for `ppem` among strikes of the given sizes.
+fn select_strike(sizes: impl Iterator<Item = u16>, ppem: f32) -> Option<usize> {
+    let mut best: Option<(usize, u16)> = None;
+    for (index, size) in sizes.enumerate() {
+        let better = match best {
+            None => true,
+            Some((_, best_size)) => {
+                if (best_size as f32) < ppem {
+                    size > best_size
+                } else {
+                    size as f32 >= ppem && size < best_size
+                }
+            }
+        };
+        if better {
+            best = Some((index, size));
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+fn cbdt_source<'a>(
+    cblc: &[u8],
+    cbdt: &'a [u8],
+    glyph_id: u16,
+    ppem: f32,
+) -> Option<BitmapSource<'a>> {
+    const BITMAP_SIZE_LEN: usize = 48;
+    let num_sizes = read_u32(cblc, 4)? as usize;
+    // Only strikes which contain the glyph are considered.
+    let sizes = (0..num_sizes)
+        .filter_map(|i| {
+            let size = cblc.get(8 + i * BITMAP_SIZE_LEN..8 + (i + 1) * BITMAP_SIZE_LEN)?;
+            let start = read_u16(size, 40)?;
+            let end = read_u16(size, 42)?;
+            (start..=end).contains(&glyph_id).then_some(size)
+        })
+        .collect::<Vec<_>>();
+    let size = sizes[select_strike(sizes.iter().map(|size| size[45] as u16), ppem)?];
+    let list_offset = read_u32(size, 0)? as usize;
+    let num_subtables = read_u32(size, 8)? as usize;
+    for i in 0..num_subtables {
+        let record = list_offset + i * 8;
+        let first = read_u16(cblc, record)?;
+        let last = read_u16(cblc, record + 2)?;
+        if !(first..=last).contains(&glyph_id) {
+            continue;
+        }
+        let subtable = list_offset + read_u32(cblc, record + 4)? as usize;
+        let index_format = read_u16(cblc, subtable)?;
+        let image_format = read_u16(cblc, subtable + 2)?;
+        let image_data_offset = read_u32(cblc, subtable + 4)? as usize;
+        let body = subtable + 8;
+        let index = (glyph_id - first) as usize;
+        // Big glyph metrics shared by the glyphs of the subtable, for the image formats
+        // which don't store their own.
+        let mut metrics = None;
+        let (start, end) = match index_format {
+            1 => (
+                read_u32(cblc, body + index * 4)? as usize,
+                read_u32(cblc, body + index * 4 + 4)? as usize,
+            ),
+            2 => {
+                let image_size = read_u32(cblc, body)? as usize;
+                metrics = cblc.get(body + 4..body + 12);
+                (index * image_size, (index + 1) * image_size)
+            }
+            3 => (
+                read_u16(cblc, body + index * 2)? as usize,
+                read_u16(cblc, body + index * 2 + 2)? as usize,
+            ),
+            4 => {
+                let num_glyphs = read_u32(cblc, body)? as usize;
+                let pairs = body + 4;
+                let i =
+                    (0..num_glyphs).find(|i| read_u16(cblc, pairs + i * 4) == Some(glyph_id))?;
+                (
+                    read_u16(cblc, pairs + i * 4 + 2)? as usize,
+                    read_u16(cblc, pairs + i * 4 + 6)? as usize,
+                )
+            }
+            5 => {
+                let image_size = read_u32(cblc, body)? as usize;
+                metrics = cblc.get(body + 4..body + 12);
+                let num_glyphs = read_u32(cblc, body + 12)? as usize;
+                let ids = body + 16;
+                let i = (0..num_glyphs).find(|i| read_u16(cblc, ids + i * 2) == Some(glyph_id))?;
+                (i * image_size, (i + 1) * image_size)
+            }
+            _ => return None,
+        };
+        let data = cbdt.get(image_data_offset + start..image_data_offset + end)?;
+        // Metrics are height, width, bearing x and bearing y, followed by advances.
+        let (metrics, png) = match image_format {
+            17 => (data.get(..4)?, data.get(9..)?),
+            18 => (data.get(..4)?, data.get(12..)?),
+            19 => (metrics?, data.get(4..)?),
+            _ => return None,
+        };
+        return Some(BitmapSource {
+            png,
+            ppem: size[45] as u16,
+            left: metrics[2] as i8 as f32,
+            vertical: Vertical::Top(metrics[3] as i8 as f32),
+        });
+    }
+    None
+}
+
+fn sbix_source(sbix: &[u8], num_glyphs: u16, glyph_id: u16, ppem: f32) -> Option<BitmapSource<'_>> {
+    if glyph_id >= num_glyphs {
+        return None;
+    }
+    let num_strikes = read_u32(sbix, 4)? as usize;
+    let strikes = (0..num_strikes)
+        .filter_map(|i| Some(read_u32(sbix, 8 + i * 4)? as usize))
+        .collect::<Vec<_>>();
+    let strike = strikes[select_strike(
+        strikes
+            .iter()
+            .map(|&strike| read_u16(sbix, strike).unwrap_or(0)),
+        ppem,
+    )?];
+    let strike_ppem = read_u16(sbix, strike)?;
+    let mut glyph_id = glyph_id as usize;
+    // A glyph may refer to the bitmap of another glyph, once.
+    for _ in 0..2 {
+        let start = strike + read_u32(sbix, strike + 4 + glyph_id * 4)? as usize;
+        let end = strike + read_u32(sbix, strike + 8 + glyph_id * 4)? as usize;
+        let data = sbix.get(start..end)?;
+        let graphic_type = data.get(4..8)?;
+        match graphic_type {
+            b"png " => {
+                return Some(BitmapSource {
+                    png: data.get(8..)?,
+                    ppem: strike_ppem,
+                    left: read_u16(data, 0)? as i16 as f32,
+                    vertical: Vertical::Bottom(read_u16(data, 2)? as i16 as f32),
+                });
+            }
+            b"dupe" => glyph_id = read_u16(data, 8)? as usize,
+            _ => return None,
+        }
+    }
+    None
+}
+
+/// Decodes a PNG image to RGBA8 with separate alpha.
+#[cfg(feature = "bitmap_glyphs")]
+fn decode_png(data: &[u8]) -> Option<Image> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().ok()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).ok()?;
+    let pixels = &buf[..info.buffer_size()];
+    let rgba = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks_exact(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks_exact(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        png::ColorType::Grayscale => pixels.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => return None,
+    };
+    Some(Image::new(
+        Blob::new(Arc::new(rgba)),
+        Format::Rgba8,
+        info.width,
+        info.height,
+    ))
+}
+
+#[cfg(not(feature = "bitmap_glyphs"))]
+fn decode_png(_data: &[u8]) -> Option<Image> {
+    None
+}
+
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
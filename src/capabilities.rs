@@ -0,0 +1,116 @@
+//! Detection of what an adapter supports, and the choice of shader variants that fit it.
+
+use wgpu::{Adapter, Device, DownlevelFlags, Features, Limits, TextureFormat};
+
+use crate::engine::BindType;
+use crate::shaders::{variants, ShaderVariant};
+
+/// Workgroup memory of the largest shader, coarse rasterization, in bytes.
+const REQUIRED_WORKGROUP_STORAGE: u32 = 16384;
+/// Workgroup size of the stages which can't be tuned with [`WorkgroupSizes`].
+///
+/// [`WorkgroupSizes`]: crate::WorkgroupSizes
+const REQUIRED_WORKGROUP_SIZE: u32 = 256;
+/// Features the renderer uses where they are available.
+const OPTIONAL_FEATURES: Features = Features::CLEAR_TEXTURE
+    .union(Features::BGRA8UNORM_STORAGE)
+    .union(Features::SHADER_F16);
+
+/// The limits and features of an adapter, which decide the shader variants the renderer uses
+/// on it.
+///
+/// The [`Renderer`](crate::Renderer) checks the capabilities of its device up front, instead of
+/// failing when a pipeline is created: optional variants which don't fit, such as tile
+/// compaction, are left out, and devices which can't run the required variants render on the
+/// CPU. wgpu doesn't expose subgroup operations yet, so no variant depends on them.
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    /// The limits to request for a device, or those of an existing device.
+    pub limits: Limits,
+    /// The features to request for a device, or those of an existing device.
+    pub features: Features,
+    /// Whether compute shaders can run at all, which they can't on WebGL2.
+    pub compute_shaders: bool,
+}
+
+impl Capabilities {
+    /// Queries the capabilities of `adapter`.
+    ///
+    /// The limits are WebGPU's defaults, which every shader fits in, where the adapter supports
+    /// them, and otherwise the adapter's own. [`Capabilities::limits`] and
+    /// [`Capabilities::features`] can be requested for a device as they are, so that a
+    /// constrained adapter still gets a device to run on.
+    pub fn detect(adapter: &Adapter) -> Self {
+        let supported = adapter.limits();
+        let compute_shaders = adapter
+            .get_downlevel_capabilities()
+            .flags
+            .contains(DownlevelFlags::COMPUTE_SHADERS);
+        let limits = if !compute_shaders {
+            Limits::downlevel_webgl2_defaults().using_resolution(supported)
+        } else if Limits::default().check_limits(&supported) {
+            Limits::default()
+        } else {
+            supported
+        };
+        Self {
+            limits,
+            features: adapter.features() & OPTIONAL_FEATURES,
+            compute_shaders,
+        }
+    }
+
+    /// Returns the capabilities of an existing device.
+    pub fn from_device(device: &Device) -> Self {
+        Self {
+            limits: device.limits(),
+            features: device.features(),
+            compute_shaders: crate::util::supports_compute(device),
+        }
+    }
+
+    /// Returns whether every stage of the pipeline can run on the GPU.
+    ///
+    /// The [`Renderer`](crate::Renderer) runs every stage on the CPU otherwise.
+    pub fn supports_gpu_pipeline(&self) -> bool {
+        self.compute_shaders
+            && self.limits.max_compute_workgroup_storage_size >= REQUIRED_WORKGROUP_STORAGE
+            && self.limits.max_compute_invocations_per_workgroup >= REQUIRED_WORKGROUP_SIZE
+            && self.limits.max_compute_workgroup_size_x >= REQUIRED_WORKGROUP_SIZE
+            && variants::REQUIRED
+                .iter()
+                .all(|variant| self.supports_variant(variant))
+    }
+
+    /// Returns whether the bindings of `variant` fit in the limits of a shader stage.
+    pub(crate) fn supports_variant(&self, variant: &ShaderVariant) -> bool {
+        let count = |f: fn(&BindType) -> bool| variant.layout.iter().filter(|ty| f(ty)).count();
+        let storage_buffers = count(|ty| matches!(ty, BindType::Buffer | BindType::BufReadOnly));
+        let uniform_buffers = count(|ty| matches!(ty, BindType::Uniform));
+        let storage_textures = count(|ty| matches!(ty, BindType::Image(_)));
+        let sampled_textures = count(|ty| matches!(ty, BindType::ImageRead(_)));
+        let limits = &self.limits;
+        storage_buffers <= limits.max_storage_buffers_per_shader_stage as usize
+            && uniform_buffers <= limits.max_uniform_buffers_per_shader_stage as usize
+            && storage_textures <= limits.max_storage_textures_per_shader_stage as usize
+            && sampled_textures <= limits.max_sampled_textures_per_shader_stage as usize
+    }
+
+    /// Returns whether fine rasterization can write to textures of `format`, see
+    /// [`RendererOptions::output_format`](crate::RendererOptions::output_format).
+    pub fn supports_output_format(&self, format: TextureFormat) -> bool {
+        match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => true,
+            TextureFormat::Rgba16Float => true,
+            TextureFormat::Bgra8Unorm => self.features.contains(Features::BGRA8UNORM_STORAGE),
+            _ => false,
+        }
+    }
+
+    /// Returns whether shaders can use 16 bit floats.
+    ///
+    /// No shader variant uses them yet; this is reported for embedders' own shaders.
+    pub fn shader_f16(&self) -> bool {
+        self.features.contains(Features::SHADER_F16)
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Capture of the intermediate buffers of a render, for diagnosing bugs in the pipeline.
+
+use std::fmt::Debug;
+use std::io::{self, Write};
+use std::path::Path;
+
+use bytemuck::Pod;
+use vello_encoding::{
+    BinHeader, BumpAllocators, Clip, ClipBbox, ClipBic, ClipElement, ConfigUniform, DrawBbox,
+    DrawMonoid, LineSoup, PathBbox, PathMonoid, PathSegment, SegmentCount, Tile,
+};
+
+use crate::cpu_shader::{
+    CMD_BEGIN_CLIP, CMD_COLOR, CMD_END, CMD_END_CLIP, CMD_FILL, CMD_IMAGE, CMD_JUMP, CMD_LIN_GRAD,
+    CMD_RAD_GRAD, CMD_SOLID, CMD_SOLID_COLOR, CMD_STROKE, PTCL_INITIAL_ALLOC,
+};
+
+/// Contents of the intermediate buffers of one render, as captured by
+/// [`Renderer::capture_frame`](crate::Renderer::capture_frame).
+pub struct Capture {
+    /// Width of the target in tiles, which the command lists are laid out by.
+    pub width_in_tiles: u32,
+    /// Height of the target in tiles.
+    pub height_in_tiles: u32,
+    /// The buffers, starting with the `config` and `scene` inputs, then the intermediate
+    /// buffers in the order the pipeline finished with them.
+    pub buffers: Vec<CapturedBuffer>,
+}
+
+/// Contents of one buffer of a [`Capture`].
+pub struct CapturedBuffer {
+    /// Name of the buffer, such as `path_monoids` or `ptcl`.
+    pub name: &'static str,
+    pub data: Vec<u8>,
+}
+
+impl Capture {
+    /// Returns the contents of the buffer with the given name, if it was captured.
+    pub fn get(&self, name: &str) -> Option<&[u8]> {
+        self.buffers
+            .iter()
+            .find(|buffer| buffer.name == name)
+            .map(|buffer| &buffer.data[..])
+    }
+
+    /// Replaces the buffer with the same name as `buffer`, or adds it after the others.
+    pub(crate) fn insert(&mut self, buffer: CapturedBuffer) {
+        match self.buffers.iter_mut().find(|b| b.name == buffer.name) {
+            Some(existing) => *existing = buffer,
+            None => self.buffers.push(buffer),
+        }
+    }
+
+    /// Writes each buffer to `dir`, as `<name>.bin` with its raw contents and `<name>.txt`
+    /// with one element per line, decoded as the type the pipeline stores in it.
+    ///
+    /// The directory is created if it doesn't exist.
+    pub fn write_to_dir(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        std::fs::create_dir_all(dir)?;
+        for buffer in &self.buffers {
+            std::fs::write(dir.join(format!("{}.bin", buffer.name)), &buffer.data)?;
+            let file = std::fs::File::create(dir.join(format!("{}.txt", buffer.name)))?;
+            let mut out = io::BufWriter::new(file);
+            self.pretty_print(buffer, &mut out)?;
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the elements of `buffer`, decoded by its name, to `out`.
+    pub fn pretty_print(&self, buffer: &CapturedBuffer, out: &mut impl Write) -> io::Result<()> {
+        let data = &buffer.data;
+        match buffer.name {
+            "config" => print_elements::<ConfigUniform>(data, out),
+            "path_reduced" | "path_monoids" => print_elements::<PathMonoid>(data, out),
+            "path_bboxes" => print_elements::<PathBbox>(data, out),
+            "draw_reduced" | "draw_monoids" => print_elements::<DrawMonoid>(data, out),
+            "clip_inps" => print_elements::<Clip>(data, out),
+            "clip_els" => print_elements::<ClipElement>(data, out),
+            "clip_bics" => print_elements::<ClipBic>(data, out),
+            "clip_bboxes" => print_elements::<ClipBbox>(data, out),
+            "draw_bboxes" => print_elements::<DrawBbox>(data, out),
+            "bin_headers" => print_elements::<BinHeader>(data, out),
+            "paths" => print_elements::<vello_encoding::Path>(data, out),
+            "lines" => print_elements::<LineSoup>(data, out),
+            "seg_counts" => print_elements::<SegmentCount>(data, out),
+            "tiles" => print_elements::<Tile>(data, out),
+            "segments" => print_elements::<PathSegment>(data, out),
+            "bump" => print_elements::<BumpAllocators>(data, out),
+            "ptcl" => print_ptcl(data, self.width_in_tiles, self.height_in_tiles, out),
+            _ => print_elements::<u32>(data, out),
+        }
+    }
+}
+
+/// Writes each whole element of type `T` in `data` on its own line, prefixed by its index.
+fn print_elements<T: Pod + Debug>(data: &[u8], out: &mut impl Write) -> io::Result<()> {
+    for (ix, element) in data.chunks_exact(std::mem::size_of::<T>()).enumerate() {
+        let element: T = bytemuck::pod_read_unaligned(element);
+        writeln!(out, "{ix}: {element:?}")?;
+    }
+    Ok(())
+}
+
+/// Writes the command list of each tile of the target which has any commands.
+fn print_ptcl(
+    data: &[u8],
+    width_in_tiles: u32,
+    height_in_tiles: u32,
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let ptcl: Vec<u32> = data
+        .chunks_exact(4)
+        .map(bytemuck::pod_read_unaligned)
+        .collect();
+    let n_tiles = (width_in_tiles * height_in_tiles) as usize;
+    let read = |ix: usize| ptcl.get(ix).copied();
+    for tile_ix in 0..n_tiles {
+        let mut cmd_ix = tile_ix * PTCL_INITIAL_ALLOC as usize;
+        if read(cmd_ix + 1) == Some(CMD_END) {
+            continue;
+        }
+        let (x, y) = (
+            tile_ix as u32 % width_in_tiles,
+            tile_ix as u32 / width_in_tiles,
+        );
+        writeln!(out, "tile {tile_ix} ({x}, {y}):")?;
+        writeln!(out, "  blend offset {}", read(cmd_ix).unwrap_or(0))?;
+        cmd_ix += 1;
+        // Corrupted lists can jump in a cycle, so the walk is bounded by the buffer size.
+        for _ in 0..ptcl.len() {
+            let Some(tag) = read(cmd_ix) else {
+                writeln!(out, "  <out of bounds at {cmd_ix}>")?;
+                break;
+            };
+            if tag == CMD_END {
+                break;
+            }
+            let Some((name, size)) = ptcl_command(tag) else {
+                writeln!(out, "  <unknown command {tag} at {cmd_ix}>")?;
+                break;
+            };
+            let args: Vec<String> = (1..size)
+                .map(|i| read(cmd_ix + i).map_or("?".into(), |word| format!("{word:#x}")))
+                .collect();
+            writeln!(out, "  {cmd_ix}: {name} {}", args.join(" "))?;
+            cmd_ix = if tag == CMD_JUMP {
+                read(cmd_ix + 1).unwrap_or(u32::MAX) as usize
+            } else {
+                cmd_ix + size
+            };
+        }
+    }
+    Ok(())
+}
+
+/// Returns the name of the command with `tag` and its size in words, including the tag.
+pub(crate) fn ptcl_command(tag: u32) -> Option<(&'static str, usize)> {
+    Some(match tag {
+        CMD_FILL => ("FILL", 4),
+        CMD_STROKE => ("STROKE", 3),
+        CMD_SOLID => ("SOLID", 1),
+        CMD_COLOR => ("COLOR", 2),
+        CMD_LIN_GRAD => ("LIN_GRAD", 3),
+        CMD_RAD_GRAD => ("RAD_GRAD", 3),
+        CMD_IMAGE => ("IMAGE", 2),
+        CMD_BEGIN_CLIP => ("BEGIN_CLIP", 1),
+        CMD_END_CLIP => ("END_CLIP", 3),
+        CMD_JUMP => ("JUMP", 2),
+        CMD_SOLID_COLOR => ("SOLID_COLOR", 2),
+        _ => return None,
+    })
+}
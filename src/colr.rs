@@ -0,0 +1,476 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Encoding of color glyphs from the COLR and CPAL tables.
+//!
+//! The paint graph of each glyph is translated into fills, clip layers and blend groups when
+//! the glyph run is encoded. Variable paints aren't supported, and sweep gradients, which fine
+//! rasterization can't draw yet, are approximated by a solid color.
+
+use fello::{
+    raw::{
+        tables::{
+            colr::{ColorLine, ColorStop, Colr, CompositeMode, Extend as ColrExtend, Paint},
+            cpal::{ColorRecord, Cpal},
+        },
+        types::GlyphId,
+        FontRef, TableProvider,
+    },
+    scale::{Context, Scaler},
+    NormalizedCoord, Size,
+};
+use peniko::{
+    kurbo::{Affine, Point, Rect, Vec2},
+    BlendMode, BrushRef, Color, Compose, Extend, Fill, Gradient, Mix,
+};
+use vello_encoding::{Encoding, Glyph, Transform};
+
+/// Maximum depth of the paint graph, which guards against cycles in malformed fonts.
+const MAX_DEPTH: u32 = 64;
+
+/// Palette index which selects the color of the brush of the run.
+const FOREGROUND_INDEX: u16 = 0xFFFF;
+
+/// Color tables of a font.
+pub struct ColorGlyphs<'a> {
+    font: FontRef<'a>,
+    colr: Colr<'a>,
+    palette: &'a [ColorRecord],
+}
+
+impl<'a> ColorGlyphs<'a> {
+    /// Returns the color tables of `font`, if it has any.
+    pub fn new(font: FontRef<'a>) -> Option<Self> {
+        let colr = font.colr().ok()?;
+        let cpal = font.cpal().ok()?;
+        // Color glyphs are drawn with the first palette.
+        let palette = palette(&cpal).unwrap_or_default();
+        Some(Self {
+            font,
+            colr,
+            palette,
+        })
+    }
+
+    /// Returns true if the glyph has a color definition.
+    pub fn contains(&self, glyph_id: u32) -> bool {
+        let gid = GlyphId::new(glyph_id as u16);
+        matches!(self.colr.v1_base_glyph(gid), Ok(Some(_)))
+            || matches!(self.colr.v0_base_glyph(gid), Ok(Some(_)))
+    }
+
+    /// Encodes the color glyphs.
+    ///
+    /// The glyphs are placed the same way as the outlines of a glyph run with the given
+    /// transforms and size, and `brush` is used for the foreground color.
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode(
+        &self,
+        encoding: &mut Encoding,
+        transform: Transform,
+        glyph_transform: Option<Transform>,
+        font_size: f32,
+        coords: &[NormalizedCoord],
+        glyphs: &[Glyph],
+        brush: BrushRef,
+        brush_alpha: f32,
+    ) {
+        let upem = self
+            .font
+            .head()
+            .map(|head| head.units_per_em())
+            .unwrap_or(1000)
+            .max(1) as f64;
+        let mut cx = Context::new();
+        let mut painter = Painter {
+            glyphs: self,
+            encoding,
+            scaler: cx
+                .new_scaler()
+                .size(Size::unscaled())
+                .coords(coords)
+                .build(&self.font),
+            foreground: brush,
+            foreground_alpha: brush_alpha,
+            bounds: Rect::new(-upem, -upem, 2.0 * upem, 2.0 * upem),
+            glyph_transform: Affine::IDENTITY,
+        };
+        let run_transform = transform.to_kurbo();
+        let outline_transform = glyph_transform.map_or(Affine::IDENTITY, |xform| xform.to_kurbo())
+            * Affine::scale(font_size as f64 / upem);
+        for glyph in glyphs {
+            painter.glyph_transform = run_transform
+                * Affine::new([1.0, 0.0, 0.0, -1.0, glyph.x as f64, glyph.y as f64])
+                * outline_transform;
+            let gid = GlyphId::new(glyph.id as u16);
+            if let Ok(Some((paint, _))) = self.colr.v1_base_glyph(gid) {
+                painter.paint(&paint, Affine::IDENTITY, 0);
+            } else if let Ok(Some(layers)) = self.colr.v0_base_glyph(gid) {
+                for index in layers {
+                    let Ok((layer_gid, palette_index)) = self.colr.v0_layer(index) else {
+                        continue;
+                    };
+                    let color = painter.color(palette_index, 1.0);
+                    painter.fill_glyph(layer_gid, Affine::IDENTITY, color);
+                }
+            }
+        }
+    }
+}
+
+fn palette<'a>(cpal: &Cpal<'a>) -> Option<&'a [ColorRecord]> {
+    let records = cpal.color_records_array()?.ok()?;
+    let start = cpal.color_record_indices().first()?.get() as usize;
+    records.get(start..)
+}
+
+/// Brush of a fill paint.
+enum PaintBrush<'p> {
+    Color(Color),
+    Foreground(BrushRef<'p>, f32),
+    Gradient(Gradient),
+}
+
+struct Painter<'a, 'p> {
+    glyphs: &'p ColorGlyphs<'a>,
+    encoding: &'p mut Encoding,
+    scaler: Scaler<'p>,
+    foreground: BrushRef<'p>,
+    foreground_alpha: f32,
+    /// Bounds of layers, in font units.
+    bounds: Rect,
+    /// Transform from font units to the scene.
+    glyph_transform: Affine,
+}
+
+impl<'a, 'p> Painter<'a, 'p> {
+    /// Encodes a paint, where `transform` is the transform of the paint in font units.
+    fn paint(&mut self, paint: &Paint<'a>, transform: Affine, depth: u32) {
+        if depth > MAX_DEPTH {
+            return;
+        }
+        let depth = depth + 1;
+        match paint {
+            Paint::ColrLayers(layers) => {
+                let start = layers.first_layer_index() as usize;
+                for index in start..start + layers.num_layers() as usize {
+                    if let Ok((layer, _)) = self.glyphs.colr.v1_layer(index) {
+                        self.paint(&layer, transform, depth);
+                    }
+                }
+            }
+            Paint::Glyph(glyph) => {
+                let Ok(child) = glyph.paint() else { return };
+                // Fills of a glyph are drawn directly rather than through a clip.
+                if let Some(brush) = self.brush(&child) {
+                    self.fill_glyph(glyph.glyph_id(), transform, brush);
+                } else {
+                    self.push_glyph_clip(glyph.glyph_id(), transform);
+                    self.paint(&child, transform, depth);
+                    self.encoding.encode_end_clip();
+                }
+            }
+            Paint::ColrGlyph(glyph) => {
+                if let Ok(Some((paint, _))) = self.glyphs.colr.v1_base_glyph(glyph.glyph_id()) {
+                    self.paint(&paint, transform, depth);
+                }
+            }
+            Paint::Transform(paint) => {
+                let (Ok(child), Ok(m)) = (paint.paint(), paint.transform()) else {
+                    return;
+                };
+                let affine = Affine::new([
+                    m.xx().to_f64(),
+                    m.yx().to_f64(),
+                    m.xy().to_f64(),
+                    m.yy().to_f64(),
+                    m.dx().to_f64(),
+                    m.dy().to_f64(),
+                ]);
+                self.paint(&child, transform * affine, depth);
+            }
+            Paint::Translate(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let offset = Vec2::new(paint.dx().to_i16() as f64, paint.dy().to_i16() as f64);
+                self.paint(&child, transform * Affine::translate(offset), depth);
+            }
+            Paint::Scale(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let scale = Affine::scale_non_uniform(
+                    paint.scale_x().to_f32() as f64,
+                    paint.scale_y().to_f32() as f64,
+                );
+                self.paint(&child, transform * scale, depth);
+            }
+            Paint::ScaleAroundCenter(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let scale = Affine::scale_non_uniform(
+                    paint.scale_x().to_f32() as f64,
+                    paint.scale_y().to_f32() as f64,
+                );
+                let center = fword_point(paint.center_x(), paint.center_y());
+                self.paint(&child, transform * around(center, scale), depth);
+            }
+            Paint::ScaleUniform(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let scale = Affine::scale(paint.scale().to_f32() as f64);
+                self.paint(&child, transform * scale, depth);
+            }
+            Paint::ScaleUniformAroundCenter(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let scale = Affine::scale(paint.scale().to_f32() as f64);
+                let center = fword_point(paint.center_x(), paint.center_y());
+                self.paint(&child, transform * around(center, scale), depth);
+            }
+            Paint::Rotate(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let rotate = rotation(paint.angle().to_f32());
+                self.paint(&child, transform * rotate, depth);
+            }
+            Paint::RotateAroundCenter(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let rotate = rotation(paint.angle().to_f32());
+                let center = fword_point(paint.center_x(), paint.center_y());
+                self.paint(&child, transform * around(center, rotate), depth);
+            }
+            Paint::Skew(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let skew = skew(paint.x_skew_angle().to_f32(), paint.y_skew_angle().to_f32());
+                self.paint(&child, transform * skew, depth);
+            }
+            Paint::SkewAroundCenter(paint) => {
+                let Ok(child) = paint.paint() else { return };
+                let skew = skew(paint.x_skew_angle().to_f32(), paint.y_skew_angle().to_f32());
+                let center = fword_point(paint.center_x(), paint.center_y());
+                self.paint(&child, transform * around(center, skew), depth);
+            }
+            Paint::Composite(paint) => {
+                let (Ok(source), Ok(backdrop)) = (paint.source_paint(), paint.backdrop_paint())
+                else {
+                    return;
+                };
+                self.push_bounds_layer(BlendMode::default());
+                self.paint(&backdrop, transform, depth);
+                self.push_bounds_layer(blend_mode(paint.composite_mode()));
+                self.paint(&source, transform, depth);
+                self.encoding.encode_end_clip();
+                self.encoding.encode_end_clip();
+            }
+            _ => {
+                if let Some(brush) = self.brush(paint) {
+                    // A fill outside of a glyph covers the enclosing clip.
+                    let bounds = self.bounds;
+                    self.fill(&bounds, Affine::IDENTITY, transform, brush);
+                }
+                // Otherwise this is a variable paint, which isn't supported.
+            }
+        }
+    }
+
+    /// Returns the brush of a fill paint, or `None` for any other paint.
+    fn brush(&self, paint: &Paint<'a>) -> Option<PaintBrush<'p>> {
+        Some(match paint {
+            Paint::Solid(solid) => self.color(solid.palette_index(), solid.alpha().to_f32()),
+            Paint::LinearGradient(linear) => {
+                let p0 = fword_point(linear.x0(), linear.y0());
+                let p1 = fword_point(linear.x1(), linear.y1());
+                let p2 = fword_point(linear.x2(), linear.y2());
+                // The gradient runs along the projection of p0p1 onto the normal of p0p2.
+                let normal = Vec2::new(p2.y - p0.y, p0.x - p2.x);
+                let end = if normal.hypot2() == 0.0 {
+                    p1
+                } else {
+                    p0 + normal * ((p1 - p0).dot(normal) / normal.hypot2())
+                };
+                let color_line = linear.color_line().ok()?;
+                self.gradient(Gradient::new_linear(p0, end), &color_line)
+            }
+            Paint::RadialGradient(radial) => {
+                let c0 = fword_point(radial.x0(), radial.y0());
+                let c1 = fword_point(radial.x1(), radial.y1());
+                let r0 = radial.radius0().to_u16() as f32;
+                let r1 = radial.radius1().to_u16() as f32;
+                let color_line = radial.color_line().ok()?;
+                self.gradient(Gradient::new_two_point_radial(c0, r0, c1, r1), &color_line)
+            }
+            Paint::SweepGradient(sweep) => {
+                let color_line = sweep.color_line().ok()?;
+                let stops = color_line.color_stops();
+                let Some(stop) = stops.get(stops.len() / 2) else {
+                    return Some(PaintBrush::Color(Color::TRANSPARENT));
+                };
+                self.color(stop.palette_index(), stop.alpha().to_f32())
+            }
+            _ => return None,
+        })
+    }
+
+    fn gradient(&self, mut gradient: Gradient, color_line: &ColorLine<'a>) -> PaintBrush<'p> {
+        gradient.extend = match color_line.extend() {
+            ColrExtend::Repeat => Extend::Repeat,
+            ColrExtend::Reflect => Extend::Reflect,
+            _ => Extend::Pad,
+        };
+        let mut stops = color_line.color_stops().to_vec();
+        stops.sort_by(|a: &ColorStop, b: &ColorStop| {
+            a.stop_offset()
+                .to_f32()
+                .total_cmp(&b.stop_offset().to_f32())
+        });
+        gradient.stops.extend(stops.iter().map(|stop| {
+            let color = match self.color(stop.palette_index(), stop.alpha().to_f32()) {
+                PaintBrush::Color(color) => color,
+                _ => self.foreground_color(stop.alpha().to_f32()),
+            };
+            peniko::ColorStop {
+                offset: stop.stop_offset().to_f32(),
+                color,
+            }
+        }));
+        PaintBrush::Gradient(gradient)
+    }
+
+    /// Returns the brush of a palette entry.
+    fn color(&self, palette_index: u16, alpha: f32) -> PaintBrush<'p> {
+        if palette_index == FOREGROUND_INDEX {
+            return PaintBrush::Foreground(self.foreground.clone(), self.foreground_alpha * alpha);
+        }
+        let Some(record) = self.glyphs.palette.get(palette_index as usize) else {
+            return PaintBrush::Color(Color::TRANSPARENT);
+        };
+        let color = Color::rgba8(record.red(), record.green(), record.blue(), record.alpha());
+        PaintBrush::Color(color.with_alpha_factor(alpha))
+    }
+
+    /// Returns the foreground color for use in gradients, which is black for brushes other
+    /// than solid colors.
+    fn foreground_color(&self, alpha: f32) -> Color {
+        let color = match &self.foreground {
+            BrushRef::Solid(color) => *color,
+            _ => Color::BLACK,
+        };
+        color.with_alpha_factor(self.foreground_alpha * alpha)
+    }
+
+    fn fill_glyph(&mut self, gid: GlyphId, transform: Affine, brush: PaintBrush) {
+        self.encoding
+            .encode_transform(Transform::from_kurbo(&(self.glyph_transform * transform)));
+        self.encoding.encode_fill_style(Fill::NonZero);
+        let mut path = self.encoding.encode_path(true);
+        if self.scaler.outline(gid, &mut path).is_err() {
+            return;
+        }
+        if path.finish(true) != 0 {
+            self.encode_brush(brush);
+        }
+    }
+
+    /// Fills a shape in font units with a brush whose geometry is transformed by
+    /// `brush_transform`.
+    fn fill(
+        &mut self,
+        shape: &Rect,
+        transform: Affine,
+        brush_transform: Affine,
+        brush: PaintBrush,
+    ) {
+        let transform = self.glyph_transform * transform;
+        self.encoding
+            .encode_transform(Transform::from_kurbo(&transform));
+        self.encoding.encode_fill_style(Fill::NonZero);
+        if self.encoding.encode_shape(shape, true) {
+            if self
+                .encoding
+                .encode_transform(Transform::from_kurbo(&(transform * brush_transform)))
+            {
+                self.encoding.swap_last_path_tags();
+            }
+            self.encode_brush(brush);
+        }
+    }
+
+    fn encode_brush(&mut self, brush: PaintBrush) {
+        match brush {
+            PaintBrush::Color(color) => self.encoding.encode_brush(color, 1.0),
+            PaintBrush::Foreground(brush, alpha) => self.encoding.encode_brush(brush, alpha),
+            PaintBrush::Gradient(gradient) => self.encoding.encode_brush(&gradient, 1.0),
+        }
+    }
+
+    /// Pushes a layer clipped to the outline of a glyph.
+    fn push_glyph_clip(&mut self, gid: GlyphId, transform: Affine) {
+        self.encoding
+            .encode_transform(Transform::from_kurbo(&(self.glyph_transform * transform)));
+        self.encoding.encode_fill_style(Fill::NonZero);
+        let mut path = self.encoding.encode_path(true);
+        let _ = self.scaler.outline(gid, &mut path);
+        if path.finish(true) == 0 {
+            // Suppress drawing until the layer is popped, like an empty layer shape.
+            self.encoding
+                .encode_shape(&Rect::new(0.0, 0.0, 0.0, 0.0), true);
+        }
+        self.encoding.encode_begin_clip(BlendMode::default(), 1.0);
+    }
+
+    /// Pushes a layer covering the bounds of the glyph, for blending.
+    fn push_bounds_layer(&mut self, blend: BlendMode) {
+        self.encoding
+            .encode_transform(Transform::from_kurbo(&self.glyph_transform));
+        self.encoding.encode_fill_style(Fill::NonZero);
+        self.encoding.encode_shape(&self.bounds, true);
+        self.encoding.encode_begin_clip(blend, 1.0);
+    }
+}
+
+fn fword_point(x: fello::raw::types::FWord, y: fello::raw::types::FWord) -> Point {
+    Point::new(x.to_i16() as f64, y.to_i16() as f64)
+}
+
+fn around(center: Point, transform: Affine) -> Affine {
+    Affine::translate(center.to_vec2()) * transform * Affine::translate(-center.to_vec2())
+}
+
+/// Returns a counter-clockwise rotation by an angle in half turns.
+fn rotation(angle: f32) -> Affine {
+    Affine::rotate(angle as f64 * std::f64::consts::PI)
+}
+
+/// Returns a counter-clockwise skew by angles in half turns.
+fn skew(x_angle: f32, y_angle: f32) -> Affine {
+    let x = (x_angle as f64 * std::f64::consts::PI).tan();
+    let y = (y_angle as f64 * std::f64::consts::PI).tan();
+    Affine::new([1.0, y, -x, 1.0, 0.0, 0.0])
+}
+
+fn blend_mode(mode: CompositeMode) -> BlendMode {
+    match mode {
+        CompositeMode::Clear => Compose::Clear.into(),
+        CompositeMode::Src => Compose::Copy.into(),
+        CompositeMode::Dest => Compose::Dest.into(),
+        CompositeMode::DestOver => Compose::DestOver.into(),
+        CompositeMode::SrcIn => Compose::SrcIn.into(),
+        CompositeMode::DestIn => Compose::DestIn.into(),
+        CompositeMode::SrcOut => Compose::SrcOut.into(),
+        CompositeMode::DestOut => Compose::DestOut.into(),
+        CompositeMode::SrcAtop => Compose::SrcAtop.into(),
+        CompositeMode::DestAtop => Compose::DestAtop.into(),
+        CompositeMode::Xor => Compose::Xor.into(),
+        CompositeMode::Plus => Compose::Plus.into(),
+        CompositeMode::Screen => Mix::Screen.into(),
+        CompositeMode::Overlay => Mix::Overlay.into(),
+        CompositeMode::Darken => Mix::Darken.into(),
+        CompositeMode::Lighten => Mix::Lighten.into(),
+        CompositeMode::ColorDodge => Mix::ColorDodge.into(),
+        CompositeMode::ColorBurn => Mix::ColorBurn.into(),
+        CompositeMode::HardLight => Mix::HardLight.into(),
+        CompositeMode::SoftLight => Mix::SoftLight.into(),
+        CompositeMode::Difference => Mix::Difference.into(),
+        CompositeMode::Exclusion => Mix::Exclusion.into(),
+        CompositeMode::Multiply => Mix::Multiply.into(),
+        CompositeMode::HslHue => Mix::Hue.into(),
+        CompositeMode::HslSaturation => Mix::Saturation.into(),
+        CompositeMode::HslColor => Mix::Color.into(),
+        CompositeMode::HslLuminosity => Mix::Luminosity.into(),
+        _ => BlendMode::default(),
+    }
+}
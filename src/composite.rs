@@ -0,0 +1,173 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Compositing of rendered textures over the render passes of other renderers.
+
+use wgpu::Device;
+
+/// Pipeline which draws a target texture over the viewport of a render pass, blending it with
+/// premultiplied alpha.
+pub(crate) struct CompositePipeline {
+    pub bind_layout: wgpu::BindGroupLayout,
+    pub pipeline: wgpu::RenderPipeline,
+}
+
+impl CompositePipeline {
+    /// Creates the pipeline for render passes of the given format and sample count.
+    ///
+    /// The targets hold sRGB encoded colors, which are decoded to linear light if
+    /// `decode_srgb` is set, for render passes which blend in linear light.
+    pub fn new(
+        device: &Device,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        decode_srgb: bool,
+    ) -> Self {
+        const SHADERS: &str = r#"
+            struct VertexOutput {
+                @builtin(position) position: vec4<f32>,
+                @location(0) uv: vec2<f32>,
+            }
+
+            @vertex
+            fn vs_main(@builtin(vertex_index) ix: u32) -> VertexOutput {
+                // Generate a quad over the viewport in NDCs
+                var vertex = vec2(-1.0, 1.0);
+                switch ix {
+                    case 1u: {
+                        vertex = vec2(-1.0, -1.0);
+                    }
+                    case 2u, 4u: {
+                        vertex = vec2(1.0, -1.0);
+                    }
+                    case 5u: {
+                        vertex = vec2(1.0, 1.0);
+                    }
+                    default: {}
+                }
+                var out: VertexOutput;
+                out.position = vec4(vertex, 0.0, 1.0);
+                out.uv = vec2(vertex.x + 1.0, 1.0 - vertex.y) * 0.5;
+                return out;
+            }
+
+            @group(0) @binding(0)
+            var fine_output: texture_2d<f32>;
+
+            fn load(uv: vec2<f32>) -> vec4<f32> {
+                // The viewport isn't necessarily at the origin of the framebuffer, so the
+                // texture is indexed by the position within the quad rather than the fragment
+                // position.
+                let size = textureDimensions(fine_output);
+                let coords = min(vec2<u32>(uv * vec2<f32>(size)), size - 1u);
+                return textureLoad(fine_output, coords, 0);
+            }
+
+            @fragment
+            fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+                let rgba_sep = load(in.uv);
+                return vec4(rgba_sep.rgb * rgba_sep.a, rgba_sep.a);
+            }
+
+            fn srgb_to_linear(srgb: vec3<f32>) -> vec3<f32> {
+                let low = srgb / 12.92;
+                let high = pow((srgb + 0.055) / 1.055, vec3(2.4));
+                return select(high, low, srgb <= vec3(0.04045));
+            }
+
+            @fragment
+            fn fs_main_linear(in: VertexOutput) -> @location(0) vec4<f32> {
+                let rgba_sep = load(in.uv);
+                return vec4(srgb_to_linear(rgba_sep.rgb) * rgba_sep.a, rgba_sep.a);
+            }
+        "#;
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("composite shaders"),
+            source: wgpu::ShaderSource::Wgsl(SHADERS.into()),
+        });
+        let bind_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: None,
+            entries: &[wgpu::BindGroupLayoutEntry {
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                binding: 0,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            }],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &[&bind_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("composite pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: if decode_srgb {
+                    "fs_main_linear"
+                } else {
+                    "fs_main"
+                },
+                targets: &[Some(wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                polygon_mode: wgpu::PolygonMode::Fill,
+                unclipped_depth: false,
+                conservative: false,
+            },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState {
+                count: sample_count,
+                mask: !0,
+                alpha_to_coverage_enabled: false,
+            },
+            multiview: None,
+        });
+        Self {
+            bind_layout,
+            pipeline,
+        }
+    }
+
+    /// Creates the bind group which draws `view`.
+    pub fn bind_group(&self, device: &Device, view: &wgpu::TextureView) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: None,
+            layout: &self.bind_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            }],
+        })
+    }
+
+    /// Draws the texture of `bind_group` over the viewport of the render pass.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        bind_group: &'a wgpu::BindGroup,
+    ) {
+        render_pass.set_pipeline(&self.pipeline);
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..6, 0..1);
+    }
+}
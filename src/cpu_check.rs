@@ -0,0 +1,551 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Comparison of the buffers of a captured GPU render with the CPU implementations of the
+//! stages which produced them.
+
+use std::cell::RefCell;
+use std::fmt::{self, Debug, Display};
+
+use bytemuck::Pod;
+use vello_encoding::{
+    BumpAllocators, Clip, ClipBbox, ClipBic, ConfigUniform, DrawBbox, DrawMonoid, PathBbox,
+    PathMonoid, PathSegment, Tile, WorkgroupCounts,
+};
+
+use crate::capture::ptcl_command;
+use crate::cpu_dispatch::CpuBinding;
+use crate::cpu_shader::{CMD_FILL, CMD_JUMP, PTCL_INITIAL_ALLOC};
+use crate::{cpu_shader, Capture, Result};
+
+/// Largest difference between the coordinates of a segment written on the GPU and by the
+/// CPU implementation which is not reported, as the stages may round differently.
+const SEGMENT_TOLERANCE: f32 = 1e-3;
+
+/// First element of a buffer which a stage wrote differently on the GPU than its CPU
+/// implementation, given the same inputs.
+#[derive(Clone, Debug)]
+pub struct StageMismatch {
+    /// Name of the stage, such as `pathtag_scan`.
+    pub stage: &'static str,
+    /// Name of the buffer, as in the [`Capture`].
+    pub buffer: &'static str,
+    /// Index of the first element which differs. For the command lists of `ptcl`, which
+    /// are compared per tile, this is the index of the tile.
+    pub index: usize,
+    /// The element written on the GPU.
+    pub gpu: String,
+    /// The element written by the CPU implementation.
+    pub cpu: String,
+}
+
+impl Display for StageMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} wrote {}[{}] as {} on the GPU, but {} on the CPU",
+            self.stage, self.buffer, self.index, self.gpu, self.cpu
+        )
+    }
+}
+
+impl Capture {
+    /// Runs the CPU implementation of each stage with its inputs taken from the capture, and
+    /// returns the first mismatch in each buffer it writes.
+    ///
+    /// Each stage is given the buffers as the GPU wrote them rather than as the CPU stages
+    /// before it would have, so the earliest stage with a mismatch is the one to look at.
+    /// This covers the stages from the path tag reduction to path tiling. Buffers whose
+    /// layout depends on the order of atomic allocations on the GPU, such as the lines, bin
+    /// data and segment counts, aren't compared. Of the tiles, only the backdrops are
+    /// compared, the command lists are compared per tile without the offsets of segments,
+    /// jumps and blend spills, and the segments are compared per fill command, in any order.
+    ///
+    /// Fine rasterization isn't checked, as its output and the gradient and image textures
+    /// it samples aren't part of the capture.
+    pub fn compare_with_cpu(&self) -> Result<Vec<StageMismatch>> {
+        let config: ConfigUniform = bytemuck::pod_read_unaligned(self.buffer("config")?);
+        let layout = &config.layout;
+        let wg_counts = WorkgroupCounts::new(
+            layout,
+            config.width_in_tiles,
+            config.height_in_tiles,
+            layout.path_tags_size(),
+        );
+        let config = bytemuck::bytes_of(&config);
+        let config = CpuBinding::Buffer(config);
+        let scene = CpuBinding::Buffer(self.buffer("scene")?);
+        let n_path_wgs = wg_counts.path_reduce.0;
+        let n_draw_objects = layout.n_draw_objects as usize;
+        let n_clips = layout.n_clips as usize;
+        let mut mismatches = vec![];
+
+        let path_reduced = output::<PathMonoid>(n_path_wgs as usize);
+        cpu_shader::pathtag_reduce(
+            n_path_wgs,
+            &[config, scene, CpuBinding::BufferRW(&path_reduced)],
+        );
+        // The single pass scan doesn't write the reduction.
+        let gpu_path_reduced = match self.get("path_reduced") {
+            Some(gpu) => {
+                mismatches.extend(compare::<PathMonoid>(
+                    "pathtag_reduce",
+                    "path_reduced",
+                    gpu,
+                    &path_reduced.borrow(),
+                    n_path_wgs as usize,
+                ));
+                CpuBinding::Buffer(gpu)
+            }
+            None => CpuBinding::BufferRW(&path_reduced),
+        };
+        let n_path_monoids = (n_path_wgs * vello_encoding::PATH_REDUCE_WG) as usize;
+        let path_monoids = output::<PathMonoid>(n_path_monoids);
+        cpu_shader::pathtag_scan(
+            n_path_wgs,
+            &[
+                config,
+                scene,
+                gpu_path_reduced,
+                CpuBinding::BufferRW(&path_monoids),
+            ],
+        );
+        mismatches.extend(compare::<PathMonoid>(
+            "pathtag_scan",
+            "path_monoids",
+            self.buffer("path_monoids")?,
+            &path_monoids.borrow(),
+            n_path_monoids,
+        ));
+
+        let path_bboxes = output::<PathBbox>(layout.n_paths as usize);
+        let bump = output::<BumpAllocators>(1);
+        let lines = RefCell::new(vec![0; self.buffer("lines")?.len()]);
+        cpu_shader::bbox_clear(0, &[config, CpuBinding::BufferRW(&path_bboxes)]);
+        cpu_shader::flatten(
+            wg_counts.flatten.0,
+            &[
+                config,
+                scene,
+                CpuBinding::Buffer(self.buffer("path_monoids")?),
+                CpuBinding::BufferRW(&path_bboxes),
+                CpuBinding::BufferRW(&bump),
+                CpuBinding::BufferRW(&lines),
+            ],
+        );
+        mismatches.extend(compare::<PathBbox>(
+            "flatten",
+            "path_bboxes",
+            self.buffer("path_bboxes")?,
+            &path_bboxes.borrow(),
+            layout.n_paths as usize,
+        ));
+
+        let n_draw_wgs = wg_counts.draw_reduce.0;
+        let draw_reduced = output::<DrawMonoid>(n_draw_wgs as usize);
+        cpu_shader::draw_reduce(
+            n_draw_wgs,
+            &[config, scene, CpuBinding::BufferRW(&draw_reduced)],
+        );
+        mismatches.extend(compare::<DrawMonoid>(
+            "draw_reduce",
+            "draw_reduced",
+            self.buffer("draw_reduced")?,
+            &draw_reduced.borrow(),
+            n_draw_wgs as usize,
+        ));
+
+        let draw_monoids = output::<DrawMonoid>(n_draw_objects);
+        let info_bin_data = RefCell::new(vec![0; self.buffer("info_bin_data")?.len()]);
+        let clip_inps = output::<Clip>(n_clips);
+        cpu_shader::draw_leaf(
+            wg_counts.draw_leaf.0,
+            &[
+                config,
+                scene,
+                CpuBinding::Buffer(self.buffer("draw_reduced")?),
+                CpuBinding::Buffer(self.buffer("path_bboxes")?),
+                CpuBinding::BufferRW(&draw_monoids),
+                CpuBinding::BufferRW(&info_bin_data),
+                CpuBinding::BufferRW(&clip_inps),
+            ],
+        );
+        mismatches.extend(compare::<Clip>(
+            "draw_leaf",
+            "clip_inps",
+            self.buffer("clip_inps")?,
+            &clip_inps.borrow(),
+            n_clips,
+        ));
+        // The bin data which follows the info is written by binning.
+        mismatches.extend(compare::<u32>(
+            "draw_leaf",
+            "info_bin_data",
+            self.buffer("info_bin_data")?,
+            &info_bin_data.borrow(),
+            layout.bin_data_start as usize,
+        ));
+
+        let n_clip_wgs = wg_counts.clip_reduce.0;
+        if n_clip_wgs > 0 {
+            let clip_bics = output::<ClipBic>(n_clip_wgs as usize);
+            let clip_els = RefCell::new(vec![0; self.buffer("clip_els")?.len()]);
+            cpu_shader::clip_reduce(
+                n_clip_wgs,
+                &[
+                    CpuBinding::Buffer(self.buffer("clip_inps")?),
+                    CpuBinding::Buffer(self.buffer("path_bboxes")?),
+                    CpuBinding::BufferRW(&clip_bics),
+                    CpuBinding::BufferRW(&clip_els),
+                ],
+            );
+            mismatches.extend(compare::<ClipBic>(
+                "clip_reduce",
+                "clip_bics",
+                self.buffer("clip_bics")?,
+                &clip_bics.borrow(),
+                n_clip_wgs as usize,
+            ));
+        }
+
+        // Clip leaf finishes the draw monoids of draw leaf, so they are compared after it.
+        let clip_bboxes = output::<ClipBbox>(n_clips);
+        cpu_shader::clip_leaf(
+            wg_counts.clip_leaf.0,
+            &[
+                config,
+                CpuBinding::Buffer(self.buffer("clip_inps")?),
+                CpuBinding::Buffer(self.buffer("path_bboxes")?),
+                CpuBinding::Buffer(self.buffer("clip_bics")?),
+                CpuBinding::Buffer(self.buffer("clip_els")?),
+                CpuBinding::BufferRW(&draw_monoids),
+                CpuBinding::BufferRW(&clip_bboxes),
+            ],
+        );
+        mismatches.extend(compare::<DrawMonoid>(
+            "clip_leaf",
+            "draw_monoids",
+            self.buffer("draw_monoids")?,
+            &draw_monoids.borrow(),
+            n_draw_objects,
+        ));
+        mismatches.extend(compare::<ClipBbox>(
+            "clip_leaf",
+            "clip_bboxes",
+            self.buffer("clip_bboxes")?,
+            &clip_bboxes.borrow(),
+            n_clips,
+        ));
+
+        let draw_bboxes = output::<DrawBbox>(n_draw_objects);
+        let bump = output::<BumpAllocators>(1);
+        let bin_data = RefCell::new(self.buffer("info_bin_data")?.to_vec());
+        let bin_headers = RefCell::new(vec![0; self.buffer("bin_headers")?.len()]);
+        cpu_shader::binning(
+            wg_counts.binning.0,
+            &[
+                config,
+                CpuBinding::Buffer(self.buffer("draw_monoids")?),
+                CpuBinding::Buffer(self.buffer("path_bboxes")?),
+                CpuBinding::Buffer(self.buffer("clip_bboxes")?),
+                CpuBinding::BufferRW(&draw_bboxes),
+                CpuBinding::BufferRW(&bump),
+                CpuBinding::BufferRW(&bin_data),
+                CpuBinding::BufferRW(&bin_headers),
+            ],
+        );
+        mismatches.extend(compare::<DrawBbox>(
+            "binning",
+            "draw_bboxes",
+            self.buffer("draw_bboxes")?,
+            &draw_bboxes.borrow(),
+            n_draw_objects,
+        ));
+
+        // Only the bounding boxes of the paths are compared, as the offsets of their tiles
+        // depend on the order the workgroups allocate in.
+        let bump = output::<BumpAllocators>(1);
+        let paths = RefCell::new(vec![0; self.buffer("paths")?.len()]);
+        let tiles = RefCell::new(vec![0; self.buffer("tiles")?.len()]);
+        cpu_shader::tile_alloc(
+            wg_counts.tile_alloc.0,
+            &[
+                config,
+                scene,
+                CpuBinding::Buffer(self.buffer("draw_bboxes")?),
+                CpuBinding::BufferRW(&bump),
+                CpuBinding::BufferRW(&paths),
+                CpuBinding::BufferRW(&tiles),
+            ],
+        );
+        let tile_bboxes = |data: &[u8]| -> Vec<[u32; 4]> {
+            data.chunks_exact(std::mem::size_of::<vello_encoding::Path>())
+                .map(|path| bytemuck::pod_read_unaligned::<vello_encoding::Path>(path).bbox)
+                .collect()
+        };
+        mismatches.extend(compare::<[u32; 4]>(
+            "tile_alloc",
+            "paths",
+            bytemuck::cast_slice(&tile_bboxes(self.buffer("paths")?)),
+            bytemuck::cast_slice(&tile_bboxes(&paths.borrow())),
+            layout.n_paths as usize,
+        ));
+
+        // Path counting, coarse and path tiling run on the lines the GPU flattened, into the
+        // tiles the GPU allocated, sharing one bump allocator as on the GPU.
+        let gpu_bump: BumpAllocators = bytemuck::pod_read_unaligned(
+            self.buffer("bump")?
+                .get(..std::mem::size_of::<BumpAllocators>())
+                .ok_or("capture has a truncated bump buffer")?,
+        );
+        let bump = RefCell::new(
+            bytemuck::bytes_of(&BumpAllocators {
+                lines: gpu_bump.lines,
+                ..Default::default()
+            })
+            .to_vec(),
+        );
+        let gpu_paths = CpuBinding::Buffer(self.buffer("paths")?);
+        let lines = CpuBinding::Buffer(self.buffer("lines")?);
+        let tiles = RefCell::new(vec![0; self.buffer("tiles")?.len()]);
+        let seg_counts = RefCell::new(vec![0; self.buffer("seg_counts")?.len()]);
+        cpu_shader::path_count(
+            0,
+            &[
+                config,
+                CpuBinding::BufferRW(&bump),
+                lines,
+                gpu_paths,
+                CpuBinding::BufferRW(&tiles),
+                CpuBinding::BufferRW(&seg_counts),
+            ],
+        );
+        cpu_shader::backdrop(
+            wg_counts.backdrop.0,
+            &[config, gpu_paths, CpuBinding::BufferRW(&tiles)],
+        );
+        // Coarse replaces the segment counts of the tiles with the offsets of their segments,
+        // so only the backdrops are compared.
+        let backdrops = |data: &[u8]| -> Vec<i32> {
+            data.chunks_exact(std::mem::size_of::<Tile>())
+                .map(|tile| bytemuck::pod_read_unaligned::<Tile>(tile).backdrop)
+                .collect()
+        };
+        mismatches.extend(compare::<i32>(
+            "backdrop",
+            "tiles",
+            bytemuck::cast_slice(&backdrops(self.buffer("tiles")?)),
+            bytemuck::cast_slice(&backdrops(&tiles.borrow())),
+            gpu_bump.tile as usize,
+        ));
+
+        let ptcl = RefCell::new(vec![0; self.buffer("ptcl")?.len()]);
+        cpu_shader::coarse(
+            wg_counts.coarse.0,
+            &[
+                config,
+                scene,
+                CpuBinding::Buffer(self.buffer("draw_monoids")?),
+                CpuBinding::Buffer(self.buffer("bin_headers")?),
+                CpuBinding::Buffer(self.buffer("info_bin_data")?),
+                gpu_paths,
+                CpuBinding::BufferRW(&tiles),
+                CpuBinding::BufferRW(&bump),
+                CpuBinding::BufferRW(&ptcl),
+            ],
+        );
+        let n_tiles = (self.width_in_tiles * self.height_in_tiles) as usize;
+        let gpu_commands = tile_commands(self.buffer("ptcl")?, n_tiles);
+        let cpu_commands = tile_commands(&ptcl.borrow(), n_tiles);
+        let coarse_mismatch = gpu_commands
+            .iter()
+            .zip(&cpu_commands)
+            .position(|(gpu, cpu)| without_offsets(gpu) != without_offsets(cpu));
+        if let Some(index) = coarse_mismatch {
+            mismatches.push(StageMismatch {
+                stage: "coarse",
+                buffer: "ptcl",
+                index,
+                gpu: format_commands(&gpu_commands[index]),
+                cpu: format_commands(&cpu_commands[index]),
+            });
+        }
+
+        let segments = RefCell::new(vec![0; self.buffer("segments")?.len()]);
+        cpu_shader::path_tiling(
+            0,
+            &[
+                CpuBinding::BufferRW(&bump),
+                CpuBinding::BufferRW(&seg_counts),
+                lines,
+                gpu_paths,
+                CpuBinding::BufferRW(&tiles),
+                CpuBinding::BufferRW(&segments),
+            ],
+        );
+        // The segments of a fill are compared in any order, and only for the tiles whose
+        // command lists match, as both their offsets and their order within a tile depend on
+        // the order of allocations.
+        let gpu_segments = self.buffer("segments")?;
+        let cpu_segments = segments.borrow();
+        let fill_pairs = gpu_commands
+            .iter()
+            .zip(&cpu_commands)
+            .take(coarse_mismatch.unwrap_or(n_tiles))
+            .flat_map(|(gpu, cpu)| fills(gpu).zip(fills(cpu)));
+        for ((gpu_ix, n_segs), (cpu_ix, _)) in fill_pairs {
+            let gpu = sorted_segments(gpu_segments, gpu_ix, n_segs);
+            let cpu = sorted_segments(&cpu_segments, cpu_ix, n_segs);
+            let mismatch = gpu
+                .iter()
+                .zip(&cpu)
+                .position(|(gpu, cpu)| !segments_match(gpu, cpu));
+            if let Some(i) = mismatch {
+                mismatches.push(StageMismatch {
+                    stage: "path_tiling",
+                    buffer: "segments",
+                    index: gpu_ix as usize + i,
+                    gpu: format!("{:?}", gpu[i]),
+                    cpu: format!("{:?}", cpu[i]),
+                });
+                break;
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Returns the contents of the buffer with the given name, or an error if it wasn't
+    /// captured.
+    fn buffer(&self, name: &str) -> Result<&[u8]> {
+        self.get(name)
+            .ok_or_else(|| format!("capture has no {name} buffer").into())
+    }
+}
+
+/// Creates a zeroed buffer of `len` elements of `T` for a CPU stage to write to.
+fn output<T: Pod>(len: usize) -> RefCell<Vec<u8>> {
+    // Stages bind at least one element, like the buffers of the GPU pipeline.
+    RefCell::new(vec![0; len.max(1) * std::mem::size_of::<T>()])
+}
+
+/// Returns the first of the first `len` elements of `T` which differ between `gpu` and
+/// `cpu`.
+fn compare<T: Pod + Debug>(
+    stage: &'static str,
+    buffer: &'static str,
+    gpu: &[u8],
+    cpu: &[u8],
+    len: usize,
+) -> Option<StageMismatch> {
+    let size = std::mem::size_of::<T>();
+    let gpu = gpu.chunks_exact(size).take(len);
+    let cpu = cpu.chunks_exact(size).take(len);
+    let (index, (gpu, cpu)) = gpu
+        .zip(cpu)
+        .enumerate()
+        .find(|(_, (gpu, cpu))| gpu != cpu)?;
+    Some(StageMismatch {
+        stage,
+        buffer,
+        index,
+        gpu: format!("{:?}", bytemuck::pod_read_unaligned::<T>(gpu)),
+        cpu: format!("{:?}", bytemuck::pod_read_unaligned::<T>(cpu)),
+    })
+}
+
+/// Returns the commands of each tile's command list, following jumps, with each command as
+/// its tag followed by its arguments. Jumps and the blend spill offset are left out, as
+/// they depend on the order of allocations.
+fn tile_commands(ptcl: &[u8], n_tiles: usize) -> Vec<Vec<Vec<u32>>> {
+    let ptcl: Vec<u32> = ptcl
+        .chunks_exact(4)
+        .map(bytemuck::pod_read_unaligned)
+        .collect();
+    (0..n_tiles)
+        .map(|tile_ix| {
+            let mut commands = vec![];
+            // The first word of a list is the blend spill offset.
+            let mut cmd_ix = tile_ix * PTCL_INITIAL_ALLOC as usize + 1;
+            // Corrupted lists can jump in a cycle, so the walk is bounded by the buffer size.
+            for _ in 0..ptcl.len() {
+                let Some(&tag) = ptcl.get(cmd_ix) else {
+                    break;
+                };
+                if tag == CMD_JUMP {
+                    cmd_ix = ptcl.get(cmd_ix + 1).map_or(usize::MAX, |&ix| ix as usize);
+                    continue;
+                }
+                let Some((_, size)) = ptcl_command(tag) else {
+                    break;
+                };
+                let Some(command) = ptcl.get(cmd_ix..cmd_ix + size) else {
+                    break;
+                };
+                commands.push(command.to_vec());
+                cmd_ix += size;
+            }
+            commands
+        })
+        .collect()
+}
+
+/// Returns the commands with the segment offsets of fills cleared.
+fn without_offsets(commands: &[Vec<u32>]) -> Vec<Vec<u32>> {
+    commands
+        .iter()
+        .map(|command| {
+            let mut command = command.clone();
+            if command[0] == CMD_FILL {
+                command[2] = 0;
+            }
+            command
+        })
+        .collect()
+}
+
+/// Returns the offset and count of the segments of each fill command.
+fn fills(commands: &[Vec<u32>]) -> impl Iterator<Item = (u32, u32)> + '_ {
+    commands
+        .iter()
+        .filter(|command| command[0] == CMD_FILL)
+        .map(|command| (command[2], command[1] >> 1))
+}
+
+/// Formats commands like the command lists written by [`Capture::pretty_print`].
+fn format_commands(commands: &[Vec<u32>]) -> String {
+    let commands: Vec<String> = commands
+        .iter()
+        .map(|command| {
+            let name = ptcl_command(command[0]).map_or("?", |(name, _)| name);
+            let args = command[1..].iter().map(|word| format!(" {word:#x}"));
+            std::iter::once(name.to_string()).chain(args).collect()
+        })
+        .collect();
+    format!("[{}]", commands.join(", "))
+}
+
+/// Returns `count` segments from `offset`, sorted by their position.
+fn sorted_segments(data: &[u8], offset: u32, count: u32) -> Vec<PathSegment> {
+    let size = std::mem::size_of::<PathSegment>();
+    let mut segments: Vec<PathSegment> = data
+        .chunks_exact(size)
+        .skip(offset as usize)
+        .take(count as usize)
+        .map(bytemuck::pod_read_unaligned)
+        .collect();
+    segments.sort_by(|a, b| {
+        let key = |s: &PathSegment| [s.origin[1], s.origin[0], s.delta[1], s.delta[0]];
+        key(a)
+            .partial_cmp(&key(b))
+            .unwrap_or(std::cmp::Ordering::Equal)
+    });
+    segments
+}
+
+fn segments_match(a: &PathSegment, b: &PathSegment) -> bool {
+    let coords = |s: &PathSegment| [s.origin[0], s.origin[1], s.delta[0], s.delta[1], s.y_edge];
+    coords(a)
+        .iter()
+        .zip(coords(b))
+        .all(|(a, b)| a == &b || (a - b).abs() <= SEGMENT_TOLERANCE)
+}
@@ -14,8 +14,7 @@ use bytemuck::Pod;
 pub enum CpuBinding<'a> {
     Buffer(&'a [u8]),
     BufferRW(&'a RefCell<Vec<u8>>),
-    #[allow(unused)]
-    Texture(&'a CpuTexture),
+    Texture(&'a RefCell<CpuTexture>),
 }
 
 pub enum TypedBufGuard<'a, T: ?Sized> {
@@ -104,11 +103,16 @@ impl<'a> CpuBinding<'a> {
         }
     }
 
-    // TODO: same guard as buf to make mutable
-    #[allow(unused)]
-    pub fn as_tex(&self) -> &CpuTexture {
+    pub fn as_tex(&self) -> Ref<CpuTexture> {
         match self {
-            CpuBinding::Texture(t) => t,
+            CpuBinding::Texture(t) => t.borrow(),
+            _ => panic!("resource type mismatch"),
+        }
+    }
+
+    pub fn as_tex_mut(&self) -> RefMut<CpuTexture> {
+        match self {
+            CpuBinding::Texture(t) => t.borrow_mut(),
             _ => panic!("resource type mismatch"),
         }
     }
@@ -121,3 +125,31 @@ pub struct CpuTexture {
     // In RGBA format. May expand in the future.
     pub pixels: Vec<u32>,
 }
+
+impl CpuTexture {
+    /// Creates a transparent texture.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; width * height],
+        }
+    }
+
+    /// Copies RGBA8 rows of `width` pixels into the texture, with their top left corner at
+    /// `x`, `y`.
+    pub fn write(&mut self, x: usize, y: usize, width: usize, data: &[u8]) {
+        if width == 0 {
+            return;
+        }
+        for (row, src) in data.chunks_exact(width * 4).enumerate() {
+            let base = (y + row) * self.width + x;
+            let Some(dst) = self.pixels.get_mut(base..base + width) else {
+                break;
+            };
+            for (dst, src) in dst.iter_mut().zip(src.chunks_exact(4)) {
+                *dst = u32::from_le_bytes([src[0], src[1], src[2], src[3]]);
+            }
+        }
+    }
+}
@@ -5,7 +5,7 @@ use vello_encoding::{BinHeader, BumpAllocators, ConfigUniform, DrawMonoid, PathB
 
 use crate::cpu_dispatch::CpuBinding;
 
-const WG_SIZE: usize = 256;
+const WG_SIZE: usize = vello_encoding::PATH_BBOX_WG as usize;
 const TILE_WIDTH: usize = 16;
 const TILE_HEIGHT: usize = 16;
 const N_TILE_X: usize = 16;
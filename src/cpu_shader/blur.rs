@@ -0,0 +1,58 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT OR Unlicense
+
+use vello_encoding::BlurConfig;
+
+use crate::cpu_dispatch::{CpuBinding, CpuTexture};
+
+use super::util::{load, pack4x8unorm, unpack_color};
+
+fn blur_main(config: &BlurConfig, input: &CpuTexture, output: &mut CpuTexture) {
+    let (width, height) = (config.width as i32, config.height as i32);
+    let scale = -0.5 / (config.std_dev * config.std_dev).max(1e-6);
+    let radius = config.radius as i32;
+    let weights: Vec<f32> = (-radius..=radius)
+        .map(|i| ((i * i) as f32 * scale).exp())
+        .collect();
+    let weight_sum: f32 = weights.iter().sum();
+    for y in 0..height {
+        for x in 0..width {
+            let mut sum = [0.0f32; 4];
+            for (i, weight) in (-radius..=radius).zip(&weights) {
+                // Pixels outside of the region are transparent.
+                let (sx, sy) = if config.vertical != 0 {
+                    (x, y + i)
+                } else {
+                    (x + i, y)
+                };
+                if sx >= 0 && sy >= 0 && sx < width && sy < height {
+                    let rgba_sep = load(input, sx, sy);
+                    for j in 0..3 {
+                        sum[j] += weight * rgba_sep[j] * rgba_sep[3];
+                    }
+                    sum[3] += weight * rgba_sep[3];
+                }
+            }
+            let mut rgba = sum.map(|c| c / weight_sum);
+            if config.use_color != 0 {
+                let alpha = rgba[3];
+                rgba = unpack_color(config.color).map(|c| c * alpha);
+            }
+            // The images hold separate alpha, like the output of fine rasterization.
+            let a_inv = 1.0 / rgba[3].max(1e-6);
+            let rgba_sep = [rgba[0] * a_inv, rgba[1] * a_inv, rgba[2] * a_inv, rgba[3]];
+            let out_x = (x as u32 + config.dst_x) as usize;
+            let out_y = (y as u32 + config.dst_y) as usize;
+            if out_x < output.width && out_y < output.height {
+                output.pixels[out_y * output.width + out_x] = pack4x8unorm(rgba_sep);
+            }
+        }
+    }
+}
+
+pub fn blur(_n_wg: u32, resources: &[CpuBinding]) {
+    let config = resources[0].as_typed();
+    let input = resources[1].as_tex();
+    let mut output = resources[2].as_tex_mut();
+    blur_main(&config, &input, &mut output);
+}
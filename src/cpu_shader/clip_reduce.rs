@@ -5,7 +5,7 @@ use vello_encoding::{Clip, ClipBic, ClipElement, PathBbox};
 
 use crate::cpu_dispatch::CpuBinding;
 
-const WG_SIZE: usize = 256;
+const WG_SIZE: usize = vello_encoding::CLIP_REDUCE_WG as usize;
 
 fn clip_reduce_main(
     n_wg: u32,
@@ -7,7 +7,7 @@ use crate::cpu_dispatch::CpuBinding;
 
 use super::{
     CMD_BEGIN_CLIP, CMD_COLOR, CMD_END, CMD_END_CLIP, CMD_FILL, CMD_IMAGE, CMD_JUMP, CMD_LIN_GRAD,
-    CMD_RAD_GRAD, CMD_SOLID, PTCL_INITIAL_ALLOC,
+    CMD_RAD_GRAD, CMD_SOLID, CMD_SOLID_COLOR, PTCL_INITIAL_ALLOC,
 };
 
 const N_TILE_X: usize = 16;
@@ -17,6 +17,10 @@ const N_TILE: usize = N_TILE_X * N_TILE_Y;
 const PTCL_INCREMENT: u32 = 256;
 const PTCL_HEADROOM: u32 = 2;
 
+const TILE_WIDTH: u32 = 16;
+const TILE_HEIGHT: u32 = 16;
+const BLEND_STACK_SPLIT: u32 = 4;
+
 // Modeled in the WGSL as private-scoped variables
 struct TileState {
     cmd_offset: u32,
@@ -53,6 +57,12 @@ impl TileState {
         }
     }
 
+    /// Drops the commands written so far, after the blend spill offset.
+    fn rewind(&mut self, tile_ix: u32) {
+        *self = TileState::new(tile_ix);
+        self.cmd_offset += 1;
+    }
+
     fn write(&mut self, ptcl: &mut [u32], offset: u32, value: u32) {
         ptcl[(self.cmd_offset + offset) as usize] = value;
     }
@@ -63,7 +73,9 @@ impl TileState {
         bump: &mut BumpAllocators,
         ptcl: &mut [u32],
         tile: &mut Tile,
-    ) {
+        linewidth: f32,
+    ) -> bool {
+        let even_odd = linewidth < -1.0;
         let n_segs = tile.segment_count_or_ix;
         if n_segs != 0 {
             let seg_ix = bump.segments;
@@ -71,17 +83,20 @@ impl TileState {
             bump.segments += n_segs;
             self.alloc_cmd(4, config, bump, ptcl);
             self.write(ptcl, 0, CMD_FILL);
-            let even_odd = false; // TODO
             let size_and_rule = (n_segs << 1) | (even_odd as u32);
             self.write(ptcl, 1, size_and_rule);
             self.write(ptcl, 2, seg_ix);
             self.write(ptcl, 3, tile.backdrop as u32);
             self.cmd_offset += 4;
         } else {
+            if even_odd && (tile.backdrop.abs() & 1) == 0 {
+                return false;
+            }
             self.alloc_cmd(1, config, bump, ptcl);
             self.write(ptcl, 0, CMD_SOLID);
             self.cmd_offset += 1;
         }
+        true
     }
 
     fn write_color(
@@ -97,6 +112,19 @@ impl TileState {
         self.cmd_offset += 2;
     }
 
+    fn write_solid_color(
+        &mut self,
+        config: &ConfigUniform,
+        bump: &mut BumpAllocators,
+        ptcl: &mut [u32],
+        rgba_color: u32,
+    ) {
+        self.alloc_cmd(2, config, bump, ptcl);
+        self.write(ptcl, 0, CMD_SOLID_COLOR);
+        self.write(ptcl, 1, rgba_color);
+        self.cmd_offset += 2;
+    }
+
     fn write_image(
         &mut self,
         config: &ConfigUniform,
@@ -153,6 +181,18 @@ impl TileState {
     }
 }
 
+/// Returns true if a fill with this color covers the whole tile opaquely, which hides
+/// everything drawn in the tile before it.
+fn is_opaque_cover(tile: &Tile, linewidth: f32, rgba_color: u32) -> bool {
+    let even_odd = linewidth < -1.0;
+    let covered = if even_odd {
+        (tile.backdrop.abs() & 1) != 0
+    } else {
+        tile.backdrop != 0
+    };
+    tile.segment_count_or_ix == 0 && covered && (rgba_color & 0xff) == 0xff
+}
+
 fn coarse_main(
     config: &ConfigUniform,
     scene: &[u32],
@@ -218,6 +258,8 @@ fn coarse_main(
             tile_state.cmd_offset += 1;
             let mut clip_depth = 0;
             let mut clip_zero_depth = 0;
+            let mut render_blend_depth = 0;
+            let mut max_blend_depth = 0;
             for drawobj_ix in &compacted[tile_ix] {
                 let drawtag = scene[(drawtag_base + drawobj_ix) as usize];
                 if clip_zero_depth == 0 {
@@ -241,56 +283,72 @@ fn coarse_main(
                     let n_segs = tile.segment_count_or_ix;
                     let include_tile = n_segs != 0 || (tile.backdrop == 0) == is_clip || is_blend;
                     if include_tile {
-                        // TODO: get drawinfo (linewidth for fills)
                         match DrawTag(drawtag) {
                             DrawTag::COLOR => {
-                                tile_state.write_path(config, bump, ptcl, tile);
+                                let linewidth = f32::from_bits(info_bin_data[di as usize]);
                                 let rgba_color = scene[dd as usize];
-                                tile_state.write_color(config, bump, ptcl, rgba_color);
+                                let is_cover = is_opaque_cover(tile, linewidth, rgba_color);
+                                if is_cover && clip_depth == 0 {
+                                    tile_state.rewind(this_tile_ix);
+                                }
+                                if is_cover {
+                                    tile_state.write_solid_color(config, bump, ptcl, rgba_color);
+                                } else if tile_state.write_path(config, bump, ptcl, tile, linewidth)
+                                {
+                                    tile_state.write_color(config, bump, ptcl, rgba_color);
+                                }
                             }
                             DrawTag::IMAGE => {
-                                tile_state.write_path(config, bump, ptcl, tile);
-                                tile_state.write_image(config, bump, ptcl, di + 1);
+                                let linewidth = f32::from_bits(info_bin_data[di as usize]);
+                                if tile_state.write_path(config, bump, ptcl, tile, linewidth) {
+                                    tile_state.write_image(config, bump, ptcl, di + 1);
+                                }
                             }
                             DrawTag::LINEAR_GRADIENT => {
-                                tile_state.write_path(config, bump, ptcl, tile);
-                                let index = scene[dd as usize];
-                                tile_state.write_grad(
-                                    config,
-                                    bump,
-                                    ptcl,
-                                    CMD_LIN_GRAD,
-                                    index,
-                                    di + 1,
-                                );
+                                let linewidth = f32::from_bits(info_bin_data[di as usize]);
+                                if tile_state.write_path(config, bump, ptcl, tile, linewidth) {
+                                    let index = scene[dd as usize];
+                                    tile_state.write_grad(
+                                        config,
+                                        bump,
+                                        ptcl,
+                                        CMD_LIN_GRAD,
+                                        index,
+                                        di + 1,
+                                    );
+                                }
                             }
                             DrawTag::RADIAL_GRADIENT => {
-                                tile_state.write_path(config, bump, ptcl, tile);
-                                let index = scene[dd as usize];
-                                tile_state.write_grad(
-                                    config,
-                                    bump,
-                                    ptcl,
-                                    CMD_RAD_GRAD,
-                                    index,
-                                    di + 1,
-                                );
+                                let linewidth = f32::from_bits(info_bin_data[di as usize]);
+                                if tile_state.write_path(config, bump, ptcl, tile, linewidth) {
+                                    let index = scene[dd as usize];
+                                    tile_state.write_grad(
+                                        config,
+                                        bump,
+                                        ptcl,
+                                        CMD_RAD_GRAD,
+                                        index,
+                                        di + 1,
+                                    );
+                                }
                             }
                             DrawTag::BEGIN_CLIP => {
                                 if tile.segment_count_or_ix == 0 && tile.backdrop == 0 {
                                     clip_zero_depth = clip_depth + 1;
                                 } else {
                                     tile_state.write_begin_clip(config, bump, ptcl);
-                                    // TODO: update blend depth
+                                    render_blend_depth += 1;
+                                    max_blend_depth = max_blend_depth.max(render_blend_depth);
                                 }
                                 clip_depth += 1;
                             }
                             DrawTag::END_CLIP => {
                                 clip_depth -= 1;
-                                tile_state.write_path(config, bump, ptcl, tile);
+                                tile_state.write_path(config, bump, ptcl, tile, -1.0);
                                 let blend = scene[dd as usize];
                                 let alpha = f32::from_bits(scene[dd as usize + 1]);
                                 tile_state.write_end_clip(config, bump, ptcl, blend, alpha);
+                                render_blend_depth -= 1;
                             }
                             _ => todo!(),
                         }
@@ -312,9 +370,11 @@ fn coarse_main(
 
             if bin_tile_x + tile_x < width_in_tiles && bin_tile_y + tile_y < height_in_tiles {
                 ptcl[tile_state.cmd_offset as usize] = CMD_END;
-                let scratch_size = 0; // TODO: actually compute blend depth
-                ptcl[blend_offset as usize] = bump.blend;
-                bump.blend += scratch_size;
+                if max_blend_depth > BLEND_STACK_SPLIT {
+                    let scratch_size = max_blend_depth * TILE_WIDTH * TILE_HEIGHT;
+                    ptcl[blend_offset as usize] = bump.blend;
+                    bump.blend += scratch_size;
+                }
             }
         }
     }
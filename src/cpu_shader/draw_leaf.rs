@@ -7,7 +7,7 @@ use crate::cpu_dispatch::CpuBinding;
 
 use super::util::{read_draw_tag_from_scene, Transform, Vec2};
 
-const WG_SIZE: usize = 256;
+const WG_SIZE: usize = vello_encoding::PATH_BBOX_WG as usize;
 
 fn draw_leaf_main(
     n_wg: u32,
@@ -1,20 +1,28 @@
 // Copyright 2023 The Vello authors
 // SPDX-License-Identifier: Apache-2.0 OR MIT OR Unlicense
 
-use vello_encoding::{ConfigUniform, PathSegment, Tile};
+use vello_encoding::{ConfigUniform, DrawImage, PathSegment};
 
-use crate::cpu_dispatch::CpuTexture;
+use crate::cpu_dispatch::{CpuBinding, CpuTexture};
 
-use super::{CMD_COLOR, CMD_END, CMD_FILL, CMD_JUMP, CMD_SOLID, PTCL_INITIAL_ALLOC};
+use super::{
+    blend::blend_mix_compose,
+    util::{load, pack4x8unorm, unpack4x8unorm, unpack_color},
+    CMD_BEGIN_CLIP, CMD_COLOR, CMD_END, CMD_END_CLIP, CMD_FILL, CMD_IMAGE, CMD_JUMP, CMD_LIN_GRAD,
+    CMD_RAD_GRAD, CMD_SOLID, CMD_SOLID_COLOR, CMD_STROKE, PTCL_INITIAL_ALLOC,
+};
 
 // These should also move into a common area
 const TILE_WIDTH: usize = 16;
 const TILE_HEIGHT: usize = 16;
 const TILE_SIZE: usize = TILE_WIDTH * TILE_HEIGHT;
 
-fn read_color(ptcl: &[u32], offset: u32) -> u32 {
-    ptcl[(offset + 1) as usize]
-}
+const GRADIENT_WIDTH: i32 = 512;
+
+const RAD_GRAD_KIND_CIRCULAR: u32 = 1;
+const RAD_GRAD_KIND_STRIP: u32 = 2;
+const RAD_GRAD_KIND_FOCAL_ON_CIRCLE: u32 = 3;
+const RAD_GRAD_SWAPPED: u32 = 1;
 
 struct CmdFill {
     size_and_rule: u32,
@@ -33,21 +41,95 @@ fn read_fill(ptcl: &[u32], offset: u32) -> CmdFill {
     }
 }
 
-fn unpack4x8unorm(x: u32) -> [f32; 4] {
-    let mut result = [0.0; 4];
-    for i in 0..4 {
-        result[i] = ((x >> (i * 8)) & 0xff) as f32 * (1.0 / 255.0);
+fn read_color(ptcl: &[u32], offset: u32) -> [f32; 4] {
+    unpack_color(ptcl[(offset + 1) as usize])
+}
+
+fn read_f32s<const N: usize>(info: &[u32], offset: u32) -> [f32; N] {
+    std::array::from_fn(|i| f32::from_bits(info[offset as usize + i]))
+}
+
+/// The sign function of WGSL, which is zero for zero.
+fn sign(x: f32) -> f32 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x.signum()
+    }
+}
+
+fn extend_mode(t: f32, mode: u32) -> f32 {
+    match mode {
+        // EXTEND_PAD
+        0 => t.clamp(0.0, 1.0),
+        // EXTEND_REPEAT
+        1 => t - t.floor(),
+        // EXTEND_REFLECT
+        _ => (t - 2.0 * (0.5 * t).round()).abs(),
+    }
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c * (1.0 / 12.92)
+    } else {
+        ((c + 0.055) * (1.0 / 1.055)).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.0031308 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Converts a premultiplied sRGB color which wasn't converted when the scene was packed to
+/// the color space used for blending.
+fn input_color(config: &ConfigUniform, rgba: [f32; 4]) -> [f32; 4] {
+    if config.linear_blending == 0 {
+        return rgba;
     }
-    result
+    let a_inv = 1.0 / rgba[3].max(1e-6);
+    let [r, g, b] = [0, 1, 2].map(|i| srgb_to_linear(rgba[i] * a_inv) * rgba[3]);
+    [r, g, b, rgba[3]]
 }
 
-fn pack4x8unorm(x: [f32; 4]) -> u32 {
-    let mut result = 0;
-    for i in 0..4 {
-        let byte = (x[i].clamp(0.0, 1.0) * 255.0).round() as u32;
-        result |= byte << (i * 8);
+/// Converts a texel of the image atlas, which has separate alpha, to a premultiplied color
+/// in the color space used for blending.
+fn image_color(config: &ConfigUniform, rgba: [f32; 4]) -> [f32; 4] {
+    let [r, g, b] = [0, 1, 2].map(|i| {
+        let c = if config.linear_blending == 0 {
+            rgba[i]
+        } else {
+            srgb_to_linear(rgba[i])
+        };
+        c * rgba[3]
+    });
+    [r, g, b, rgba[3]]
+}
+
+/// Ordered dithering with an 8x8 Bayer matrix, see `dither` in fine.wgsl.
+fn dither(rgba: [f32; 4], x: u32, y: u32) -> [f32; 4] {
+    let y = y & 7;
+    let z = (x & 7) ^ y;
+    let index = ((z & 1) << 5)
+        | ((y & 1) << 4)
+        | ((z & 2) << 2)
+        | ((y & 2) << 1)
+        | ((z & 4) >> 1)
+        | ((y & 4) >> 2);
+    let offset = (index as f32 + 0.5) / 64.0 - 0.5;
+    let [r, g, b] = [0, 1, 2].map(|i| (rgba[i] + offset / 255.0).clamp(0.0, 1.0));
+    [r, g, b, rgba[3]]
+}
+
+fn paint(rgba: &mut [f32; 4], fg: [f32; 4], area: f32) {
+    let fg_i = fg.map(|c| c * area);
+    for j in 0..4 {
+        rgba[j] = rgba[j] * (1.0 - fg_i[3]) + fg_i[j];
     }
-    result
 }
 
 fn fill_path(area: &mut [f32], segments: &[PathSegment], fill: &CmdFill, x_tile: f32, y_tile: f32) {
@@ -63,7 +145,7 @@ fn fill_path(area: &mut [f32], segments: &[PathSegment], fill: &CmdFill, x_tile:
             let y0 = y.clamp(0.0, 1.0);
             let y1 = (y + segment.delta[1]).clamp(0.0, 1.0);
             let dy = y0 - y1;
-            let y_edge = segment.delta[0].signum()
+            let y_edge = sign(segment.delta[0])
                 * (y_tile + yi as f32 - segment.y_edge + 1.0).clamp(0.0, 1.0);
             if dy != 0.0 {
                 let vec_y_recip = segment.delta[1].recip();
@@ -92,47 +174,53 @@ fn fill_path(area: &mut [f32], segments: &[PathSegment], fill: &CmdFill, x_tile:
         }
     }
     if even_odd {
+        // even-odd winding rule
         for a in area.iter_mut() {
-            {
-                *a = (*a - 2.0 * (0.5 * *a).round()).abs();
-            }
+            *a = (*a - 2.0 * (0.5 * *a).round()).abs();
         }
     } else {
+        // non-zero winding rule
         for a in area.iter_mut() {
-            {
-                *a = a.abs().min(1.0);
-            }
+            *a = a.abs().min(1.0);
         }
     }
 }
 
-// Note: this is a draft. Texture resources are not yet wired up, so it
-// has not yet been tested.
-#[allow(unused)]
+/// Fine rasterization with area antialiasing.
+///
+/// The blend stack is kept in memory rather than spilled, so the blend spill buffer isn't
+/// used.
 fn fine_main(
     config: &ConfigUniform,
-    tiles: &[Tile],
     segments: &[PathSegment],
-    output: &mut CpuTexture,
     ptcl: &[u32],
     info: &[u32],
-    // TODO: image texture resources
-    // TODO: masks?
+    output: &mut CpuTexture,
+    gradients: &CpuTexture,
+    image_atlas: &CpuTexture,
+    external_images: &[&CpuTexture],
 ) {
     let width_in_tiles = config.width_in_tiles;
     let height_in_tiles = config.height_in_tiles;
     let n_tiles = width_in_tiles * height_in_tiles;
+    let base_color = input_color(config, unpack_color(config.base_color));
     let mut area = vec![0.0f32; TILE_SIZE];
     let mut rgba = vec![[0.0f32; 4]; TILE_SIZE];
+    let mut blend_stack: Vec<u32> = vec![];
     for tile_ix in 0..n_tiles {
         for x in &mut rgba {
-            *x = [0.0; 4];
+            *x = base_color;
         }
         for a in &mut area {
             *a = 0.0;
         }
+        blend_stack.clear();
         let tile_x = tile_ix % width_in_tiles;
         let tile_y = tile_ix / width_in_tiles;
+        // x0 and y0 will go away when we do tile-relative coords
+        let x0 = (tile_x as usize * TILE_WIDTH) as f32;
+        let y0 = (tile_y as usize * TILE_HEIGHT) as f32;
+        let xy = |i: usize| (x0 + (i % TILE_WIDTH) as f32, y0 + (i / TILE_WIDTH) as f32);
         let mut cmd_ix = tile_ix * PTCL_INITIAL_ALLOC;
         // skip over blend stack allocation
         cmd_ix += 1;
@@ -144,45 +232,205 @@ fn fine_main(
             match tag {
                 CMD_FILL => {
                     let fill = read_fill(ptcl, cmd_ix);
-                    // x0 and y0 will go away when we do tile-relative coords
-                    let x0 = (tile_x as usize * TILE_WIDTH) as f32;
-                    let y0 = (tile_y as usize * TILE_HEIGHT) as f32;
                     fill_path(&mut area, segments, &fill, x0, y0);
                     cmd_ix += 4;
                 }
+                CMD_STROKE => {
+                    // Strokes are expanded to fills earlier in the pipeline, as in fine.wgsl.
+                    area.fill(0.0);
+                    cmd_ix += 3;
+                }
                 CMD_SOLID => {
-                    for a in &mut area {
-                        *a = 1.0;
+                    area.fill(1.0);
+                    cmd_ix += 1;
+                }
+                CMD_COLOR => {
+                    let fg = read_color(ptcl, cmd_ix);
+                    for i in 0..TILE_SIZE {
+                        paint(&mut rgba[i], fg, area[i]);
                     }
                     cmd_ix += 2;
                 }
-                CMD_COLOR => {
-                    let color = read_color(ptcl, cmd_ix);
-                    let fg = unpack4x8unorm(color);
-                    let fg = [fg[3], fg[2], fg[1], fg[0]];
+                CMD_SOLID_COLOR => {
+                    let fg = read_color(ptcl, cmd_ix);
+                    rgba.fill(fg);
+                    cmd_ix += 2;
+                }
+                CMD_LIN_GRAD => {
+                    let index_mode = ptcl[cmd_ix as usize + 1];
+                    let info_offset = ptcl[cmd_ix as usize + 2];
+                    let [line_x, line_y, line_c] = read_f32s(info, info_offset);
+                    for i in 0..TILE_SIZE {
+                        let (x, y) = xy(i);
+                        let d = line_x * x + line_y * y + line_c;
+                        let t = extend_mode(d, index_mode & 0x3);
+                        let x = (t * (GRADIENT_WIDTH - 1) as f32).round() as i32;
+                        let fg = load(gradients, x, (index_mode >> 2) as i32);
+                        paint(&mut rgba[i], fg, area[i]);
+                    }
+                    cmd_ix += 3;
+                }
+                CMD_RAD_GRAD => {
+                    let index_mode = ptcl[cmd_ix as usize + 1];
+                    let info_offset = ptcl[cmd_ix as usize + 2];
+                    let [m0, m1, m2, m3, xlat_x, xlat_y, focal_x, radius] =
+                        read_f32s(info, info_offset);
+                    let flags_kind = info[info_offset as usize + 8];
+                    let kind = flags_kind & 0x7;
+                    let is_swapped = ((flags_kind >> 3) & RAD_GRAD_SWAPPED) != 0;
+                    let r1_recip = if kind == RAD_GRAD_KIND_CIRCULAR {
+                        0.0
+                    } else {
+                        1.0 / radius
+                    };
+                    let less_scale = if is_swapped || (1.0 - focal_x) < 0.0 {
+                        -1.0
+                    } else {
+                        1.0
+                    };
+                    let t_sign = sign(1.0 - focal_x);
                     for i in 0..TILE_SIZE {
-                        let ai = area[i];
-                        let fg_i = [fg[0] * ai, fg[1] * ai, fg[2] * ai, fg[3] * ai];
-                        for j in 0..4 {
-                            rgba[i][j] = rgba[i][j] * (1.0 - fg_i[3]) + fg_i[j];
+                        let (px, py) = xy(i);
+                        let x = m0 * px + m2 * py + xlat_x;
+                        let y = m1 * px + m3 * py + xlat_y;
+                        let xx = x * x;
+                        let yy = y * y;
+                        let (t, is_valid) = if kind == RAD_GRAD_KIND_STRIP {
+                            let a = radius - yy;
+                            (a.sqrt() + x, a >= 0.0)
+                        } else if kind == RAD_GRAD_KIND_FOCAL_ON_CIRCLE {
+                            let t = (xx + yy) / x;
+                            (t, t >= 0.0 && x != 0.0)
+                        } else if radius > 1.0 {
+                            ((xx + yy).sqrt() - x * r1_recip, true)
+                        } else {
+                            let a = xx - yy;
+                            let t = less_scale * a.sqrt() - x * r1_recip;
+                            (t, a >= 0.0 && t >= 0.0)
+                        };
+                        if is_valid {
+                            let t = extend_mode(focal_x + t_sign * t, index_mode & 0x3);
+                            let t = if is_swapped { 1.0 - t } else { t };
+                            let x = (t * (GRADIENT_WIDTH - 1) as f32).round() as i32;
+                            let fg = load(gradients, x, (index_mode >> 2) as i32);
+                            paint(&mut rgba[i], fg, area[i]);
+                        }
+                    }
+                    cmd_ix += 3;
+                }
+                CMD_IMAGE => {
+                    let info_offset = ptcl[cmd_ix as usize + 1];
+                    let [m0, m1, m2, m3, xlat_x, xlat_y] = read_f32s(info, info_offset);
+                    let mut xy_packed = info[info_offset as usize + 6];
+                    let width_height = info[info_offset as usize + 7];
+                    // External images are sampled from their own texture, from the origin.
+                    let mut image = image_atlas;
+                    if xy_packed & DrawImage::EXTERNAL_FLAG != 0 {
+                        image = external_images[(xy_packed & 0xffff) as usize];
+                        xy_packed = 0;
+                    }
+                    let offset = [(xy_packed >> 16) as f32, (xy_packed & 0xffff) as f32];
+                    let extents = [
+                        offset[0] + (width_height >> 16) as f32,
+                        offset[1] + (width_height & 0xffff) as f32,
+                    ];
+                    for i in 0..TILE_SIZE {
+                        let (px, py) = xy(i);
+                        let u = m0 * px + m2 * py + xlat_x + offset[0];
+                        let v = m1 * px + m3 * py + xlat_y + offset[1];
+                        // This currently clips to the image bounds. TODO: extend modes
+                        if u < extents[0] && v < extents[1] && area[i] != 0.0 {
+                            let u0 = u.floor().max(offset[0]) as i32;
+                            let v0 = v.floor().max(offset[1]) as i32;
+                            let u1 = u.ceil().min(extents[0]) as i32;
+                            let v1 = v.ceil().min(extents[1]) as i32;
+                            let (fu, fv) = (u - u.floor(), v - v.floor());
+                            let a = image_color(config, load(image, u0, v0));
+                            let b = image_color(config, load(image, u0, v1));
+                            let c = image_color(config, load(image, u1, v0));
+                            let d = image_color(config, load(image, u1, v1));
+                            let fg = [0, 1, 2, 3].map(|j| {
+                                let left = a[j] + (b[j] - a[j]) * fv;
+                                let right = c[j] + (d[j] - c[j]) * fv;
+                                left + (right - left) * fu
+                            });
+                            paint(&mut rgba[i], fg, area[i]);
                         }
                     }
                     cmd_ix += 2;
                 }
+                CMD_BEGIN_CLIP => {
+                    for x in &mut rgba {
+                        blend_stack.push(pack4x8unorm(*x));
+                        *x = [0.0; 4];
+                    }
+                    cmd_ix += 1;
+                }
+                CMD_END_CLIP => {
+                    let blend = ptcl[cmd_ix as usize + 1];
+                    let alpha = f32::from_bits(ptcl[cmd_ix as usize + 2]);
+                    let saved = blend_stack.split_off(blend_stack.len() - TILE_SIZE);
+                    for i in 0..TILE_SIZE {
+                        let bg = unpack4x8unorm(saved[i]);
+                        let fg = rgba[i].map(|c| c * area[i] * alpha);
+                        rgba[i] = blend_mix_compose(bg, fg, blend);
+                    }
+                    cmd_ix += 3;
+                }
                 CMD_JUMP => {
                     cmd_ix = ptcl[(cmd_ix + 1) as usize];
                 }
-                _ => todo!("unhandled ptcl command {tag}"),
+                _ => panic!("unhandled ptcl command {tag}"),
             }
         }
         // Write tile (in rgba)
-        for y in 0..TILE_HEIGHT {
-            let base =
-                output.width * (tile_y as usize * TILE_HEIGHT + y) + tile_x as usize * TILE_WIDTH;
-            for x in 0..TILE_WIDTH {
-                let rgba32 = pack4x8unorm(rgba[y * TILE_WIDTH + x]);
-                output.pixels[base + x] = rgba32;
+        for i in 0..TILE_SIZE {
+            let (x, y) = xy(i);
+            let (x, y) = (x as u32, y as u32);
+            if x >= config.target_width || y >= config.target_height {
+                continue;
+            }
+            let fg = rgba[i];
+            // Max with a small epsilon to avoid NaNs
+            let a_inv = 1.0 / fg[3].max(1e-6);
+            let mut rgba_sep = [fg[0] * a_inv, fg[1] * a_inv, fg[2] * a_inv, fg[3]];
+            if config.linear_blending != 0 {
+                for c in &mut rgba_sep[..3] {
+                    *c = linear_to_srgb(*c);
+                }
+            }
+            if config.dithering != 0 {
+                rgba_sep = dither(rgba_sep, x, y);
+            }
+            let out_x = (x + config.target_x_offset) as usize;
+            let out_y = (y + config.target_y_offset) as usize;
+            if out_x < output.width && out_y < output.height {
+                output.pixels[out_y * output.width + out_x] = pack4x8unorm(rgba_sep);
             }
         }
     }
 }
+
+pub fn fine(_n_wg: u32, resources: &[CpuBinding]) {
+    let config = resources[0].as_typed();
+    let segments = resources[1].as_slice();
+    let ptcl = resources[2].as_slice();
+    let info = resources[3].as_slice();
+    let mut output = resources[4].as_tex_mut();
+    let gradients = resources[5].as_tex();
+    let image_atlas = resources[6].as_tex();
+    let external_images: Vec<_> = (8..8 + DrawImage::MAX_EXTERNAL_IMAGES as usize)
+        .map(|i| resources[i].as_tex())
+        .collect();
+    let external_images: Vec<&CpuTexture> = external_images.iter().map(|image| &**image).collect();
+    fine_main(
+        &config,
+        &segments,
+        &ptcl,
+        &info,
+        &mut output,
+        &gradients,
+        &image_atlas,
+        &external_images,
+    );
+}
@@ -4,7 +4,9 @@
 use crate::cpu_dispatch::CpuBinding;
 
 use super::util::{Transform, Vec2};
-use vello_encoding::{BumpAllocators, ConfigUniform, LineSoup, Monoid, PathBbox, PathMonoid};
+use vello_encoding::{
+    BumpAllocators, ConfigUniform, LineSoup, Monoid, PathBbox, PathMonoid, Style,
+};
 
 fn to_minus_one_quarter(x: f32) -> f32 {
     // could also be written x.powf(-0.25)
@@ -70,6 +72,9 @@ fn eval_cubic(p0: Vec2, p1: Vec2, p2: Vec2, p3: Vec2, t: f32) -> Vec2 {
 
 const MAX_QUADS: u32 = 16;
 
+/// Flattening accuracy, in pixels.
+const ACCURACY: f32 = 0.25;
+
 struct Cubic {
     p0: Vec2,
     p1: Vec2,
@@ -78,18 +83,36 @@ struct Cubic {
     path_ix: u32,
 }
 
-fn flatten_cubic(cubic: Cubic, line_ix: &mut usize, lines: &mut [LineSoup]) {
+struct Lines<'a> {
+    line_ix: usize,
+    lines: &'a mut [LineSoup],
+}
+
+impl Lines<'_> {
+    fn write(&mut self, path_ix: u32, p0: Vec2, p1: Vec2) {
+        self.lines[self.line_ix] = LineSoup {
+            path_ix,
+            _padding: Default::default(),
+            p0: p0.to_array(),
+            p1: p1.to_array(),
+        };
+        self.line_ix += 1;
+    }
+}
+
+/// Flattens a cubic into lines. Strokes are flattened in the local coordinates of the path,
+/// and each line is expanded by the stroker.
+fn flatten_cubic(cubic: Cubic, accuracy: f32, stroker: Option<&Stroker>, out: &mut Lines) {
     let p0 = cubic.p0;
     let p1 = cubic.p1;
     let p2 = cubic.p2;
     let p3 = cubic.p3;
     let err_v = (p2 - p1) * 3.0 + p0 - p3;
     let err = err_v.dot(err_v);
-    const ACCURACY: f32 = 0.25;
-    const Q_ACCURACY: f32 = ACCURACY * 0.1;
-    const REM_ACCURACY: f32 = ACCURACY - Q_ACCURACY;
-    const MAX_HYPOT2: f32 = 432.0 * Q_ACCURACY * Q_ACCURACY;
-    let mut n_quads = ((err * (1.0 / MAX_HYPOT2)).powf(1.0 / 6.0).ceil() as u32).max(1);
+    let q_accuracy = accuracy * 0.1;
+    let rem_accuracy = accuracy - q_accuracy;
+    let max_hypot2 = 432.0 * q_accuracy * q_accuracy;
+    let mut n_quads = ((err * (1.0 / max_hypot2)).powf(1.0 / 6.0).ceil() as u32).max(1);
     n_quads = n_quads.min(MAX_QUADS);
     let mut keep_params = [SubdivResult::default(); MAX_QUADS as usize];
     let mut val = 0.0;
@@ -100,17 +123,19 @@ fn flatten_cubic(cubic: Cubic, line_ix: &mut usize, lines: &mut [LineSoup]) {
         let qp2 = eval_cubic(p0, p1, p2, p3, t);
         let mut qp1 = eval_cubic(p0, p1, p2, p3, t - 0.5 * step);
         qp1 = qp1 * 2.0 - (qp0 + qp2) * 0.5;
-        let params = estimate_subdiv(qp0, qp1, qp2, REM_ACCURACY.sqrt());
+        let params = estimate_subdiv(qp0, qp1, qp2, rem_accuracy.sqrt());
         keep_params[i as usize] = params;
         val += params.val;
         qp0 = qp2;
     }
-    let n = ((val * (0.5 / REM_ACCURACY.sqrt())).ceil() as u32).max(1);
+    let n = ((val * (0.5 / rem_accuracy.sqrt())).ceil() as u32).max(1);
     let mut lp0 = p0;
     qp0 = p0;
     let v_step = val / (n as f32);
     let mut n_out = 1;
     let mut val_sum = 0.0;
+    let mut last_tangent = Vec2::default();
+    let mut lhw0 = stroker.map(|stroker| stroker.hw0).unwrap_or_default();
     for i in 0..n_quads {
         let t = (i + 1) as f32 * step;
         let qp2 = eval_cubic(p0, p1, p2, p3, t);
@@ -122,23 +147,31 @@ fn flatten_cubic(cubic: Cubic, line_ix: &mut usize, lines: &mut [LineSoup]) {
         let uscale = (u2 - u0).recip();
         let mut val_target = (n_out as f32) * v_step;
         while n_out == n || val_target < val_sum + params.val {
-            let lp1 = if n_out == n {
-                p3
+            // The parameter of the end of the line along the cubic is approximated by the
+            // parameter along the quad.
+            let (lp1, t_cubic) = if n_out == n {
+                (p3, 1.0)
             } else {
                 let u = (val_target - val_sum) / params.val;
                 let a = params.a0 + (params.a2 - params.a0) * u;
                 let au = approx_parabola_inv_integral(a);
                 let t = (au - u0) * uscale;
-                eval_quad(qp0, qp1, qp2, t)
-            };
-            let ls = LineSoup {
-                path_ix: cubic.path_ix,
-                _padding: Default::default(),
-                p0: lp0.to_array(),
-                p1: lp1.to_array(),
+                (eval_quad(qp0, qp1, qp2, t), (i as f32 + t) * step)
             };
-            lines[*line_ix] = ls;
-            *line_ix += 1;
+            if let Some(stroker) = stroker {
+                // Lines within a segment are joined with bevels, which are close to the
+                // offset curve as long as the angles between consecutive lines are small.
+                // The width of variable width strokes is interpolated along the segment.
+                let lhw1 = stroker.hw0 + (stroker.hw1 - stroker.hw0) * t_cubic;
+                stroker.join(out, lp0, last_tangent, lp1 - lp0, Style::JOIN_BEVEL, lhw0);
+                stroker.line(out, lp0, lp1, lhw0, lhw1);
+                if lp1 != lp0 {
+                    last_tangent = lp1 - lp0;
+                }
+                lhw0 = lhw1;
+            } else {
+                out.write(cubic.path_ix, lp0, lp1);
+            }
             n_out += 1;
             val_target += v_step;
             lp0 = lp1;
@@ -148,6 +181,299 @@ fn flatten_cubic(cubic: Cubic, line_ix: &mut usize, lines: &mut [LineSoup]) {
     }
 }
 
+/// Maximum number of lines along the arc of a round join, or of half of a round cap.
+const MAX_ARC_LINES: u32 = 32;
+
+fn cross(a: Vec2, b: Vec2) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+/// Expands strokes into convex polygons in the local coordinates of the path. All of the
+/// polygons are written with the same orientation, so that they add up under the non-zero
+/// fill rule wherever they overlap.
+struct Stroker {
+    path_ix: u32,
+    transform: Transform,
+    /// True if the transform flips the orientation of the polygons.
+    flip: bool,
+    /// Half the line width.
+    hw: f32,
+    /// Half the line width at the start and end of the segment, which differ from `hw` for
+    /// variable width strokes.
+    hw0: f32,
+    hw1: f32,
+    miter_limit: f32,
+    /// Flattening accuracy, in local coordinates.
+    accuracy: f32,
+}
+
+impl Stroker {
+    fn output_line(&self, out: &mut Lines, p0: Vec2, p1: Vec2) {
+        let q0 = self.transform.apply(p0);
+        let q1 = self.transform.apply(p1);
+        if self.flip {
+            out.write(self.path_ix, q1, q0);
+        } else {
+            out.write(self.path_ix, q0, q1);
+        }
+    }
+
+    /// Outputs a convex polygon.
+    fn output_polygon(&self, out: &mut Lines, points: &[Vec2]) {
+        let n = points.len();
+        if cross(points[1] - points[0], points[2] - points[0]) <= 0.0 {
+            for i in 0..n {
+                self.output_line(out, points[i], points[(i + 1) % n]);
+            }
+        } else {
+            for i in (0..n).rev() {
+                self.output_line(out, points[(i + 1) % n], points[i]);
+            }
+        }
+    }
+
+    /// Outputs the sector of a circle around `center` between the offsets `n0` and `n1`,
+    /// which have the same length and are at most a quarter turn apart.
+    fn output_arc(&self, out: &mut Lines, center: Vec2, n0: Vec2, n1: Vec2) {
+        let radius = n0.length();
+        if radius == 0.0 {
+            return;
+        }
+        let mut angle = cross(n0, n1).atan2(n0.dot(n1));
+        let (n0, n1) = if angle > 0.0 {
+            angle = -angle;
+            (n1, n0)
+        } else {
+            (n0, n1)
+        };
+        // Largest angle between the points of the arc that keeps the chords within the
+        // accuracy.
+        let max_angle = 2.0 * (1.0 - self.accuracy / radius).max(0.0).acos();
+        let n_lines = ((-angle / max_angle).ceil() as u32).clamp(1, MAX_ARC_LINES);
+        let step = angle / n_lines as f32;
+        let (sin, cos) = step.sin_cos();
+        self.output_line(out, center, center + n0);
+        let mut n = n0;
+        for i in 1..=n_lines {
+            let next = if i < n_lines {
+                Vec2::new(n.x * cos - n.y * sin, n.x * sin + n.y * cos)
+            } else {
+                n1
+            };
+            self.output_line(out, center + n, center + next);
+            n = next;
+        }
+        self.output_line(out, center + n1, center);
+    }
+
+    /// Outputs a line expanded to the half widths `hw0` at its start and `hw1` at its end.
+    fn line(&self, out: &mut Lines, p0: Vec2, p1: Vec2, hw0: f32, hw1: f32) {
+        let d = p1 - p0;
+        let len = d.length();
+        if len == 0.0 {
+            return;
+        }
+        let n = Vec2::new(-d.y, d.x) * len.recip();
+        let (n0, n1) = (n * hw0, n * hw1);
+        self.output_polygon(out, &[p0 + n0, p1 + n1, p1 - n1, p0 - n0]);
+    }
+
+    /// Outputs the join at `p` between a curve ending with tangent `t0` and a curve starting
+    /// with tangent `t1`, with the half width `half_width`. Only the outer side of the join
+    /// needs to be filled, as the inner side is covered by the expanded curves.
+    fn join(&self, out: &mut Lines, p: Vec2, t0: Vec2, t1: Vec2, join: u32, half_width: f32) {
+        let len0 = t0.length();
+        let len1 = t1.length();
+        if len0 == 0.0 || len1 == 0.0 {
+            return;
+        }
+        let d0 = t0 * len0.recip();
+        let d1 = t1 * len1.recip();
+        let cr = cross(d0, d1);
+        let d = d0.dot(d1);
+        if d > 0.0 && cr.abs() < 1e-6 {
+            return;
+        }
+        // The outer side of the join is opposite the direction the curve turns to.
+        let hw = if cr > 0.0 { -half_width } else { half_width };
+        let n0 = Vec2::new(-d0.y, d0.x) * hw;
+        let n1 = Vec2::new(-d1.y, d1.x) * hw;
+        if join == Style::JOIN_ROUND {
+            // Split the arc where it points away from both curves, so that each half is well
+            // defined even when the curve turns back on itself.
+            let mid = d0 - d1;
+            let mid = mid * (half_width / mid.length());
+            self.output_arc(out, p, n0, mid);
+            self.output_arc(out, p, mid, n1);
+        } else if join == Style::JOIN_MITER && (0.5 * (1.0 + d)).sqrt() * self.miter_limit >= 1.0 {
+            let tip = p + (n0 + n1) * (1.0 + d).recip();
+            self.output_polygon(out, &[p, p + n0, tip, p + n1]);
+        } else {
+            self.output_polygon(out, &[p, p + n0, p + n1]);
+        }
+    }
+
+    /// Outputs the cap at `p` of a curve leaving `p` in the direction `t`, with the half
+    /// width `half_width`.
+    fn cap(&self, out: &mut Lines, p: Vec2, t: Vec2, cap: u32, half_width: f32) {
+        let len = t.length();
+        if cap == Style::CAP_BUTT || len == 0.0 {
+            return;
+        }
+        let d = t * (half_width / len);
+        let n = Vec2::new(-d.y, d.x);
+        if cap == Style::CAP_SQUARE {
+            self.output_polygon(out, &[p + n, p + n + d, p - n + d, p - n]);
+        } else {
+            self.output_arc(out, p, n, d);
+            self.output_arc(out, p, d, n * -1.0);
+        }
+    }
+}
+
+fn start_tangent(c: &[Vec2; 4]) -> Vec2 {
+    [c[1] - c[0], c[2] - c[0]]
+        .into_iter()
+        .find(|d| *d != Vec2::default())
+        .unwrap_or(c[3] - c[0])
+}
+
+fn end_tangent(c: &[Vec2; 4]) -> Vec2 {
+    [c[3] - c[2], c[3] - c[1]]
+        .into_iter()
+        .find(|d| *d != Vec2::default())
+        .unwrap_or(c[3] - c[0])
+}
+
+/// Random access to the path segments of the scene.
+struct Segments<'a> {
+    config: &'a ConfigUniform,
+    scene: &'a [u32],
+    tag_monoids: &'a [PathMonoid],
+}
+
+impl Segments<'_> {
+    fn tag_byte(&self, ix: usize) -> u8 {
+        let tag_word = self.scene[self.config.layout.path_tag_base as usize + (ix >> 2)];
+        (tag_word >> ((ix & 3) * 8)) as u8
+    }
+
+    fn tag_monoid(&self, ix: usize) -> PathMonoid {
+        let tag_word = self.scene[self.config.layout.path_tag_base as usize + (ix >> 2)];
+        let shift = (ix & 3) * 8;
+        let tm = PathMonoid::new(tag_word & ((1 << shift) - 1));
+        self.tag_monoids[ix >> 2].combine(&tm)
+    }
+
+    /// Reads a path segment, raised to a cubic, in the local coordinates of the path.
+    fn read(&self, tm: &PathMonoid, tag_byte: u8) -> [Vec2; 4] {
+        let pathdata = &self.scene[self.config.layout.path_data_base as usize..];
+        let instance_offset =
+            self.scene[(self.config.layout.instance_base + tm.instance_ix) as usize];
+        let pathseg_offset = tm.pathseg_offset.wrapping_add(instance_offset);
+        let seg_type = tag_byte & PATH_TAG_SEG_TYPE;
+        let mut p = [Vec2::default(); 4];
+        if (tag_byte & PATH_TAG_F32) != 0 {
+            for (i, point) in p.iter_mut().take(seg_type as usize + 1).enumerate() {
+                *point = read_f32_point(pathseg_offset + 2 * i as u32, pathdata);
+            }
+        } else {
+            todo!("i16 path data not supported yet");
+        }
+        if seg_type == PATH_TAG_LINETO {
+            p[3] = p[1];
+            p[2] = p[3].mix(p[0], 1.0 / 3.0);
+            p[1] = p[0].mix(p[3], 1.0 / 3.0);
+        } else if seg_type == PATH_TAG_QUADTO {
+            p[3] = p[2];
+            p[2] = p[1].mix(p[2], 1.0 / 3.0);
+            p[1] = p[1].mix(p[0], 1.0 / 3.0);
+        }
+        p
+    }
+
+    fn read_at(&self, ix: usize) -> [Vec2; 4] {
+        self.read(&self.tag_monoid(ix), self.tag_byte(ix))
+    }
+
+    fn is_subpath_start(&self, ix: usize) -> bool {
+        if ix == 0 {
+            return true;
+        }
+        let prev = self.tag_byte(ix - 1);
+        (prev & PATH_TAG_SEG_TYPE) == 0 || (prev & PATH_TAG_SUBPATH_END) != 0
+    }
+
+    fn n_tags(&self) -> usize {
+        (self.config.layout.path_data_base - self.config.layout.path_tag_base) as usize * 4
+    }
+}
+
+/// Outputs the join or cap at the end of the segment, and the cap at its start if the
+/// segment starts an open subpath. A subpath is considered closed if it ends where it starts.
+fn stroke_segment_ends(
+    segments: &Segments,
+    stroker: &Stroker,
+    out: &mut Lines,
+    ix: usize,
+    tag_byte: u8,
+    seg: &[Vec2; 4],
+    style_flags: u32,
+) {
+    let join = style_flags & Style::JOIN_MASK;
+    if (tag_byte & PATH_TAG_SUBPATH_END) == 0 {
+        let next = segments.read_at(ix + 1);
+        stroker.join(
+            out,
+            seg[3],
+            end_tangent(seg),
+            start_tangent(&next),
+            join,
+            stroker.hw1,
+        );
+    } else {
+        let mut first_ix = ix;
+        while !segments.is_subpath_start(first_ix) {
+            first_ix -= 1;
+        }
+        let first = segments.read_at(first_ix);
+        if first[0] == seg[3] {
+            stroker.join(
+                out,
+                seg[3],
+                end_tangent(seg),
+                start_tangent(&first),
+                join,
+                stroker.hw1,
+            );
+        } else {
+            let cap = (style_flags >> Style::END_CAP_SHIFT) & Style::CAP_MASK;
+            stroker.cap(out, seg[3], end_tangent(seg), cap, stroker.hw1);
+        }
+    }
+    if segments.is_subpath_start(ix) {
+        let mut last_ix = ix;
+        while (segments.tag_byte(last_ix) & PATH_TAG_SUBPATH_END) == 0
+            && last_ix + 1 < segments.n_tags()
+        {
+            last_ix += 1;
+        }
+        let last = segments.read_at(last_ix);
+        if last[3] != seg[0] {
+            let cap = (style_flags >> Style::START_CAP_SHIFT) & Style::CAP_MASK;
+            stroker.cap(out, seg[0], start_tangent(seg) * -1.0, cap, stroker.hw0);
+        }
+    }
+}
+
+/// Returns the largest factor the transform scales a distance by.
+fn max_scale(transform: &Transform) -> f32 {
+    let [a, b, c, d, _, _] = transform.0;
+    let det = a * d - b * c;
+    let s = a * a + b * b + c * c + d * d;
+    (0.5 * (s + (s * s - 4.0 * det * det).max(0.0).sqrt())).sqrt()
+}
+
 fn read_f32_point(ix: u32, pathdata: &[u32]) -> Vec2 {
     let x = f32::from_bits(pathdata[ix as usize]);
     let y = f32::from_bits(pathdata[ix as usize + 1]);
@@ -183,13 +509,14 @@ impl IntBbox {
 
 // TODO: we're skipping i16 point reading as it's not present in our scenes
 
-const WG_SIZE: usize = 256;
+const WG_SIZE: usize = vello_encoding::FLATTEN_WG as usize;
 
 const PATH_TAG_SEG_TYPE: u8 = 3;
 const PATH_TAG_PATH: u8 = 0x10;
 const PATH_TAG_LINETO: u8 = 1;
 const PATH_TAG_QUADTO: u8 = 2;
 const PATH_TAG_CUBICTO: u8 = 3;
+const PATH_TAG_SUBPATH_END: u8 = 4;
 const PATH_TAG_F32: u8 = 8;
 
 fn flatten_main(
@@ -201,7 +528,12 @@ fn flatten_main(
     bump: &mut BumpAllocators,
     lines: &mut [LineSoup],
 ) {
-    let mut line_ix = 0;
+    let mut out = Lines { line_ix: 0, lines };
+    let segments = Segments {
+        config,
+        scene,
+        tag_monoids,
+    };
     let mut bbox = IntBbox::default();
     for ix in 0..n_wg as usize * WG_SIZE {
         let tag_word = scene[config.layout.path_tag_base as usize + (ix >> 2)];
@@ -211,62 +543,89 @@ fn flatten_main(
         if tag_byte != 0 {
             tm = tag_monoids[ix >> 2].combine(&tm);
         }
-        let linewidth =
-            f32::from_bits(scene[(config.layout.linewidth_base + tm.linewidth_ix) as usize]);
+        let style_base = (config.layout.style_base + tm.style_ix * 3) as usize;
+        let linewidth = f32::from_bits(scene[style_base]);
         if (tag_byte & PATH_TAG_PATH) != 0 {
             let out = &mut path_bboxes[tm.path_ix as usize];
             out.linewidth = linewidth;
             out.trans_ix = tm.trans_ix;
         }
         let seg_type = tag_byte & PATH_TAG_SEG_TYPE;
-        let pathdata = &scene[config.layout.path_data_base as usize..];
         if seg_type != 0 {
-            let mut p0;
-            let mut p1;
-            let mut p2 = Vec2::default();
-            let mut p3 = Vec2::default();
-            if (tag_byte & PATH_TAG_F32) != 0 {
-                p0 = read_f32_point(tm.pathseg_offset, pathdata);
-                p1 = read_f32_point(tm.pathseg_offset + 2, pathdata);
-                if seg_type >= PATH_TAG_QUADTO {
-                    p2 = read_f32_point(tm.pathseg_offset + 4, pathdata);
-                    if seg_type == PATH_TAG_CUBICTO {
-                        p3 = read_f32_point(tm.pathseg_offset + 6, pathdata);
-                    }
-                }
-            } else {
-                todo!("i16 path data not supported yet");
-            }
+            let seg = segments.read(&tm, tag_byte);
             let transform = Transform::read(config.layout.transform_base, tm.trans_ix, scene);
-            p0 = transform.apply(p0);
-            bbox.add_pt(p0);
-            p1 = transform.apply(p1);
-            bbox.add_pt(p1);
-            if seg_type == PATH_TAG_LINETO {
-                p3 = p1;
-                p2 = p3.mix(p0, 1.0 / 3.0);
-                p1 = p0.mix(p3, 1.0 / 3.0);
-            } else if seg_type >= PATH_TAG_QUADTO {
-                p2 = transform.apply(p2);
-                bbox.add_pt(p2);
-                if seg_type == PATH_TAG_CUBICTO {
-                    p3 = transform.apply(p3);
-                    bbox.add_pt(p3);
+            let [p0, p1, p2, p3] = seg.map(|p| transform.apply(p));
+            let path_ix = tm.path_ix;
+            if linewidth >= 0.0 {
+                let style_flags = scene[style_base + 2];
+                let [a, b, c, d, _, _] = transform.0;
+                let accuracy = ACCURACY / max_scale(&transform).max(1e-6);
+                let hw = 0.5 * linewidth;
+                let (hw0, hw1) = if style_flags & Style::VARIABLE_WIDTH != 0 {
+                    let width_base = (config.layout.width_base + tm.pathseg_ix * 2) as usize;
+                    (
+                        0.5 * f32::from_bits(scene[width_base]),
+                        0.5 * f32::from_bits(scene[width_base + 1]),
+                    )
                 } else {
-                    p3 = p2;
-                    p2 = p1.mix(p2, 1.0 / 3.0);
-                    p1 = p1.mix(p0, 1.0 / 3.0);
+                    (hw, hw)
+                };
+                let stroker = Stroker {
+                    path_ix,
+                    flip: a * d - b * c < 0.0,
+                    hw,
+                    hw0,
+                    hw1,
+                    miter_limit: f32::from_bits(scene[style_base + 1]),
+                    accuracy,
+                    transform,
+                };
+                let cubic = Cubic {
+                    p0: seg[0],
+                    p1: seg[1],
+                    p2: seg[2],
+                    p3: seg[3],
+                    path_ix,
+                };
+                flatten_cubic(cubic, accuracy, Some(&stroker), &mut out);
+                stroke_segment_ends(
+                    &segments,
+                    &stroker,
+                    &mut out,
+                    ix,
+                    tag_byte,
+                    &seg,
+                    style_flags,
+                );
+                // Miter joins and square caps extend further than half the line width.
+                let mut extent = 1.0f32;
+                if style_flags & Style::JOIN_MASK == Style::JOIN_MITER {
+                    extent = extent.max(stroker.miter_limit);
                 }
+                let start_cap = (style_flags >> Style::START_CAP_SHIFT) & Style::CAP_MASK;
+                let end_cap = (style_flags >> Style::END_CAP_SHIFT) & Style::CAP_MASK;
+                if start_cap == Style::CAP_SQUARE || end_cap == Style::CAP_SQUARE {
+                    extent = extent.max(std::f32::consts::SQRT_2);
+                }
+                let radius = stroker.hw * extent;
+                let stroke = Vec2::new(radius * a.hypot(c), radius * b.hypot(d));
+                for p in [p0, p1, p2, p3] {
+                    bbox.add_pt(p - stroke);
+                    bbox.add_pt(p + stroke);
+                }
+            } else {
+                for p in [p0, p1, p2, p3] {
+                    bbox.add_pt(p);
+                }
+                let cubic = Cubic {
+                    p0,
+                    p1,
+                    p2,
+                    p3,
+                    path_ix,
+                };
+                flatten_cubic(cubic, ACCURACY, None, &mut out);
             }
-            let path_ix = tm.path_ix;
-            let cubic = Cubic {
-                p0,
-                p1,
-                p2,
-                p3,
-                path_ix,
-            };
-            flatten_cubic(cubic, &mut line_ix, lines);
         }
         if (tag_byte & PATH_TAG_PATH) != 0 {
             let out = &mut path_bboxes[tm.path_ix as usize];
@@ -277,7 +636,7 @@ fn flatten_main(
             bbox = IntBbox::default();
         }
     }
-    bump.lines = line_ix as u32;
+    bump.lines = out.line_ix as u32;
 }
 
 pub fn flatten(n_wg: u32, resources: &[CpuBinding]) {
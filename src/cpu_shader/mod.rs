@@ -10,6 +10,8 @@
 mod backdrop;
 mod bbox_clear;
 mod binning;
+mod blend;
+mod blur;
 mod clip_leaf;
 mod clip_reduce;
 mod coarse;
@@ -29,11 +31,13 @@ mod util;
 pub use backdrop::backdrop;
 pub use bbox_clear::bbox_clear;
 pub use binning::binning;
+pub use blur::blur;
 pub use clip_leaf::clip_leaf;
 pub use clip_reduce::clip_reduce;
 pub use coarse::coarse;
 pub use draw_leaf::draw_leaf;
 pub use draw_reduce::draw_reduce;
+pub use fine::fine;
 pub use flatten::flatten;
 pub use path_count::path_count;
 pub use path_count_setup::path_count_setup;
@@ -45,17 +49,18 @@ pub use tile_alloc::tile_alloc;
 
 // Common definitions
 
-const PTCL_INITIAL_ALLOC: u32 = 64;
+pub(crate) const PTCL_INITIAL_ALLOC: u32 = 64;
 
 // Tags for PTCL commands
-const CMD_END: u32 = 0;
-const CMD_FILL: u32 = 1;
-//const CMD_STROKE: u32 = 2;
-const CMD_SOLID: u32 = 3;
-const CMD_COLOR: u32 = 5;
-const CMD_LIN_GRAD: u32 = 6;
-const CMD_RAD_GRAD: u32 = 7;
-const CMD_IMAGE: u32 = 8;
-const CMD_BEGIN_CLIP: u32 = 9;
-const CMD_END_CLIP: u32 = 10;
-const CMD_JUMP: u32 = 11;
+pub(crate) const CMD_END: u32 = 0;
+pub(crate) const CMD_FILL: u32 = 1;
+pub(crate) const CMD_STROKE: u32 = 2;
+pub(crate) const CMD_SOLID: u32 = 3;
+pub(crate) const CMD_COLOR: u32 = 5;
+pub(crate) const CMD_LIN_GRAD: u32 = 6;
+pub(crate) const CMD_RAD_GRAD: u32 = 7;
+pub(crate) const CMD_IMAGE: u32 = 8;
+pub(crate) const CMD_BEGIN_CLIP: u32 = 9;
+pub(crate) const CMD_END_CLIP: u32 = 10;
+pub(crate) const CMD_JUMP: u32 = 11;
+pub(crate) const CMD_SOLID_COLOR: u32 = 12;
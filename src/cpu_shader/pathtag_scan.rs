@@ -5,7 +5,7 @@ use vello_encoding::{ConfigUniform, Monoid, PathMonoid};
 
 use crate::cpu_dispatch::CpuBinding;
 
-const WG_SIZE: usize = 256;
+const WG_SIZE: usize = vello_encoding::PATH_REDUCE_WG as usize;
 
 fn pathtag_scan_main(
     n_wg: u32,
@@ -5,7 +5,9 @@
 
 use vello_encoding::ConfigUniform;
 
-#[derive(Clone, Copy, Default, Debug)]
+use crate::cpu_dispatch::CpuTexture;
+
+#[derive(Clone, Copy, Default, Debug, PartialEq)]
 #[repr(C)]
 pub struct Vec2 {
     pub x: f32,
@@ -126,3 +128,37 @@ pub const ONE_MINUS_ULP: f32 = 0.99999994;
 /// direction. The theory is that a is not off by more than a few ulp, and it's
 /// always in the range of 0..1.
 pub const ROBUST_EPSILON: f32 = 2e-7;
+
+/// Unpacks four normalized bytes, the first in the least significant byte.
+pub fn unpack4x8unorm(x: u32) -> [f32; 4] {
+    let mut result = [0.0; 4];
+    for i in 0..4 {
+        result[i] = ((x >> (i * 8)) & 0xff) as f32 * (1.0 / 255.0);
+    }
+    result
+}
+
+pub fn pack4x8unorm(x: [f32; 4]) -> u32 {
+    let mut result = 0;
+    for i in 0..4 {
+        let byte = (x[i].clamp(0.0, 1.0) * 255.0).round() as u32;
+        result |= byte << (i * 8);
+    }
+    result
+}
+
+/// Unpacks a color packed with red in the most significant byte, like `.wzyx` in shaders.
+pub fn unpack_color(x: u32) -> [f32; 4] {
+    let [a, b, g, r] = unpack4x8unorm(x);
+    [r, g, b, a]
+}
+
+/// Loads a texel like `textureLoad`, with coordinates clamped to the texture.
+pub fn load(texture: &CpuTexture, x: i32, y: i32) -> [f32; 4] {
+    if texture.width == 0 || texture.height == 0 {
+        return [0.0; 4];
+    }
+    let x = x.clamp(0, texture.width as i32 - 1) as usize;
+    let y = y.clamp(0, texture.height as i32 - 1) as usize;
+    unpack4x8unorm(texture.pixels[y * texture.width + x])
+}
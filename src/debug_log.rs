@@ -0,0 +1,57 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Printing of the assertions and values recorded by shaders built with the `debug_log`
+//! feature, with the helpers of `shader/shared/debug.wgsl`.
+
+/// Size of the log in bytes, including the count of words written.
+pub(crate) const DEBUG_LOG_SIZE: u64 = 1 << 16;
+
+// Kinds of records. This must be kept in sync with shader/shared/debug.wgsl
+const DEBUG_ASSERT: u32 = 1;
+const DEBUG_PRINT_U32: u32 = 2;
+const DEBUG_PRINT_I32: u32 = 3;
+const DEBUG_PRINT_F32: u32 = 4;
+
+/// Prints the records of `log` to stderr, in the order they were written.
+///
+/// `locate` returns the location of a record in the source, given the index of its shader and
+/// its line in the preprocessed output of that shader.
+pub(crate) fn print(log: &[u32], locate: impl Fn(u32, u32) -> String) {
+    let Some((&n_words, words)) = log.split_first() else {
+        return;
+    };
+    let mut ix = 0;
+    while ix < words.len() {
+        let header = words[ix];
+        let kind = header >> 28;
+        let shader = (header >> 20) & 0xff;
+        let line = (header >> 4) & 0xffff;
+        let n_values = (header & 0xf) as usize;
+        let Some(values) = words.get(ix + 1..ix + 1 + n_values) else {
+            break;
+        };
+        let location = || locate(shader, line);
+        match kind {
+            DEBUG_ASSERT => eprintln!("{}: assertion failed", location()),
+            DEBUG_PRINT_U32 => eprintln!("{}: {values:?}", location()),
+            DEBUG_PRINT_I32 => {
+                let values: Vec<i32> = values.iter().map(|v| *v as i32).collect();
+                eprintln!("{}: {values:?}", location());
+            }
+            DEBUG_PRINT_F32 => {
+                let values: Vec<f32> = values.iter().map(|v| f32::from_bits(*v)).collect();
+                eprintln!("{}: {values:?}", location());
+            }
+            // The rest of the log is still cleared, where records didn't fit.
+            _ => break,
+        }
+        ix += 1 + n_values;
+    }
+    if n_words as usize > words.len() {
+        eprintln!(
+            "debug log overflowed, {} words were dropped",
+            n_words as usize - words.len()
+        );
+    }
+}
@@ -0,0 +1,228 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Embedding of scenes in egui panels, through the paint callbacks of `egui-wgpu`.
+//!
+//! [`SceneRenderer::install`] adds a renderer to the callback resources of the egui renderer,
+//! sharing its device. Each frame, [`SceneCallback::paint`] returns a paint callback which
+//! renders a scene to a texture before the egui render pass, and draws that texture in the
+//! rectangle of the callback in the target format of egui.
+
+use std::collections::HashMap;
+
+use egui_wgpu::{CallbackResources, CallbackTrait, RenderState};
+use peniko::Color;
+use wgpu::{Device, Queue};
+
+use crate::composite::CompositePipeline;
+use crate::{kurbo, AaConfig, RenderParams, Renderer, RendererOptions, Result, Scene};
+
+/// Renderer of the scenes painted by [`SceneCallback`], kept in the callback resources of
+/// the egui renderer.
+pub struct SceneRenderer {
+    renderer: Renderer,
+    composite: CompositePipeline,
+    targets: HashMap<egui::Id, PanelTarget>,
+}
+
+/// Texture a scene is rendered to, kept between frames for the same id.
+struct PanelTarget {
+    view: wgpu::TextureView,
+    bind_group: wgpu::BindGroup,
+    width: u32,
+    height: u32,
+}
+
+impl SceneRenderer {
+    /// Creates a renderer with the device of `render_state` and adds it to the callback
+    /// resources of its egui renderer. This should be called once, before any scene is
+    /// painted.
+    ///
+    /// `msaa_samples` is the sample count egui renders with. The egui render pass must not
+    /// have a depth buffer.
+    pub fn install(render_state: &RenderState, msaa_samples: u32) -> Result<()> {
+        let device = &render_state.device;
+        let renderer = Renderer::new(
+            device,
+            &RendererOptions {
+                surface_format: None,
+                timestamp_period: render_state.queue.get_timestamp_period(),
+                use_cpu: false,
+                num_init_threads: None,
+                memory_budget_bytes: None,
+                compact_tiles: false,
+                antialiasing_method: AaConfig::Area,
+                linear_blending: false,
+                dithering: false,
+                output_format: wgpu::TextureFormat::Rgba8Unorm,
+                split_submissions: false,
+                single_pass_scan: false,
+                shader_source: None,
+            },
+        )?;
+        let composite = CompositePipeline::new(
+            device,
+            render_state.target_format,
+            msaa_samples.max(1),
+            false,
+        );
+        render_state
+            .renderer
+            .write()
+            .callback_resources
+            .insert(Self {
+                renderer,
+                composite,
+                targets: HashMap::new(),
+            });
+        Ok(())
+    }
+
+    /// Drops the texture of the scene painted with `id`, once it is no longer shown.
+    pub fn remove_target(&mut self, id: egui::Id) {
+        self.targets.remove(&id);
+    }
+
+    /// Creates the texture for `id`, unless it exists and is `width` by `height` pixels.
+    fn prepare_target(&mut self, device: &Device, id: egui::Id, width: u32, height: u32) {
+        let target = self.targets.get(&id);
+        if target.map_or(true, |target| {
+            (target.width, target.height) != (width, height)
+        }) {
+            let texture = device.create_texture(&wgpu::TextureDescriptor {
+                label: Some("egui scene target"),
+                size: wgpu::Extent3d {
+                    width,
+                    height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                view_formats: &[],
+            });
+            let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+            let bind_group = self.composite.bind_group(device, &view);
+            self.targets.insert(
+                id,
+                PanelTarget {
+                    view,
+                    bind_group,
+                    width,
+                    height,
+                },
+            );
+        }
+    }
+}
+
+/// Paint callback which draws a scene in an egui panel.
+pub struct SceneCallback {
+    id: egui::Id,
+    scene: Scene,
+    base_color: Color,
+    width: u32,
+    height: u32,
+    /// Visible part of the panel, in pixels of the texture.
+    region: kurbo::Rect,
+}
+
+impl SceneCallback {
+    /// Returns a paint callback which draws `scene` in `rect` of `ui`, with the origin of the
+    /// scene at the top left corner of `rect` and one unit of the scene per physical pixel.
+    ///
+    /// Only the part of the scene within the clip rectangle of `ui` is rendered. `id`
+    /// identifies the texture the scene is rendered to, which is kept between frames, so it
+    /// should stay the same from frame to frame and differ between the scenes painted in a
+    /// frame.
+    pub fn paint(
+        ui: &egui::Ui,
+        id: egui::Id,
+        rect: egui::Rect,
+        scene: Scene,
+        base_color: Color,
+    ) -> egui::PaintCallback {
+        let pixels_per_point = ui.ctx().pixels_per_point();
+        let to_pixels = |points: f32| (points * pixels_per_point).round().max(1.0) as u32;
+        // The clip rectangle in points of the screen becomes a region in pixels of the panel.
+        let clip = ui
+            .clip_rect()
+            .intersect(rect)
+            .translate(-rect.min.to_vec2());
+        let region = if clip.is_positive() {
+            kurbo::Rect::new(
+                (clip.min.x * pixels_per_point) as f64,
+                (clip.min.y * pixels_per_point) as f64,
+                (clip.max.x * pixels_per_point) as f64,
+                (clip.max.y * pixels_per_point) as f64,
+            )
+        } else {
+            kurbo::Rect::ZERO
+        };
+        let callback = Self {
+            id,
+            scene,
+            base_color,
+            width: to_pixels(rect.width()),
+            height: to_pixels(rect.height()),
+            region,
+        };
+        egui_wgpu::Callback::new_paint_callback(rect, callback)
+    }
+}
+
+impl CallbackTrait for SceneCallback {
+    fn prepare(
+        &self,
+        device: &Device,
+        queue: &Queue,
+        _egui_encoder: &mut wgpu::CommandEncoder,
+        callback_resources: &mut CallbackResources,
+    ) -> Vec<wgpu::CommandBuffer> {
+        let Some(scene_renderer) = callback_resources.get_mut::<SceneRenderer>() else {
+            return Vec::new();
+        };
+        if self.region.is_empty() {
+            return Vec::new();
+        }
+        scene_renderer.prepare_target(device, self.id, self.width, self.height);
+        let params = RenderParams {
+            base_color: self.base_color,
+            width: self.width,
+            height: self.height,
+        };
+        // The renderer submits its own work, which runs before the egui render pass.
+        if let Err(e) = scene_renderer.renderer.render_region_to_texture(
+            device,
+            queue,
+            &self.scene,
+            &scene_renderer.targets[&self.id].view,
+            &params,
+            self.region,
+        ) {
+            log::error!("Failed to render egui scene: {e}");
+        }
+        Vec::new()
+    }
+
+    fn paint<'a>(
+        &'a self,
+        _info: egui::PaintCallbackInfo,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        callback_resources: &'a CallbackResources,
+    ) {
+        let Some(scene_renderer) = callback_resources.get::<SceneRenderer>() else {
+            return;
+        };
+        let Some(target) = scene_renderer.targets.get(&self.id) else {
+            return;
+        };
+        // egui sets the viewport to the rectangle of the callback and the scissor rectangle
+        // to its clip rectangle.
+        scene_renderer
+            .composite
+            .draw(render_pass, &target.bind_group);
+    }
+}
@@ -15,12 +15,61 @@
 // Also licensed under MIT license, at your choice.
 
 use std::{
+    collections::HashSet,
+    fmt,
     num::NonZeroU64,
     sync::atomic::{AtomicU64, Ordering},
 };
 
 pub type Error = Box<dyn std::error::Error>;
 
+/// Error produced when a shader fails to compile.
+///
+/// When the shader was preprocessed, `file` and `line` refer to the original shader or shared
+/// import rather than to the spliced source handed to the GPU API.
+#[derive(Debug)]
+pub struct ShaderCompileError {
+    /// Name of the shader or import containing the error, if known.
+    pub file: Option<String>,
+    /// 1-based line number of the error, if known.
+    pub line: Option<usize>,
+    /// The full diagnostic reported by the shader compiler.
+    pub message: String,
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, "{file}.wgsl:{line}: {}", self.message),
+            (Some(file), None) => write!(f, "{file}.wgsl: {}", self.message),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderCompileError {}
+
+/// Error returned when running a recording would exceed the GPU memory budget.
+#[derive(Debug)]
+pub struct MemoryBudgetError {
+    /// Bytes of GPU memory needed by the resources of the recording.
+    pub required: u64,
+    /// The configured budget, in bytes.
+    pub budget: u64,
+}
+
+impl fmt::Display for MemoryBudgetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "recording needs {} bytes of GPU memory, exceeding the budget of {} bytes",
+            self.required, self.budget
+        )
+    }
+}
+
+impl std::error::Error for MemoryBudgetError {}
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub struct ShaderId(pub usize);
 
@@ -41,11 +90,11 @@ pub struct BufProxy {
     pub name: &'static str,
 }
 
-#[derive(Copy, Clone, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ImageFormat {
     Rgba8,
-    #[allow(unused)]
     Bgra8,
+    Rgba16Float,
 }
 
 #[derive(Clone, Copy)]
@@ -64,6 +113,9 @@ pub enum ResourceProxy {
 
 pub enum Command {
     Upload(BufProxy, Vec<u8>),
+    /// Upload to a buffer which the engine keeps across recordings, see
+    /// [`Recording::upload_retained`].
+    UploadRetained(BufProxy, Vec<u8>),
     UploadUniform(BufProxy, Vec<u8>),
     UploadImage(ImageProxy, Vec<u8>),
     WriteImage(ImageProxy, [u32; 4], Vec<u8>),
@@ -106,6 +158,18 @@ impl Recording {
         buf_proxy
     }
 
+    /// Uploads data to a buffer which the engine keeps across recordings under `name`.
+    ///
+    /// Only the parts of the data that differ from the previous upload with the same name and
+    /// size are written to the GPU, which saves most of the upload for data that changes little
+    /// from one frame to the next.
+    pub fn upload_retained(&mut self, name: &'static str, data: impl Into<Vec<u8>>) -> BufProxy {
+        let data = data.into();
+        let buf_proxy = BufProxy::new(data.len() as u64, name);
+        self.push(Command::UploadRetained(buf_proxy, data));
+        buf_proxy
+    }
+
     pub fn upload_uniform(&mut self, name: &'static str, data: impl Into<Vec<u8>>) -> BufProxy {
         let data = data.into();
         let buf_proxy = BufProxy::new(data.len() as u64, name);
@@ -195,6 +259,49 @@ impl Recording {
         }
     }
 
+    /// Returns the number of bytes of GPU memory used by the resources of this recording, not
+    /// counting the resources in `external`.
+    ///
+    /// `buf_size` maps the size of a buffer to the size allocated for it, such as when buffers
+    /// are rounded up to size classes. Resources are counted for the whole recording, even if
+    /// they are freed part way through.
+    pub fn memory_footprint(&self, external: &[Id], buf_size: impl Fn(u64) -> u64) -> u64 {
+        let mut seen = HashSet::new();
+        let mut total = 0;
+        let mut staging = 0;
+        let mut add = |resource: ResourceProxy| {
+            let (id, size) = match resource {
+                ResourceProxy::Buf(buf) => (buf.id, buf_size(buf.size)),
+                ResourceProxy::Image(image) => (
+                    image.id,
+                    image.width as u64 * image.height as u64 * image.format.bytes_per_pixel(),
+                ),
+            };
+            if !external.contains(&id) && seen.insert(id) {
+                total += size;
+            }
+        };
+        for command in &self.commands {
+            match command {
+                Command::Upload(buf, _)
+                | Command::UploadRetained(buf, _)
+                | Command::UploadUniform(buf, _) => add((*buf).into()),
+                Command::UploadImage(image, _) | Command::WriteImage(image, _, _) => {
+                    add((*image).into())
+                }
+                Command::Dispatch(_, _, resources) => resources.iter().for_each(|r| add(*r)),
+                Command::DispatchIndirect(_, buf, _, resources) => {
+                    add((*buf).into());
+                    resources.iter().for_each(|r| add(*r));
+                }
+                // The download is copied into a separate staging buffer of the same size.
+                Command::Download(buf) => staging += buf_size(buf.size),
+                Command::Clear(..) | Command::FreeBuf(_) | Command::FreeImage(_) => (),
+            }
+        }
+        total + staging
+    }
+
     pub fn into_commands(self) -> Vec<Command> {
         self.commands
     }
@@ -209,11 +316,47 @@ impl BufProxy {
 }
 
 impl ImageFormat {
+    /// Returns the number of bytes each pixel of an image of this format takes.
+    pub fn bytes_per_pixel(self) -> u64 {
+        match self {
+            Self::Rgba8 | Self::Bgra8 => 4,
+            Self::Rgba16Float => 8,
+        }
+    }
+
     #[cfg(feature = "wgpu")]
     pub fn to_wgpu(self) -> wgpu::TextureFormat {
         match self {
             Self::Rgba8 => wgpu::TextureFormat::Rgba8Unorm,
             Self::Bgra8 => wgpu::TextureFormat::Bgra8Unorm,
+            Self::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+        }
+    }
+
+    /// Returns the format that shaders write to a texture of the given format, if it can be
+    /// used as a render target.
+    ///
+    /// sRGB formats can't be bound as storage textures, so `Rgba8UnormSrgb` targets are
+    /// written through an `Rgba8Unorm` view, with the same encoded values.
+    #[cfg(feature = "wgpu")]
+    pub fn from_wgpu(format: wgpu::TextureFormat) -> Option<Self> {
+        match format {
+            wgpu::TextureFormat::Rgba8Unorm | wgpu::TextureFormat::Rgba8UnormSrgb => {
+                Some(Self::Rgba8)
+            }
+            wgpu::TextureFormat::Bgra8Unorm => Some(Self::Bgra8),
+            wgpu::TextureFormat::Rgba16Float => Some(Self::Rgba16Float),
+            _ => None,
+        }
+    }
+
+    /// Returns the preprocessor definition that selects this format for the storage textures
+    /// written by a shader.
+    pub fn define(self) -> &'static str {
+        match self {
+            Self::Rgba8 => "output_rgba8",
+            Self::Bgra8 => "output_bgra8",
+            Self::Rgba16Float => "output_rgba16float",
         }
     }
 }
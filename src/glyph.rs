@@ -15,23 +15,167 @@
 // Also licensed under MIT license, at your choice.
 
 //! Support for glyph rendering.
+//!
+//! Fonts are loaded from files or bytes into a [`FontRegistry`], such as the one of a
+//! [`Renderer`](crate::Renderer), which identifies them by [`FontId`]. The [`FontRef`] of a
+//! registered font gives its metrics, normalizes variation settings to the coordinates glyph
+//! runs are drawn with, and loads the outlines of its glyphs.
+
+use std::path::Path;
+use std::sync::Arc;
 
 use crate::scene::{SceneBuilder, SceneFragment};
+use crate::Result;
 use {
     fello::{
+        meta::{
+            metrics::{GlyphMetrics, Metrics},
+            MetadataProvider,
+        },
         raw::types::GlyphId,
-        raw::FontRef,
+        raw::FileRef,
         scale::{Context, Pen, Scaler},
-        FontKey, Setting, Size,
+        FontKey, NormalizedCoord, Setting, Size,
     },
-    peniko::kurbo::Affine,
-    peniko::{Brush, Color, Fill, Style},
+    peniko::kurbo::{Affine, BezPath},
+    peniko::{Blob, Brush, Color, Fill, Font, Style},
     vello_encoding::Encoding,
 };
 
 pub use fello;
 use peniko::kurbo::Shape;
-pub use vello_encoding::Glyph;
+pub use vello_encoding::{font_ref, Glyph};
+
+/// Identifies a font registered with a [`FontRegistry`].
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct FontId(u32);
+
+/// Fonts loaded from files or bytes, identified by [`FontId`].
+#[derive(Clone, Default)]
+pub struct FontRegistry {
+    fonts: Vec<Font>,
+}
+
+impl FontRegistry {
+    /// Creates an empty registry.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers `font`, returning the id it was already registered with if any.
+    ///
+    /// Fails if the data of the font can't be parsed, or the font is not in it.
+    pub fn register(&mut self, font: Font) -> Result<FontId> {
+        let existing = self
+            .fonts
+            .iter()
+            .position(|f| f.data.id() == font.data.id() && f.index == font.index);
+        if let Some(index) = existing {
+            return Ok(FontId(index as u32));
+        }
+        font_ref(&font).ok_or("invalid font data")?;
+        self.fonts.push(font);
+        Ok(FontId(self.fonts.len() as u32 - 1))
+    }
+
+    /// Registers each font in `data`, which holds a font file or a font collection.
+    pub fn register_data(&mut self, data: Blob<u8>) -> Result<Vec<FontId>> {
+        let count = match FileRef::new(data.as_ref()).map_err(|_| "invalid font data")? {
+            FileRef::Font(_) => 1,
+            FileRef::Collection(collection) => (0..)
+                .take_while(|&index| collection.get(index).is_ok())
+                .count() as u32,
+        };
+        (0..count)
+            .map(|index| self.register(Font::new(data.clone(), index)))
+            .collect()
+    }
+
+    /// Reads the font file or font collection at `path` and registers each font in it.
+    pub fn register_file(&mut self, path: impl AsRef<Path>) -> Result<Vec<FontId>> {
+        let data = std::fs::read(path)?;
+        self.register_data(Blob::new(Arc::new(data)))
+    }
+
+    /// Returns the font registered with `id`.
+    pub fn get(&self, id: FontId) -> Option<FontRef<'_>> {
+        let font = self.fonts.get(id.0 as usize)?;
+        Some(FontRef {
+            font,
+            raw: font_ref(font)?,
+        })
+    }
+}
+
+/// A registered font, parsed for its metrics and outlines.
+#[derive(Clone)]
+pub struct FontRef<'a> {
+    font: &'a Font,
+    raw: fello::raw::FontRef<'a>,
+}
+
+impl<'a> FontRef<'a> {
+    /// Returns the font, to draw glyph runs with [`SceneBuilder::draw_glyphs`].
+    pub fn font(&self) -> &'a Font {
+        self.font
+    }
+
+    /// Returns the parsed font tables.
+    pub fn raw(&self) -> &fello::raw::FontRef<'a> {
+        &self.raw
+    }
+
+    /// Returns the normalized coordinates of the variation axes for `variations`, which are
+    /// pairs of axis tags and user coordinates, such as `("wght", 700.0)`. Axes which are not
+    /// set keep their default.
+    pub fn normalized_coords(&self, variations: &[(&str, f32)]) -> Vec<NormalizedCoord> {
+        self.raw
+            .axes()
+            .normalize(variations.iter().copied())
+            .collect()
+    }
+
+    /// Returns the global metrics of the font at `size` in pixels per em, with the variation
+    /// `coords`.
+    pub fn metrics(&self, size: f32, coords: &[NormalizedCoord]) -> Metrics {
+        self.raw.metrics(Size::new(size), coords.into())
+    }
+
+    /// Returns the metrics of the glyphs of the font at `size` in pixels per em, with the
+    /// variation `coords`.
+    pub fn glyph_metrics(&self, size: f32, coords: &'a [NormalizedCoord]) -> GlyphMetrics<'a> {
+        self.raw.glyph_metrics(Size::new(size), coords.into())
+    }
+
+    /// Returns the glyph for `ch` in the character map of the font.
+    pub fn glyph_id(&self, ch: char) -> Option<u16> {
+        self.raw.charmap().map(ch).map(|gid| gid.to_u16())
+    }
+
+    /// Returns the outline of a glyph at `size` in pixels per em, with the variation
+    /// `coords`, with y up.
+    pub fn outline(
+        &self,
+        cx: &mut GlyphContext,
+        glyph_id: u16,
+        size: f32,
+        coords: &[NormalizedCoord],
+    ) -> Option<BezPath> {
+        let mut scaler = cx
+            .ctx
+            .new_scaler()
+            .key(Some(FontKey {
+                data_id: self.font.data.id(),
+                index: self.font.index,
+            }))
+            .coords(coords)
+            .size(Size::new(size))
+            .build(&self.raw);
+        let mut pen = BezPathPen::default();
+        scaler.outline(GlyphId::new(glyph_id), &mut pen).ok()?;
+        Some(pen.0)
+    }
+}
 
 /// General context for creating scene fragments for glyph outlines.
 pub struct GlyphContext {
@@ -56,7 +200,7 @@ impl GlyphContext {
     /// the specified font and settings.
     pub fn new_provider<'a, V>(
         &'a mut self,
-        font: &FontRef<'a>,
+        font: &fello::raw::FontRef<'a>,
         font_id: Option<FontKey>,
         ppem: f32,
         hint: bool,
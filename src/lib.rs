@@ -14,14 +14,27 @@
 //
 // Also licensed under MIT license, at your choice.
 
+mod bitmap;
+#[cfg(feature = "wgpu")]
+mod capabilities;
+mod capture;
+mod colr;
+mod cpu_check;
 mod cpu_dispatch;
 mod cpu_shader;
+#[cfg(feature = "wgpu")]
+mod debug_log;
 mod engine;
 mod mask;
 mod render;
 mod scene;
 mod shaders;
 #[cfg(feature = "wgpu")]
+mod step;
+mod trace;
+#[cfg(all(feature = "wgpu", not(target_arch = "wasm32")))]
+mod tuning;
+#[cfg(feature = "wgpu")]
 mod wgpu_engine;
 
 /// Styling and composition primitives.
@@ -34,23 +47,64 @@ pub use fello;
 
 pub mod glyph;
 
+#[cfg(feature = "bevy")]
+pub mod bevy_node;
+#[cfg(any(feature = "egui", feature = "bevy"))]
+mod composite;
+#[cfg(feature = "egui")]
+pub mod egui_callback;
+#[cfg(feature = "wgpu")]
+pub mod pacing;
 #[cfg(feature = "wgpu")]
 pub mod util;
 
+#[cfg(feature = "wgpu")]
+pub use capabilities::Capabilities;
+pub use capture::{Capture, CapturedBuffer};
+pub use cpu_check::StageMismatch;
 pub use render::Render;
-pub use scene::{DrawGlyphs, Scene, SceneBuilder, SceneFragment};
+pub use scene::{DrawGlyphs, Filter, FragmentSlot, Scene, SceneBuilder, SceneFragment};
+#[cfg(feature = "wgpu")]
+pub use step::Step;
+pub use trace::{TraceFrame, TraceReader, TraceWriter};
 #[cfg(feature = "wgpu")]
 pub use util::block_on_wgpu;
 
 pub use engine::{
-    BufProxy, Command, Id, ImageFormat, ImageProxy, Recording, ResourceProxy, ShaderId,
+    BufProxy, Command, Id, ImageFormat, ImageProxy, MemoryBudgetError, Recording, ResourceProxy,
+    ShaderCompileError, ShaderId,
+};
+#[cfg(feature = "hot_reload")]
+pub use shaders::shader_dir;
+#[cfg(all(feature = "wgpu", not(target_arch = "wasm32")))]
+pub use shaders::validate_shaders;
+#[cfg(not(target_arch = "wasm32"))]
+pub use shaders::FileShaders;
+pub use shaders::{
+    CompactShaders, EmbeddedShaders, FullShaders, FullShadersOptions, ShaderSourceProvider,
+    ShaderSources, SimpleShaders, WorkgroupSizes, WorkgroupSizesError,
 };
-pub use shaders::FullShaders;
 #[cfg(feature = "wgpu")]
 use wgpu_engine::{ExternalResource, WgpuEngine};
 
+#[cfg(feature = "wgpu")]
+use std::collections::HashMap;
+#[cfg(feature = "wgpu")]
+use std::num::NonZeroUsize;
+#[cfg(feature = "wgpu")]
+use std::sync::Arc;
 /// Temporary export, used in with_winit for stats
-pub use vello_encoding::BumpAllocators;
+pub use vello_encoding::{BumpAllocators, EncodingStats, GlyphCacheStats};
+
+pub use vello_encoding::ExternalImage;
+
+/// Number of different external images a scene can draw, each bound to fine rasterization
+/// as its own texture, including cached layers. Rendering a scene which draws more returns an
+/// error.
+pub const MAX_EXTERNAL_IMAGES: u32 = vello_encoding::DrawImage::MAX_EXTERNAL_IMAGES;
+
+#[cfg(feature = "wgpu")]
+use vello_encoding::{Encoding, Resolver, Transform};
 #[cfg(feature = "wgpu")]
 use wgpu::{Device, Queue, SurfaceTexture, TextureFormat, TextureView};
 #[cfg(feature = "wgpu-profiler")]
@@ -63,31 +117,394 @@ pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 /// Possible configurations for antialiasing.
-#[derive(PartialEq, Eq)]
-#[allow(unused)]
-enum AaConfig {
+///
+/// Each configuration is a separate permutation of the fine rasterization shader.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AaConfig {
+    /// Analytic area coverage, computed exactly for each pixel.
+    #[default]
     Area,
+    /// Multisampled coverage with 8 samples per pixel.
     Msaa8,
+    /// Multisampled coverage with 16 samples per pixel.
     Msaa16,
+    /// Multisampling with 8 samples per pixel, where paths are blended into each sample
+    /// separately and the samples are averaged when the target is written.
+    ///
+    /// Unlike the other methods, this doesn't show seams where the edges of paths meet,
+    /// known as conflation artifacts, at the cost of storing 8 colors per pixel during fine
+    /// rasterization. The content of a layer is still blended into the samples under it as
+    /// their average, so seams can remain along the edges of paths within layers.
+    Msaa8Resolve,
+    /// No antialiasing. Pixels are either fully covered or not covered at all.
+    Aliased,
+    /// Analytic area coverage, computed separately for the red, green and blue subpixels of
+    /// LCD panels with horizontal RGB stripes.
+    ///
+    /// This sharpens text at small sizes on such panels, but shows color fringes elsewhere,
+    /// so it should only be used when the output is opaque and shown at its native
+    /// resolution. Layers are clipped with the average coverage of the subpixels.
+    AreaSubpixel,
+}
+
+/// Error returned when a texture can't be the target of a render.
+#[cfg(feature = "wgpu")]
+#[derive(Debug)]
+pub enum TargetTextureError {
+    /// The texture wasn't created with the [wgpu::TextureUsages::STORAGE_BINDING] usage.
+    MissingStorageUsage,
+    /// The texture wasn't created with the [wgpu::TextureUsages::COPY_DST] usage, which the
+    /// result is written with when rendering without compute shaders.
+    MissingCopyDstUsage,
+    /// The texture format isn't the output format of the renderer.
+    Format {
+        expected: TextureFormat,
+        actual: TextureFormat,
+    },
+    /// The texture is smaller than the render, which is `width` by `height` pixels.
+    Size {
+        width: u32,
+        height: u32,
+        texture_width: u32,
+        texture_height: u32,
+    },
+}
+
+#[cfg(feature = "wgpu")]
+impl std::fmt::Display for TargetTextureError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::MissingStorageUsage => {
+                write!(f, "target texture doesn't have the STORAGE_BINDING usage")
+            }
+            Self::MissingCopyDstUsage => {
+                write!(f, "target texture doesn't have the COPY_DST usage")
+            }
+            Self::Format { expected, actual } => write!(
+                f,
+                "target texture has format {actual:?}, but the renderer outputs {expected:?}"
+            ),
+            Self::Size {
+                width,
+                height,
+                texture_width,
+                texture_height,
+            } => write!(
+                f,
+                "target texture is {texture_width}x{texture_height}, smaller than the \
+                 {width}x{height} render"
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "wgpu")]
+impl std::error::Error for TargetTextureError {}
+
+/// Error returned when a texture can't be drawn as an external image.
+#[cfg(feature = "wgpu")]
+#[derive(Debug)]
+pub enum ExternalImageError {
+    /// The texture wasn't created with the [wgpu::TextureUsages::TEXTURE_BINDING] usage.
+    MissingTextureUsage,
+    /// The texture isn't a 2D texture of a filterable float format.
+    Format(TextureFormat),
+    /// The texture isn't the size of the external image it replaces.
+    Size {
+        width: u32,
+        height: u32,
+        texture_width: u32,
+        texture_height: u32,
+    },
+    /// The external image was retired or created by another renderer.
+    Retired,
+}
+
+#[cfg(feature = "wgpu")]
+impl std::fmt::Display for ExternalImageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::MissingTextureUsage => {
+                write!(f, "external texture doesn't have the TEXTURE_BINDING usage")
+            }
+            Self::Format(format) => write!(
+                f,
+                "external texture has format {format:?}, which can't be sampled as a 2D float \
+                 texture"
+            ),
+            Self::Size {
+                width,
+                height,
+                texture_width,
+                texture_height,
+            } => write!(
+                f,
+                "external texture is {texture_width}x{texture_height}, but the image is \
+                 {width}x{height}"
+            ),
+            Self::Retired => write!(f, "external image isn't registered with the renderer"),
+        }
+    }
 }
 
-/// Configuration of antialiasing. Currently this is static, but could be switched to
-/// a launch option or even finer-grained.
-const ANTIALIASING: AaConfig = AaConfig::Area;
+#[cfg(feature = "wgpu")]
+impl std::error::Error for ExternalImageError {}
+
+/// Contents of a fragment rendered into a texture by [`Renderer::cache_layer`].
+#[cfg(feature = "wgpu")]
+pub struct CachedLayer {
+    image: ExternalImage,
+    texture: wgpu::Texture,
+    /// Position of the top left corner of the texture in the coordinates of the fragment.
+    origin: kurbo::Point,
+    /// Whether the texture holds the current contents of the fragment.
+    valid: bool,
+}
+
+#[cfg(feature = "wgpu")]
+impl CachedLayer {
+    /// Draws the layer where the fragment would be drawn with the given transform.
+    pub fn draw(&self, builder: &mut SceneBuilder, transform: kurbo::Affine) {
+        builder.draw_external_image(
+            &self.image,
+            transform * kurbo::Affine::translate(self.origin.to_vec2()),
+        );
+    }
+
+    /// Marks the layer to be rendered again by the next [`Renderer::update_cached_layer`],
+    /// when the fragment it was rendered from has changed.
+    pub fn invalidate(&mut self) {
+        self.valid = false;
+    }
+
+    /// Returns false if the layer was invalidated since it was last rendered.
+    pub fn is_valid(&self) -> bool {
+        self.valid
+    }
+
+    /// Returns the area of the fragment covered by the layer.
+    pub fn bounds(&self) -> kurbo::Rect {
+        let size = kurbo::Size::new(self.image.width as f64, self.image.height as f64);
+        kurbo::Rect::from_origin_size(self.origin, size)
+    }
+}
+
+/// Checks that a texture can be sampled as an external image.
+#[cfg(feature = "wgpu")]
+fn check_external_texture(texture: &wgpu::Texture) -> std::result::Result<(), ExternalImageError> {
+    if !texture
+        .usage()
+        .contains(wgpu::TextureUsages::TEXTURE_BINDING)
+    {
+        return Err(ExternalImageError::MissingTextureUsage);
+    }
+    let format = texture.format();
+    let filterable = matches!(
+        format.sample_type(None),
+        Some(wgpu::TextureSampleType::Float { filterable: true })
+    );
+    if texture.dimension() != wgpu::TextureDimension::D2 || !filterable {
+        return Err(ExternalImageError::Format(format));
+    }
+    Ok(())
+}
+
+/// Returns the resources which are bound from outside of a recording: the textures of the
+/// external images, and the target of the render if it is recorded.
+#[cfg(feature = "wgpu")]
+fn external_resources<'a>(
+    external_images: &'a [(ExternalImage, TextureView)],
+    target: Option<(ImageProxy, &'a TextureView)>,
+) -> Vec<ExternalResource<'a>> {
+    let images = external_images.iter().filter_map(|(image, view)| {
+        Some(ExternalResource::Image(
+            Render::external_image_proxy(image)?,
+            view,
+        ))
+    });
+    let target = target.map(|(proxy, view)| ExternalResource::Image(proxy, view));
+    images.chain(target).collect()
+}
+
+/// How many times the coarse phase is recorded again with larger buffers after running out of
+/// memory, before the possibly incomplete output is rasterized anyway.
+#[cfg(feature = "wgpu")]
+const MAX_COARSE_ATTEMPTS: u32 = 4;
 
 /// Renders a scene into a texture or surface.
 #[cfg(feature = "wgpu")]
 pub struct Renderer {
     engine: WgpuEngine,
     shaders: FullShaders,
-    blit: Option<BlitPipeline>,
+    /// Pipelines which copy intermediate textures to surfaces, one for each surface format.
+    blits: Vec<BlitPipeline>,
+    /// Intermediate texture for surfaces which can't be rendered to directly, of the size of
+    /// the surface rendered to most recently.
     target: Option<TargetTexture>,
+    /// Shaders for [`RenderTarget`]s whose format or antialiasing method differ from those of
+    /// the renderer, which only differ in fine rasterization.
+    target_shaders: Vec<FullShaders>,
+    /// Sizes of the bump allocated buffers needed by the scenes rendered so far.
+    bump_sizes: BumpAllocators,
+    last_frame_stats: Option<FrameStats>,
+    /// The downloaded bump buffer of the last frame rendered without waiting for it, and the
+    /// capacity of its buffers, which are checked when the next frame is rendered.
+    pending_bump: Option<(BufProxy, BumpAllocators)>,
+    /// Whether the next frame waits for its allocations to be read back, because no frame has
+    /// been checked yet or the last one checked didn't fit.
+    wait_for_bump: bool,
+    /// Resolves the resources of scenes, caching glyph outlines across frames.
+    resolver: Resolver,
+    /// Textures drawn as external images, with the handles they were registered with.
+    external_images: Vec<(ExternalImage, TextureView)>,
+    /// Fonts registered with [`Self::register_font`], whose glyphs scenes may draw.
+    fonts: glyph::FontRegistry,
+    linear_blending: bool,
+    dithering: bool,
     #[cfg(feature = "wgpu-profiler")]
     profiler: GpuProfiler,
     #[cfg(feature = "wgpu-profiler")]
     pub profile_result: Option<Vec<wgpu_profiler::GpuTimerScopeResult>>,
-    #[cfg(feature = "hot_reload")]
     use_cpu: bool,
+    /// Whether every stage runs on the CPU because the device can't run the pipeline.
+    cpu_fallback: bool,
+    shader_source: Arc<dyn ShaderSourceProvider>,
+    /// The capabilities of the device, which the shader variants are chosen to fit.
+    capabilities: Capabilities,
+    /// Pipelines still being created on background threads, see [`Renderer::new_deferred`].
+    #[cfg(not(target_arch = "wasm32"))]
+    shader_build: Option<wgpu_engine::ShaderBuild>,
+}
+
+/// GPU timings of the pipeline stages of a frame.
+#[cfg(feature = "wgpu-profiler")]
+#[derive(Clone, Debug, Default)]
+pub struct EngineStats {
+    /// The label of each stage (e.g. `pathtag_reduce`, `coarse`, `fine`) and the time in
+    /// seconds it took on the GPU, in dispatch order.
+    ///
+    /// Stages which are dispatched more than once in a frame are accumulated into the entry of
+    /// their first dispatch.
+    pub stages: Vec<(String, f64)>,
+}
+
+#[cfg(feature = "wgpu-profiler")]
+impl EngineStats {
+    /// Collects the stage timings from the scopes recorded by the profiler.
+    ///
+    /// Each dispatch is recorded as a scope nested in the scope of its recording, so only the
+    /// innermost scopes are counted as stages.
+    pub fn from_profile(results: &[wgpu_profiler::GpuTimerScopeResult]) -> Self {
+        fn visit(stats: &mut EngineStats, results: &[wgpu_profiler::GpuTimerScopeResult]) {
+            for result in results {
+                if !result.nested_scopes.is_empty() {
+                    visit(stats, &result.nested_scopes);
+                    continue;
+                }
+                let duration = result.time.end - result.time.start;
+                match stats.stages.iter_mut().find(|(l, _)| *l == result.label) {
+                    Some((_, time)) => *time += duration,
+                    None => stats.stages.push((result.label.clone(), duration)),
+                }
+            }
+        }
+        let mut stats = Self::default();
+        visit(&mut stats, results);
+        stats
+    }
+
+    /// Returns the total GPU time of all stages, in seconds.
+    pub fn total(&self) -> f64 {
+        self.stages.iter().map(|(_, time)| time).sum()
+    }
+}
+
+/// Memory usage of the bump allocated buffers of a frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameStats {
+    /// The allocations each stage of the frame made from the bump allocated buffers, as read
+    /// back from the GPU.
+    ///
+    /// These are the sizes the stages needed, even if the buffers were too small to hold them.
+    pub used: BumpAllocators,
+    /// The capacity of each of the bump allocated buffers, in the same units as `used`.
+    pub capacity: BumpAllocators,
+    /// How many times the coarse phase was recorded before its output fit in the buffers.
+    pub coarse_attempts: u32,
+}
+
+impl FrameStats {
+    /// Returns the number of tiles allocated for the paths of the frame.
+    pub fn tiles(&self) -> u32 {
+        self.used.tile
+    }
+
+    /// Returns the number of bytes of per tile command list memory allocated beyond the initial
+    /// allocation of each tile.
+    pub fn ptcl_bytes(&self) -> u64 {
+        self.used.ptcl as u64 * 4
+    }
+
+    /// Returns `true` if any stage ran out of space, in which case the frame was incomplete.
+    pub fn overflowed(&self) -> bool {
+        let (used, capacity) = (&self.used, &self.capacity);
+        used.failed != 0
+            || used.binning > capacity.binning
+            || used.ptcl > capacity.ptcl
+            || used.tile > capacity.tile
+            || used.seg_counts > capacity.seg_counts
+            || used.segments > capacity.segments
+            || used.lines > capacity.lines
+    }
+}
+
+/// The outcome of [`Renderer::render_to_texture_async`] and
+/// [`Renderer::render_to_surface_async`].
+#[derive(Clone, Copy, Debug)]
+pub enum RenderResult {
+    /// The frame fit in the bump allocated buffers it was recorded with.
+    ///
+    /// `bump` holds the allocations read back from the GPU, or `None` if the stages were run
+    /// on the CPU, which don't read them back.
+    Rendered { bump: Option<BumpAllocators> },
+    /// The frame was rendered without waiting for its allocations to be read back.
+    ///
+    /// They are checked when the next frame is rendered, and are then reported by
+    /// [`Renderer::last_frame_stats`]. If they didn't fit, this frame is incomplete, and the
+    /// next one is rendered with larger buffers and waits for its own allocations.
+    Unchecked,
+    /// The coarse phase ran out of space and was recorded again with larger buffers.
+    ///
+    /// `new_sizes` are the sizes the renderer starts later frames with. If `complete` is
+    /// `false`, the frame still didn't fit after the last attempt and its output is
+    /// incomplete, but the next frame starts with buffers large enough for what it needed.
+    Retried {
+        new_sizes: BumpAllocators,
+        bump: BumpAllocators,
+        complete: bool,
+    },
+}
+
+impl RenderResult {
+    /// Returns the allocations of the last attempt, as read back from the GPU, or `None` if
+    /// they weren't read back while the frame was rendered.
+    pub fn bump(&self) -> Option<BumpAllocators> {
+        match self {
+            Self::Rendered { bump } => *bump,
+            Self::Unchecked => None,
+            Self::Retried { bump, .. } => Some(*bump),
+        }
+    }
+
+    /// Returns `false` if the frame didn't fit in the buffers even after retrying.
+    ///
+    /// [`RenderResult::Unchecked`] frames are assumed to be complete.
+    pub fn is_complete(&self) -> bool {
+        match self {
+            Self::Rendered { .. } | Self::Unchecked => true,
+            Self::Retried { complete, .. } => *complete,
+        }
+    }
 }
 
 /// Parameters used in a single render that are configurable by the client.
@@ -103,66 +520,814 @@ pub struct RenderParams {
 
 #[cfg(feature = "wgpu")]
 pub struct RendererOptions {
-    /// The format of the texture used for surfaces with this renderer/device
-    /// If None, the renderer cannot be used with surfaces
+    /// The format of the texture used for surfaces with this renderer/device, whose blit
+    /// pipeline is created up front. Pipelines for other formats are created when a surface
+    /// of that format is first rendered to.
     pub surface_format: Option<TextureFormat>,
     /// The timestamp period from [`wgpu::Queue::get_timestamp_period`]
     /// Used when the wgpu-profiler feature is enabled
     pub timestamp_period: f32,
     pub use_cpu: bool,
+    /// How many threads to use for initialisation of shaders.
+    ///
+    /// Use `Some(1)` to create all pipelines on the calling thread. Set to `None` to use a
+    /// heuristic which will use many but not all threads.
+    ///
+    /// Has no effect on WebAssembly.
+    pub num_init_threads: Option<NonZeroUsize>,
+    /// Maximum number of bytes of GPU memory the resources of a render may use.
+    ///
+    /// Renders that would need more fail with a [`MemoryBudgetError`] before allocating
+    /// anything. Set to `None` for no limit.
+    pub memory_budget_bytes: Option<u64>,
+    /// Whether to list the tiles that have any commands after the coarse phase, so that fine
+    /// rasterization only runs for those.
+    ///
+    /// This saves work for scenes which leave much of the target empty. Tiles without commands
+    /// are filled with the base color directly. Only supported with area antialiasing, and
+    /// ignored for targets with more tiles than fit in a single indirect dispatch.
+    pub compact_tiles: bool,
+    /// The antialiasing method used by fine rasterization. Devices which don't support its
+    /// shader variant use area antialiasing, see [`Renderer::antialiasing_method`].
+    pub antialiasing_method: AaConfig,
+    /// Whether to blend colors in linear light rather than in sRGB.
+    ///
+    /// Solid colors and gradient stops are converted to linear light when the scene is
+    /// packed, images when they are sampled, and the output is encoded back to sRGB. This
+    /// gives physically accurate blending and antialiasing, at the cost of some precision in
+    /// dark colors, as layers are stored with 8 bits per channel.
+    pub linear_blending: bool,
+    /// Whether to dither the colors written to 8 bit outputs.
+    ///
+    /// This hides the banding of large smooth gradients, at the cost of some noise.
+    pub dithering: bool,
+    /// The format of the textures rendered to with [`Renderer::render_to_texture`].
+    ///
+    /// Supported formats are `Rgba8Unorm`, `Rgba8UnormSrgb`, `Bgra8Unorm` and `Rgba16Float`.
+    /// The output always holds sRGB encoded colors. As sRGB formats can't be used for storage,
+    /// `Rgba8UnormSrgb` textures must be passed as an `Rgba8Unorm` view, listed in the view
+    /// formats of the texture. `Bgra8Unorm` requires the
+    /// [`BGRA8UNORM_STORAGE`](wgpu::Features::BGRA8UNORM_STORAGE) feature.
+    pub output_format: TextureFormat,
+    /// Whether to split the work of a render into several queue submissions.
+    ///
+    /// This keeps very heavy frames from tripping the GPU watchdog of the operating system,
+    /// which can reset the device if a single submission runs for too long.
+    pub split_submissions: bool,
+    /// Whether to scan the path tags in a single pass, with a decoupled look-back across
+    /// workgroups, rather than a reduction followed by a scan.
+    ///
+    /// This saves a full pass over the path tags, but requires that running workgroups make
+    /// forward progress while others wait on them, which not every adapter guarantees. Use
+    /// [`util::supports_single_pass_scan`] to enable it only where that is known to hold.
+    pub single_pass_scan: bool,
+    /// Where the WGSL sources of the shaders are loaded from, such as patched versions
+    /// shipped by the application.
+    ///
+    /// Set to `None` to use the sources embedded in the library, or with the `hot_reload`
+    /// feature, those in [`shader_dir`].
+    pub shader_source: Option<Arc<dyn ShaderSourceProvider>>,
 }
 
-#[cfg(feature = "wgpu")]
-impl Renderer {
-    /// Creates a new renderer for the specified device.
-    pub fn new(device: &Device, render_options: &RendererOptions) -> Result<Self> {
-        let mut engine = WgpuEngine::new();
-        let mut shaders = shaders::full_shaders(device, &mut engine)?;
-        if render_options.use_cpu {
-            shaders.install_cpu_shaders(&mut engine);
+#[cfg(feature = "wgpu")]
+impl Renderer {
+    /// Creates a new renderer for the specified device.
+    ///
+    /// The shader variants are chosen to fit the [`Capabilities`] of the device. Options
+    /// whose variants don't fit, such as an antialiasing method which needs more bindings than
+    /// the device has, fall back to what it supports rather than failing to create a pipeline.
+    ///
+    /// On devices which can't run the pipeline, such as those of wgpu's WebGL2 backend, every
+    /// stage runs on the CPU, and the GPU is only used to present the result. This fallback
+    /// uses area antialiasing regardless of `antialiasing_method`, ignores `compact_tiles` and
+    /// `single_pass_scan`, and requires the `Rgba8Unorm` output format. See
+    /// [`Renderer::is_cpu_fallback`] for the methods it supports.
+    pub fn new(device: &Device, render_options: &RendererOptions) -> Result<Self> {
+        Self::create(device, render_options, true)
+    }
+
+    /// Creates a new renderer for the specified device, whose compute pipelines are created on
+    /// background threads rather than before returning.
+    ///
+    /// This lets applications show a loading state instead of blocking startup on pipeline
+    /// creation, which takes hundreds of milliseconds on some drivers. Renders fail until
+    /// [`Self::poll_shaders`] reports that all pipelines have been created. The threads are
+    /// chosen by [`RendererOptions::num_init_threads`], except that `Some(1)` still uses one
+    /// background thread.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_deferred(device: &Arc<Device>, render_options: &RendererOptions) -> Result<Self> {
+        let mut renderer = Self::create(device, render_options, false)?;
+        let build = renderer
+            .engine
+            .build_shaders_in_background(device, render_options.num_init_threads);
+        renderer.shader_build = Some(build);
+        Ok(renderer)
+    }
+
+    /// Installs the compute pipelines created so far by a renderer from
+    /// [`Self::new_deferred`], and returns whether it has all of them and can render.
+    ///
+    /// Always returns true for renderers created with [`Self::new`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll_shaders(&mut self) -> bool {
+        let Some(build) = self.shader_build.as_mut() else {
+            return true;
+        };
+        if !self.engine.poll_shader_build(build) {
+            return false;
+        }
+        self.shader_build = None;
+        true
+    }
+
+    /// Creates a renderer, and its compute pipelines if `build_shaders` is set. Otherwise they
+    /// are left for the caller to build.
+    fn create(
+        device: &Device,
+        render_options: &RendererOptions,
+        build_shaders: bool,
+    ) -> Result<Self> {
+        let capabilities = Capabilities::from_device(device);
+        let cpu_fallback = !capabilities.supports_gpu_pipeline();
+        let output_format =
+            ImageFormat::from_wgpu(render_options.output_format).ok_or_else(|| {
+                format!(
+                    "unsupported output format {:?}",
+                    render_options.output_format
+                )
+            })?;
+        if !capabilities.supports_output_format(render_options.output_format) {
+            return Err("Bgra8Unorm output requires the BGRA8UNORM_STORAGE feature".into());
+        }
+        if cpu_fallback && render_options.output_format != TextureFormat::Rgba8Unorm {
+            return Err("rendering without compute shaders requires Rgba8Unorm output".into());
+        }
+        let shader_source = render_options
+            .shader_source
+            .clone()
+            .unwrap_or_else(shaders::default_shader_source);
+        let mut engine = if cpu_fallback {
+            WgpuEngine::new_cpu_only()
+        } else {
+            WgpuEngine::new(
+                !build_shaders || render_options.num_init_threads != NonZeroUsize::new(1),
+            )
+        };
+        let options = FullShadersOptions {
+            compact_tiles: render_options.compact_tiles && !cpu_fallback,
+            aa_config: if cpu_fallback {
+                AaConfig::Area
+            } else {
+                render_options.antialiasing_method
+            },
+            output_format,
+            workgroup_sizes: WorkgroupSizes::default(),
+            single_pass_scan: render_options.single_pass_scan && !cpu_fallback,
+            debug_overlay: false,
+        };
+        let mut shaders =
+            shaders::full_shaders(device, &mut engine, &options, &shader_source, &capabilities)?;
+        if build_shaders {
+            engine.build_shaders_if_needed(device, render_options.num_init_threads);
+        }
+        engine.set_memory_budget(render_options.memory_budget_bytes);
+        engine.set_split_submissions(render_options.split_submissions);
+        if render_options.use_cpu || cpu_fallback {
+            shaders.install_cpu_shaders(&mut engine);
+        }
+        if cpu_fallback {
+            shaders.install_cpu_raster_shaders(&mut engine);
+        }
+        let blits = render_options
+            .surface_format
+            .map(|surface_format| BlitPipeline::new(device, surface_format))
+            .into_iter()
+            .collect();
+        Ok(Self {
+            engine,
+            shaders,
+            blits,
+            target: None,
+            target_shaders: vec![],
+            bump_sizes: BumpAllocators::default(),
+            last_frame_stats: None,
+            pending_bump: None,
+            wait_for_bump: true,
+            resolver: Resolver::new(),
+            external_images: vec![],
+            fonts: glyph::FontRegistry::new(),
+            linear_blending: render_options.linear_blending,
+            dithering: render_options.dithering,
+            // Use 3 pending frames
+            #[cfg(feature = "wgpu-profiler")]
+            profiler: GpuProfiler::new(3, render_options.timestamp_period, device.features()),
+            #[cfg(feature = "wgpu-profiler")]
+            profile_result: None,
+            use_cpu: render_options.use_cpu || cpu_fallback,
+            cpu_fallback,
+            shader_source,
+            capabilities,
+            #[cfg(not(target_arch = "wasm32"))]
+            shader_build: None,
+        })
+    }
+
+    /// Checks that every shader variant compiles with the shader sources of this renderer,
+    /// such as after editing them for hot reloading. See [`validate_shaders`].
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn validate_shaders(&self, device: &Device) -> Vec<(String, Error)> {
+        shaders::validate_shaders(device, self.shader_source.clone())
+    }
+
+    /// Returns the capabilities of the device, which the shader variants were chosen to fit.
+    pub fn capabilities(&self) -> &Capabilities {
+        &self.capabilities
+    }
+
+    /// Returns the antialiasing method fine rasterization uses.
+    ///
+    /// This is [`RendererOptions::antialiasing_method`] unless the device doesn't support its
+    /// shader variant, or every stage runs on the CPU, in which case area antialiasing is
+    /// used instead.
+    pub fn antialiasing_method(&self) -> AaConfig {
+        self.shaders.aa_config
+    }
+
+    /// Returns whether the device can't run the pipeline, such as one without compute shaders,
+    /// so that every stage runs on the CPU.
+    ///
+    /// Only [`Self::render_to_wgpu_texture`], [`Self::render_to_surface`] and
+    /// [`Self::render_to_surface_async`] are supported then, as the result is copied into a
+    /// texture rather than written to a view. Target textures need the
+    /// [wgpu::TextureUsages::COPY_DST] usage instead of `STORAGE_BINDING`. External images,
+    /// cached layers and the debug overlay aren't supported.
+    pub fn is_cpu_fallback(&self) -> bool {
+        self.cpu_fallback
+    }
+
+    /// Replaces the shaders of this renderer with ones created in `engine`.
+    fn replace_shaders(&mut self, engine: WgpuEngine, shaders: FullShaders) {
+        // Only swap out the pipelines so that pooled buffers survive.
+        self.engine.replace_shaders(engine);
+        self.shaders = shaders;
+        self.target_shaders.clear();
+        // Pipelines still being created belong to the replaced shaders.
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.shader_build = None;
+        }
+    }
+
+    /// Creates a render with the options of this renderer.
+    fn new_render(&self, bump_sizes: BumpAllocators) -> Render {
+        let mut render = Render::with_bump_sizes(bump_sizes);
+        render.set_linear_blending(self.linear_blending);
+        render.set_dithering(self.dithering);
+        render.set_retain_scene(true);
+        render
+    }
+
+    /// Registers a font, so that its metrics and outlines can be looked up by the returned
+    /// id with [`Self::font`]. Fonts are also drawn without being registered.
+    pub fn register_font(&mut self, font: peniko::Font) -> Result<glyph::FontId> {
+        self.fonts.register(font)
+    }
+
+    /// Registers each font in `data`, which holds a font file or a font collection.
+    pub fn register_font_data(&mut self, data: peniko::Blob<u8>) -> Result<Vec<glyph::FontId>> {
+        self.fonts.register_data(data)
+    }
+
+    /// Returns the font registered with `id`.
+    pub fn font(&self, id: glyph::FontId) -> Option<glyph::FontRef<'_>> {
+        self.fonts.get(id)
+    }
+
+    /// Returns the fonts registered with this renderer.
+    pub fn fonts(&self) -> &glyph::FontRegistry {
+        &self.fonts
+    }
+
+    /// Registers a texture to be drawn as an image, such as a decoded video frame or a camera
+    /// feed, without reading it back to the CPU.
+    ///
+    /// The returned handle is drawn with [`SceneBuilder::draw_external_image`] or
+    /// [`SceneBuilder::fill_external_image`] in scenes rendered by this renderer, which bind
+    /// the texture to fine rasterization and sample it directly, without copying it. A scene
+    /// draws at most [`MAX_EXTERNAL_IMAGES`] different external images.
+    ///
+    /// The texture must have been created with the [wgpu::TextureUsages::TEXTURE_BINDING]
+    /// usage and a filterable float format, and hold colors with separate alpha. The renderer
+    /// keeps a view of the texture until the image is retired with
+    /// [`Self::retire_external_image`].
+    pub fn register_external_image(&mut self, texture: &wgpu::Texture) -> Result<ExternalImage> {
+        if self.cpu_fallback {
+            return Err("external images require compute shaders".into());
+        }
+        check_external_texture(texture)?;
+        let image = ExternalImage {
+            id: Id::next().0.get(),
+            width: texture.width(),
+            height: texture.height(),
+        };
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.external_images.push((image, view));
+        Ok(image)
+    }
+
+    /// Replaces the texture drawn for an external image, such as with the next frame of a
+    /// video, from the next render on. The texture must be the size of the image.
+    pub fn update_external_image(
+        &mut self,
+        image: &ExternalImage,
+        texture: &wgpu::Texture,
+    ) -> Result<()> {
+        check_external_texture(texture)?;
+        if (texture.width(), texture.height()) != (image.width, image.height) {
+            return Err(ExternalImageError::Size {
+                width: image.width,
+                height: image.height,
+                texture_width: texture.width(),
+                texture_height: texture.height(),
+            }
+            .into());
+        }
+        let entry = self
+            .external_images
+            .iter_mut()
+            .find(|(registered, _)| registered == image)
+            .ok_or(ExternalImageError::Retired)?;
+        entry.1 = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(())
+    }
+
+    /// Releases the view of the texture of an external image, after which the texture can be
+    /// destroyed. Scenes which still draw the image draw it as transparent.
+    ///
+    /// Returns false if the image was already retired or created by another renderer.
+    pub fn retire_external_image(&mut self, image: &ExternalImage) -> bool {
+        let len = self.external_images.len();
+        self.external_images
+            .retain(|(registered, _)| registered != image);
+        self.external_images.len() != len
+    }
+
+    /// Renders the part of a fragment within `bounds` into a texture, which is drawn as an
+    /// image with [`CachedLayer::draw`] instead of drawing the fragment itself.
+    ///
+    /// This suits static content which is costly to render, at the cost of the memory of the
+    /// texture. The bounds are rounded out to whole units of the fragment, which are rendered
+    /// at one pixel each, so the fragment should already be at its final scale. The layer is
+    /// kept until it is invalidated and rendered again with [`Self::update_cached_layer`], or
+    /// released with [`Self::release_cached_layer`].
+    ///
+    /// A cached layer is drawn as an external image, so it takes one of the
+    /// [`MAX_EXTERNAL_IMAGES`] slots of each scene that draws it. Rendering a scene which draws
+    /// more different cached layers and external images than that returns an error.
+    pub fn cache_layer(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        fragment: &SceneFragment,
+        bounds: kurbo::Rect,
+    ) -> Result<CachedLayer> {
+        if self.cpu_fallback {
+            return Err("cached layers require compute shaders".into());
+        }
+        let bounds = bounds.expand();
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("cached layer"),
+            size: wgpu::Extent3d {
+                width: (bounds.width() as u32).max(1),
+                height: (bounds.height() as u32).max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: self.shaders.output_format.to_wgpu(),
+            view_formats: &[],
+        });
+        let image = self.register_external_image(&texture)?;
+        let mut layer = CachedLayer {
+            image,
+            texture,
+            origin: bounds.origin(),
+            valid: false,
+        };
+        self.update_cached_layer(device, queue, &mut layer, fragment)?;
+        Ok(layer)
+    }
+
+    /// Renders a cached layer again from `fragment` if it was invalidated, returning whether
+    /// it was rendered.
+    pub fn update_cached_layer(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        layer: &mut CachedLayer,
+        fragment: &SceneFragment,
+    ) -> Result<bool> {
+        if layer.valid {
+            return Ok(false);
+        }
+        let mut scene = Scene::new();
+        let mut builder = SceneBuilder::for_scene(&mut scene);
+        builder.append(
+            fragment,
+            Some(kurbo::Affine::translate(-layer.origin.to_vec2())),
+        );
+        let params = RenderParams {
+            base_color: peniko::Color::TRANSPARENT,
+            width: layer.image.width,
+            height: layer.image.height,
+        };
+        let view = layer
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_to_texture(device, queue, &scene, &view, &params)?;
+        layer.valid = true;
+        Ok(true)
+    }
+
+    /// Releases the texture of a cached layer. Scenes which still draw it draw it as
+    /// transparent.
+    pub fn release_cached_layer(&mut self, layer: CachedLayer) {
+        self.retire_external_image(&layer.image);
+    }
+
+    /// Renders a scene to the target texture.
+    ///
+    /// The texture is assumed to be of the specified dimensions and have been created with
+    /// the `output_format` passed in the constructing [`RendererOptions`] and the
+    /// [wgpu::TextureUsages::STORAGE_BINDING] flag set.
+    pub fn render_to_texture(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: &TextureView,
+        params: &RenderParams,
+    ) -> Result<()> {
+        if self.cpu_fallback {
+            return Err("rendering to a texture view requires compute shaders".into());
+        }
+        let render = self.new_render(self.bump_sizes);
+        let (recording, target) =
+            render::render_full(render, scene, &mut self.resolver, &self.shaders, params)?;
+        let external_resources = external_resources(
+            &self.external_images,
+            Some((*target.as_image().unwrap(), texture)),
+        );
+        self.engine.run_recording(
+            device,
+            queue,
+            &recording,
+            &external_resources,
+            "render_to_texture",
+            #[cfg(feature = "wgpu-profiler")]
+            &mut self.profiler,
+        )?;
+        Ok(())
+    }
+
+    /// Renders a scene to a texture owned by the caller, such as one that is composited into a
+    /// 3D scene or the texture atlas of a UI toolkit.
+    ///
+    /// Unlike [`Self::render_to_texture`], the texture is checked first: it must have been
+    /// created with the [wgpu::TextureUsages::STORAGE_BINDING] usage and the `output_format` of
+    /// the renderer, and be at least as large as the render. Otherwise a [`TargetTextureError`]
+    /// is returned and nothing is rendered.
+    pub fn render_to_wgpu_texture(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: &wgpu::Texture,
+        params: &RenderParams,
+    ) -> Result<()> {
+        if self.cpu_fallback {
+            if !texture.usage().contains(wgpu::TextureUsages::COPY_DST) {
+                return Err(TargetTextureError::MissingCopyDstUsage.into());
+            }
+        } else if !texture
+            .usage()
+            .contains(wgpu::TextureUsages::STORAGE_BINDING)
+        {
+            return Err(TargetTextureError::MissingStorageUsage.into());
+        }
+        let expected = self.shaders.output_format.to_wgpu();
+        if texture.format() != expected {
+            return Err(TargetTextureError::Format {
+                expected,
+                actual: texture.format(),
+            }
+            .into());
+        }
+        if texture.width() < params.width || texture.height() < params.height {
+            return Err(TargetTextureError::Size {
+                width: params.width,
+                height: params.height,
+                texture_width: texture.width(),
+                texture_height: texture.height(),
+            }
+            .into());
+        }
+        if self.cpu_fallback {
+            return self.render_cpu_fallback(device, queue, scene, texture, params);
+        }
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        self.render_to_texture(device, queue, scene, &view, params)
+    }
+
+    /// Creates an offscreen target with its own size, format and configuration.
+    ///
+    /// Targets whose format or antialiasing method differ from those of the renderer get a
+    /// variant of fine rasterization of their own, which is built here. Fails if the device
+    /// can't write to the format or doesn't support the antialiasing method, and when every
+    /// stage runs on the CPU.
+    pub fn create_render_target(
+        &mut self,
+        device: &Device,
+        options: &RenderTargetOptions,
+    ) -> Result<RenderTarget> {
+        if self.cpu_fallback {
+            return Err("render targets require compute shaders".into());
+        }
+        let shaders = self.target_shaders(device, options)?;
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("render target"),
+            size: wgpu::Extent3d {
+                width: options.width.max(1),
+                height: options.height.max(1),
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+            format: shaders.output_format.to_wgpu(),
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        Ok(RenderTarget {
+            texture,
+            view,
+            options: *options,
+        })
+    }
+
+    /// Renders a scene to a target created with [`Self::create_render_target`], with the
+    /// format and configuration of the target, over `base_color`.
+    ///
+    /// The intermediate buffers are sized for the scene and the size of the target on each
+    /// render, so several targets can be rendered in a frame without affecting each other.
+    pub fn render_to_target(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        target: &RenderTarget,
+        base_color: peniko::Color,
+    ) -> Result<()> {
+        let options = &target.options;
+        let shaders = self.target_shaders(device, options)?;
+        let mut render = Render::with_bump_sizes(self.bump_sizes);
+        render.set_linear_blending(options.linear_blending);
+        render.set_dithering(options.dithering);
+        render.set_retain_scene(true);
+        let params = RenderParams {
+            base_color,
+            width: options.width,
+            height: options.height,
+        };
+        let (recording, out_image) =
+            render::render_full(render, scene, &mut self.resolver, &shaders, &params)?;
+        let external_resources = external_resources(
+            &self.external_images,
+            Some((*out_image.as_image().unwrap(), &target.view)),
+        );
+        self.engine.run_recording(
+            device,
+            queue,
+            &recording,
+            &external_resources,
+            "render_to_target",
+            #[cfg(feature = "wgpu-profiler")]
+            &mut self.profiler,
+        )?;
+        Ok(())
+    }
+
+    /// Returns the shaders for rendering to a target with `options`, building the fine
+    /// rasterization variant for its format and antialiasing method if the renderer's differ.
+    ///
+    /// The variants of targets are built without tile compaction or the debug overlay.
+    fn target_shaders(
+        &mut self,
+        device: &Device,
+        options: &RenderTargetOptions,
+    ) -> Result<FullShaders> {
+        let format = ImageFormat::from_wgpu(options.format)
+            .filter(|_| self.capabilities.supports_output_format(options.format))
+            .ok_or_else(|| format!("unsupported render target format {:?}", options.format))?;
+        let aa_config = options.antialiasing_method;
+        let matches = |shaders: &FullShaders| {
+            shaders.output_format == format && shaders.aa_config == aa_config
+        };
+        if matches(&self.shaders) {
+            return Ok(self.shaders.clone());
+        }
+        if let Some(shaders) = self.target_shaders.iter().find(|shaders| matches(shaders)) {
+            return Ok(shaders.clone());
+        }
+        let variant = shaders::variants::fine(aa_config);
+        if !self.capabilities.supports_variant(variant) {
+            return Err(format!("{aa_config:?} antialiasing isn't supported by the device").into());
+        }
+        let mut registry = shaders::ShaderRegistry::new(self.shader_source.clone());
+        registry.set_workgroup_sizes(self.shaders.workgroup_sizes);
+        registry.set_output_format(format);
+        let fine = registry.get(device, &mut self.engine, variant)?;
+        self.engine.build_shaders_if_needed(device, None);
+        let shaders = FullShaders {
+            fine,
+            aa_config,
+            output_format: format,
+            compact: None,
+            debug_fine: None,
+            ..self.shaders.clone()
+        };
+        self.target_shaders.push(shaders.clone());
+        Ok(shaders)
+    }
+
+    /// Renders a scene on the CPU and copies the result into `texture`, for devices without
+    /// compute shaders.
+    fn render_cpu_fallback(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: &wgpu::Texture,
+        params: &RenderParams,
+    ) -> Result<()> {
+        let render = self.new_render(self.bump_sizes);
+        let (recording, target) =
+            render::render_full(render, scene, &mut self.resolver, &self.shaders, params)?;
+        let target = *target.as_image().unwrap();
+        self.engine.run_recording(
+            device,
+            queue,
+            &recording,
+            &[],
+            "render_cpu_fallback",
+            #[cfg(feature = "wgpu-profiler")]
+            &mut self.profiler,
+        )?;
+        let image = self
+            .engine
+            .take_cpu_image(target)
+            .ok_or("render target wasn't written")?;
+        queue.write_texture(
+            texture.as_image_copy(),
+            bytemuck::cast_slice(&image.pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(image.width as u32 * 4),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: image.width as u32,
+                height: image.height as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(())
+    }
+
+    /// Renders a scene to the target texture in horizontal bands of at most `band_height` rows.
+    ///
+    /// Each band runs the whole pipeline on its own, so the intermediate buffers only have to
+    /// hold the part of the scene that overlaps the band, and are reused from one band to the
+    /// next. This bounds the memory needed to render very large or complex scenes, at the cost
+    /// of processing the whole scene once per band.
+    ///
+    /// The texture has the same requirements as in [`Self::render_to_texture`].
+    pub fn render_to_texture_banded(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: &TextureView,
+        params: &RenderParams,
+        band_height: u32,
+    ) -> Result<()> {
+        let band_height = band_height.max(1);
+        for y in (0..params.height).step_by(band_height as usize) {
+            let band_params = RenderParams {
+                base_color: params.base_color,
+                width: params.width,
+                height: band_height.min(params.height - y),
+            };
+            self.render_part_to_texture(
+                device,
+                queue,
+                scene,
+                texture,
+                &band_params,
+                (0, y),
+                "render_to_texture_banded",
+            )?;
         }
-        let blit = render_options
-            .surface_format
-            .map(|surface_format| BlitPipeline::new(device, surface_format));
-        Ok(Self {
-            engine,
-            shaders,
-            blit,
-            target: None,
-            // Use 3 pending frames
-            #[cfg(feature = "wgpu-profiler")]
-            profiler: GpuProfiler::new(3, render_options.timestamp_period, device.features()),
-            #[cfg(feature = "wgpu-profiler")]
-            profile_result: None,
-            #[cfg(feature = "hot_reload")]
-            use_cpu: render_options.use_cpu,
-        })
+        Ok(())
     }
 
-    /// Renders a scene to the target texture.
+    /// Renders the part of a scene within `region` to the target texture, leaving the rest of
+    /// the texture untouched.
     ///
-    /// The texture is assumed to be of the specified dimensions and have been created with
-    /// the [wgpu::TextureFormat::Rgba8Unorm] format and the [wgpu::TextureUsages::STORAGE_BINDING]
-    /// flag set.
-    pub fn render_to_texture(
+    /// The region is in pixels and rounded out to whole pixels within the target of `params`.
+    /// Only the tiles of the region are binned, allocated and rasterized, which makes this
+    /// suitable for redrawing the damaged parts of a window. The texture has the same
+    /// requirements as in [`Self::render_to_texture`].
+    pub fn render_region_to_texture(
         &mut self,
         device: &Device,
         queue: &Queue,
         scene: &Scene,
         texture: &TextureView,
         params: &RenderParams,
+        region: kurbo::Rect,
     ) -> Result<()> {
-        let (recording, target) = render::render_full(scene, &self.shaders, params);
-        let external_resources = [ExternalResource::Image(
-            *target.as_image().unwrap(),
+        let clamp = |v: f64, max: u32| v.max(0.0).min(max as f64) as u32;
+        let x0 = clamp(region.x0.floor(), params.width);
+        let y0 = clamp(region.y0.floor(), params.height);
+        let x1 = clamp(region.x1.ceil(), params.width);
+        let y1 = clamp(region.y1.ceil(), params.height);
+        if x0 >= x1 || y0 >= y1 {
+            return Ok(());
+        }
+        let region_params = RenderParams {
+            base_color: params.base_color,
+            width: x1 - x0,
+            height: y1 - y0,
+        };
+        self.render_part_to_texture(
+            device,
+            queue,
+            scene,
             texture,
-        )];
+            &region_params,
+            (x0, y0),
+            "render_region_to_texture",
+        )
+    }
+
+    /// Renders the part of a scene in a target of the size of `params`, whose top left corner
+    /// is at `origin` in the scene and in the texture.
+    #[allow(clippy::too_many_arguments)]
+    fn render_part_to_texture(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: &TextureView,
+        params: &RenderParams,
+        origin: (u32, u32),
+        label: &'static str,
+    ) -> Result<()> {
+        if self.cpu_fallback {
+            return Err("rendering to a texture view requires compute shaders".into());
+        }
+        // Move the part to the top left of the target.
+        let transform = Transform {
+            matrix: [1.0, 0.0, 0.0, 1.0],
+            translation: [-(origin.0 as f32), -(origin.1 as f32)],
+        };
+        let mut encoding = Encoding::new();
+        encoding.append(scene.data(), &Some(transform));
+        let mut render = self.new_render(self.bump_sizes);
+        render.set_target_x_offset(origin.0);
+        render.set_target_y_offset(origin.1);
+        let mut recording = render.render_encoding_coarse(
+            &encoding,
+            &mut self.resolver,
+            &self.shaders,
+            params,
+            false,
+        )?;
+        let target = render.out_image();
+        render.record_fine(&self.shaders, &mut recording);
+        let external_resources = external_resources(&self.external_images, Some((target, texture)));
         self.engine.run_recording(
             device,
             queue,
             &recording,
             &external_resources,
-            "render_to_texture",
+            label,
             #[cfg(feature = "wgpu-profiler")]
             &mut self.profiler,
         )?;
@@ -172,11 +1337,15 @@ impl Renderer {
     /// Renders a scene to the target surface.
     ///
     /// This renders to an intermediate texture and then runs a render pass to blit to the
-    /// specified surface texture.
+    /// specified surface texture. Surfaces configured with the
+    /// [wgpu::TextureUsages::STORAGE_BINDING] usage and the `output_format` of the renderer
+    /// are rendered to directly instead, without the intermediate texture.
     ///
-    /// The surface is assumed to be of the specified dimensions and have been configured with
-    /// the same format passed in the constructing [`RendererOptions`]' `surface_format`.
-    /// Panics if `surface_format` was `None`
+    /// The surface is assumed to be of the specified dimensions. Surfaces of different formats
+    /// can be rendered to with the same renderer, which keeps a blit pipeline for each format.
+    /// Only the intermediate texture of the most recent size is kept, so that resizing a window
+    /// doesn't keep stale textures alive; several panels of different sizes are better rendered
+    /// to [`RenderTarget`]s.
     pub fn render_to_surface(
         &mut self,
         device: &Device,
@@ -185,22 +1354,71 @@ impl Renderer {
         surface: &SurfaceTexture,
         params: &RenderParams,
     ) -> Result<()> {
-        let width = params.width;
-        let height = params.height;
-        let mut target = self
+        if self.cpu_fallback {
+            let target = self.take_target(device, params.width, params.height);
+            self.render_cpu_fallback(device, queue, scene, &target.texture, params)?;
+            let encoder = self.encode_blit(device, &target, surface);
+            self.keep_target(target);
+            self.submit_surface_frame(queue, encoder)?;
+            return Ok(());
+        }
+        if self.is_direct_target(surface) {
+            let view = surface
+                .texture
+                .create_view(&wgpu::TextureViewDescriptor::default());
+            self.render_to_texture(device, queue, scene, &view, params)?;
+            let encoder = device.create_command_encoder(&Default::default());
+            self.submit_surface_frame(queue, encoder)?;
+            return Ok(());
+        }
+        let target = self.take_target(device, params.width, params.height);
+        self.render_to_texture(device, queue, scene, &target.view, params)?;
+        let encoder = self.encode_blit(device, &target, surface);
+        self.keep_target(target);
+        self.submit_surface_frame(queue, encoder)?;
+        Ok(())
+    }
+
+    /// Takes the intermediate texture if it has the given size, or creates one, dropping a
+    /// texture of a different size.
+    fn take_target(&mut self, device: &Device, width: u32, height: u32) -> TargetTexture {
+        let kept = self
             .target
             .take()
-            .unwrap_or_else(|| TargetTexture::new(device, width, height));
-        // TODO: implement clever resizing semantics here to avoid thrashing the memory allocator
-        // during resize, specifically on metal.
-        if target.width != width || target.height != height {
-            target = TargetTexture::new(device, width, height);
+            .filter(|target| target.width == width && target.height == height);
+        match kept {
+            Some(target) => target,
+            None => TargetTexture::new(
+                device,
+                width,
+                height,
+                self.shaders.output_format,
+                self.cpu_fallback,
+            ),
         }
-        self.render_to_texture(device, queue, scene, &target.view, params)?;
-        let blit = self
-            .blit
-            .as_ref()
-            .expect("renderer should have configured surface_format to use on a surface");
+    }
+
+    /// Keeps the intermediate texture for later frames.
+    fn keep_target(&mut self, target: TargetTexture) {
+        self.target = Some(target);
+    }
+
+    /// Encodes a render pass which copies an intermediate texture to a surface, with a
+    /// pipeline for the format of the surface.
+    fn encode_blit(
+        &mut self,
+        device: &Device,
+        target: &TargetTexture,
+        surface: &SurfaceTexture,
+    ) -> wgpu::CommandEncoder {
+        let format = surface.texture.format();
+        let blit = match self.blits.iter().position(|blit| blit.format == format) {
+            Some(ix) => &self.blits[ix],
+            None => {
+                self.blits.push(BlitPipeline::new(device, format));
+                self.blits.last().unwrap()
+            }
+        };
         let mut encoder =
             device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
         {
@@ -231,17 +1449,344 @@ impl Renderer {
             render_pass.set_bind_group(0, &bind_group, &[]);
             render_pass.draw(0..6, 0..1);
         }
+        encoder
+    }
+
+    /// Returns true if the surface can be used as the target of the pipeline, rather than
+    /// blitting an intermediate texture to it.
+    fn is_direct_target(&self, surface: &SurfaceTexture) -> bool {
+        surface
+            .texture
+            .usage()
+            .contains(wgpu::TextureUsages::STORAGE_BINDING)
+            && surface.texture.format() == self.shaders.output_format.to_wgpu()
+    }
+
+    /// Submits the commands that finish a frame rendered to a surface, and ends the frame of
+    /// the profiler.
+    ///
+    /// The commands are submitted even if the profiler fails to end its frame, which happens
+    /// when a profiling scope was left open.
+    #[cfg_attr(not(feature = "wgpu-profiler"), allow(unused_mut))]
+    fn submit_surface_frame(
+        &mut self,
+        queue: &Queue,
+        mut encoder: wgpu::CommandEncoder,
+    ) -> Result<()> {
+        #[cfg(feature = "wgpu-profiler")]
+        self.profiler.resolve_queries(&mut encoder);
         queue.submit(Some(encoder.finish()));
-        self.target = Some(target);
+        #[cfg(feature = "wgpu-profiler")]
+        {
+            self.profiler
+                .end_frame()
+                .map_err(|_| "the profiler frame ended with a scope still open")?;
+            if let Some(result) = self.profiler.process_finished_frame() {
+                self.profile_result = Some(result);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the per-stage GPU timings of the most recently profiled frame.
+    ///
+    /// Timings become available a few frames after they were recorded, once the timestamp
+    /// queries have been read back.
+    #[cfg(feature = "wgpu-profiler")]
+    pub fn engine_stats(&self) -> Option<EngineStats> {
+        self.profile_result
+            .as_deref()
+            .map(EngineStats::from_profile)
+    }
+
+    /// Returns the memory usage of the most recent frame rendered with
+    /// [`Self::render_to_texture_async`] or [`Self::render_to_surface_async`] whose
+    /// allocations were read back. Those of a [`RenderResult::Unchecked`] frame are read back
+    /// when the next frame is rendered.
+    ///
+    /// This is `None` if no such frame has been rendered yet, or if the stages were run on the
+    /// CPU. The synchronous render methods don't read back the allocations and leave the stats
+    /// unchanged.
+    pub fn last_frame_stats(&self) -> Option<FrameStats> {
+        self.last_frame_stats
+    }
+
+    /// Returns the statistics of the glyph outlines cached across frames, where the hit and
+    /// miss counts are those of the most recently rendered scene.
+    pub fn glyph_cache_stats(&self) -> GlyphCacheStats {
+        self.resolver.glyph_cache_stats()
+    }
+
+    /// Sets the size budget of the glyph outlines cached across frames, in bytes.
+    ///
+    /// The least recently used glyphs are evicted before rendering a frame once the cache
+    /// grows beyond this. The default is 16 MiB.
+    pub fn set_glyph_cache_budget(&mut self, bytes: usize) {
+        self.resolver.set_glyph_cache_budget(bytes);
+    }
+
+    /// Returns the workgroup sizes of the tunable stages.
+    pub fn workgroup_sizes(&self) -> WorkgroupSizes {
+        self.shaders.workgroup_sizes
+    }
+
+    /// Rebuilds the shaders of the tunable stages with the given workgroup sizes, such as
+    /// the result of an earlier [`Renderer::calibrate_workgroup_sizes`] on the same adapter.
+    ///
+    /// The sizes can't be changed when rendering with the CPU shaders, which are written
+    /// for the default sizes.
+    pub fn set_workgroup_sizes(&mut self, device: &Device, sizes: WorkgroupSizes) -> Result<()> {
+        if sizes == self.shaders.workgroup_sizes {
+            return Ok(());
+        }
+        sizes.validate()?;
+        if self.use_cpu {
+            return Err("workgroup sizes can't be changed when using the CPU shaders".into());
+        }
+        let mut engine = WgpuEngine::new(false);
+        let options = FullShadersOptions {
+            workgroup_sizes: sizes,
+            ..self.shaders.options()
+        };
+        let shaders = shaders::full_shaders(
+            device,
+            &mut engine,
+            &options,
+            &self.shader_source,
+            &self.capabilities,
+        )?;
+        self.replace_shaders(engine, shaders);
+        Ok(())
+    }
+
+    /// Returns whether the tile and bin debug overlay is drawn.
+    pub fn debug_overlay(&self) -> bool {
+        self.shaders.debug_fine.is_some()
+    }
+
+    /// Enables or disables the debug overlay, which shows how the coarse stages divided up
+    /// the target.
+    ///
+    /// Tiles are tinted from blue to red by the number of commands coarse rasterization
+    /// wrote for them, and the boundaries of tiles and bins are drawn over the output, with
+    /// bins brighter the more draw objects binning assigned to them. The overlay is only
+    /// available with area antialiasing, and disables tile compaction while it is drawn.
+    pub fn set_debug_overlay(&mut self, device: &Device, enabled: bool) -> Result<()> {
+        if enabled == self.debug_overlay() {
+            return Ok(());
+        }
+        if enabled && self.shaders.aa_config != AaConfig::Area {
+            return Err("the debug overlay requires area antialiasing".into());
+        }
+        if enabled && self.cpu_fallback {
+            return Err("the debug overlay requires compute shaders".into());
+        }
+        if enabled
+            && !self
+                .capabilities
+                .supports_variant(&shaders::variants::FINE_AREA_DEBUG_OVERLAY)
+        {
+            return Err("the debug overlay needs more bindings than the device supports".into());
+        }
+        let mut engine = WgpuEngine::new(false);
+        let options = FullShadersOptions {
+            debug_overlay: enabled,
+            ..self.shaders.options()
+        };
+        let mut shaders = shaders::full_shaders(
+            device,
+            &mut engine,
+            &options,
+            &self.shader_source,
+            &self.capabilities,
+        )?;
+        if self.use_cpu {
+            shaders.install_cpu_shaders(&mut engine);
+        }
+        self.replace_shaders(engine, shaders);
         Ok(())
     }
 
+    /// Benchmarks the supported workgroup sizes of the tunable stages on the adapter of
+    /// `device`, and keeps the fastest.
+    ///
+    /// This renders a synthetic scene a few times with each combination of sizes, which
+    /// takes a noticeable amount of time, so applications should cache the result for the
+    /// adapter and restore it with [`Renderer::set_workgroup_sizes`]. The sizes are left
+    /// unchanged when rendering with the CPU shaders.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn calibrate_workgroup_sizes(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+    ) -> Result<WorkgroupSizes> {
+        if self.use_cpu {
+            return Ok(self.shaders.workgroup_sizes);
+        }
+        let initial = self.shaders.workgroup_sizes;
+        let result = tuning::calibrate(self, device, queue);
+        if result.is_err() {
+            self.set_workgroup_sizes(device, initial)?;
+        }
+        result
+    }
+
+    /// Renders a scene like [`Renderer::render_to_texture`], and returns the contents of the
+    /// intermediate buffers of the pipeline, from the path tag monoids to the per tile
+    /// command lists.
+    ///
+    /// This downloads every buffer, so it is only meant for diagnosing a frame that renders
+    /// incorrectly, by inspecting the output of each stage, for example after writing it out
+    /// with [`Capture::write_to_dir`], or by comparing it with the CPU implementations of
+    /// the stages with [`Capture::compare_with_cpu`]. It isn't supported with the CPU shaders.
+    pub async fn capture_frame(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: &TextureView,
+        params: &RenderParams,
+    ) -> Result<Capture> {
+        if self.shaders.pathtag_is_cpu {
+            return Err("frames can't be captured when using the CPU shaders".into());
+        }
+        let mut render = self.new_render(self.bump_sizes);
+        render.set_capture(true);
+        let mut recording = render.render_encoding_coarse(
+            scene.data(),
+            &mut self.resolver,
+            &self.shaders,
+            params,
+            false,
+        )?;
+        let target = render.out_image();
+        render.record_fine(&self.shaders, &mut recording);
+        self.engine.run_recording(
+            device,
+            queue,
+            &recording,
+            &external_resources(&self.external_images, Some((target, texture))),
+            "capture_frame",
+            #[cfg(feature = "wgpu-profiler")]
+            &mut self.profiler,
+        )?;
+        let mut buffers = render.take_captured_inputs();
+        for (name, buf) in render.take_captures() {
+            let data = self.engine.read_download(buf).await?;
+            buffers.push(CapturedBuffer { name, data });
+        }
+        Ok(Capture {
+            width_in_tiles: params.width.div_ceil(vello_encoding::TILE_WIDTH),
+            height_in_tiles: params.height.div_ceil(vello_encoding::TILE_HEIGHT),
+            buffers,
+        })
+    }
+
+    /// Renders a scene like [`Renderer::render_to_texture`], one dispatch at a time, reading
+    /// back the buffers bound to each dispatch and passing them to `hook` before running the
+    /// next one.
+    ///
+    /// An error returned by `hook` is returned along with the dispatch it was returned for,
+    /// which bisects a frame that renders incorrectly to the first stage which goes wrong,
+    /// for example by checking the buffers with the CPU implementations of the stages. The
+    /// rest of the render still runs, so that its buffers are released, but `hook` isn't
+    /// called again. Each dispatch is waited for separately, so this is much slower than
+    /// [`Renderer::capture_frame`].
+    ///
+    /// Returns the contents of each buffer at the end of the render, in the order they were
+    /// first bound. It isn't supported with the CPU shaders.
+    pub async fn step_frame(
+        &mut self,
+        device: &Device,
+        queue: &Queue,
+        scene: &Scene,
+        texture: &TextureView,
+        params: &RenderParams,
+        mut hook: impl FnMut(&Step) -> Result<()>,
+    ) -> Result<Capture> {
+        if self.shaders.pathtag_is_cpu {
+            return Err("frames can't be stepped through when using the CPU shaders".into());
+        }
+        let mut render = self.new_render(self.bump_sizes);
+        // Capturing keeps the inputs, and the names of the intermediate buffers.
+        render.set_capture(true);
+        let mut recording = render.render_encoding_coarse(
+            scene.data(),
+            &mut self.resolver,
+            &self.shaders,
+            params,
+            false,
+        )?;
+        let target = render.out_image();
+        render.record_fine(&self.shaders, &mut recording);
+        let names: HashMap<Id, &'static str> = render
+            .take_captures()
+            .into_iter()
+            .map(|(name, buf)| (buf.id, name))
+            .collect();
+        let mut capture = Capture {
+            width_in_tiles: params.width.div_ceil(vello_encoding::TILE_WIDTH),
+            height_in_tiles: params.height.div_ceil(vello_encoding::TILE_HEIGHT),
+            buffers: render.take_captured_inputs(),
+        };
+        let external = external_resources(&self.external_images, Some((target, texture)));
+        let mut failure = None;
+        for (index, step) in step::split_dispatches(recording).into_iter().enumerate() {
+            self.engine.run_recording(
+                device,
+                queue,
+                &step.recording,
+                &external,
+                "step_frame",
+                #[cfg(feature = "wgpu-profiler")]
+                &mut self.profiler,
+            )?;
+            let Some((shader, bufs)) = step.dispatch else {
+                continue;
+            };
+            let mut bound = vec![];
+            for buf in bufs {
+                let name = names.get(&buf.id).copied().unwrap_or(buf.name);
+                let data = self.engine.read_download(buf).await?;
+                capture.insert(CapturedBuffer { name, data });
+                bound.push(name);
+            }
+            if failure.is_some() {
+                continue;
+            }
+            let stage = self.engine.shader_label(shader);
+            let state = Step {
+                index,
+                stage,
+                bound: &bound,
+                capture: &capture,
+            };
+            if let Err(err) = hook(&state) {
+                failure = Some(format!("dispatch {index} ({stage}): {err}"));
+            }
+        }
+        match failure {
+            Some(failure) => Err(failure.into()),
+            None => Ok(capture),
+        }
+    }
+
     /// Reload the shaders. This should only be used during `vello` development
     #[cfg(feature = "hot_reload")]
     pub async fn reload_shaders(&mut self, device: &Device) -> Result<()> {
+        if self.cpu_fallback {
+            // All stages run on the CPU, so there are no shaders to reload.
+            return Ok(());
+        }
         device.push_error_scope(wgpu::ErrorFilter::Validation);
-        let mut engine = WgpuEngine::new();
-        let mut shaders = shaders::full_shaders(device, &mut engine)?;
+        let mut engine = WgpuEngine::new(false);
+        let mut shaders = shaders::full_shaders(
+            device,
+            &mut engine,
+            &self.shaders.options(),
+            &self.shader_source,
+            &self.capabilities,
+        )?;
         if self.use_cpu {
             shaders.install_cpu_shaders(&mut engine);
         }
@@ -249,22 +1794,22 @@ impl Renderer {
         if let Some(error) = error {
             return Err(error.into());
         }
-        self.engine = engine;
-        self.shaders = shaders;
+        self.replace_shaders(engine, shaders);
         Ok(())
     }
 
     /// Renders a scene to the target texture.
     ///
     /// The texture is assumed to be of the specified dimensions and have been created with
-    /// the [wgpu::TextureFormat::Rgba8Unorm] format and the [wgpu::TextureUsages::STORAGE_BINDING]
-    /// flag set.
-    ///
-    /// The return value is the value of the `BumpAllocators` in this rendering, which is currently used
-    /// for debug output.
+    /// the `output_format` passed in the constructing [`RendererOptions`] and the
+    /// [wgpu::TextureUsages::STORAGE_BINDING] flag set.
     ///
-    /// This return type is not stable, and will likely be changed when a more principled way to access
-    /// relevant statistics is implemented
+    /// The allocations of the coarse phase are read back without waiting for them, and
+    /// checked when the next frame is rendered. If they didn't fit, the buffers grow for later
+    /// frames, and the next frame waits for its own allocations and, if it runs out of space,
+    /// records the coarse phase again with larger buffers. The first frame also waits. The
+    /// returned [`RenderResult`] reports whether the frame was checked, whether it was
+    /// retried, and the sizes the buffers grew to.
     pub async fn render_to_texture_async(
         &mut self,
         device: &Device,
@@ -272,54 +1817,106 @@ impl Renderer {
         scene: &Scene,
         texture: &TextureView,
         params: &RenderParams,
-    ) -> Result<Option<BumpAllocators>> {
-        let mut render = Render::new();
+    ) -> Result<RenderResult> {
+        if self.cpu_fallback {
+            return Err("rendering to a texture view requires compute shaders".into());
+        }
         let encoding = scene.data();
-        // TODO: turn this on; the download feature interacts with CPU dispatch
-        let robust = false;
-        let recording = render.render_encoding_coarse(encoding, &self.shaders, params, robust);
-        let target = render.out_image();
-        let bump_buf = render.bump_buf();
-        self.engine.run_recording(
-            device,
-            queue,
-            &recording,
-            &[],
-            "t_async_coarse",
-            #[cfg(feature = "wgpu-profiler")]
-            &mut self.profiler,
-        )?;
-
-        let mut bump: Option<BumpAllocators> = None;
-        if let Some(bump_buf) = self.engine.get_download(bump_buf) {
-            let buf_slice = bump_buf.slice(..);
-            let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
-            buf_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
-            if let Some(recv_result) = receiver.receive().await {
-                recv_result?;
-            } else {
-                return Err("channel was closed".into());
+        // The CPU shaders don't materialize GPU buffers, which the download requires.
+        let robust = !self.shaders.pathtag_is_cpu;
+        if let Some((bump_buf, capacity)) = self.pending_bump.take() {
+            let data = self.engine.read_download(bump_buf).await?;
+            let stats = FrameStats {
+                used: bytemuck::pod_read_unaligned(&data),
+                capacity,
+                coarse_attempts: 1,
+            };
+            if stats.overflowed() {
+                self.bump_sizes = self.bump_sizes.max(&stats.used);
+                self.wait_for_bump = true;
             }
-            let mapped = buf_slice.get_mapped_range();
-            bump = Some(bytemuck::pod_read_unaligned(&mapped));
+            self.last_frame_stats = Some(stats);
         }
-        // TODO: apply logic to determine whether we need to rerun coarse, and also
-        // allocate the blend stack as needed.
-        self.engine.free_download(bump_buf);
-        // Maybe clear to reuse allocation?
+        let mut attempts = 0;
+        let (mut render, result) = loop {
+            let mut render = self.new_render(self.bump_sizes);
+            let recording = render.render_encoding_coarse(
+                encoding,
+                &mut self.resolver,
+                &self.shaders,
+                params,
+                robust,
+            )?;
+            let bump_buf = render.bump_buf();
+            self.engine.run_recording(
+                device,
+                queue,
+                &recording,
+                &external_resources(&self.external_images, None),
+                "t_async_coarse",
+                #[cfg(feature = "wgpu-profiler")]
+                &mut self.profiler,
+            )?;
+            if !robust {
+                self.last_frame_stats = None;
+                break (render, RenderResult::Rendered { bump: None });
+            }
+            if !self.wait_for_bump {
+                self.pending_bump = Some((bump_buf, render.bump_capacity().unwrap_or_default()));
+                break (render, RenderResult::Unchecked);
+            }
+            let data = self.engine.read_download(bump_buf).await?;
+            let bump: BumpAllocators = bytemuck::pod_read_unaligned(&data);
+            attempts += 1;
+            self.last_frame_stats = Some(FrameStats {
+                used: bump,
+                capacity: render.bump_capacity().unwrap_or_default(),
+                coarse_attempts: attempts,
+            });
+            let overflowed = render.overflowed(&bump);
+            if overflowed {
+                // Keep the larger sizes for subsequent frames so that a scene which needs them
+                // doesn't have to be retried every time, even if it didn't fit this time.
+                self.bump_sizes = self.bump_sizes.max(&bump);
+            }
+            if !overflowed || attempts == MAX_COARSE_ATTEMPTS {
+                self.wait_for_bump = overflowed;
+                let result = if attempts == 1 {
+                    RenderResult::Rendered { bump: Some(bump) }
+                } else {
+                    RenderResult::Retried {
+                        new_sizes: self.bump_sizes,
+                        bump,
+                        complete: !overflowed,
+                    }
+                };
+                break (render, result);
+            }
+            let mut recording = Recording::default();
+            render.discard_fine(&mut recording);
+            self.engine.run_recording(
+                device,
+                queue,
+                &recording,
+                &[],
+                "t_async_discard",
+                #[cfg(feature = "wgpu-profiler")]
+                &mut self.profiler,
+            )?;
+        };
+        let target = render.out_image();
         let mut recording = Recording::default();
         render.record_fine(&self.shaders, &mut recording);
-        let external_resources = [ExternalResource::Image(target, texture)];
         self.engine.run_recording(
             device,
             queue,
             &recording,
-            &external_resources,
+            &external_resources(&self.external_images, Some((target, texture))),
             "t_async_fine",
             #[cfg(feature = "wgpu-profiler")]
             &mut self.profiler,
         )?;
-        Ok(bump)
+        Ok(result)
     }
 
     /// See [Self::render_to_surface]
@@ -330,71 +1927,80 @@ impl Renderer {
         scene: &Scene,
         surface: &SurfaceTexture,
         params: &RenderParams,
-    ) -> Result<Option<BumpAllocators>> {
-        let width = params.width;
-        let height = params.height;
-        let mut target = self
-            .target
-            .take()
-            .unwrap_or_else(|| TargetTexture::new(device, width, height));
-        // TODO: implement clever resizing semantics here to avoid thrashing the memory allocator
-        // during resize, specifically on metal.
-        if target.width != width || target.height != height {
-            target = TargetTexture::new(device, width, height);
+    ) -> Result<RenderResult> {
+        if self.cpu_fallback {
+            self.render_to_surface(device, queue, scene, surface, params)?;
+            return Ok(RenderResult::Rendered { bump: None });
         }
-        let bump = self
-            .render_to_texture_async(device, queue, scene, &target.view, params)
-            .await?;
-        let blit = self
-            .blit
-            .as_ref()
-            .expect("renderer should have configured surface_format to use on a surface");
-        let mut encoder =
-            device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        {
-            let surface_view = surface
+        if self.is_direct_target(surface) {
+            let view = surface
                 .texture
                 .create_view(&wgpu::TextureViewDescriptor::default());
-            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None,
-                layout: &blit.bind_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&target.view),
-                }],
-            });
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &surface_view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color::default()),
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
-            render_pass.set_pipeline(&blit.pipeline);
-            render_pass.set_bind_group(0, &bind_group, &[]);
-            render_pass.draw(0..6, 0..1);
-        }
-        #[cfg(feature = "wgpu-profiler")]
-        self.profiler.resolve_queries(&mut encoder);
-        queue.submit(Some(encoder.finish()));
-        self.target = Some(target);
-        #[cfg(feature = "wgpu-profiler")]
-        self.profiler.end_frame().unwrap();
-        #[cfg(feature = "wgpu-profiler")]
-        if let Some(result) = self.profiler.process_finished_frame() {
-            self.profile_result = Some(result);
+            let result = self
+                .render_to_texture_async(device, queue, scene, &view, params)
+                .await?;
+            let encoder = device.create_command_encoder(&Default::default());
+            self.submit_surface_frame(queue, encoder)?;
+            return Ok(result);
         }
-        Ok(bump)
+        let target = self.take_target(device, params.width, params.height);
+        let result = self
+            .render_to_texture_async(device, queue, scene, &target.view, params)
+            .await?;
+        let encoder = self.encode_blit(device, &target, surface);
+        self.keep_target(target);
+        self.submit_surface_frame(queue, encoder)?;
+        Ok(result)
+    }
+}
+
+/// The size, format and configuration of a [`RenderTarget`].
+#[cfg(feature = "wgpu")]
+#[derive(Clone, Copy, Debug)]
+pub struct RenderTargetOptions {
+    pub width: u32,
+    pub height: u32,
+    /// The format of the target, one of those supported for
+    /// [`RendererOptions::output_format`].
+    pub format: TextureFormat,
+    /// The antialiasing method used by fine rasterization.
+    pub antialiasing_method: AaConfig,
+    /// Whether colors are blended in linear light, see [`RendererOptions::linear_blending`].
+    pub linear_blending: bool,
+    /// Whether the output is dithered, see [`RendererOptions::dithering`].
+    pub dithering: bool,
+}
+
+/// An offscreen texture rendered to with its own size, format and configuration, such as one
+/// of several panels rendered by an application alongside its main window.
+///
+/// Targets are created with [`Renderer::create_render_target`] and rendered to with
+/// [`Renderer::render_to_target`]. The texture can be sampled and copied from.
+#[cfg(feature = "wgpu")]
+pub struct RenderTarget {
+    texture: wgpu::Texture,
+    view: TextureView,
+    options: RenderTargetOptions,
+}
+
+#[cfg(feature = "wgpu")]
+impl RenderTarget {
+    pub fn texture(&self) -> &wgpu::Texture {
+        &self.texture
+    }
+
+    pub fn view(&self) -> &TextureView {
+        &self.view
+    }
+
+    pub fn options(&self) -> &RenderTargetOptions {
+        &self.options
     }
 }
 
 #[cfg(feature = "wgpu")]
 struct TargetTexture {
+    texture: wgpu::Texture,
     view: TextureView,
     width: u32,
     height: u32,
@@ -402,7 +2008,20 @@ struct TargetTexture {
 
 #[cfg(feature = "wgpu")]
 impl TargetTexture {
-    pub fn new(device: &Device, width: u32, height: u32) -> Self {
+    /// Creates a target for the pipeline, or one which the result is copied into when
+    /// `cpu_fallback` is set.
+    pub fn new(
+        device: &Device,
+        width: u32,
+        height: u32,
+        format: ImageFormat,
+        cpu_fallback: bool,
+    ) -> Self {
+        let usage = if cpu_fallback {
+            wgpu::TextureUsages::COPY_DST
+        } else {
+            wgpu::TextureUsages::STORAGE_BINDING
+        };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label: None,
             size: wgpu::Extent3d {
@@ -413,12 +2032,13 @@ impl TargetTexture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::TEXTURE_BINDING,
-            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: usage | wgpu::TextureUsages::TEXTURE_BINDING,
+            format: format.to_wgpu(),
             view_formats: &[],
         });
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         Self {
+            texture,
             view,
             width,
             height,
@@ -428,6 +2048,7 @@ impl TargetTexture {
 
 #[cfg(feature = "wgpu")]
 struct BlitPipeline {
+    format: TextureFormat,
     bind_layout: wgpu::BindGroupLayout,
     pipeline: wgpu::RenderPipeline,
 }
@@ -522,6 +2143,7 @@ impl BlitPipeline {
             multiview: None,
         });
         Self {
+            format,
             bind_layout,
             pipeline,
         }
@@ -0,0 +1,274 @@
+//! Frame pacing: the choice of present mode, bounding the latency of queued frames, and
+//! driving animation from the display on the web.
+//!
+//! A [`FramePacer`] wraps the frames of an application's render loop:
+//!
+//! ```ignore
+//! if pacer.begin_frame(&device) {
+//!     let texture = surface.surface.get_current_texture()?;
+//!     renderer.render_to_surface(&device, &queue, &scene, &texture, &params)?;
+//!     texture.present();
+//!     let frame_time = pacer.end_frame(&queue);
+//! }
+//! ```
+//!
+//! Frame latency waitable objects, which DXGI swap chains use to signal when a new frame
+//! should begin, aren't supported, as wgpu doesn't expose them. Latency is bounded by waiting
+//! for the submissions of earlier frames instead, on every backend.
+
+use std::time::Duration;
+
+use wgpu::{Device, PresentMode, Queue};
+
+use crate::util::{RenderContext, RenderSurface};
+
+/// How presented frames are synchronized with the refresh of the display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Vsync {
+    /// Frames are queued and each is shown for at least one refresh, which never tears but
+    /// adds latency when frames are rendered faster than the display refreshes.
+    #[default]
+    On,
+    /// The latest frame is shown at each refresh, and older queued frames are dropped, which
+    /// never tears and keeps latency low, at the cost of rendering frames which aren't shown.
+    /// Falls back to [`Vsync::On`] on surfaces which don't support it.
+    LowLatency,
+    /// Frames are shown as soon as they are presented, which has the least latency but can
+    /// tear. Falls back to [`Vsync::LowLatency`], then to [`Vsync::On`].
+    Off,
+}
+
+impl Vsync {
+    /// Chooses the present mode for this setting among the `supported` modes of a surface.
+    pub fn present_mode(self, supported: &[PresentMode]) -> PresentMode {
+        let preferred: &[PresentMode] = match self {
+            Self::On => &[PresentMode::Fifo],
+            Self::LowLatency => &[PresentMode::Mailbox, PresentMode::Fifo],
+            Self::Off => &[
+                PresentMode::Immediate,
+                PresentMode::Mailbox,
+                PresentMode::Fifo,
+            ],
+        };
+        preferred
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            // Fifo is always supported, so this is only reached without a list of modes.
+            .unwrap_or(PresentMode::AutoVsync)
+    }
+}
+
+/// Paces the frames presented to a surface.
+///
+/// Before each frame, [`FramePacer::begin_frame`] waits until at most
+/// [`FramePacer::max_frames_in_flight`] earlier frames are still queued on the GPU, so that
+/// the frame is built from recent input rather than running ahead of the display, and
+/// applies the frame rate limit if one is set. [`FramePacer::end_frame`] records the frame
+/// once it is presented.
+///
+/// On the web, the browser paces presentation and frames in flight aren't counted; only the
+/// frame rate limit applies. Frames should be driven by [`run_animation_frames`] there.
+pub struct FramePacer {
+    vsync: Vsync,
+    max_frames_in_flight: usize,
+    frame_rate_limit: Option<f64>,
+    #[cfg(not(target_arch = "wasm32"))]
+    in_flight: std::collections::VecDeque<wgpu::SubmissionIndex>,
+    /// When the current frame began, after any wait for the frame rate limit.
+    frame_start: clock::Timestamp,
+    /// Time between the beginnings of the previous frame and the current one.
+    frame_time: Duration,
+}
+
+impl FramePacer {
+    /// Creates a pacer which allows two frames in flight, without a frame rate limit.
+    pub fn new(vsync: Vsync) -> Self {
+        Self {
+            vsync,
+            max_frames_in_flight: 2,
+            frame_rate_limit: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            in_flight: Default::default(),
+            frame_start: clock::now(),
+            frame_time: Duration::ZERO,
+        }
+    }
+
+    pub fn vsync(&self) -> Vsync {
+        self.vsync
+    }
+
+    /// Changes the vsync setting, and reconfigures `surface` with the matching present mode.
+    pub fn set_vsync(
+        &mut self,
+        render_cx: &RenderContext,
+        surface: &mut RenderSurface,
+        vsync: Vsync,
+    ) {
+        self.vsync = vsync;
+        self.configure(render_cx, surface);
+    }
+
+    /// Configures `surface` with the present mode of the current vsync setting, such as after
+    /// creating it.
+    pub fn configure(&self, render_cx: &RenderContext, surface: &mut RenderSurface) {
+        let adapter = &render_cx.devices[surface.dev_id].adapter;
+        let supported = surface.surface.get_capabilities(adapter).present_modes;
+        let present_mode = self.vsync.present_mode(&supported);
+        if present_mode != surface.config.present_mode {
+            render_cx.set_present_mode(surface, present_mode);
+        }
+    }
+
+    pub fn max_frames_in_flight(&self) -> usize {
+        self.max_frames_in_flight
+    }
+
+    /// Sets how many frames may be queued on the GPU when a new one begins.
+    ///
+    /// Lower values reduce the latency between input and the frame which shows it, while
+    /// higher values keep the GPU busy when frame times vary. Zero waits for each frame to
+    /// finish before starting the next.
+    pub fn set_max_frames_in_flight(&mut self, frames: usize) {
+        self.max_frames_in_flight = frames;
+    }
+
+    /// Limits the rate at which frames begin, in frames per second, or removes the limit.
+    ///
+    /// This saves power when vsync is off, or for content which doesn't need the full refresh
+    /// rate of the display.
+    pub fn set_frame_rate_limit(&mut self, frames_per_second: Option<f64>) {
+        self.frame_rate_limit = frames_per_second.filter(|fps| *fps > 0.0);
+    }
+
+    /// Waits until a new frame can begin, and returns whether it should be rendered.
+    ///
+    /// This blocks natively, and returns `false` on the web if the frame rate limit hasn't
+    /// elapsed yet, in which case the frame should be skipped until the next animation frame.
+    pub fn begin_frame(&mut self, device: &Device) -> bool {
+        #[cfg(not(target_arch = "wasm32"))]
+        while self.in_flight.len() > self.max_frames_in_flight {
+            let index = self.in_flight.pop_front().unwrap();
+            device.poll(wgpu::Maintain::WaitForSubmissionIndex(index));
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = device;
+        // The limit is measured between the beginnings of frames, so that the time spent
+        // rendering counts towards the interval.
+        if let Some(fps) = self.frame_rate_limit {
+            let interval = Duration::from_secs_f64(1.0 / fps);
+            let elapsed = clock::elapsed(self.frame_start);
+            if elapsed < interval {
+                #[cfg(not(target_arch = "wasm32"))]
+                std::thread::sleep(interval - elapsed);
+                #[cfg(target_arch = "wasm32")]
+                return false;
+            }
+        }
+        let now = clock::now();
+        self.frame_time = clock::between(self.frame_start, now);
+        self.frame_start = now;
+        true
+    }
+
+    /// Ends the frame after it has been presented, and returns the time between the
+    /// beginnings of the previous frame and this one.
+    pub fn end_frame(&mut self, queue: &Queue) -> Duration {
+        // An empty submission is ordered after the work of the frame, so waiting for it waits
+        // for the whole frame.
+        #[cfg(not(target_arch = "wasm32"))]
+        self.in_flight.push_back(queue.submit([]));
+        #[cfg(target_arch = "wasm32")]
+        let _ = queue;
+        self.frame_time
+    }
+}
+
+impl Default for FramePacer {
+    fn default() -> Self {
+        Self::new(Vsync::default())
+    }
+}
+
+/// Calls `frame` on every animation frame of the browser, with the time of the frame in
+/// milliseconds, until it returns `false`.
+///
+/// Animation frames are aligned with the refresh of the display, and stop while the page is
+/// hidden. This works both on the main thread and in a dedicated worker which renders to an
+/// `OffscreenCanvas`.
+#[cfg(target_arch = "wasm32")]
+pub fn run_animation_frames(frame: impl FnMut(f64) -> bool + 'static) {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    // Each request gets a one-shot callback, which is freed once it has run.
+    fn request(mut frame: Box<dyn FnMut(f64) -> bool>) {
+        let callback = Closure::once_into_js(move |time: f64| {
+            if frame(time) {
+                request(frame);
+            }
+        });
+        // Workers only have `requestAnimationFrame` when they render to an `OffscreenCanvas`,
+        // so it is looked up on whichever global scope this runs in.
+        let global = js_sys::global();
+        let request = js_sys::Reflect::get(&global, &"requestAnimationFrame".into())
+            .ok()
+            .and_then(|request| request.dyn_into::<js_sys::Function>().ok())
+            .expect("requestAnimationFrame isn't available in this context");
+        request
+            .call1(&global, &callback)
+            .expect("failed to request an animation frame");
+    }
+
+    request(Box::new(frame));
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod clock {
+    use std::time::{Duration, Instant};
+
+    pub type Timestamp = Instant;
+
+    pub fn now() -> Timestamp {
+        Instant::now()
+    }
+
+    pub fn elapsed(since: Timestamp) -> Duration {
+        since.elapsed()
+    }
+
+    pub fn between(start: Timestamp, end: Timestamp) -> Duration {
+        end.saturating_duration_since(start)
+    }
+}
+
+/// `std::time::Instant` isn't available on the web, so time is taken from the performance API
+/// of the window or worker, in milliseconds.
+#[cfg(target_arch = "wasm32")]
+mod clock {
+    use std::time::Duration;
+
+    pub type Timestamp = f64;
+
+    pub fn now() -> Timestamp {
+        use wasm_bindgen::JsCast;
+
+        let global = js_sys::global();
+        let performance = if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+            window.performance()
+        } else if let Some(worker) = global.dyn_ref::<web_sys::WorkerGlobalScope>() {
+            worker.performance()
+        } else {
+            None
+        };
+        performance.map_or_else(js_sys::Date::now, |performance| performance.now())
+    }
+
+    pub fn elapsed(since: Timestamp) -> Duration {
+        between(since, now())
+    }
+
+    pub fn between(start: Timestamp, end: Timestamp) -> Duration {
+        Duration::from_secs_f64((end - start).max(0.0) / 1000.0)
+    }
+}
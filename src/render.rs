@@ -1,17 +1,46 @@
 //! Take an encoded scene and create a graph to render it
 
+use std::num::NonZeroU64;
+
 use crate::{
-    engine::{BufProxy, ImageFormat, ImageProxy, Recording, ResourceProxy},
+    engine::{BufProxy, Id, ImageFormat, ImageProxy, Recording, ResourceProxy},
     shaders::FullShaders,
-    AaConfig, RenderParams, Scene, ANTIALIASING,
+    AaConfig, CapturedBuffer, RenderParams, Result, Scene,
+};
+use vello_encoding::{
+    BlurConfig, BumpAllocators, DrawImage, Encoding, ExternalImage, FilterLayer, IndirectCount,
+    RenderConfig, Resolver, WorkgroupSize,
 };
-use vello_encoding::{Encoding, WorkgroupSize};
+
+/// Maximum number of tiles in a target for which fine rasterization is compacted, as the
+/// listed tiles are dispatched along a single dimension.
+const MAX_COMPACT_TILES: u32 = 65535;
 
 /// State for a render in progress.
 pub struct Render {
     fine_wg_count: Option<WorkgroupSize>,
     fine_resources: Option<FineResources>,
     mask_buf: Option<ResourceProxy>,
+    /// Minimum sizes of the bump allocated buffers, from earlier runs of the pipeline.
+    bump_sizes: BumpAllocators,
+    /// Configuration of the coarse phase, kept to check its allocations after readback.
+    config: Option<RenderConfig>,
+    /// Column of the output image that the left of the target is written to.
+    target_x_offset: u32,
+    /// Row of the output image that the top of the target is written to.
+    target_y_offset: u32,
+    /// Whether colors are blended in linear light rather than in sRGB.
+    linear_blending: bool,
+    /// Whether the output is dithered.
+    dithering: bool,
+    /// Whether the scene buffer is kept by the engine for the next render.
+    retain_scene: bool,
+    /// Whether intermediate buffers are downloaded before they are freed.
+    capture: bool,
+    /// Intermediate buffers downloaded so far, by name.
+    captures: Vec<(&'static str, BufProxy)>,
+    /// Configuration and packed scene of the captured render, which are kept on the CPU.
+    captured_inputs: Vec<CapturedBuffer>,
 }
 
 /// Resources produced by pipeline, needed for fine rasterization.
@@ -21,19 +50,70 @@ struct FineResources {
     tile_buf: ResourceProxy,
     segments_buf: ResourceProxy,
     ptcl_buf: ResourceProxy,
+    blend_spill_buf: ResourceProxy,
     gradient_image: ResourceProxy,
     info_bin_data_buf: ResourceProxy,
     image_atlas: ResourceProxy,
+    /// The textures of external images, one for each slot, or placeholders for the slots the
+    /// scene doesn't use.
+    external_images: Vec<ImageProxy>,
+    /// Bin headers, kept for the debug overlay.
+    bin_header_buf: Option<ResourceProxy>,
 
     out_image: ImageProxy,
 }
 
+impl FineResources {
+    /// Returns the bindings of fine rasterization, followed by those of its variant.
+    fn bindings<const N: usize>(&self, variant: [ResourceProxy; N]) -> Vec<ResourceProxy> {
+        let mut bindings = vec![
+            self.config_buf,
+            self.segments_buf,
+            self.ptcl_buf,
+            self.info_bin_data_buf,
+            ResourceProxy::Image(self.out_image),
+            self.gradient_image,
+            self.image_atlas,
+            self.blend_spill_buf,
+        ];
+        bindings.extend(
+            self.external_images
+                .iter()
+                .copied()
+                .map(ResourceProxy::Image),
+        );
+        bindings.extend(variant);
+        bindings
+    }
+
+    fn free(self, recording: &mut Recording) {
+        recording.free_resource(self.config_buf);
+        recording.free_resource(self.tile_buf);
+        recording.free_resource(self.segments_buf);
+        recording.free_resource(self.ptcl_buf);
+        recording.free_resource(self.blend_spill_buf);
+        recording.free_resource(self.gradient_image);
+        recording.free_resource(self.image_atlas);
+        recording.free_resource(self.info_bin_data_buf);
+        // This only frees the placeholders, as the textures of external images are bound by
+        // the renderer.
+        for image in self.external_images {
+            recording.free_image(image);
+        }
+        if let Some(bin_header_buf) = self.bin_header_buf {
+            recording.free_resource(bin_header_buf);
+        }
+    }
+}
+
 pub fn render_full(
+    render: Render,
     scene: &Scene,
+    resolver: &mut Resolver,
     shaders: &FullShaders,
     params: &RenderParams,
-) -> (Recording, ResourceProxy) {
-    render_encoding_full(scene.data(), shaders, params)
+) -> Result<(Recording, ResourceProxy)> {
+    render_encoding_full(render, scene.data(), resolver, shaders, params)
 }
 
 /// Create a single recording with both coarse and fine render stages.
@@ -41,15 +121,17 @@ pub fn render_full(
 /// This function is not recommended when the scene can be complex, as it does not
 /// implement robust dynamic memory.
 pub fn render_encoding_full(
+    mut render: Render,
     encoding: &Encoding,
+    resolver: &mut Resolver,
     shaders: &FullShaders,
     params: &RenderParams,
-) -> (Recording, ResourceProxy) {
-    let mut render = Render::new();
-    let mut recording = render.render_encoding_coarse(encoding, shaders, params, false);
+) -> Result<(Recording, ResourceProxy)> {
+    let mut recording =
+        render.render_encoding_coarse(encoding, resolver, shaders, params, false)?;
     let out_image = render.out_image();
     render.record_fine(shaders, &mut recording);
-    (recording, out_image.into())
+    Ok((recording, out_image.into()))
 }
 
 impl Default for Render {
@@ -60,30 +142,135 @@ impl Default for Render {
 
 impl Render {
     pub fn new() -> Self {
+        Self::with_bump_sizes(BumpAllocators::default())
+    }
+
+    /// Create a render whose bump allocated buffers are large enough to hold the allocations
+    /// of `bump_sizes`, typically reported by an earlier run that ran out of memory.
+    pub fn with_bump_sizes(bump_sizes: BumpAllocators) -> Self {
         Render {
             fine_wg_count: None,
             fine_resources: None,
             mask_buf: None,
+            bump_sizes,
+            config: None,
+            target_x_offset: 0,
+            target_y_offset: 0,
+            linear_blending: false,
+            dithering: false,
+            retain_scene: false,
+            capture: false,
+            captures: vec![],
+            captured_inputs: vec![],
         }
     }
 
+    /// Write the target to the output image starting at column `x_offset`, rather than at the
+    /// left.
+    ///
+    /// This is used to render a region of a scene, where the region is a separate target.
+    pub fn set_target_x_offset(&mut self, x_offset: u32) {
+        self.target_x_offset = x_offset;
+    }
+
+    /// Write the target to the output image starting at row `y_offset`, rather than at the top.
+    ///
+    /// This is used to render a scene in bands, where each band is a separate target.
+    pub fn set_target_y_offset(&mut self, y_offset: u32) {
+        self.target_y_offset = y_offset;
+    }
+
+    /// Blend colors in linear light and encode the output to sRGB, rather than blending the
+    /// sRGB encoded colors directly.
+    pub fn set_linear_blending(&mut self, linear_blending: bool) {
+        self.linear_blending = linear_blending;
+    }
+
+    /// Dither the colors written to 8 bit outputs, to hide banding in smooth gradients.
+    pub fn set_dithering(&mut self, dithering: bool) {
+        self.dithering = dithering;
+    }
+
+    /// Upload the scene to a buffer that the engine keeps for the next render that also sets
+    /// this, so that only the parts of the scene which changed are written to the GPU.
+    pub fn set_retain_scene(&mut self, retain_scene: bool) {
+        self.retain_scene = retain_scene;
+    }
+
+    /// Download each intermediate buffer of the pipeline before it is freed, for
+    /// [`Renderer::capture_frame`](crate::Renderer::capture_frame).
+    pub fn set_capture(&mut self, capture: bool) {
+        self.capture = capture;
+    }
+
+    /// Returns the names and downloads of the buffers captured so far.
+    pub fn take_captures(&mut self) -> Vec<(&'static str, BufProxy)> {
+        std::mem::take(&mut self.captures)
+    }
+
+    /// Returns the configuration and packed scene of the renders captured so far, named
+    /// `config` and `scene`.
+    pub fn take_captured_inputs(&mut self) -> Vec<CapturedBuffer> {
+        std::mem::take(&mut self.captured_inputs)
+    }
+
+    /// Download `buf` if buffers are captured.
+    fn capture_buf(&mut self, recording: &mut Recording, name: &'static str, buf: ResourceProxy) {
+        if self.capture {
+            let buf = *buf.as_buf().unwrap();
+            recording.download(buf);
+            self.captures.push((name, buf));
+        }
+    }
+
+    /// Free `buf` at the end of the recording, downloading it first if buffers are captured.
+    fn free_captured(&mut self, recording: &mut Recording, name: &'static str, buf: ResourceProxy) {
+        self.capture_buf(recording, name, buf);
+        recording.free_resource(buf);
+    }
+
     /// Prepare a recording for the coarse rasterization phase.
     ///
     /// The `robust` parameter controls whether we're preparing for readback
     /// of the atomic bump buffer, for robust dynamic memory.
+    ///
+    /// The `resolver` holds the glyph outlines and gradient ramps, which are cached across
+    /// renders that use the same resolver.
+    ///
+    /// Returns an error if the scene draws more different external images, including cached
+    /// layers, than there are slots to bind them to, or with the `validate` feature, if the
+    /// encoding is invalid.
     pub fn render_encoding_coarse(
         &mut self,
         encoding: &Encoding,
+        resolver: &mut Resolver,
         shaders: &FullShaders,
         params: &RenderParams,
         robust: bool,
-    ) -> Recording {
-        use vello_encoding::{RenderConfig, Resolver};
+    ) -> Result<Recording> {
+        use vello_encoding::estimate_bump_sizes;
 
+        #[cfg(feature = "validate")]
+        if let Err(err) = encoding.validate() {
+            return Err(format!("invalid scene encoding: {err}").into());
+        }
         let mut recording = Recording::default();
-        let mut resolver = Resolver::new();
+        // Scenes with only color fills skip the handling of clips, gradients and images.
+        let (draw_leaf, coarse) = if encoding.is_simple() {
+            (shaders.simple.draw_leaf, shaders.simple.coarse)
+        } else {
+            (shaders.draw_leaf, shaders.coarse)
+        };
+        resolver.set_linear_colors(self.linear_blending);
         let mut packed = vec![];
         let (layout, ramps, images) = resolver.resolve(encoding, &mut packed);
+        if images.dropped_external_images != 0 {
+            return Err(format!(
+                "scene draws more than {} different external images and cached layers",
+                DrawImage::MAX_EXTERNAL_IMAGES
+            )
+            .into());
+        }
         let gradient_image = if ramps.height == 0 {
             ResourceProxy::new_image(1, 1, ImageFormat::Rgba8)
         } else {
@@ -95,7 +282,7 @@ impl Render {
                 data,
             ))
         };
-        let image_atlas = if images.images.is_empty() {
+        let image_atlas = if images.images.is_empty() && images.layers.is_empty() {
             ImageProxy::new(1, 1, ImageFormat::Rgba8)
         } else {
             ImageProxy::new(images.width, images.height, ImageFormat::Rgba8)
@@ -110,13 +297,58 @@ impl Render {
                 image.0.data.data(),
             );
         }
+        for &(index, x, y) in images.layers {
+            let layer = &encoding.resources.filter_layers[index];
+            self.record_filter_layer(layer, shaders, &mut recording, image_atlas, (x, y))?;
+        }
+        // External images are sampled by fine rasterization from their own textures. Images
+        // whose texture is no longer bound by the renderer are drawn from a transparent
+        // image of the same size.
+        let external_images = (0..DrawImage::MAX_EXTERNAL_IMAGES as usize)
+            .map(|slot| {
+                images
+                    .external_images
+                    .get(slot)
+                    .and_then(Render::external_image_proxy)
+                    .unwrap_or_else(|| ImageProxy::new(1, 1, ImageFormat::Rgba8))
+            })
+            .collect();
 
-        let cpu_config =
+        let mut cpu_config =
             RenderConfig::new(&layout, params.width, params.height, &params.base_color);
+        let estimate = estimate_bump_sizes(&layout, &packed, params.width, params.height);
+        // The estimate is a heuristic, so buffers only shrink below their default sizes when the
+        // allocations are read back and the coarse phase can be run again if they overflow.
+        if robust {
+            cpu_config.fit_bump_sizes(&estimate);
+        } else {
+            cpu_config.grow_to_fit(&estimate);
+        }
+        cpu_config.grow_to_fit(&self.bump_sizes);
+        cpu_config.gpu.target_x_offset = self.target_x_offset;
+        cpu_config.gpu.target_y_offset = self.target_y_offset;
+        cpu_config.gpu.linear_blending = self.linear_blending as u32;
+        cpu_config.gpu.dithering = self.dithering as u32;
+        cpu_config.set_flatten_workgroup_size(shaders.workgroup_sizes.flatten);
         let buffer_sizes = &cpu_config.buffer_sizes;
         let wg_counts = &cpu_config.workgroup_counts;
+        if self.capture {
+            self.captured_inputs.push(CapturedBuffer {
+                name: "config",
+                data: bytemuck::bytes_of(&cpu_config.gpu).to_vec(),
+            });
+            self.captured_inputs.push(CapturedBuffer {
+                name: "scene",
+                data: packed.clone(),
+            });
+        }
 
-        let scene_buf = ResourceProxy::Buf(recording.upload("scene", packed));
+        let scene_buf = if self.retain_scene {
+            recording.upload_retained("scene", packed)
+        } else {
+            recording.upload("scene", packed)
+        };
+        let scene_buf = ResourceProxy::Buf(scene_buf);
         let config_buf = ResourceProxy::Buf(
             recording.upload_uniform("config", bytemuck::bytes_of(&cpu_config.gpu)),
         );
@@ -129,60 +361,82 @@ impl Render {
         let segments_buf =
             ResourceProxy::new_buf(buffer_sizes.segments.size_in_bytes().into(), "segments_buf");
         let ptcl_buf = ResourceProxy::new_buf(buffer_sizes.ptcl.size_in_bytes().into(), "ptcl_buf");
-        let reduced_buf = ResourceProxy::new_buf(
-            buffer_sizes.path_reduced.size_in_bytes().into(),
-            "reduced_buf",
+        let blend_spill_buf = ResourceProxy::new_buf(
+            buffer_sizes.blend_spill.size_in_bytes().into(),
+            "blend_spill_buf",
         );
-        // TODO: really only need pathtag_wgs - 1
-        recording.dispatch(
-            shaders.pathtag_reduce,
-            wg_counts.path_reduce,
-            [config_buf, scene_buf, reduced_buf],
+        let tagmonoid_buf = ResourceProxy::new_buf(
+            buffer_sizes.path_monoids.size_in_bytes().into(),
+            "tagmonoid_buf",
         );
-        let mut pathtag_parent = reduced_buf;
-        let mut large_pathtag_bufs = None;
-        let use_large_path_scan = wg_counts.use_large_path_scan && !shaders.pathtag_is_cpu;
-        if use_large_path_scan {
-            let reduced2_buf = ResourceProxy::new_buf(
-                buffer_sizes.path_reduced2.size_in_bytes().into(),
-                "reduced2_buf",
+        // The CPU shaders only implement the reduce and scan.
+        let pathtag_scan_lookback = shaders
+            .pathtag_scan_lookback
+            .filter(|_| !shaders.pathtag_is_cpu);
+        if let Some(pathtag_scan_lookback) = pathtag_scan_lookback {
+            let scan_state_buf = BufProxy::new(
+                buffer_sizes.path_scan_state.size_in_bytes().into(),
+                "scan_state_buf",
             );
+            recording.clear_all(scan_state_buf);
+            let scan_state_buf = ResourceProxy::Buf(scan_state_buf);
             recording.dispatch(
-                shaders.pathtag_reduce2,
-                wg_counts.path_reduce2,
-                [reduced_buf, reduced2_buf],
+                pathtag_scan_lookback,
+                wg_counts.path_scan,
+                [config_buf, scene_buf, scan_state_buf, tagmonoid_buf],
             );
-            let reduced_scan_buf = ResourceProxy::new_buf(
-                buffer_sizes.path_reduced_scan.size_in_bytes().into(),
-                "reduced_scan_buf",
+            recording.free_resource(scan_state_buf);
+        } else {
+            let reduced_buf = ResourceProxy::new_buf(
+                buffer_sizes.path_reduced.size_in_bytes().into(),
+                "reduced_buf",
             );
+            // TODO: really only need pathtag_wgs - 1
             recording.dispatch(
-                shaders.pathtag_scan1,
-                wg_counts.path_scan1,
-                [reduced_buf, reduced2_buf, reduced_scan_buf],
+                shaders.pathtag_reduce,
+                wg_counts.path_reduce,
+                [config_buf, scene_buf, reduced_buf],
             );
-            pathtag_parent = reduced_scan_buf;
-            large_pathtag_bufs = Some((reduced2_buf, reduced_scan_buf));
-        }
-
-        let tagmonoid_buf = ResourceProxy::new_buf(
-            buffer_sizes.path_monoids.size_in_bytes().into(),
-            "tagmonoid_buf",
-        );
-        let pathtag_scan = if use_large_path_scan {
-            shaders.pathtag_scan_large
-        } else {
-            shaders.pathtag_scan
-        };
-        recording.dispatch(
-            pathtag_scan,
-            wg_counts.path_scan,
-            [config_buf, scene_buf, pathtag_parent, tagmonoid_buf],
-        );
-        recording.free_resource(reduced_buf);
-        if let Some((reduced2, reduced_scan)) = large_pathtag_bufs {
-            recording.free_resource(reduced2);
-            recording.free_resource(reduced_scan);
+            let mut pathtag_parent = reduced_buf;
+            let mut large_pathtag_bufs = None;
+            let use_large_path_scan = wg_counts.use_large_path_scan && !shaders.pathtag_is_cpu;
+            if use_large_path_scan {
+                let reduced2_buf = ResourceProxy::new_buf(
+                    buffer_sizes.path_reduced2.size_in_bytes().into(),
+                    "reduced2_buf",
+                );
+                recording.dispatch(
+                    shaders.pathtag_reduce2,
+                    wg_counts.path_reduce2,
+                    [reduced_buf, reduced2_buf],
+                );
+                let reduced_scan_buf = ResourceProxy::new_buf(
+                    buffer_sizes.path_reduced_scan.size_in_bytes().into(),
+                    "reduced_scan_buf",
+                );
+                recording.dispatch(
+                    shaders.pathtag_scan1,
+                    wg_counts.path_scan1,
+                    [reduced_buf, reduced2_buf, reduced_scan_buf],
+                );
+                pathtag_parent = reduced_scan_buf;
+                large_pathtag_bufs = Some((reduced2_buf, reduced_scan_buf));
+            }
+            let pathtag_scan = if use_large_path_scan {
+                shaders.pathtag_scan_large
+            } else {
+                shaders.pathtag_scan
+            };
+            recording.dispatch(
+                pathtag_scan,
+                wg_counts.path_scan,
+                [config_buf, scene_buf, pathtag_parent, tagmonoid_buf],
+            );
+            self.free_captured(&mut recording, "path_reduced", reduced_buf);
+            if let Some((reduced2, reduced_scan)) = large_pathtag_bufs {
+                recording.free_resource(reduced2);
+                recording.free_resource(reduced_scan);
+            }
         }
         let path_bbox_buf = ResourceProxy::new_buf(
             buffer_sizes.path_bboxes.size_in_bytes().into(),
@@ -228,7 +482,7 @@ impl Render {
             "clip_inp_buf",
         );
         recording.dispatch(
-            shaders.draw_leaf,
+            draw_leaf,
             wg_counts.draw_leaf,
             [
                 config_buf,
@@ -240,7 +494,7 @@ impl Render {
                 clip_inp_buf,
             ],
         );
-        recording.free_resource(draw_reduced_buf);
+        self.free_captured(&mut recording, "draw_reduced", draw_reduced_buf);
         let clip_el_buf =
             ResourceProxy::new_buf(buffer_sizes.clip_els.size_in_bytes().into(), "clip_el_buf");
         let clip_bic_buf = ResourceProxy::new_buf(
@@ -273,9 +527,9 @@ impl Render {
                 ],
             );
         }
-        recording.free_resource(clip_inp_buf);
-        recording.free_resource(clip_bic_buf);
-        recording.free_resource(clip_el_buf);
+        self.free_captured(&mut recording, "clip_inps", clip_inp_buf);
+        self.free_captured(&mut recording, "clip_bics", clip_bic_buf);
+        self.free_captured(&mut recording, "clip_els", clip_el_buf);
         let draw_bbox_buf = ResourceProxy::new_buf(
             buffer_sizes.draw_bboxes.size_in_bytes().into(),
             "draw_bbox_buf",
@@ -298,9 +552,9 @@ impl Render {
                 bin_header_buf,
             ],
         );
-        recording.free_resource(draw_monoid_buf);
-        recording.free_resource(path_bbox_buf);
-        recording.free_resource(clip_bbox_buf);
+        self.free_captured(&mut recording, "draw_monoids", draw_monoid_buf);
+        self.free_captured(&mut recording, "path_bboxes", path_bbox_buf);
+        self.free_captured(&mut recording, "clip_bboxes", clip_bbox_buf);
         // Note: this only needs to be rounded up because of the workaround to store the tile_offset
         // in storage rather than workgroup memory.
         let path_buf =
@@ -317,8 +571,8 @@ impl Render {
                 tile_buf,
             ],
         );
-        recording.free_resource(draw_bbox_buf);
-        recording.free_resource(tagmonoid_buf);
+        self.free_captured(&mut recording, "draw_bboxes", draw_bbox_buf);
+        self.free_captured(&mut recording, "path_monoids", tagmonoid_buf);
         let indirect_count_buf = BufProxy::new(
             buffer_sizes.indirect_count.size_in_bytes().into(),
             "indirect_count",
@@ -351,7 +605,7 @@ impl Render {
             [config_buf, path_buf, tile_buf],
         );
         recording.dispatch(
-            shaders.coarse,
+            coarse,
             wg_counts.coarse,
             [
                 config_buf,
@@ -384,13 +638,19 @@ impl Render {
             ],
         );
         recording.free_buf(indirect_count_buf);
-        recording.free_resource(seg_counts_buf);
-        recording.free_resource(lines_buf);
+        self.free_captured(&mut recording, "seg_counts", seg_counts_buf);
+        self.free_captured(&mut recording, "lines", lines_buf);
         recording.free_resource(scene_buf);
         recording.free_resource(draw_monoid_buf);
-        recording.free_resource(bin_header_buf);
-        recording.free_resource(path_buf);
-        let out_image = ImageProxy::new(params.width, params.height, ImageFormat::Rgba8);
+        self.capture_buf(&mut recording, "bin_headers", bin_header_buf);
+        let bin_header_buf = if shaders.debug_fine.is_some() {
+            Some(bin_header_buf)
+        } else {
+            recording.free_resource(bin_header_buf);
+            None
+        };
+        self.free_captured(&mut recording, "paths", path_buf);
+        let out_image = ImageProxy::new(params.width, params.height, shaders.output_format);
         self.fine_wg_count = Some(wg_counts.fine);
         self.fine_resources = Some(FineResources {
             config_buf,
@@ -398,43 +658,176 @@ impl Render {
             tile_buf,
             segments_buf,
             ptcl_buf,
+            blend_spill_buf,
             gradient_image,
             info_bin_data_buf,
             image_atlas: ResourceProxy::Image(image_atlas),
+            external_images,
+            bin_header_buf,
             out_image,
         });
         if robust {
             recording.download(*bump_buf.as_buf().unwrap());
+            recording.free_resource(bump_buf);
+        } else {
+            self.free_captured(&mut recording, "bump", bump_buf);
+        }
+        self.config = Some(cpu_config);
+        Ok(recording)
+    }
+
+    /// Check the allocations read back after the coarse phase against its buffer sizes.
+    ///
+    /// Returns `true` if any of the bump allocated buffers overflowed. In that case the coarse
+    /// phase needs to be discarded with [`Render::discard_fine`] and recorded again, using a
+    /// render created with [`Render::with_bump_sizes`].
+    pub fn overflowed(&mut self, bump: &BumpAllocators) -> bool {
+        self.config
+            .as_mut()
+            .map_or(false, |config| config.grow_to_fit(bump))
+    }
+
+    /// Returns the capacity of the bump allocated buffers of the recorded coarse phase.
+    pub fn bump_capacity(&self) -> Option<BumpAllocators> {
+        self.config.as_ref().map(RenderConfig::bump_capacity)
+    }
+
+    /// Free the resources of the coarse phase without running fine rasterization.
+    pub fn discard_fine(&mut self, recording: &mut Recording) {
+        self.fine_wg_count = None;
+        if let Some(fine) = self.fine_resources.take() {
+            fine.free(recording);
+        }
+    }
+
+    /// Render a filter layer offscreen and blur it into the image atlas at `atlas_xy`.
+    fn record_filter_layer(
+        &self,
+        layer: &FilterLayer,
+        shaders: &FullShaders,
+        recording: &mut Recording,
+        image_atlas: ImageProxy,
+        atlas_xy: (u32, u32),
+    ) -> Result<()> {
+        let params = RenderParams {
+            base_color: peniko::Color::TRANSPARENT,
+            width: layer.width,
+            height: layer.height,
+        };
+        let mut render = Render::new();
+        render.set_linear_blending(self.linear_blending);
+        // The resources of the outer render are still borrowed from its resolver.
+        let mut resolver = Resolver::new();
+        let mut layer_recording = render.render_encoding_coarse(
+            &layer.encoding,
+            &mut resolver,
+            shaders,
+            &params,
+            false,
+        )?;
+        let layer_image = render.out_image();
+        render.record_fine(shaders, &mut layer_recording);
+        recording.commands.extend(layer_recording.into_commands());
+        // Blur horizontally into an intermediate image, then vertically into the atlas.
+        let blurred_image = ImageProxy::new(layer.width, layer.height, ImageFormat::Rgba8);
+        let passes = [
+            (layer_image, blurred_image, (0, 0), None),
+            (blurred_image, image_atlas, atlas_xy, layer.color),
+        ];
+        let wg_count = (layer.width.div_ceil(16), layer.height.div_ceil(16), 1);
+        for (vertical, (input, output, (dst_x, dst_y), color)) in passes.into_iter().enumerate() {
+            let config = BlurConfig {
+                width: layer.width,
+                height: layer.height,
+                dst_x,
+                dst_y,
+                vertical: vertical as u32,
+                // The kernel is truncated at three standard deviations.
+                radius: (3.0 * layer.std_dev).ceil().max(0.0) as u32,
+                std_dev: layer.std_dev,
+                use_color: color.is_some() as u32,
+                color: color.map_or(0, |color| color.to_premul_u32()),
+            };
+            let config_buf = recording.upload_uniform("blur_config", bytemuck::bytes_of(&config));
+            recording.dispatch(
+                shaders.blur,
+                wg_count,
+                [
+                    config_buf.into(),
+                    ResourceProxy::Image(input),
+                    ResourceProxy::Image(output),
+                ],
+            );
+            recording.free_buf(config_buf);
         }
-        recording.free_resource(bump_buf);
-        recording
+        recording.free_image(layer_image);
+        recording.free_image(blurred_image);
+        Ok(())
+    }
+
+    /// Proxy of the texture of an external image, which the renderer that created the handle
+    /// binds when it runs the recording.
+    pub(crate) fn external_image_proxy(image: &ExternalImage) -> Option<ImageProxy> {
+        Some(ImageProxy {
+            width: image.width,
+            height: image.height,
+            format: ImageFormat::Rgba8,
+            id: Id(NonZeroU64::new(image.id)?),
+        })
     }
 
     /// Run fine rasterization assuming the coarse phase succeeded.
     pub fn record_fine(&mut self, shaders: &FullShaders, recording: &mut Recording) {
         let fine_wg_count = self.fine_wg_count.take().unwrap();
         let fine = self.fine_resources.take().unwrap();
-        match ANTIALIASING {
-            AaConfig::Area => {
+        let (width_in_tiles, height_in_tiles, _) = fine_wg_count;
+        let compact = shaders
+            .compact
+            .filter(|_| width_in_tiles * height_in_tiles <= MAX_COMPACT_TILES);
+        match (shaders.aa_config, compact) {
+            (AaConfig::Area, _) if shaders.debug_fine.is_some() => {
                 recording.dispatch(
-                    shaders.fine,
+                    shaders.debug_fine.unwrap(),
+                    fine_wg_count,
+                    fine.bindings([fine.bin_header_buf.unwrap()]),
+                );
+            }
+            (AaConfig::Area, Some(compact)) => {
+                let n_tiles = width_in_tiles * height_in_tiles;
+                let tile_list_buf = BufProxy::new(n_tiles as u64 * 4, "tile_list_buf");
+                let indirect_buf = BufProxy::new(
+                    std::mem::size_of::<IndirectCount>() as u64,
+                    "tile_list_count_buf",
+                );
+                recording.clear_all(indirect_buf);
+                recording.dispatch(
+                    compact.tile_compact,
                     fine_wg_count,
                     [
                         fine.config_buf,
-                        fine.segments_buf,
                         fine.ptcl_buf,
-                        fine.info_bin_data_buf,
+                        indirect_buf.into(),
+                        tile_list_buf.into(),
                         ResourceProxy::Image(fine.out_image),
-                        fine.gradient_image,
-                        fine.image_atlas,
                     ],
                 );
+                recording.dispatch_indirect(
+                    compact.fine,
+                    indirect_buf,
+                    0,
+                    fine.bindings([tile_list_buf.into()]),
+                );
+                recording.free_buf(indirect_buf);
+                recording.free_buf(tile_list_buf);
+            }
+            (AaConfig::Area | AaConfig::Aliased | AaConfig::AreaSubpixel, None) => {
+                recording.dispatch(shaders.fine, fine_wg_count, fine.bindings([]));
             }
             _ => {
                 if self.mask_buf.is_none() {
-                    let mask_lut = match ANTIALIASING {
+                    let mask_lut = match shaders.aa_config {
                         AaConfig::Msaa16 => crate::mask::make_mask_lut_16(),
-                        AaConfig::Msaa8 => crate::mask::make_mask_lut(),
+                        AaConfig::Msaa8 | AaConfig::Msaa8Resolve => crate::mask::make_mask_lut(),
                         _ => unreachable!(),
                     };
                     let buf = recording.upload("mask lut", mask_lut);
@@ -443,26 +836,20 @@ impl Render {
                 recording.dispatch(
                     shaders.fine,
                     fine_wg_count,
-                    [
-                        fine.config_buf,
-                        fine.segments_buf,
-                        fine.ptcl_buf,
-                        fine.info_bin_data_buf,
-                        ResourceProxy::Image(fine.out_image),
-                        fine.gradient_image,
-                        fine.image_atlas,
-                        self.mask_buf.unwrap(),
-                    ],
+                    fine.bindings([self.mask_buf.unwrap()]),
                 );
             }
         }
-        recording.free_resource(fine.config_buf);
-        recording.free_resource(fine.tile_buf);
-        recording.free_resource(fine.segments_buf);
-        recording.free_resource(fine.ptcl_buf);
-        recording.free_resource(fine.gradient_image);
-        recording.free_resource(fine.image_atlas);
-        recording.free_resource(fine.info_bin_data_buf);
+        // The buffers written by the coarse phase are only read from here on.
+        for (name, buf) in [
+            ("tiles", fine.tile_buf),
+            ("segments", fine.segments_buf),
+            ("ptcl", fine.ptcl_buf),
+            ("info_bin_data", fine.info_bin_data_buf),
+        ] {
+            self.capture_buf(recording, name, buf);
+        }
+        fine.free(recording);
         // TODO: make mask buf persistent
         if let Some(mask_buf) = self.mask_buf.take() {
             recording.free_resource(mask_buf);
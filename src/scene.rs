@@ -14,10 +14,16 @@
 //
 // Also licensed under MIT license, at your choice.
 
+use crate::bitmap::BitmapGlyphs;
+use crate::colr::ColorGlyphs;
 use fello::NormalizedCoord;
-use peniko::kurbo::{Affine, Rect, Shape, Stroke};
-use peniko::{BlendMode, BrushRef, Color, Fill, Font, Image, StyleRef};
-use vello_encoding::{Encoding, Glyph, GlyphRun, Patch, Transform};
+use std::f64::consts::SQRT_2;
+
+use peniko::kurbo::{Affine, Join, Rect, Shape, Stroke, Vec2};
+use peniko::{BlendMode, BrushRef, Color, Fill, Font, Image, Style, StyleRef};
+use vello_encoding::{
+    Encoding, ExternalImage, FilterLayer, Glyph, GlyphRun, Patch, StreamOffsets, Transform,
+};
 
 /// Encoded definition of a scene and associated resources.
 #[derive(Default)]
@@ -31,18 +37,62 @@ impl Scene {
         Self::default()
     }
 
+    /// Creates a scene from an encoding, such as one read from a trace.
+    pub(crate) fn from_data(data: Encoding) -> Self {
+        Self { data }
+    }
+
     /// Returns the raw encoded scene data streams.
     pub fn data(&self) -> &Encoding {
         &self.data
     }
+
+    /// Replaces the fragment appended in `slot` with `fragment`, drawn with an optional
+    /// transform, without encoding the rest of the scene again. The renderer only uploads the
+    /// parts of the scene that changed since its previous frame.
+    ///
+    /// The new fragment must have the same structure as the one it replaces: the same paths
+    /// with the same kinds of segments, and the same kinds of brushes. Only its points,
+    /// colors, transforms and styles may differ. Returns false if this isn't the case, or if
+    /// either fragment uses gradients, images or glyphs, and the scene is left unchanged.
+    ///
+    /// Slots are invalidated when the scene is built again.
+    pub fn update_slot(
+        &mut self,
+        slot: &FragmentSlot,
+        fragment: &SceneFragment,
+        transform: Option<Affine>,
+    ) -> bool {
+        self.data.patch(
+            &slot.offsets,
+            &fragment.data,
+            &transform.map(|xform| Transform::from_kurbo(&xform)),
+        )
+    }
+}
+
+/// Location of a fragment in a scene, which can be updated with [`Scene::update_slot`].
+#[derive(Copy, Clone, Debug)]
+pub struct FragmentSlot {
+    offsets: StreamOffsets,
 }
 
 /// Encoded definition of a scene fragment and associated resources.
+///
+/// Fragments are independent of each other, so the parts of a large scene can be built into
+/// separate fragments on multiple threads and then appended to the scene with
+/// [`SceneBuilder::append_all`].
 #[derive(Default)]
 pub struct SceneFragment {
     data: Encoding,
 }
 
+// Fragments are built on other threads and sent to the one that builds the scene.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<SceneFragment>();
+};
+
 impl SceneFragment {
     /// Creates a new scene fragment.
     pub fn new() -> Self {
@@ -64,9 +114,27 @@ impl SceneFragment {
     }
 }
 
+/// Filter applied to a fragment drawn with [`SceneBuilder::draw_filtered`].
+#[derive(Copy, Clone, Debug)]
+pub enum Filter {
+    /// Gaussian blur with the given standard deviation, in pixels.
+    Blur(f64),
+    /// Draws the fragment over its shadow, which is its alpha blurred with a standard deviation
+    /// of `std_dev` pixels, filled with `color` and moved by `offset`.
+    DropShadow {
+        std_dev: f64,
+        offset: Vec2,
+        color: Color,
+    },
+}
+
 /// Builder for constructing a scene or scene fragment.
 pub struct SceneBuilder<'a> {
     scene: &'a mut Encoding,
+    /// Size under which fills and strokes are replaced by a rectangle, if set.
+    collapse_size: Option<f64>,
+    /// Rectangle outside of which fills and strokes are dropped, if set.
+    cull_rect: Option<Rect>,
 }
 
 impl<'a> SceneBuilder<'a> {
@@ -85,7 +153,40 @@ impl<'a> SceneBuilder<'a> {
     /// Creates a new builder for constructing a scene.
     fn new(scene: &'a mut Encoding, is_fragment: bool) -> Self {
         scene.reset(is_fragment);
-        Self { scene }
+        Self {
+            scene,
+            collapse_size: None,
+            cull_rect: None,
+        }
+    }
+
+    /// Sets the rectangle, in the coordinates of the target, outside of which fills and
+    /// strokes are dropped.
+    ///
+    /// Shapes whose bounding box after their transform doesn't overlap `rect` aren't encoded
+    /// at all, so that a view over a large canvas only pays for what it shows. The GPU
+    /// already culls such geometry when flattening, but only after it is encoded and
+    /// uploaded. As with [`set_collapse_size`](Self::set_collapse_size), this uses the
+    /// transform of each draw, so fragments which are later appended with a transform should
+    /// be built with the rectangle mapped through its inverse.
+    ///
+    /// Set to `None`, the default, to encode every shape.
+    pub fn set_cull_rect(&mut self, rect: Option<Rect>) {
+        self.cull_rect = rect;
+    }
+
+    /// Sets the size in pixels under which fills and strokes are collapsed to a rectangle.
+    ///
+    /// Shapes whose bounding box after their transform is no wider or taller than `size`
+    /// are encoded as a rectangle at the center of that box, scaled so its area matches the
+    /// area covered by the shape. This keeps the average coverage of dense small geometry,
+    /// such as map detail at a low zoom, without flattening and tiling every curve of it.
+    /// The size is measured with the transform of each draw, so fragments which are later
+    /// appended with a scaling transform should be built with the size scaled to match.
+    ///
+    /// Set to `None`, the default, to encode every shape as is.
+    pub fn set_collapse_size(&mut self, size: Option<f64>) {
+        self.collapse_size = size;
     }
 
     /// Pushes a new layer bound by the specifed shape and composed with
@@ -124,6 +225,17 @@ impl<'a> SceneBuilder<'a> {
         brush_transform: Option<Affine>,
         shape: &impl Shape,
     ) {
+        let brush = brush.into();
+        let bbox = transform.transform_rect_bbox(shape.bounding_box());
+        if self.is_culled(bbox) {
+            return;
+        }
+        if let Some(rect) =
+            self.collapse(bbox, || shape.area().abs() * transform.determinant().abs())
+        {
+            self.fill_collapsed(rect, transform, brush, brush_transform);
+            return;
+        }
         self.scene
             .encode_transform(Transform::from_kurbo(&transform));
         self.scene.encode_fill_style(style);
@@ -149,6 +261,43 @@ impl<'a> SceneBuilder<'a> {
         brush_transform: Option<Affine>,
         shape: &impl Shape,
     ) {
+        if style.dash_pattern.is_empty() {
+            let brush = brush.into();
+            // Miter joins and square caps extend further than half the line width.
+            let extent = match style.join {
+                Join::Miter => style.miter_limit.max(SQRT_2),
+                _ => SQRT_2,
+            };
+            let radius = style.width * 0.5 * extent;
+            let bbox = transform.transform_rect_bbox(shape.bounding_box().inflate(radius, radius));
+            if self.is_culled(bbox) {
+                return;
+            }
+            if let Some(rect) = self.collapse(bbox, || {
+                shape.perimeter(0.1) * style.width * transform.determinant().abs()
+            }) {
+                self.fill_collapsed(rect, transform, brush, brush_transform);
+                return;
+            }
+            // Undashed strokes are expanded on the GPU, where the transform is known.
+            self.scene
+                .encode_transform(Transform::from_kurbo(&transform));
+            self.scene.encode_stroke_style(style);
+            if self.scene.encode_shape(shape, false) {
+                if let Some(brush_transform) = brush_transform {
+                    if self
+                        .scene
+                        .encode_transform(Transform::from_kurbo(&(transform * brush_transform)))
+                    {
+                        self.scene.swap_last_path_tags();
+                    }
+                }
+                self.scene.encode_brush(brush, 1.0);
+            }
+            return;
+        }
+        // Dashes are applied on the CPU, and the dashed stroke is then expanded into a fill.
+        //
         // The setting for tolerance are a compromise. For most applications,
         // shape tolerance doesn't matter, as the input is likely Bézier paths,
         // which is exact. Note that shape tolerance is hard-coded as 0.1 in
@@ -157,9 +306,7 @@ impl<'a> SceneBuilder<'a> {
         // Stroke tolerance is a different matter. Generally, the cost scales
         // with inverse O(n^6), so there is moderate rendering cost to setting
         // too fine a value. On the other hand, error scales with the transform
-        // applied post-stroking, so may exceed visible threshold. When we do
-        // GPU-side stroking, the transform will be known. In the meantime,
-        // this is a compromise.
+        // applied post-stroking, so may exceed visible threshold.
         const SHAPE_TOLERANCE: f64 = 0.01;
         const STROKE_TOLERANCE: f64 = SHAPE_TOLERANCE;
         let stroked = peniko::kurbo::stroke(
@@ -171,6 +318,97 @@ impl<'a> SceneBuilder<'a> {
         self.fill(Fill::NonZero, transform, brush, brush_transform, &stroked);
     }
 
+    /// Strokes a shape with a width that varies along the path, as for tapered strokes.
+    ///
+    /// `widths` has the width at the end point of each element of the path other than a
+    /// close, which is interpolated along each segment. Points past the end of `widths` use
+    /// its last width. The width and dash pattern of `style` are ignored, unless `widths` is
+    /// empty, in which case this is the same as [`stroke`](Self::stroke).
+    pub fn stroke_with_widths<'b>(
+        &mut self,
+        style: &Stroke,
+        transform: Affine,
+        brush: impl Into<BrushRef<'b>>,
+        brush_transform: Option<Affine>,
+        shape: &impl Shape,
+        widths: &[f64],
+    ) {
+        if widths.is_empty() {
+            self.stroke(style, transform, brush, brush_transform, shape);
+            return;
+        }
+        let max_width = widths.iter().copied().fold(0.0, f64::max);
+        self.scene
+            .encode_transform(Transform::from_kurbo(&transform));
+        self.scene
+            .encode_variable_width_stroke_style(style, max_width as f32);
+        let mut encoder = self.scene.encode_variable_width_path();
+        encoder.shape_with_widths(shape, widths.iter().map(|width| *width as f32));
+        if encoder.finish(true) != 0 {
+            if let Some(brush_transform) = brush_transform {
+                if self
+                    .scene
+                    .encode_transform(Transform::from_kurbo(&(transform * brush_transform)))
+                {
+                    self.scene.swap_last_path_tags();
+                }
+            }
+            self.scene.encode_brush(brush, 1.0);
+        }
+    }
+
+    /// Returns whether a shape with the given bounding box in device space lies wholly
+    /// outside the cull rectangle.
+    fn is_culled(&self, bbox: Rect) -> bool {
+        self.cull_rect.map_or(false, |rect| {
+            bbox.x0 >= rect.x1 || bbox.x1 <= rect.x0 || bbox.y0 >= rect.y1 || bbox.y1 <= rect.y0
+        })
+    }
+
+    /// Returns the rectangle which replaces a shape with the given bounding box in device
+    /// space, if collapsing is enabled and the shape is small enough.
+    ///
+    /// `area` returns the area covered by the shape in device space, and is only called for
+    /// shapes which are collapsed.
+    fn collapse(&self, bbox: Rect, area: impl FnOnce() -> f64) -> Option<Rect> {
+        let size = self.collapse_size?;
+        if !(bbox.width() <= size && bbox.height() <= size) {
+            return None;
+        }
+        let bbox_area = bbox.area();
+        let scale = if bbox_area > 0.0 {
+            (area() / bbox_area).clamp(0.0, 1.0).sqrt()
+        } else {
+            0.0
+        };
+        Some(Rect::from_center_size(bbox.center(), bbox.size() * scale))
+    }
+
+    /// Fills `rect`, given in device space, in place of a shape drawn with `transform`.
+    fn fill_collapsed(
+        &mut self,
+        rect: Rect,
+        transform: Affine,
+        brush: BrushRef,
+        brush_transform: Option<Affine>,
+    ) {
+        self.scene.encode_transform(Transform::IDENTITY);
+        self.scene.encode_fill_style(Fill::NonZero);
+        if self.scene.encode_shape(&rect, true) {
+            // Brushes other than solid colors are placed by the transform of the shape.
+            if !matches!(brush, BrushRef::Solid(_)) {
+                let brush_transform = transform * brush_transform.unwrap_or(Affine::IDENTITY);
+                if self
+                    .scene
+                    .encode_transform(Transform::from_kurbo(&brush_transform))
+                {
+                    self.scene.swap_last_path_tags();
+                }
+            }
+            self.scene.encode_brush(brush, 1.0);
+        }
+    }
+
     /// Draws an image at its natural size with the given transform.
     pub fn draw_image(&mut self, image: &Image, transform: Affine) {
         self.fill(
@@ -182,6 +420,103 @@ impl<'a> SceneBuilder<'a> {
         );
     }
 
+    /// Fills a shape with an external image, positioned with `brush_transform` like an image
+    /// brush.
+    ///
+    /// The image is drawn by the renderer which registered its texture; other renderers draw
+    /// it as transparent.
+    pub fn fill_external_image(
+        &mut self,
+        style: Fill,
+        transform: Affine,
+        image: &ExternalImage,
+        brush_transform: Option<Affine>,
+        shape: &impl Shape,
+    ) {
+        self.scene
+            .encode_transform(Transform::from_kurbo(&transform));
+        self.scene.encode_fill_style(style);
+        if self.scene.encode_shape(shape, true) {
+            if let Some(brush_transform) = brush_transform {
+                if self
+                    .scene
+                    .encode_transform(Transform::from_kurbo(&(transform * brush_transform)))
+                {
+                    self.scene.swap_last_path_tags();
+                }
+            }
+            self.scene.encode_external_image(image);
+        }
+    }
+
+    /// Draws an external image at its natural size with the given transform.
+    pub fn draw_external_image(&mut self, image: &ExternalImage, transform: Affine) {
+        self.fill_external_image(
+            Fill::NonZero,
+            transform,
+            image,
+            None,
+            &Rect::new(0.0, 0.0, image.width as f64, image.height as f64),
+        );
+    }
+
+    /// Draws a fragment with a filter applied to it.
+    ///
+    /// The part of the fragment inside `bounds` is rendered into an offscreen image, with one
+    /// pixel per unit of the fragment's coordinate space and a margin for the blur. The
+    /// filtered image is then drawn with `transform`, so the fragment should already be at
+    /// its final scale. Filtered images which don't fit in the image atlas are not drawn.
+    pub fn draw_filtered(
+        &mut self,
+        filter: Filter,
+        transform: Affine,
+        bounds: Rect,
+        fragment: &SceneFragment,
+    ) {
+        let (std_dev, shadow) = match filter {
+            Filter::Blur(std_dev) => (std_dev, None),
+            Filter::DropShadow {
+                std_dev,
+                offset,
+                color,
+            } => (std_dev, Some((offset, color))),
+        };
+        let std_dev = std_dev.max(0.0);
+        let margin = (3.0 * std_dev).ceil();
+        let bounds = bounds.inflate(margin, margin).expand();
+        if !(bounds.width() >= 1.0 && bounds.height() >= 1.0) {
+            return;
+        }
+        let mut encoding = Encoding::new();
+        encoding.append(
+            &fragment.data,
+            &Some(Transform::from_kurbo(&Affine::translate(
+                -bounds.origin().to_vec2(),
+            ))),
+        );
+        let layer = FilterLayer {
+            encoding,
+            width: bounds.width() as u32,
+            height: bounds.height() as u32,
+            std_dev: std_dev as f32,
+            color: shadow.map(|(_, color)| color),
+        };
+        let offset = shadow.map_or(Vec2::ZERO, |(offset, _)| offset);
+        let layer_transform = transform * Affine::translate(bounds.origin().to_vec2() + offset);
+        self.scene
+            .encode_transform(Transform::from_kurbo(&layer_transform));
+        self.scene.encode_fill_style(Fill::NonZero);
+        if self
+            .scene
+            .encode_shape(&Rect::new(0.0, 0.0, bounds.width(), bounds.height()), true)
+        {
+            self.scene.encode_filter_layer(layer);
+        }
+        if shadow.is_some() {
+            self.append(fragment, Some(transform));
+        }
+    }
+
     /// Returns a builder for encoding a glyph run.
     pub fn draw_glyphs(&mut self, font: &Font) -> DrawGlyphs {
         DrawGlyphs::new(self.scene, font)
@@ -194,6 +529,78 @@ impl<'a> SceneBuilder<'a> {
             &transform.map(|xform| Transform::from_kurbo(&xform)),
         );
     }
+
+    /// Appends fragments to the scene in order, all with the same optional transform.
+    ///
+    /// Space for all of the fragments is reserved up front, so this is cheaper than appending
+    /// them one at a time.
+    pub fn append_all<'f>(
+        &mut self,
+        fragments: impl IntoIterator<Item = &'f SceneFragment>,
+        transform: Option<Affine>,
+    ) {
+        let encodings: Vec<&Encoding> = fragments
+            .into_iter()
+            .map(|fragment| &fragment.data)
+            .collect();
+        self.scene.append_all(
+            &encodings,
+            &transform.map(|xform| Transform::from_kurbo(&xform)),
+        );
+    }
+
+    /// Appends a fragment to the scene inside a layer bound by `clip` and composed with the
+    /// specified blend mode and alpha.
+    ///
+    /// The transform applies to both the fragment and the clip shape. This is equivalent to
+    /// appending the fragment between [`push_layer`](Self::push_layer) and
+    /// [`pop_layer`](Self::pop_layer).
+    pub fn append_clipped(
+        &mut self,
+        fragment: &SceneFragment,
+        transform: Option<Affine>,
+        blend: impl Into<BlendMode>,
+        alpha: f32,
+        clip: &impl Shape,
+    ) {
+        self.push_layer(blend, alpha, transform.unwrap_or(Affine::IDENTITY), clip);
+        self.append(fragment, transform);
+        self.pop_layer();
+    }
+
+    /// Appends a fragment to the scene, returning a slot in which it can be replaced later.
+    pub fn append_slot(
+        &mut self,
+        fragment: &SceneFragment,
+        transform: Option<Affine>,
+    ) -> FragmentSlot {
+        let offsets = self.scene.stream_offsets();
+        self.append(fragment, transform);
+        FragmentSlot { offsets }
+    }
+
+    /// Appends a fragment to the scene once for each transform.
+    ///
+    /// The path data of the fragment is only stored once, and shared by all of its copies.
+    pub fn append_instances(
+        &mut self,
+        fragment: &SceneFragment,
+        transforms: impl IntoIterator<Item = Affine>,
+    ) {
+        let mut source = None;
+        for transform in transforms {
+            let transform = Some(Transform::from_kurbo(&transform));
+            match &source {
+                Some(source) => self
+                    .scene
+                    .append_instance(&fragment.data, &transform, source),
+                None => {
+                    source = Some(self.scene.stream_offsets());
+                    self.scene.append(&fragment.data, &transform);
+                }
+            }
+        }
+    }
 }
 
 /// Builder for encoding a glyph run.
@@ -258,6 +665,10 @@ impl<'a> DrawGlyphs<'a> {
 
     /// Sets whether to enable hinting.
     ///
+    /// Hinting fits the outlines to the pixel grid vertically, which keeps small text crisp.
+    /// It is only applied when the run transform is a uniform scale and translation, and the
+    /// glyph baselines are then rounded to whole pixels.
+    ///
     /// The default value is `false`.
     pub fn hint(mut self, hint: bool) -> Self {
         self.run.hint = hint;
@@ -298,20 +709,74 @@ impl<'a> DrawGlyphs<'a> {
     /// the builder.
     ///
     /// The `style` parameter accepts either `Fill` or `&Stroke` types.
+    ///
+    /// Filled glyphs with a color definition in the font are drawn with their own colors, on
+    /// top of the other glyphs of the run. The brush is used where they refer to the
+    /// foreground color. Fonts without outlines are drawn from their bitmap strikes, with the
+    /// `bitmap_glyphs` feature.
     pub fn draw(mut self, style: impl Into<StyleRef<'a>>, glyphs: impl Iterator<Item = Glyph>) {
         let resources = &mut self.encoding.resources;
         self.run.style = style.into().to_owned();
         resources.glyphs.extend(glyphs);
+        let font_ref = vello_encoding::font_ref(&self.run.font);
+        let bitmap_glyphs = font_ref
+            .clone()
+            .and_then(|font_ref| BitmapGlyphs::new(&self.run.font, font_ref));
+        let color_glyphs = font_ref
+            .and_then(ColorGlyphs::new)
+            .filter(|_| matches!(self.run.style, Style::Fill(_)));
+        let mut colored = vec![];
+        if bitmap_glyphs.is_some() || color_glyphs.is_some() {
+            let start = self.run.glyphs.start;
+            let mut glyphs = resources.glyphs.split_off(start);
+            glyphs.retain(|glyph| {
+                let is_color = bitmap_glyphs.is_some()
+                    || color_glyphs
+                        .as_ref()
+                        .map_or(false, |color_glyphs| color_glyphs.contains(glyph.id));
+                if is_color {
+                    colored.push(*glyph);
+                }
+                !is_color
+            });
+            resources.glyphs.extend(glyphs);
+        }
         self.run.glyphs.end = resources.glyphs.len();
+        let coords = resources.normalized_coords[self.run.normalized_coords.clone()].to_vec();
         if self.run.glyphs.is_empty() {
             resources
                 .normalized_coords
                 .truncate(self.run.normalized_coords.start);
+        } else {
+            let index = resources.glyph_runs.len();
+            resources.glyph_runs.push(self.run.clone());
+            resources.patches.push(Patch::GlyphRun { index });
+            self.encoding
+                .encode_brush(self.brush.clone(), self.brush_alpha);
+        }
+        if colored.is_empty() {
             return;
         }
-        let index = resources.glyph_runs.len();
-        resources.glyph_runs.push(self.run);
-        resources.patches.push(Patch::GlyphRun { index });
-        self.encoding.encode_brush(self.brush, self.brush_alpha);
+        if let Some(bitmap_glyphs) = bitmap_glyphs {
+            bitmap_glyphs.encode(
+                self.encoding,
+                self.run.transform,
+                self.run.glyph_transform,
+                self.run.font_size,
+                &colored,
+                self.brush_alpha,
+            );
+        } else if let Some(color_glyphs) = color_glyphs {
+            color_glyphs.encode(
+                self.encoding,
+                self.run.transform,
+                self.run.glyph_transform,
+                self.run.font_size,
+                &coords,
+                &colored,
+                self.brush,
+                self.brush_alpha,
+            );
+        }
     }
 }
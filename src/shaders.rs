@@ -17,8 +17,22 @@
 //! Load rendering shaders.
 
 mod preprocess;
+mod source;
 
-use std::collections::HashSet;
+pub use preprocess::SourceMap;
+#[cfg(not(target_arch = "wasm32"))]
+pub use source::FileShaders;
+pub use source::{EmbeddedShaders, ShaderSourceProvider, ShaderSources};
+
+use std::{
+    borrow::Cow,
+    collections::{BTreeSet, HashMap},
+    fmt,
+    str::FromStr,
+};
+
+#[cfg(feature = "wgpu")]
+use std::sync::Arc;
 
 #[cfg(feature = "wgpu")]
 use wgpu::Device;
@@ -26,44 +40,48 @@ use wgpu::Device;
 use crate::{
     cpu_shader,
     engine::{BindType, Error, ImageFormat, ShaderId},
+    AaConfig,
 };
 
 #[cfg(feature = "wgpu")]
-use crate::wgpu_engine::WgpuEngine;
-
-macro_rules! shader {
-    ($name:expr) => {&{
-        let shader = include_str!(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/shader/",
-            $name,
-            ".wgsl"
-        ));
-        #[cfg(feature = "hot_reload")]
-        let shader = std::fs::read_to_string(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/shader/",
-            $name,
-            ".wgsl"
-        ))
-        .unwrap_or_else(|e| {
-            eprintln!(
-                "Failed to read shader {name}, error falling back to version at compilation time. Error: {e:?}",
-                name = $name
-            );
-            shader.to_string()
-        });
-        shader
-    }};
+use crate::{wgpu_engine::WgpuEngine, Capabilities};
+
+/// Returns the directory that shader sources are read from when hot reloading.
+///
+/// The shaders are always embedded in the library at build time, so this is only consulted
+/// in development builds. It defaults to the `shader` directory of the source tree the crate
+/// was built from, which can be overridden with the `VELLO_SHADER_DIR` environment variable
+/// when that tree isn't present on the machine running the application.
+#[cfg(feature = "hot_reload")]
+pub fn shader_dir() -> std::path::PathBuf {
+    std::env::var_os("VELLO_SHADER_DIR")
+        .map(Into::into)
+        .unwrap_or_else(|| concat!(env!("CARGO_MANIFEST_DIR"), "/shader").into())
+}
+
+/// Returns the provider of the shader sources used when none is specified.
+///
+/// With hot reloading, the sources are read from [`shader_dir`], falling back to the versions
+/// embedded at compilation time.
+#[cfg(feature = "wgpu")]
+pub fn default_shader_source() -> Arc<dyn ShaderSourceProvider> {
+    #[cfg(all(feature = "hot_reload", not(target_arch = "wasm32")))]
+    let source = Arc::new(FileShaders::new(shader_dir()));
+    #[cfg(not(all(feature = "hot_reload", not(target_arch = "wasm32"))))]
+    let source = Arc::new(EmbeddedShaders);
+    source
 }
 
 // Shaders for the full pipeline
+#[derive(Clone)]
 pub struct FullShaders {
     pub pathtag_reduce: ShaderId,
     pub pathtag_reduce2: ShaderId,
     pub pathtag_scan1: ShaderId,
     pub pathtag_scan: ShaderId,
     pub pathtag_scan_large: ShaderId,
+    /// Single pass scan of the path tags with a decoupled look-back, if enabled.
+    pub pathtag_scan_lookback: Option<ShaderId>,
     pub bbox_clear: ShaderId,
     pub flatten: ShaderId,
     pub draw_reduce: ShaderId,
@@ -79,91 +97,693 @@ pub struct FullShaders {
     pub path_tiling_setup: ShaderId,
     pub path_tiling: ShaderId,
     pub fine: ShaderId,
+    /// Pass of the Gaussian blur applied to filter layers.
+    pub blur: ShaderId,
+    /// Shaders for skipping tiles without commands in fine rasterization, if enabled.
+    pub compact: Option<CompactShaders>,
+    /// Fine rasterization with the tile and bin debug overlay, if enabled.
+    pub debug_fine: Option<ShaderId>,
+    /// Shaders for scenes which only have color fills.
+    pub simple: SimpleShaders,
+    /// The antialiasing method the fine shader was built with.
+    pub aa_config: AaConfig,
+    /// The format of the output image written by fine rasterization.
+    pub output_format: ImageFormat,
     // 2-level dispatch works for CPU pathtag scan even for large
     // inputs, 3-level is not yet implemented.
     pub pathtag_is_cpu: bool,
+    /// The workgroup sizes of the tunable stages the shaders were built with.
+    pub workgroup_sizes: WorkgroupSizes,
+}
+
+/// Workgroup sizes of the stages whose best size depends on the adapter.
+///
+/// These can be chosen by benchmarking with
+/// [`Renderer::calibrate_workgroup_sizes`](crate::Renderer::calibrate_workgroup_sizes). The
+/// result can be cached across runs in its string form, for example
+/// `flatten=128,path_count=256`, and restored with
+/// [`Renderer::set_workgroup_sizes`](crate::Renderer::set_workgroup_sizes).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct WorkgroupSizes {
+    /// Workgroup size of the flattening stage.
+    pub flatten: u32,
+    /// Workgroup size of the path count and path tiling stages.
+    pub path_count: u32,
+}
+
+impl WorkgroupSizes {
+    /// The sizes supported for each stage.
+    pub const SUPPORTED: &'static [u32] = &[64, 128, 256];
+
+    /// Returns every combination of supported sizes.
+    pub fn candidates() -> impl Iterator<Item = Self> {
+        Self::SUPPORTED.iter().flat_map(|&flatten| {
+            Self::SUPPORTED.iter().map(move |&path_count| Self {
+                flatten,
+                path_count,
+            })
+        })
+    }
+
+    /// Checks that each size is supported.
+    pub fn validate(&self) -> Result<(), WorkgroupSizesError> {
+        for (stage, size) in [("flatten", self.flatten), ("path_count", self.path_count)] {
+            if !Self::SUPPORTED.contains(&size) {
+                return Err(WorkgroupSizesError::Unsupported { stage, size });
+            }
+        }
+        Ok(())
+    }
+
+    /// The constants substituted into the shader sources for these sizes.
+    fn constants(&self) -> Vec<(&'static str, u32)> {
+        vello_encoding::WORKGROUP_SIZES
+            .iter()
+            .map(|&(name, value)| match name {
+                "FLATTEN_WG" => (name, self.flatten),
+                "PATH_COUNT_WG" => (name, self.path_count),
+                _ => (name, value),
+            })
+            .collect()
+    }
+}
+
+impl Default for WorkgroupSizes {
+    fn default() -> Self {
+        Self {
+            flatten: vello_encoding::FLATTEN_WG,
+            path_count: vello_encoding::PATH_COUNT_WG,
+        }
+    }
 }
 
+impl fmt::Display for WorkgroupSizes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "flatten={},path_count={}", self.flatten, self.path_count)
+    }
+}
+
+impl FromStr for WorkgroupSizes {
+    type Err = WorkgroupSizesError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut sizes = Self::default();
+        for entry in s
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+        {
+            let parsed = entry
+                .split_once('=')
+                .and_then(|(stage, size)| Some((stage.trim(), size.trim().parse().ok()?)));
+            match parsed {
+                Some(("flatten", size)) => sizes.flatten = size,
+                Some(("path_count", size)) => sizes.path_count = size,
+                _ => return Err(WorkgroupSizesError::Parse(entry.to_string())),
+            }
+        }
+        sizes.validate()?;
+        Ok(sizes)
+    }
+}
+
+/// Errors of [`WorkgroupSizes`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WorkgroupSizesError {
+    /// An entry of the string form isn't a known stage and a size.
+    Parse(String),
+    /// The size of a stage isn't one of [`WorkgroupSizes::SUPPORTED`].
+    Unsupported { stage: &'static str, size: u32 },
+}
+
+impl fmt::Display for WorkgroupSizesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Parse(entry) => write!(f, "invalid workgroup size entry `{entry}`"),
+            Self::Unsupported { stage, size } => {
+                write!(f, "unsupported workgroup size {size} for the {stage} stage")
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorkgroupSizesError {}
+
+/// Shaders which list the tiles that have commands, so that fine rasterization is only
+/// dispatched for those.
+#[derive(Clone, Copy)]
+pub struct CompactShaders {
+    pub tile_compact: ShaderId,
+    /// Fine rasterization of the listed tiles, with an indirect dispatch.
+    pub fine: ShaderId,
+}
+
+/// Variants of the draw and coarse stages which skip the handling of clips, layers,
+/// gradients and images, for scenes which only have color fills.
+#[derive(Clone, Copy)]
+pub struct SimpleShaders {
+    pub draw_leaf: ShaderId,
+    pub coarse: ShaderId,
+}
+
+/// Describes one permutation of a shader in the `shader` directory.
+#[derive(Clone, Copy)]
+pub struct ShaderVariant {
+    /// Name of the shader source, without the `.wgsl` extension.
+    pub name: &'static str,
+    /// The preprocessor definitions used to build this permutation.
+    pub defines: &'static [&'static str],
+    /// The types of the resources bound by the shader, in binding order.
+    pub layout: &'static [BindType],
+    /// Whether the storage textures of the shader are the output image, so that they are
+    /// built for the output format of the registry. Other storage textures, such as the
+    /// image atlas, keep the format of the layout.
+    pub writes_output: bool,
+}
+
+/// Builds shader variants on demand.
+///
+/// Each permutation is only compiled once: requesting a variant with the same source and set of
+/// defines as one built previously returns the existing shader.
 #[cfg(feature = "wgpu")]
-pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShaders, Error> {
-    use crate::ANTIALIASING;
-
-    let imports = SHARED_SHADERS
-        .iter()
-        .copied()
-        .collect::<std::collections::HashMap<_, _>>();
-    let empty = HashSet::new();
-    let mut full_config = HashSet::new();
-    full_config.insert("full".into());
-    match crate::ANTIALIASING {
-        crate::AaConfig::Msaa16 => {
-            full_config.insert("msaa".into());
-            full_config.insert("msaa16".into());
+pub struct ShaderRegistry {
+    source: Arc<dyn ShaderSourceProvider>,
+    imports: HashMap<&'static str, Cow<'static, str>>,
+    /// Definitions added to every variant built by this registry.
+    defines: Vec<&'static str>,
+    /// Format of the storage textures written by the shaders.
+    output_format: ImageFormat,
+    /// Workgroup sizes substituted into the shader sources.
+    constants: Vec<(&'static str, u32)>,
+    variants: HashMap<(&'static str, BTreeSet<&'static str>), ShaderId>,
+}
+
+#[cfg(feature = "wgpu")]
+impl ShaderRegistry {
+    /// Creates a registry which builds shaders from the sources of `source`.
+    pub fn new(source: Arc<dyn ShaderSourceProvider>) -> Self {
+        let imports = EmbeddedShaders::import_names()
+            .filter_map(|name| Some((name, source.import(name)?)))
+            .collect();
+        let mut defines = vec![];
+        // Guard the stores into bump allocated buffers whose stages don't check for overflow,
+        // for debugging on drivers without robust buffer access.
+        if cfg!(feature = "bounds_checks") {
+            defines.push("bounds_checks");
+        }
+        // Bind the log of the shaders which import `debug`, see debug.wgsl.
+        if cfg!(feature = "debug_log") {
+            defines.push("debug_log");
         }
-        crate::AaConfig::Msaa8 => {
-            full_config.insert("msaa".into());
-            full_config.insert("msaa8".into());
+        Self {
+            source,
+            imports,
+            defines,
+            output_format: ImageFormat::Rgba8,
+            constants: vello_encoding::WORKGROUP_SIZES.to_vec(),
+            variants: HashMap::new(),
         }
-        crate::AaConfig::Area => (),
     }
-    let mut small_config = HashSet::new();
-    small_config.insert("full".into());
-    small_config.insert("small".into());
-    let pathtag_reduce = engine.add_shader(
-        device,
-        "pathtag_reduce",
-        preprocess::preprocess(shader!("pathtag_reduce"), &full_config, &imports).into(),
-        &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
-    )?;
-    let pathtag_reduce2 = engine.add_shader(
-        device,
-        "pathtag_reduce2",
-        preprocess::preprocess(shader!("pathtag_reduce2"), &full_config, &imports).into(),
-        &[BindType::BufReadOnly, BindType::Buffer],
-    )?;
-    let pathtag_scan1 = engine.add_shader(
-        device,
-        "pathtag_scan1",
-        preprocess::preprocess(shader!("pathtag_scan1"), &full_config, &imports).into(),
-        &[
+
+    /// Sets the sizes of the tunable stages of the variants built afterwards.
+    pub fn set_workgroup_sizes(&mut self, sizes: WorkgroupSizes) {
+        self.constants = sizes.constants();
+        // Variants are cached by their defines, which don't include the sizes.
+        self.variants.clear();
+    }
+
+    /// Sets the format of the output image written by the variants built afterwards, see
+    /// [`ShaderVariant::writes_output`].
+    pub fn set_output_format(&mut self, format: ImageFormat) {
+        self.output_format = format;
+    }
+
+    /// Returns the shader for `variant`, preprocessing and adding it to the engine if it hasn't
+    /// been built yet.
+    pub fn get(
+        &mut self,
+        device: &Device,
+        engine: &mut WgpuEngine,
+        variant: &ShaderVariant,
+    ) -> Result<ShaderId, Error> {
+        // Shaders which write to the output use the configured format for it.
+        let output_define = variant.writes_output.then(|| self.output_format.define());
+        let key: (_, BTreeSet<_>) = (
+            variant.name,
+            variant
+                .defines
+                .iter()
+                .chain(&self.defines)
+                .chain(&output_define)
+                .copied()
+                .collect(),
+        );
+        if let Some(id) = self.variants.get(&key) {
+            return Ok(*id);
+        }
+        let (source, wgsl, source_map) = self.preprocess(variant.name, &key.1)?;
+        #[cfg(not(feature = "debug_log"))]
+        let _ = source;
+        let layout: Vec<_> = variant
+            .layout
+            .iter()
+            .map(|ty| match ty {
+                BindType::Image(_) if variant.writes_output => BindType::Image(self.output_format),
+                ty => *ty,
+            })
+            .collect();
+        #[cfg(feature = "debug_log")]
+        if source.contains("#import debug") {
+            let id = engine.add_debug_log_shader(
+                device,
+                variant.name,
+                wgsl.into(),
+                Some(&source_map),
+                &layout,
+            )?;
+            self.variants.insert(key, id);
+            return Ok(id);
+        }
+        let id = engine.add_shader(
+            device,
+            variant.name,
+            wgsl.into(),
+            Some(&source_map),
+            &layout,
+        )?;
+        self.variants.insert(key, id);
+        Ok(id)
+    }
+
+    /// Preprocesses the shader `name` with `defines` and substitutes the workgroup sizes,
+    /// returning its original source, the WGSL and the source map of the WGSL.
+    fn preprocess(
+        &self,
+        name: &str,
+        defines: &BTreeSet<&str>,
+    ) -> Result<(Cow<'static, str>, String, SourceMap), Error> {
+        let source = self
+            .source
+            .shader(name)
+            .ok_or_else(|| format!("unknown shader `{name}`"))?;
+        let defines = defines.iter().map(|d| d.to_string()).collect();
+        let imports = self
+            .imports
+            .iter()
+            .map(|(name, source)| (*name, source.as_ref()))
+            .collect();
+        // Keep a source map so that compile errors can point at the original shader file
+        // rather than the spliced output.
+        let (wgsl, source_map) =
+            preprocess::preprocess_with_source_map(name, &source, &defines, &imports);
+        let wgsl = preprocess::substitute_constants(&wgsl, &self.constants);
+        Ok((source, wgsl, source_map))
+    }
+
+    /// Checks that the shader `name` compiles with `defines`, without adding it to the engine.
+    #[cfg(not(target_arch = "wasm32"))]
+    fn validate(
+        &self,
+        device: &Device,
+        name: &'static str,
+        defines: &BTreeSet<&str>,
+    ) -> Result<(), Error> {
+        let (_, wgsl, source_map) = self.preprocess(name, defines)?;
+        WgpuEngine::validate_shader(device, name, wgsl.into(), Some(&source_map))
+    }
+}
+
+/// Checks that every shader variant the library builds compiles, with the sources of
+/// `shader_source` and the shader compiler of `device`, without creating any pipelines.
+///
+/// This covers each variant of [`variants::ALL`], those which write the output image in
+/// each output format, and each permutation listed in `shader/permutations`. Variants are
+/// checked with their own definitions only, so the `bounds_checks` feature is covered by its
+/// permutations rather than by the features this crate was built with. Returns the name and
+/// error of each variant which fails to compile.
+#[cfg(all(feature = "wgpu", not(target_arch = "wasm32")))]
+pub fn validate_shaders(
+    device: &Device,
+    shader_source: Arc<dyn ShaderSourceProvider>,
+) -> Vec<(String, Error)> {
+    let mut registry = ShaderRegistry::new(shader_source);
+    // The features of this build only add definitions which the variants don't declare.
+    registry.defines.clear();
+    let mut checked = BTreeSet::new();
+    let mut failures = vec![];
+    let mut validate = |name: &'static str, defines: BTreeSet<&'static str>| {
+        if !checked.insert((name, defines.clone())) {
+            return;
+        }
+        if let Err(error) = registry.validate(device, name, &defines) {
+            let defines: Vec<_> = defines.into_iter().collect();
+            failures.push((format!("{name}[{}]", defines.join(",")), error));
+        }
+    };
+    let formats = [
+        ImageFormat::Rgba8,
+        ImageFormat::Bgra8,
+        ImageFormat::Rgba16Float,
+    ];
+    for variant in variants::ALL {
+        let defines = variant.defines.iter().copied();
+        if variant.writes_output {
+            for format in formats {
+                validate(
+                    variant.name,
+                    defines.clone().chain([format.define()]).collect(),
+                );
+            }
+        } else {
+            validate(variant.name, defines.collect());
+        }
+    }
+    for (name, defines) in source::permutations() {
+        validate(name, defines.into_iter().chain(["full"]).collect());
+    }
+    failures
+}
+
+#[cfg(feature = "wgpu")]
+impl Default for ShaderRegistry {
+    fn default() -> Self {
+        Self::new(default_shader_source())
+    }
+}
+
+/// The variants of the full pipeline to build, see [`full_shaders`].
+#[derive(Clone, Copy, Debug)]
+pub struct FullShadersOptions {
+    /// Whether fine rasterization skips tiles without commands.
+    pub compact_tiles: bool,
+    /// The antialiasing method of fine rasterization.
+    pub aa_config: AaConfig,
+    /// The format of the output image written by fine rasterization.
+    pub output_format: ImageFormat,
+    /// The workgroup sizes of the tunable stages.
+    pub workgroup_sizes: WorkgroupSizes,
+    /// Whether the path tags are scanned in a single pass with a decoupled look-back.
+    pub single_pass_scan: bool,
+    /// Whether fine rasterization draws the tile and bin debug overlay.
+    pub debug_overlay: bool,
+}
+
+/// Builds the shaders of the full pipeline with the variants selected by `options`.
+///
+/// Options whose variants don't fit `capabilities` are left out, and an antialiasing method
+/// which doesn't fit falls back to area antialiasing; [`FullShaders::options`] returns the
+/// variants which were actually built.
+#[cfg(feature = "wgpu")]
+pub fn full_shaders(
+    device: &Device,
+    engine: &mut WgpuEngine,
+    options: &FullShadersOptions,
+    source: &Arc<dyn ShaderSourceProvider>,
+    capabilities: &Capabilities,
+) -> Result<FullShaders, Error> {
+    let FullShadersOptions {
+        compact_tiles,
+        aa_config,
+        output_format,
+        workgroup_sizes,
+        single_pass_scan,
+        debug_overlay,
+    } = *options;
+    let mut registry = ShaderRegistry::new(source.clone());
+    registry.set_workgroup_sizes(workgroup_sizes);
+    registry.set_output_format(output_format);
+    let mut get = |variant: &ShaderVariant| registry.get(device, engine, variant);
+    let supports = |variant: &ShaderVariant| capabilities.supports_variant(variant);
+    // Variants which don't fit the device fall back to area antialiasing, which every
+    // device that runs the pipeline supports.
+    let aa_config = if supports(variants::fine(aa_config)) {
+        aa_config
+    } else {
+        AaConfig::Area
+    };
+    let fine = variants::fine(aa_config);
+    // The tile list takes the binding the multisampled variants use for the mask LUT, so
+    // compaction is only supported with area antialiasing.
+    let compact = if compact_tiles
+        && aa_config == AaConfig::Area
+        && supports(&variants::TILE_COMPACT)
+        && supports(&variants::FINE_AREA_COMPACT)
+    {
+        Some(CompactShaders {
+            tile_compact: get(&variants::TILE_COMPACT)?,
+            fine: get(&variants::FINE_AREA_COMPACT)?,
+        })
+    } else {
+        None
+    };
+    // The overlay is only drawn with area antialiasing, for the same reason as compaction.
+    let debug_fine = if debug_overlay
+        && aa_config == AaConfig::Area
+        && supports(&variants::FINE_AREA_DEBUG_OVERLAY)
+    {
+        Some(get(&variants::FINE_AREA_DEBUG_OVERLAY)?)
+    } else {
+        None
+    };
+    let pathtag_scan_lookback = if single_pass_scan && supports(&variants::PATHTAG_SCAN_LOOKBACK) {
+        Some(get(&variants::PATHTAG_SCAN_LOOKBACK)?)
+    } else {
+        None
+    };
+    Ok(FullShaders {
+        pathtag_reduce: get(&variants::PATHTAG_REDUCE)?,
+        pathtag_reduce2: get(&variants::PATHTAG_REDUCE2)?,
+        pathtag_scan1: get(&variants::PATHTAG_SCAN1)?,
+        pathtag_scan: get(&variants::PATHTAG_SCAN_SMALL)?,
+        pathtag_scan_large: get(&variants::PATHTAG_SCAN_LARGE)?,
+        pathtag_scan_lookback,
+        bbox_clear: get(&variants::BBOX_CLEAR)?,
+        flatten: get(&variants::FLATTEN)?,
+        draw_reduce: get(&variants::DRAW_REDUCE)?,
+        draw_leaf: get(&variants::DRAW_LEAF)?,
+        clip_reduce: get(&variants::CLIP_REDUCE)?,
+        clip_leaf: get(&variants::CLIP_LEAF)?,
+        binning: get(&variants::BINNING)?,
+        tile_alloc: get(&variants::TILE_ALLOC)?,
+        path_count_setup: get(&variants::PATH_COUNT_SETUP)?,
+        path_count: get(&variants::PATH_COUNT)?,
+        backdrop: get(&variants::BACKDROP)?,
+        coarse: get(&variants::COARSE)?,
+        path_tiling_setup: get(&variants::PATH_TILING_SETUP)?,
+        path_tiling: get(&variants::PATH_TILING)?,
+        fine: get(fine)?,
+        blur: get(&variants::BLUR)?,
+        compact,
+        debug_fine,
+        simple: SimpleShaders {
+            draw_leaf: get(&variants::DRAW_LEAF_SIMPLE)?,
+            coarse: get(&variants::COARSE_SIMPLE)?,
+        },
+        aa_config,
+        output_format,
+        pathtag_is_cpu: false,
+        workgroup_sizes,
+    })
+}
+
+#[cfg(feature = "wgpu")]
+impl FullShaders {
+    /// Returns the options which build these shaders again.
+    pub fn options(&self) -> FullShadersOptions {
+        FullShadersOptions {
+            compact_tiles: self.compact.is_some(),
+            aa_config: self.aa_config,
+            output_format: self.output_format,
+            workgroup_sizes: self.workgroup_sizes,
+            single_pass_scan: self.pathtag_scan_lookback.is_some(),
+            debug_overlay: self.debug_fine.is_some(),
+        }
+    }
+
+    /// Install the CPU shaders.
+    ///
+    /// There are a couple things to note here. The granularity provided by
+    /// this method is coarse; it installs all the shaders. There are many
+    /// use cases (including debugging), where a mix is desired, or the
+    /// choice between GPU and CPU dispatch might be dynamic.
+    ///
+    /// Second, the actual mapping to CPU shaders is not really specific to
+    /// the engine, and should be split out into a back-end agnostic struct.
+    pub fn install_cpu_shaders(&mut self, engine: &mut WgpuEngine) {
+        engine.set_cpu_shader(self.pathtag_reduce, cpu_shader::pathtag_reduce);
+        engine.set_cpu_shader(self.pathtag_scan, cpu_shader::pathtag_scan);
+        engine.set_cpu_shader(self.bbox_clear, cpu_shader::bbox_clear);
+        engine.set_cpu_shader(self.flatten, cpu_shader::flatten);
+        engine.set_cpu_shader(self.draw_reduce, cpu_shader::draw_reduce);
+        engine.set_cpu_shader(self.draw_leaf, cpu_shader::draw_leaf);
+        engine.set_cpu_shader(self.simple.draw_leaf, cpu_shader::draw_leaf);
+        engine.set_cpu_shader(self.clip_reduce, cpu_shader::clip_reduce);
+        engine.set_cpu_shader(self.clip_leaf, cpu_shader::clip_leaf);
+        engine.set_cpu_shader(self.binning, cpu_shader::binning);
+        engine.set_cpu_shader(self.tile_alloc, cpu_shader::tile_alloc);
+        engine.set_cpu_shader(self.path_count_setup, cpu_shader::path_count_setup);
+        engine.set_cpu_shader(self.path_count, cpu_shader::path_count);
+        engine.set_cpu_shader(self.backdrop, cpu_shader::backdrop);
+        engine.set_cpu_shader(self.coarse, cpu_shader::coarse);
+        engine.set_cpu_shader(self.simple.coarse, cpu_shader::coarse);
+        engine.set_cpu_shader(self.path_tiling_setup, cpu_shader::path_tiling_setup);
+        engine.set_cpu_shader(self.path_tiling, cpu_shader::path_tiling);
+        self.pathtag_is_cpu = true;
+    }
+
+    /// Install the CPU implementations of fine rasterization and the blur of filter layers.
+    ///
+    /// Together with [`FullShaders::install_cpu_shaders`], this allows rendering entirely on
+    /// the CPU. Only area antialiasing is implemented, without the compaction of tiles or the
+    /// debug overlay, so the shaders must have been built without those.
+    pub fn install_cpu_raster_shaders(&self, engine: &mut WgpuEngine) {
+        engine.set_cpu_shader(self.fine, cpu_shader::fine);
+        engine.set_cpu_shader(self.blur, cpu_shader::blur);
+    }
+}
+
+/// The shader permutations used by the full pipeline.
+pub mod variants {
+    use super::ShaderVariant;
+    use crate::engine::{BindType, ImageFormat};
+    use crate::AaConfig;
+
+    /// The variants every render dispatches, with area antialiasing.
+    pub const REQUIRED: &[ShaderVariant] = &[
+        PATHTAG_REDUCE,
+        PATHTAG_REDUCE2,
+        PATHTAG_SCAN1,
+        PATHTAG_SCAN_SMALL,
+        PATHTAG_SCAN_LARGE,
+        BBOX_CLEAR,
+        FLATTEN,
+        DRAW_REDUCE,
+        DRAW_LEAF,
+        DRAW_LEAF_SIMPLE,
+        CLIP_REDUCE,
+        CLIP_LEAF,
+        BINNING,
+        TILE_ALLOC,
+        PATH_COUNT_SETUP,
+        PATH_COUNT,
+        BACKDROP,
+        COARSE,
+        COARSE_SIMPLE,
+        PATH_TILING_SETUP,
+        PATH_TILING,
+        FINE_AREA,
+        BLUR,
+    ];
+
+    /// Every variant the library builds, including those of optional features.
+    pub const ALL: &[ShaderVariant] = &[
+        PATHTAG_REDUCE,
+        PATHTAG_REDUCE2,
+        PATHTAG_SCAN1,
+        PATHTAG_SCAN_SMALL,
+        PATHTAG_SCAN_LARGE,
+        PATHTAG_SCAN_LOOKBACK,
+        BBOX_CLEAR,
+        FLATTEN,
+        DRAW_REDUCE,
+        DRAW_LEAF,
+        DRAW_LEAF_SIMPLE,
+        CLIP_REDUCE,
+        CLIP_LEAF,
+        BINNING,
+        TILE_ALLOC,
+        PATH_COUNT_SETUP,
+        PATH_COUNT,
+        BACKDROP,
+        COARSE,
+        COARSE_SIMPLE,
+        PATH_TILING_SETUP,
+        PATH_TILING,
+        TILE_COMPACT,
+        FINE_AREA,
+        FINE_AREA_COMPACT,
+        FINE_AREA_DEBUG_OVERLAY,
+        BLUR,
+        FINE_ALIASED,
+        FINE_AREA_SUBPIXEL,
+        FINE_MSAA8,
+        FINE_MSAA16,
+        FINE_MSAA8_RESOLVE,
+    ];
+
+    /// Returns the fine rasterization variant for `aa_config`.
+    pub fn fine(aa_config: AaConfig) -> &'static ShaderVariant {
+        match aa_config {
+            AaConfig::Area => &FINE_AREA,
+            AaConfig::Msaa8 => &FINE_MSAA8,
+            AaConfig::Msaa16 => &FINE_MSAA16,
+            AaConfig::Msaa8Resolve => &FINE_MSAA8_RESOLVE,
+            AaConfig::Aliased => &FINE_ALIASED,
+            AaConfig::AreaSubpixel => &FINE_AREA_SUBPIXEL,
+        }
+    }
+
+    pub const PATHTAG_REDUCE: ShaderVariant = ShaderVariant {
+        name: "pathtag_reduce",
+        defines: &["full"],
+        layout: &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
+        writes_output: false,
+    };
+    pub const PATHTAG_REDUCE2: ShaderVariant = ShaderVariant {
+        name: "pathtag_reduce2",
+        defines: &["full"],
+        layout: &[BindType::BufReadOnly, BindType::Buffer],
+        writes_output: false,
+    };
+    pub const PATHTAG_SCAN1: ShaderVariant = ShaderVariant {
+        name: "pathtag_scan1",
+        defines: &["full"],
+        layout: &[
             BindType::BufReadOnly,
             BindType::BufReadOnly,
             BindType::Buffer,
         ],
-    )?;
-    let pathtag_scan = engine.add_shader(
-        device,
-        "pathtag_scan",
-        preprocess::preprocess(shader!("pathtag_scan"), &small_config, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    const PATHTAG_SCAN_LAYOUT: &[BindType] = &[
+        BindType::Uniform,
+        BindType::BufReadOnly,
+        BindType::BufReadOnly,
+        BindType::Buffer,
+    ];
+    pub const PATHTAG_SCAN_SMALL: ShaderVariant = ShaderVariant {
+        name: "pathtag_scan",
+        defines: &["full", "small"],
+        layout: PATHTAG_SCAN_LAYOUT,
+        writes_output: false,
+    };
+    pub const PATHTAG_SCAN_LARGE: ShaderVariant = ShaderVariant {
+        name: "pathtag_scan",
+        defines: &["full"],
+        layout: PATHTAG_SCAN_LAYOUT,
+        writes_output: false,
+    };
+    pub const PATHTAG_SCAN_LOOKBACK: ShaderVariant = ShaderVariant {
+        name: "pathtag_scan",
+        defines: &["full", "lookback"],
+        layout: &[
             BindType::Uniform,
             BindType::BufReadOnly,
-            BindType::BufReadOnly,
             BindType::Buffer,
-        ],
-    )?;
-    let pathtag_scan_large = engine.add_shader(
-        device,
-        "pathtag_scan",
-        preprocess::preprocess(shader!("pathtag_scan"), &full_config, &imports).into(),
-        &[
-            BindType::Uniform,
-            BindType::BufReadOnly,
-            BindType::BufReadOnly,
             BindType::Buffer,
         ],
-    )?;
-    let bbox_clear = engine.add_shader(
-        device,
-        "bbox_clear",
-        preprocess::preprocess(shader!("bbox_clear"), &empty, &imports).into(),
-        &[BindType::Uniform, BindType::Buffer],
-    )?;
-    let flatten = engine.add_shader(
-        device,
-        "flatten",
-        preprocess::preprocess(shader!("flatten"), &full_config, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const BBOX_CLEAR: ShaderVariant = ShaderVariant {
+        name: "bbox_clear",
+        defines: &[],
+        layout: &[BindType::Uniform, BindType::Buffer],
+        writes_output: false,
+    };
+    pub const FLATTEN: ShaderVariant = ShaderVariant {
+        name: "flatten",
+        defines: &["full"],
+        layout: &[
             BindType::Uniform,
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -171,18 +791,18 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let draw_reduce = engine.add_shader(
-        device,
-        "draw_reduce",
-        preprocess::preprocess(shader!("draw_reduce"), &empty, &imports).into(),
-        &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
-    )?;
-    let draw_leaf = engine.add_shader(
-        device,
-        "draw_leaf",
-        preprocess::preprocess(shader!("draw_leaf"), &empty, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const DRAW_REDUCE: ShaderVariant = ShaderVariant {
+        name: "draw_reduce",
+        defines: &[],
+        layout: &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
+        writes_output: false,
+    };
+    pub const DRAW_LEAF: ShaderVariant = ShaderVariant {
+        name: "draw_leaf",
+        defines: &[],
+        layout: &[
             BindType::Uniform,
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -191,23 +811,29 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let clip_reduce = engine.add_shader(
-        device,
-        "clip_reduce",
-        preprocess::preprocess(shader!("clip_reduce"), &empty, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const DRAW_LEAF_SIMPLE: ShaderVariant = ShaderVariant {
+        name: "draw_leaf",
+        defines: &["simple"],
+        layout: DRAW_LEAF.layout,
+        writes_output: false,
+    };
+    pub const CLIP_REDUCE: ShaderVariant = ShaderVariant {
+        name: "clip_reduce",
+        defines: &[],
+        layout: &[
             BindType::BufReadOnly,
             BindType::BufReadOnly,
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let clip_leaf = engine.add_shader(
-        device,
-        "clip_leaf",
-        preprocess::preprocess(shader!("clip_leaf"), &empty, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const CLIP_LEAF: ShaderVariant = ShaderVariant {
+        name: "clip_leaf",
+        defines: &[],
+        layout: &[
             BindType::Uniform,
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -216,12 +842,12 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let binning = engine.add_shader(
-        device,
-        "binning",
-        preprocess::preprocess(shader!("binning"), &empty, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const BINNING: ShaderVariant = ShaderVariant {
+        name: "binning",
+        defines: &[],
+        layout: &[
             BindType::Uniform,
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -231,12 +857,12 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let tile_alloc = engine.add_shader(
-        device,
-        "tile_alloc",
-        preprocess::preprocess(shader!("tile_alloc"), &empty, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const TILE_ALLOC: ShaderVariant = ShaderVariant {
+        name: "tile_alloc",
+        defines: &[],
+        layout: &[
             BindType::Uniform,
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -244,18 +870,18 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let path_count_setup = engine.add_shader(
-        device,
-        "path_count_setup",
-        preprocess::preprocess(shader!("path_count_setup"), &empty, &imports).into(),
-        &[BindType::BufReadOnly, BindType::Buffer],
-    )?;
-    let path_count = engine.add_shader(
-        device,
-        "path_count",
-        preprocess::preprocess(shader!("path_count"), &full_config, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const PATH_COUNT_SETUP: ShaderVariant = ShaderVariant {
+        name: "path_count_setup",
+        defines: &[],
+        layout: &[BindType::BufReadOnly, BindType::Buffer],
+        writes_output: false,
+    };
+    pub const PATH_COUNT: ShaderVariant = ShaderVariant {
+        name: "path_count",
+        defines: &["full"],
+        layout: &[
             BindType::Uniform,
             BindType::Buffer,
             BindType::BufReadOnly,
@@ -263,18 +889,18 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let backdrop = engine.add_shader(
-        device,
-        "backdrop_dyn",
-        preprocess::preprocess(shader!("backdrop_dyn"), &empty, &imports).into(),
-        &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
-    )?;
-    let coarse = engine.add_shader(
-        device,
-        "coarse",
-        preprocess::preprocess(shader!("coarse"), &empty, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const BACKDROP: ShaderVariant = ShaderVariant {
+        name: "backdrop_dyn",
+        defines: &[],
+        layout: &[BindType::Uniform, BindType::BufReadOnly, BindType::Buffer],
+        writes_output: false,
+    };
+    pub const COARSE: ShaderVariant = ShaderVariant {
+        name: "coarse",
+        defines: &[],
+        layout: &[
             BindType::Uniform,
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -285,18 +911,24 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::Buffer,
             BindType::Buffer,
         ],
-    )?;
-    let path_tiling_setup = engine.add_shader(
-        device,
-        "path_tiling_setup",
-        preprocess::preprocess(shader!("path_tiling_setup"), &empty, &imports).into(),
-        &[BindType::BufReadOnly, BindType::Buffer],
-    )?;
-    let path_tiling = engine.add_shader(
-        device,
-        "path_tiling",
-        preprocess::preprocess(shader!("path_tiling"), &empty, &imports).into(),
-        &[
+        writes_output: false,
+    };
+    pub const COARSE_SIMPLE: ShaderVariant = ShaderVariant {
+        name: "coarse",
+        defines: &["simple"],
+        layout: COARSE.layout,
+        writes_output: false,
+    };
+    pub const PATH_TILING_SETUP: ShaderVariant = ShaderVariant {
+        name: "path_tiling_setup",
+        defines: &[],
+        layout: &[BindType::BufReadOnly, BindType::Buffer],
+        writes_output: false,
+    };
+    pub const PATH_TILING: ShaderVariant = ShaderVariant {
+        name: "path_tiling",
+        defines: &[],
+        layout: &[
             BindType::BufReadOnly,
             BindType::BufReadOnly,
             BindType::BufReadOnly,
@@ -304,118 +936,133 @@ pub fn full_shaders(device: &Device, engine: &mut WgpuEngine) -> Result<FullShad
             BindType::BufReadOnly,
             BindType::Buffer,
         ],
-    )?;
-    let fine = match ANTIALIASING {
-        crate::AaConfig::Area => engine.add_shader(
-            device,
-            "fine",
-            preprocess::preprocess(shader!("fine"), &full_config, &imports).into(),
-            &[
-                BindType::Uniform,
-                BindType::BufReadOnly,
-                BindType::BufReadOnly,
-                BindType::BufReadOnly,
-                BindType::Image(ImageFormat::Rgba8),
-                BindType::ImageRead(ImageFormat::Rgba8),
-                BindType::ImageRead(ImageFormat::Rgba8),
-            ],
-        )?,
-        _ => {
-            engine.add_shader(
-                device,
-                "fine",
-                preprocess::preprocess(shader!("fine"), &full_config, &imports).into(),
-                &[
-                    BindType::Uniform,
-                    BindType::BufReadOnly,
-                    BindType::BufReadOnly,
-                    BindType::BufReadOnly,
-                    BindType::Image(ImageFormat::Rgba8),
-                    BindType::ImageRead(ImageFormat::Rgba8),
-                    BindType::ImageRead(ImageFormat::Rgba8),
-                    BindType::BufReadOnly, // mask buffer
-                ],
-            )?
-        }
+        writes_output: false,
     };
-    Ok(FullShaders {
-        pathtag_reduce,
-        pathtag_reduce2,
-        pathtag_scan,
-        pathtag_scan1,
-        pathtag_scan_large,
-        bbox_clear,
-        flatten,
-        draw_reduce,
-        draw_leaf,
-        clip_reduce,
-        clip_leaf,
-        binning,
-        tile_alloc,
-        path_count_setup,
-        path_count,
-        backdrop,
-        coarse,
-        path_tiling_setup,
-        path_tiling,
-        fine,
-        pathtag_is_cpu: false,
-    })
-}
-
-#[cfg(feature = "wgpu")]
-impl FullShaders {
-    /// Install the CPU shaders.
-    ///
-    /// There are a couple things to note here. The granularity provided by
-    /// this method is coarse; it installs all the shaders. There are many
-    /// use cases (including debugging), where a mix is desired, or the
-    /// choice between GPU and CPU dispatch might be dynamic.
-    ///
-    /// Second, the actual mapping to CPU shaders is not really specific to
-    /// the engine, and should be split out into a back-end agnostic struct.
-    pub fn install_cpu_shaders(&mut self, engine: &mut WgpuEngine) {
-        engine.set_cpu_shader(self.pathtag_reduce, cpu_shader::pathtag_reduce);
-        engine.set_cpu_shader(self.pathtag_scan, cpu_shader::pathtag_scan);
-        engine.set_cpu_shader(self.bbox_clear, cpu_shader::bbox_clear);
-        engine.set_cpu_shader(self.flatten, cpu_shader::flatten);
-        engine.set_cpu_shader(self.draw_reduce, cpu_shader::draw_reduce);
-        engine.set_cpu_shader(self.draw_leaf, cpu_shader::draw_leaf);
-        engine.set_cpu_shader(self.clip_reduce, cpu_shader::clip_reduce);
-        engine.set_cpu_shader(self.clip_leaf, cpu_shader::clip_leaf);
-        engine.set_cpu_shader(self.binning, cpu_shader::binning);
-        engine.set_cpu_shader(self.tile_alloc, cpu_shader::tile_alloc);
-        engine.set_cpu_shader(self.path_count_setup, cpu_shader::path_count_setup);
-        engine.set_cpu_shader(self.path_count, cpu_shader::path_count);
-        engine.set_cpu_shader(self.backdrop, cpu_shader::backdrop);
-        engine.set_cpu_shader(self.coarse, cpu_shader::coarse);
-        engine.set_cpu_shader(self.path_tiling_setup, cpu_shader::path_tiling_setup);
-        engine.set_cpu_shader(self.path_tiling, cpu_shader::path_tiling);
-        self.pathtag_is_cpu = true;
-    }
-}
-
-macro_rules! shared_shader {
-    ($name:expr) => {
-        (
-            $name,
-            include_str!(concat!("../shader/shared/", $name, ".wgsl")),
-        )
+    pub const TILE_COMPACT: ShaderVariant = ShaderVariant {
+        name: "tile_compact",
+        defines: &[],
+        layout: &[
+            BindType::Uniform,
+            BindType::BufReadOnly,
+            BindType::Buffer,
+            BindType::Buffer,
+            BindType::Image(ImageFormat::Rgba8),
+        ],
+        writes_output: true,
+    };
+    pub const FINE_AREA: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full"],
+        layout: &[
+            BindType::Uniform,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::Image(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::Buffer,                        // blend spill
+            BindType::ImageRead(ImageFormat::Rgba8), // external images
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+        ],
+        writes_output: true,
+    };
+    pub const FINE_AREA_COMPACT: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full", "compact"],
+        layout: &[
+            BindType::Uniform,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::Image(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::Buffer,                        // blend spill
+            BindType::ImageRead(ImageFormat::Rgba8), // external images
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::BufReadOnly, // tile list
+        ],
+        writes_output: true,
+    };
+    pub const FINE_AREA_DEBUG_OVERLAY: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full", "debug_overlay"],
+        layout: &[
+            BindType::Uniform,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::BufReadOnly,
+            BindType::Image(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::Buffer,                        // blend spill
+            BindType::ImageRead(ImageFormat::Rgba8), // external images
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::BufReadOnly, // bin headers
+        ],
+        writes_output: true,
+    };
+    pub const BLUR: ShaderVariant = ShaderVariant {
+        name: "blur",
+        defines: &[],
+        layout: &[
+            BindType::Uniform,
+            BindType::ImageRead(ImageFormat::Rgba8),
+            BindType::Image(ImageFormat::Rgba8),
+        ],
+        writes_output: false,
+    };
+    /// Area coverage thresholded to either fully covered or uncovered, for aliased rendering.
+    pub const FINE_ALIASED: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full", "aliased"],
+        layout: FINE_AREA.layout,
+        writes_output: true,
+    };
+    pub const FINE_AREA_SUBPIXEL: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full", "subpixel"],
+        layout: FINE_AREA.layout,
+        writes_output: true,
+    };
+    const FINE_MSAA_LAYOUT: &[BindType] = &[
+        BindType::Uniform,
+        BindType::BufReadOnly,
+        BindType::BufReadOnly,
+        BindType::BufReadOnly,
+        BindType::Image(ImageFormat::Rgba8),
+        BindType::ImageRead(ImageFormat::Rgba8),
+        BindType::ImageRead(ImageFormat::Rgba8),
+        BindType::Buffer,                        // blend spill
+        BindType::ImageRead(ImageFormat::Rgba8), // external images
+        BindType::ImageRead(ImageFormat::Rgba8),
+        BindType::ImageRead(ImageFormat::Rgba8),
+        BindType::ImageRead(ImageFormat::Rgba8),
+        BindType::BufReadOnly, // mask buffer
+    ];
+    pub const FINE_MSAA8: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full", "msaa", "msaa8"],
+        layout: FINE_MSAA_LAYOUT,
+        writes_output: true,
+    };
+    pub const FINE_MSAA16: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full", "msaa", "msaa16"],
+        layout: FINE_MSAA_LAYOUT,
+        writes_output: true,
+    };
+    pub const FINE_MSAA8_RESOLVE: ShaderVariant = ShaderVariant {
+        name: "fine",
+        defines: &["full", "msaa", "msaa8", "msaa_resolve"],
+        layout: FINE_MSAA_LAYOUT,
+        writes_output: true,
     };
 }
-
-const SHARED_SHADERS: &[(&str, &str)] = &[
-    shared_shader!("bbox"),
-    shared_shader!("blend"),
-    shared_shader!("bump"),
-    shared_shader!("clip"),
-    shared_shader!("config"),
-    shared_shader!("cubic"),
-    shared_shader!("drawtag"),
-    shared_shader!("pathtag"),
-    shared_shader!("ptcl"),
-    shared_shader!("segment"),
-    shared_shader!("tile"),
-    shared_shader!("transform"),
-    shared_shader!("util"),
-];
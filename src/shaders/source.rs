@@ -0,0 +1,279 @@
+//! Providers of the WGSL sources of the shaders.
+
+use std::{borrow::Cow, collections::HashMap};
+
+#[cfg(target_arch = "wasm32")]
+use crate::engine::Error;
+
+/// Provides the WGSL sources that shaders are built from.
+///
+/// Sources are requested by name when a shader variant is first built, so a provider can load
+/// them lazily. Embedders can use this to ship patched shaders, or to avoid embedding them in
+/// the binary. The shared imports are requested once for each shader registry, which is
+/// created whenever the shaders of a renderer are built.
+pub trait ShaderSourceProvider: Send + Sync {
+    /// Returns the source of the shader `name`, such as `fine`, or `None` if it isn't known.
+    fn shader(&self, name: &str) -> Option<Cow<'static, str>>;
+
+    /// Returns the source of the shared file `name`, such as `config`, which shaders include
+    /// with `#import name`.
+    fn import(&self, name: &str) -> Option<Cow<'static, str>>;
+}
+
+/// The shaders embedded in the library at build time.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EmbeddedShaders;
+
+impl EmbeddedShaders {
+    /// Returns the names of the shaders, without the `.wgsl` extension.
+    pub fn shader_names() -> impl Iterator<Item = &'static str> {
+        SHADERS.iter().map(|(name, _)| *name)
+    }
+
+    /// Returns the names of the shared imports, without the `.wgsl` extension.
+    pub fn import_names() -> impl Iterator<Item = &'static str> {
+        SHARED_SHADERS.iter().map(|(name, _)| *name)
+    }
+}
+
+impl ShaderSourceProvider for EmbeddedShaders {
+    fn shader(&self, name: &str) -> Option<Cow<'static, str>> {
+        find(SHADERS, name)
+    }
+
+    fn import(&self, name: &str) -> Option<Cow<'static, str>> {
+        find(SHARED_SHADERS, name)
+    }
+}
+
+fn find(sources: &[(&str, &'static str)], name: &str) -> Option<Cow<'static, str>> {
+    let (_, source) = sources.iter().find(|(source, _)| *source == name)?;
+    Some(Cow::Borrowed(*source))
+}
+
+/// Reads shaders from a directory laid out like the `shader` directory of this crate, with
+/// the shared imports in its `shared` subdirectory.
+///
+/// Files which can't be read fall back to the embedded version. This is what hot reloading
+/// uses, with [`shader_dir`](crate::shader_dir).
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone, Debug)]
+pub struct FileShaders {
+    dir: std::path::PathBuf,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl FileShaders {
+    pub fn new(dir: impl Into<std::path::PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn read(&self, path: std::path::PathBuf) -> Option<Cow<'static, str>> {
+        match std::fs::read_to_string(&path) {
+            Ok(source) => Some(source.into()),
+            Err(e) => {
+                eprintln!(
+                    "Failed to read shader {path:?}, falling back to version at compilation time. Error: {e:?}"
+                );
+                None
+            }
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl ShaderSourceProvider for FileShaders {
+    fn shader(&self, name: &str) -> Option<Cow<'static, str>> {
+        self.read(self.dir.join(format!("{name}.wgsl")))
+            .or_else(|| EmbeddedShaders.shader(name))
+    }
+
+    fn import(&self, name: &str) -> Option<Cow<'static, str>> {
+        self.read(self.dir.join("shared").join(format!("{name}.wgsl")))
+            .or_else(|| EmbeddedShaders.import(name))
+    }
+}
+
+/// Shader sources held in memory, such as ones fetched over the network or patched by the
+/// application.
+///
+/// Sources which haven't been inserted fall back to the embedded version.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderSources {
+    shaders: HashMap<String, String>,
+    imports: HashMap<String, String>,
+}
+
+impl ShaderSources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the source of the shader `name`.
+    pub fn insert_shader(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.shaders.insert(name.into(), source.into());
+    }
+
+    /// Replaces the source of the shared import `name`.
+    pub fn insert_import(&mut self, name: impl Into<String>, source: impl Into<String>) {
+        self.imports.insert(name.into(), source.into());
+    }
+
+    /// Downloads every shader and shared import from `base_url`, which is laid out like the
+    /// `shader` directory of this crate, such as `https://example.com/shader`.
+    ///
+    /// The browser caches the files like any other, so later page loads needn't download them
+    /// again.
+    #[cfg(target_arch = "wasm32")]
+    pub async fn fetch(base_url: &str) -> Result<Self, Error> {
+        let base_url = base_url.trim_end_matches('/');
+        let mut sources = Self::new();
+        for name in EmbeddedShaders::shader_names() {
+            let source = fetch_text(&format!("{base_url}/{name}.wgsl")).await?;
+            sources.insert_shader(name, source);
+        }
+        for name in EmbeddedShaders::import_names() {
+            let source = fetch_text(&format!("{base_url}/shared/{name}.wgsl")).await?;
+            sources.insert_import(name, source);
+        }
+        Ok(sources)
+    }
+}
+
+impl ShaderSourceProvider for ShaderSources {
+    fn shader(&self, name: &str) -> Option<Cow<'static, str>> {
+        match self.shaders.get(name) {
+            Some(source) => Some(source.clone().into()),
+            None => EmbeddedShaders.shader(name),
+        }
+    }
+
+    fn import(&self, name: &str) -> Option<Cow<'static, str>> {
+        match self.imports.get(name) {
+            Some(source) => Some(source.clone().into()),
+            None => EmbeddedShaders.import(name),
+        }
+    }
+}
+
+/// Fetches the text at `url` with the fetch API of the window, or of the worker this runs in.
+#[cfg(target_arch = "wasm32")]
+async fn fetch_text(url: &str) -> Result<String, Error> {
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    let global = js_sys::global();
+    let request = if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+        window.fetch_with_str(url)
+    } else if let Some(worker) = global.dyn_ref::<web_sys::WorkerGlobalScope>() {
+        worker.fetch_with_str(url)
+    } else {
+        return Err("no window or worker to fetch shaders with".into());
+    };
+    let response = JsFuture::from(request)
+        .await
+        .map_err(|e| format!("failed to fetch {url}: {e:?}"))?;
+    let response: web_sys::Response = response
+        .dyn_into()
+        .map_err(|_| format!("failed to fetch {url}"))?;
+    if !response.ok() {
+        return Err(format!("failed to fetch {url}: status {}", response.status()).into());
+    }
+    let text = response
+        .text()
+        .map_err(|e| format!("failed to read {url}: {e:?}"))?;
+    let text = JsFuture::from(text)
+        .await
+        .map_err(|e| format!("failed to read {url}: {e:?}"))?;
+    text.as_string()
+        .ok_or_else(|| format!("{url} isn't text").into())
+}
+
+macro_rules! shared_shader {
+    ($name:expr) => {
+        (
+            $name,
+            include_str!(concat!("../../shader/shared/", $name, ".wgsl")),
+        )
+    };
+}
+
+const SHARED_SHADERS: &[(&str, &str)] = &[
+    shared_shader!("bbox"),
+    shared_shader!("blend"),
+    shared_shader!("bump"),
+    shared_shader!("clip"),
+    shared_shader!("config"),
+    shared_shader!("cubic"),
+    shared_shader!("debug"),
+    shared_shader!("drawtag"),
+    shared_shader!("pathtag"),
+    shared_shader!("ptcl"),
+    shared_shader!("segment"),
+    shared_shader!("tile"),
+    shared_shader!("transform"),
+    shared_shader!("util"),
+];
+
+macro_rules! shader_source {
+    ($name:expr) => {
+        (
+            $name,
+            include_str!(concat!("../../shader/", $name, ".wgsl")),
+        )
+    };
+}
+
+/// The permutations listed in `shader/permutations`, which `vello_shaders` builds.
+#[cfg(all(feature = "wgpu", not(target_arch = "wasm32")))]
+const PERMUTATIONS: &str = include_str!("../../shader/permutations");
+
+/// Returns the shader and definitions of each permutation in `shader/permutations`.
+#[cfg(all(feature = "wgpu", not(target_arch = "wasm32")))]
+///
+/// Each shader name is followed by lines of the form `+ name: define define`, one for each
+/// permutation of it.
+pub(crate) fn permutations() -> Vec<(&'static str, Vec<&'static str>)> {
+    let mut permutations = vec![];
+    let mut shader = None;
+    for line in PERMUTATIONS.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Some(permutation) = line.strip_prefix('+') {
+            let defines = permutation
+                .split_once(':')
+                .map_or("", |(_, defines)| defines)
+                .split_whitespace()
+                .collect();
+            permutations.extend(shader.map(|shader| (shader, defines)));
+        } else {
+            shader = Some(line);
+        }
+    }
+    permutations
+}
+
+const SHADERS: &[(&str, &str)] = &[
+    shader_source!("backdrop_dyn"),
+    shader_source!("bbox_clear"),
+    shader_source!("binning"),
+    shader_source!("blur"),
+    shader_source!("clip_leaf"),
+    shader_source!("clip_reduce"),
+    shader_source!("coarse"),
+    shader_source!("draw_leaf"),
+    shader_source!("draw_reduce"),
+    shader_source!("fine"),
+    shader_source!("flatten"),
+    shader_source!("path_count"),
+    shader_source!("path_count_setup"),
+    shader_source!("path_tiling"),
+    shader_source!("path_tiling_setup"),
+    shader_source!("pathtag_reduce"),
+    shader_source!("pathtag_reduce2"),
+    shader_source!("pathtag_scan"),
+    shader_source!("pathtag_scan1"),
+    shader_source!("tile_alloc"),
+    shader_source!("tile_compact"),
+];
@@ -0,0 +1,85 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Execution of a render one dispatch at a time, for bisecting a frame that renders
+//! incorrectly to the stage which first goes wrong.
+
+use std::collections::HashSet;
+
+use crate::{BufProxy, Capture, Command, Id, Recording, ResourceProxy, ShaderId};
+
+/// State of a render after one of its dispatches, as passed to the hook of
+/// [`Renderer::step_frame`](crate::Renderer::step_frame).
+pub struct Step<'a> {
+    /// Index of the dispatch in the render, counting from zero.
+    pub index: usize,
+    /// Label of the dispatched shader, such as `pathtag_reduce`.
+    pub stage: &'static str,
+    /// Names of the buffers bound to the dispatch, as in the [`Capture`].
+    pub bound: &'a [&'static str],
+    /// The inputs of the render and the contents of each buffer bound so far, as of the end
+    /// of this dispatch.
+    pub capture: &'a Capture,
+}
+
+/// Part of a recording which ends with a dispatch, followed by downloads of the buffers
+/// bound to it.
+pub(crate) struct StepRecording {
+    pub recording: Recording,
+    /// The dispatched shader and the downloaded buffers, or `None` for the commands after
+    /// the last dispatch.
+    pub dispatch: Option<(ShaderId, Vec<BufProxy>)>,
+}
+
+/// Splits `recording` after each dispatch.
+///
+/// The downloads of `recording` are dropped, as every buffer is downloaded after each
+/// dispatch which binds it instead. Uniform buffers can't be copied from, so they aren't
+/// downloaded.
+pub(crate) fn split_dispatches(recording: Recording) -> Vec<StepRecording> {
+    let mut steps = vec![];
+    let mut uniforms = HashSet::new();
+    let mut current = Recording::default();
+    for command in recording.into_commands() {
+        let dispatch = match &command {
+            Command::UploadUniform(buf, _) => {
+                uniforms.insert(buf.id);
+                None
+            }
+            Command::Download(_) => continue,
+            Command::Dispatch(shader, _, bindings)
+            | Command::DispatchIndirect(shader, _, _, bindings) => {
+                Some((*shader, bound_bufs(bindings, &uniforms)))
+            }
+            _ => None,
+        };
+        current.push(command);
+        if let Some((shader, bufs)) = dispatch {
+            for buf in &bufs {
+                current.download(*buf);
+            }
+            steps.push(StepRecording {
+                recording: std::mem::take(&mut current),
+                dispatch: Some((shader, bufs)),
+            });
+        }
+    }
+    steps.push(StepRecording {
+        recording: current,
+        dispatch: None,
+    });
+    steps
+}
+
+/// Returns each buffer of `bindings` once, leaving out `uniforms`.
+fn bound_bufs(bindings: &[ResourceProxy], uniforms: &HashSet<Id>) -> Vec<BufProxy> {
+    let mut bufs: Vec<BufProxy> = vec![];
+    for binding in bindings {
+        if let ResourceProxy::Buf(buf) = binding {
+            if !uniforms.contains(&buf.id) && !bufs.iter().any(|b| b.id == buf.id) {
+                bufs.push(*buf);
+            }
+        }
+    }
+    bufs
+}
@@ -0,0 +1,605 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Recording of the scenes rendered by an application to a trace, and reading them back to
+//! replay the workload offline.
+//!
+//! A trace is a header followed by a sequence of frames, each holding the encoding of a
+//! scene and the parameters it was rendered with. The data of fonts and images is written
+//! before the first frame which uses it and referenced by the later ones, so that a stream
+//! of frames from a live application stays small. A trace of a single frame can be written
+//! to its own file in the same way.
+//!
+//! External images are drawn by textures of the application, which aren't recorded, so they
+//! are replaced by transparent images of the same size when a trace is read.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::ops::Range;
+use std::sync::Arc;
+
+use bytemuck::Pod;
+use fello::NormalizedCoord;
+use peniko::kurbo::{Cap, Join, Stroke};
+use peniko::{Blob, Color, ColorStop, Extend, Fill, Font, Format, Image, Style};
+use vello_encoding::{
+    Encoding, FilterLayer, Glyph, GlyphRun, Instance, Patch, StreamOffsets, Transform,
+};
+
+use crate::{RenderParams, Scene};
+
+const MAGIC: &[u8; 8] = b"VELLOTRC";
+const VERSION: u32 = 1;
+
+// Tags of the records of a trace.
+const RECORD_BLOB: u8 = 0;
+const RECORD_FRAME: u8 = 1;
+
+// Tags of the patches of an encoding.
+const PATCH_RAMP: u8 = 0;
+const PATCH_GLYPH_RUN: u8 = 1;
+const PATCH_IMAGE: u8 = 2;
+const PATCH_FILTER_LAYER: u8 = 3;
+const PATCH_EXTERNAL_IMAGE: u8 = 4;
+
+/// Writes the frames of an application to a trace.
+pub struct TraceWriter<W: Write> {
+    out: W,
+    /// Ids of the blobs which have already been written.
+    blobs: HashSet<u64>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Creates a writer of a trace to `out`, writing its header.
+    pub fn new(mut out: W) -> io::Result<Self> {
+        out.write_all(MAGIC)?;
+        out.write_all(&VERSION.to_le_bytes())?;
+        Ok(Self {
+            out,
+            blobs: HashSet::new(),
+        })
+    }
+
+    /// Appends `scene`, as rendered with `params`, to the trace.
+    pub fn write_frame(&mut self, scene: &Scene, params: &RenderParams) -> io::Result<()> {
+        let encoding = scene.data();
+        self.write_blobs(encoding)?;
+        let out = &mut self.out;
+        write_u8(out, RECORD_FRAME)?;
+        write_u32(out, params.width)?;
+        write_u32(out, params.height)?;
+        write_color(out, params.base_color)?;
+        write_encoding(out, encoding)
+    }
+
+    /// Flushes the underlying writer.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Writes the fonts and images of `encoding` which haven't been written yet.
+    fn write_blobs(&mut self, encoding: &Encoding) -> io::Result<()> {
+        let resources = &encoding.resources;
+        for run in &resources.glyph_runs {
+            self.write_blob(&run.font.data)?;
+        }
+        for patch in &resources.patches {
+            if let Patch::Image { image, .. } = patch {
+                self.write_blob(&image.data)?;
+            }
+        }
+        for layer in &resources.filter_layers {
+            self.write_blobs(&layer.encoding)?;
+        }
+        Ok(())
+    }
+
+    fn write_blob(&mut self, blob: &Blob<u8>) -> io::Result<()> {
+        if !self.blobs.insert(blob.id()) {
+            return Ok(());
+        }
+        let out = &mut self.out;
+        write_u8(out, RECORD_BLOB)?;
+        write_u64(out, blob.id())?;
+        write_bytes(out, blob.as_ref())
+    }
+}
+
+/// A frame read from a trace.
+pub struct TraceFrame {
+    pub scene: Scene,
+    pub params: RenderParams,
+}
+
+/// Reads the frames of a trace written by a [`TraceWriter`].
+pub struct TraceReader<R: Read> {
+    input: R,
+    /// The blobs read so far, by the id they were written with.
+    blobs: HashMap<u64, Blob<u8>>,
+}
+
+impl<R: Read> TraceReader<R> {
+    /// Creates a reader of the trace in `input`, checking its header.
+    pub fn new(mut input: R) -> io::Result<Self> {
+        let mut magic = [0; 8];
+        input.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a scene trace"));
+        }
+        let version = read_u32(&mut input)?;
+        if version != VERSION {
+            return Err(invalid(format!("unsupported trace version {version}")));
+        }
+        Ok(Self {
+            input,
+            blobs: HashMap::new(),
+        })
+    }
+
+    /// Reads the next frame, or returns `None` at the end of the trace.
+    pub fn read_frame(&mut self) -> io::Result<Option<TraceFrame>> {
+        loop {
+            let mut tag = [0];
+            if self.input.read(&mut tag)? == 0 {
+                return Ok(None);
+            }
+            match tag[0] {
+                RECORD_BLOB => {
+                    let id = read_u64(&mut self.input)?;
+                    let data = read_bytes(&mut self.input)?;
+                    self.blobs.insert(id, Blob::new(Arc::new(data)));
+                }
+                RECORD_FRAME => {
+                    let input = &mut self.input;
+                    let params = RenderParams {
+                        width: read_u32(input)?,
+                        height: read_u32(input)?,
+                        base_color: read_color(input)?,
+                    };
+                    let encoding = read_encoding(input, &self.blobs)?;
+                    let scene = Scene::from_data(encoding);
+                    return Ok(Some(TraceFrame { scene, params }));
+                }
+                tag => return Err(invalid(format!("unknown record {tag}"))),
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for TraceReader<R> {
+    type Item = io::Result<TraceFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+fn write_encoding(out: &mut impl Write, encoding: &Encoding) -> io::Result<()> {
+    write_u32(out, encoding.n_paths)?;
+    write_u32(out, encoding.n_path_segments)?;
+    write_u32(out, encoding.n_clips)?;
+    write_u32(out, encoding.n_open_clips)?;
+    write_pods(out, &encoding.path_tags)?;
+    write_bytes(out, &encoding.path_data)?;
+    write_pods(out, &encoding.draw_tags)?;
+    write_bytes(out, &encoding.draw_data)?;
+    write_pods(out, &encoding.transforms)?;
+    write_pods(out, &encoding.styles)?;
+    write_pods(out, &encoding.widths)?;
+    write_len(out, encoding.instances.len())?;
+    for instance in &encoding.instances {
+        write_offsets(out, &instance.source)?;
+        write_offsets(out, &instance.offsets)?;
+        write_u64(out, instance.path_data_len as u64)?;
+    }
+    let resources = &encoding.resources;
+    write_len(out, resources.color_stops.len())?;
+    for stop in &resources.color_stops {
+        write_f32(out, stop.offset)?;
+        write_color(out, stop.color)?;
+    }
+    write_len(out, resources.glyphs.len())?;
+    for glyph in &resources.glyphs {
+        write_u32(out, glyph.id)?;
+        write_f32(out, glyph.x)?;
+        write_f32(out, glyph.y)?;
+    }
+    let coords: Vec<i16> = resources
+        .normalized_coords
+        .iter()
+        .map(|coord| coord.to_bits())
+        .collect();
+    write_pods(out, &coords)?;
+    write_len(out, resources.glyph_runs.len())?;
+    for run in &resources.glyph_runs {
+        write_u64(out, run.font.data.id())?;
+        write_u32(out, run.font.index)?;
+        write_pod(out, &run.transform)?;
+        write_pods(out, run.glyph_transform.as_slice())?;
+        write_f32(out, run.font_size)?;
+        write_u8(out, run.hint as u8)?;
+        write_range(out, &run.normalized_coords)?;
+        write_style(out, &run.style)?;
+        write_range(out, &run.glyphs)?;
+        write_offsets(out, &run.stream_offsets)?;
+    }
+    write_len(out, resources.filter_layers.len())?;
+    for layer in &resources.filter_layers {
+        write_encoding(out, &layer.encoding)?;
+        write_u32(out, layer.width)?;
+        write_u32(out, layer.height)?;
+        write_f32(out, layer.std_dev)?;
+        write_u8(out, layer.color.is_some() as u8)?;
+        write_color(out, layer.color.unwrap_or(Color::TRANSPARENT))?;
+    }
+    write_len(out, resources.patches.len())?;
+    for patch in &resources.patches {
+        match patch {
+            Patch::Ramp {
+                draw_data_offset,
+                stops,
+                extend,
+            } => {
+                write_u8(out, PATCH_RAMP)?;
+                write_u64(out, *draw_data_offset as u64)?;
+                write_range(out, stops)?;
+                write_u8(out, *extend as u8)?;
+            }
+            Patch::GlyphRun { index } => {
+                write_u8(out, PATCH_GLYPH_RUN)?;
+                write_u64(out, *index as u64)?;
+            }
+            Patch::Image {
+                draw_data_offset,
+                image,
+            } => {
+                write_u8(out, PATCH_IMAGE)?;
+                write_u64(out, *draw_data_offset as u64)?;
+                write_u64(out, image.data.id())?;
+                write_u32(out, image.width)?;
+                write_u32(out, image.height)?;
+                write_u8(out, image.extend as u8)?;
+            }
+            Patch::FilterLayer {
+                index,
+                draw_data_offset,
+            } => {
+                write_u8(out, PATCH_FILTER_LAYER)?;
+                write_u64(out, *index as u64)?;
+                write_u64(out, *draw_data_offset as u64)?;
+            }
+            Patch::ExternalImage {
+                draw_data_offset,
+                image,
+            } => {
+                write_u8(out, PATCH_EXTERNAL_IMAGE)?;
+                write_u64(out, *draw_data_offset as u64)?;
+                write_u32(out, image.width)?;
+                write_u32(out, image.height)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn read_encoding(input: &mut impl Read, blobs: &HashMap<u64, Blob<u8>>) -> io::Result<Encoding> {
+    let mut encoding = Encoding::new();
+    encoding.n_paths = read_u32(input)?;
+    encoding.n_path_segments = read_u32(input)?;
+    encoding.n_clips = read_u32(input)?;
+    encoding.n_open_clips = read_u32(input)?;
+    encoding.path_tags = read_pods(input)?;
+    encoding.path_data = read_bytes(input)?;
+    encoding.draw_tags = read_pods(input)?;
+    encoding.draw_data = read_bytes(input)?;
+    encoding.transforms = read_pods(input)?;
+    encoding.styles = read_pods(input)?;
+    encoding.widths = read_pods(input)?;
+    for _ in 0..read_len(input)? {
+        encoding.instances.push(Instance {
+            source: read_offsets(input)?,
+            offsets: read_offsets(input)?,
+            path_data_len: read_u64(input)? as usize,
+        });
+    }
+    let resources = &mut encoding.resources;
+    for _ in 0..read_len(input)? {
+        resources.color_stops.push(ColorStop {
+            offset: read_f32(input)?,
+            color: read_color(input)?,
+        });
+    }
+    for _ in 0..read_len(input)? {
+        resources.glyphs.push(Glyph {
+            id: read_u32(input)?,
+            x: read_f32(input)?,
+            y: read_f32(input)?,
+        });
+    }
+    resources.normalized_coords = read_pods::<i16>(input)?
+        .into_iter()
+        .map(NormalizedCoord::from_bits)
+        .collect();
+    let blob = |id| {
+        blobs
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| invalid(format!("blob {id} is used before it is written")))
+    };
+    for _ in 0..read_len(input)? {
+        let font = Font::new(blob(read_u64(input)?)?, read_u32(input)?);
+        resources.glyph_runs.push(GlyphRun {
+            font,
+            transform: read_pod(input)?,
+            glyph_transform: read_pods::<Transform>(input)?.first().copied(),
+            font_size: read_f32(input)?,
+            hint: read_u8(input)? != 0,
+            normalized_coords: read_range(input)?,
+            style: read_style(input)?,
+            glyphs: read_range(input)?,
+            stream_offsets: read_offsets(input)?,
+        });
+    }
+    for _ in 0..read_len(input)? {
+        let encoding = read_encoding(input, blobs)?;
+        let width = read_u32(input)?;
+        let height = read_u32(input)?;
+        let std_dev = read_f32(input)?;
+        let has_color = read_u8(input)? != 0;
+        let color = read_color(input)?;
+        resources.filter_layers.push(FilterLayer {
+            encoding,
+            width,
+            height,
+            std_dev,
+            color: has_color.then_some(color),
+        });
+    }
+    for _ in 0..read_len(input)? {
+        let patch = match read_u8(input)? {
+            PATCH_RAMP => Patch::Ramp {
+                draw_data_offset: read_u64(input)? as usize,
+                stops: read_range(input)?,
+                extend: read_extend(input)?,
+            },
+            PATCH_GLYPH_RUN => Patch::GlyphRun {
+                index: read_u64(input)? as usize,
+            },
+            PATCH_IMAGE => {
+                let draw_data_offset = read_u64(input)? as usize;
+                let data = blob(read_u64(input)?)?;
+                let (width, height) = (read_u32(input)?, read_u32(input)?);
+                let image =
+                    Image::new(data, Format::Rgba8, width, height).with_extend(read_extend(input)?);
+                Patch::Image {
+                    draw_data_offset,
+                    image,
+                }
+            }
+            PATCH_FILTER_LAYER => Patch::FilterLayer {
+                index: read_u64(input)? as usize,
+                draw_data_offset: read_u64(input)? as usize,
+            },
+            PATCH_EXTERNAL_IMAGE => {
+                let draw_data_offset = read_u64(input)? as usize;
+                let (width, height) = (read_u32(input)?, read_u32(input)?);
+                let data = vec![0; width as usize * height as usize * 4];
+                let image = Image::new(Blob::new(Arc::new(data)), Format::Rgba8, width, height);
+                Patch::Image {
+                    draw_data_offset,
+                    image,
+                }
+            }
+            tag => return Err(invalid(format!("unknown patch {tag}"))),
+        };
+        resources.patches.push(patch);
+    }
+    Ok(encoding)
+}
+
+fn write_style(out: &mut impl Write, style: &Style) -> io::Result<()> {
+    match style {
+        Style::Fill(fill) => {
+            write_u8(out, 0)?;
+            write_u8(out, matches!(fill, Fill::EvenOdd) as u8)
+        }
+        Style::Stroke(stroke) => {
+            write_u8(out, 1)?;
+            write_f64(out, stroke.width)?;
+            write_u8(out, stroke.join as u8)?;
+            write_f64(out, stroke.miter_limit)?;
+            write_u8(out, stroke.start_cap as u8)?;
+            write_u8(out, stroke.end_cap as u8)?;
+            write_f64(out, stroke.dash_offset)?;
+            write_pods(out, &stroke.dash_pattern[..])
+        }
+    }
+}
+
+fn read_style(input: &mut impl Read) -> io::Result<Style> {
+    match read_u8(input)? {
+        0 => match read_u8(input)? {
+            0 => Ok(Style::Fill(Fill::NonZero)),
+            _ => Ok(Style::Fill(Fill::EvenOdd)),
+        },
+        1 => {
+            let mut stroke = Stroke::new(read_f64(input)?);
+            stroke.join = match read_u8(input)? {
+                0 => Join::Bevel,
+                1 => Join::Miter,
+                _ => Join::Round,
+            };
+            stroke.miter_limit = read_f64(input)?;
+            stroke.start_cap = read_cap(input)?;
+            stroke.end_cap = read_cap(input)?;
+            let dash_offset = read_f64(input)?;
+            let dash_pattern: Vec<f64> = read_pods(input)?;
+            Ok(Style::Stroke(stroke.with_dashes(dash_offset, dash_pattern)))
+        }
+        tag => Err(invalid(format!("unknown style {tag}"))),
+    }
+}
+
+fn read_cap(input: &mut impl Read) -> io::Result<Cap> {
+    Ok(match read_u8(input)? {
+        0 => Cap::Butt,
+        1 => Cap::Square,
+        _ => Cap::Round,
+    })
+}
+
+fn read_extend(input: &mut impl Read) -> io::Result<Extend> {
+    Ok(match read_u8(input)? {
+        0 => Extend::Pad,
+        1 => Extend::Repeat,
+        _ => Extend::Reflect,
+    })
+}
+
+fn write_offsets(out: &mut impl Write, offsets: &StreamOffsets) -> io::Result<()> {
+    for offset in [
+        offsets.path_tags,
+        offsets.path_data,
+        offsets.draw_tags,
+        offsets.draw_data,
+        offsets.transforms,
+        offsets.styles,
+    ] {
+        write_u64(out, offset as u64)?;
+    }
+    Ok(())
+}
+
+fn read_offsets(input: &mut impl Read) -> io::Result<StreamOffsets> {
+    Ok(StreamOffsets {
+        path_tags: read_u64(input)? as usize,
+        path_data: read_u64(input)? as usize,
+        draw_tags: read_u64(input)? as usize,
+        draw_data: read_u64(input)? as usize,
+        transforms: read_u64(input)? as usize,
+        styles: read_u64(input)? as usize,
+    })
+}
+
+fn write_range(out: &mut impl Write, range: &Range<usize>) -> io::Result<()> {
+    write_u64(out, range.start as u64)?;
+    write_u64(out, range.end as u64)
+}
+
+fn read_range(input: &mut impl Read) -> io::Result<Range<usize>> {
+    Ok(read_u64(input)? as usize..read_u64(input)? as usize)
+}
+
+fn write_color(out: &mut impl Write, color: Color) -> io::Result<()> {
+    out.write_all(&[color.r, color.g, color.b, color.a])
+}
+
+fn read_color(input: &mut impl Read) -> io::Result<Color> {
+    let mut rgba = [0; 4];
+    input.read_exact(&mut rgba)?;
+    let [r, g, b, a] = rgba;
+    Ok(Color::rgba8(r, g, b, a))
+}
+
+fn write_pod<T: Pod>(out: &mut impl Write, value: &T) -> io::Result<()> {
+    out.write_all(bytemuck::bytes_of(value))
+}
+
+fn read_pod<T: Pod>(input: &mut impl Read) -> io::Result<T> {
+    let bytes = read_exact_vec(input, std::mem::size_of::<T>())?;
+    Ok(bytemuck::pod_read_unaligned(&bytes))
+}
+
+/// Writes the number of elements of `data`, followed by its bytes.
+fn write_pods<T: Pod>(out: &mut impl Write, data: &[T]) -> io::Result<()> {
+    write_len(out, data.len())?;
+    out.write_all(bytemuck::cast_slice(data))
+}
+
+fn read_pods<T: Pod>(input: &mut impl Read) -> io::Result<Vec<T>> {
+    let len = read_len(input)?;
+    let size = std::mem::size_of::<T>();
+    let bytes = read_exact_vec(input, len.saturating_mul(size))?;
+    Ok(bytes
+        .chunks_exact(size)
+        .map(bytemuck::pod_read_unaligned)
+        .collect())
+}
+
+fn write_bytes(out: &mut impl Write, data: &[u8]) -> io::Result<()> {
+    write_pods(out, data)
+}
+
+fn read_bytes(input: &mut impl Read) -> io::Result<Vec<u8>> {
+    let len = read_len(input)?;
+    read_exact_vec(input, len)
+}
+
+/// Reads `len` bytes, without allocating them up front, so that a corrupt length fails at the
+/// end of the input rather than exhausting memory.
+fn read_exact_vec(input: &mut impl Read, len: usize) -> io::Result<Vec<u8>> {
+    let mut data = vec![];
+    input.take(len as u64).read_to_end(&mut data)?;
+    if data.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(data)
+}
+
+fn write_len(out: &mut impl Write, len: usize) -> io::Result<()> {
+    write_u64(out, len as u64)
+}
+
+fn read_len(input: &mut impl Read) -> io::Result<usize> {
+    usize::try_from(read_u64(input)?).map_err(|_| invalid("length out of range"))
+}
+
+fn write_u8(out: &mut impl Write, value: u8) -> io::Result<()> {
+    out.write_all(&[value])
+}
+
+fn read_u8(input: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0];
+    input.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn write_u32(out: &mut impl Write, value: u32) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn write_u64(out: &mut impl Write, value: u64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    input.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn write_f32(out: &mut impl Write, value: f32) -> io::Result<()> {
+    write_u32(out, value.to_bits())
+}
+
+fn read_f32(input: &mut impl Read) -> io::Result<f32> {
+    Ok(f32::from_bits(read_u32(input)?))
+}
+
+fn write_f64(out: &mut impl Write, value: f64) -> io::Result<()> {
+    write_u64(out, value.to_bits())
+}
+
+fn read_f64(input: &mut impl Read) -> io::Result<f64> {
+    Ok(f64::from_bits(read_u64(input)?))
+}
@@ -0,0 +1,109 @@
+// Copyright 2023 The Vello authors
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+
+//! Selection of the workgroup sizes of the tunable stages by benchmarking on an adapter.
+
+use std::time::{Duration, Instant};
+
+use peniko::kurbo::{Affine, BezPath, Circle, Point, Stroke, Vec2};
+use peniko::{Color, Fill};
+use wgpu::{Device, Queue};
+
+use crate::{RenderParams, Renderer, Result, Scene, SceneBuilder, WorkgroupSizes};
+
+/// Size of the target the benchmark scene is rendered to.
+const TARGET_SIZE: u32 = 1024;
+
+/// Number of timed renders with each combination of sizes, of which the fastest is kept.
+const TIMED_RENDERS: usize = 5;
+
+/// Renders the benchmark scene with each candidate in turn, leaving `renderer` with the
+/// fastest.
+pub(crate) fn calibrate(
+    renderer: &mut Renderer,
+    device: &Device,
+    queue: &Queue,
+) -> Result<WorkgroupSizes> {
+    let scene = benchmark_scene();
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("calibration target"),
+        size: wgpu::Extent3d {
+            width: TARGET_SIZE,
+            height: TARGET_SIZE,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        usage: wgpu::TextureUsages::STORAGE_BINDING,
+        format: renderer.shaders.output_format.to_wgpu(),
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    let params = RenderParams {
+        base_color: Color::BLACK,
+        width: TARGET_SIZE,
+        height: TARGET_SIZE,
+    };
+    let mut best: Option<(Duration, WorkgroupSizes)> = None;
+    for sizes in WorkgroupSizes::candidates() {
+        renderer.set_workgroup_sizes(device, sizes)?;
+        // The first render creates the pipelines and grows the buffers to fit the scene.
+        renderer.render_to_texture(device, queue, &scene, &view, &params)?;
+        device.poll(wgpu::Maintain::Wait);
+        let mut fastest = Duration::MAX;
+        for _ in 0..TIMED_RENDERS {
+            let start = Instant::now();
+            renderer.render_to_texture(device, queue, &scene, &view, &params)?;
+            device.poll(wgpu::Maintain::Wait);
+            fastest = fastest.min(start.elapsed());
+        }
+        if best.map_or(true, |(time, _)| fastest < time) {
+            best = Some((fastest, sizes));
+        }
+    }
+    let sizes = best.map_or_else(WorkgroupSizes::default, |(_, sizes)| sizes);
+    renderer.set_workgroup_sizes(device, sizes)?;
+    Ok(sizes)
+}
+
+/// Returns a scene dominated by curves and strokes, which load the flattening, path count
+/// and path tiling stages.
+fn benchmark_scene() -> Scene {
+    let mut scene = Scene::new();
+    let mut builder = SceneBuilder::for_scene(&mut scene);
+    let center = Point::new(TARGET_SIZE as f64 / 2.0, TARGET_SIZE as f64 / 2.0);
+    for i in 0..400 {
+        let t = i as f64 / 400.0;
+        let angle = t * std::f64::consts::TAU * 7.0;
+        let radius = 40.0 + t * 440.0;
+        let p = center + radius * Vec2::from_angle(angle);
+        let color = Color::rgba(t, 1.0 - t, 0.5, 0.6);
+        builder.fill(
+            Fill::NonZero,
+            Affine::IDENTITY,
+            color,
+            None,
+            &Circle::new(p, 12.0 + 30.0 * t),
+        );
+    }
+    let mut spiral = BezPath::new();
+    spiral.move_to(center);
+    for i in 1..2000 {
+        let t = i as f64 / 2000.0;
+        let angle = t * std::f64::consts::TAU * 40.0;
+        let radius = t * 500.0;
+        spiral.quad_to(
+            center + radius * Vec2::from_angle(angle - 0.05),
+            center + radius * Vec2::from_angle(angle),
+        );
+    }
+    builder.stroke(
+        &Stroke::new(3.0),
+        Affine::IDENTITY,
+        Color::WHITE,
+        None,
+        &spiral,
+    );
+    scene
+}
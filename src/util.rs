@@ -18,12 +18,10 @@
 
 use std::future::Future;
 
-use super::Result;
+use super::{Capabilities, Result};
 
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
-use wgpu::{
-    Adapter, Device, Instance, Limits, Queue, Surface, SurfaceConfiguration, TextureFormat,
-};
+use wgpu::{Adapter, Device, Instance, Queue, Surface, SurfaceConfiguration, TextureFormat};
 
 /// Simple render context that maintains wgpu state for rendering the pipeline.
 pub struct RenderContext {
@@ -39,8 +37,15 @@ pub struct DeviceHandle {
 
 impl RenderContext {
     pub fn new() -> Result<Self> {
+        // The GL backend is only enabled for WebGL2, where the renderer falls back to the CPU
+        // shaders. Natively, a GL adapter without compute shaders could otherwise be picked, and
+        // every frame would silently render on the CPU.
+        #[cfg(all(target_arch = "wasm32", feature = "webgl"))]
+        let backends = wgpu::Backends::PRIMARY | wgpu::Backends::GL;
+        #[cfg(not(all(target_arch = "wasm32", feature = "webgl")))]
+        let backends = wgpu::Backends::PRIMARY;
         let instance = Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
+            backends,
             dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
         });
         Ok(Self {
@@ -60,6 +65,32 @@ impl RenderContext {
         W: HasRawWindowHandle + HasRawDisplayHandle,
     {
         let surface = unsafe { self.instance.create_surface(window) }?;
+        self.create_render_surface(surface, width, height).await
+    }
+
+    /// Creates a new surface for an `OffscreenCanvas`, such as one transferred to a web worker
+    /// with `transferControlToOffscreen`.
+    ///
+    /// This allows rendering entirely off the main thread. The canvas is presented when the
+    /// worker yields to its event loop.
+    #[cfg(target_arch = "wasm32")]
+    pub async fn create_surface_from_offscreen_canvas(
+        &mut self,
+        canvas: web_sys::OffscreenCanvas,
+        width: u32,
+        height: u32,
+    ) -> Result<RenderSurface> {
+        let surface = self.instance.create_surface_from_offscreen_canvas(canvas)?;
+        self.create_render_surface(surface, width, height).await
+    }
+
+    /// Configures `surface` with a format the renderer can blit to, on a compatible device.
+    async fn create_render_surface(
+        &mut self,
+        surface: Surface,
+        width: u32,
+        height: u32,
+    ) -> Result<RenderSurface> {
         let dev_id = self
             .device(Some(&surface))
             .await
@@ -135,19 +166,21 @@ impl RenderContext {
         let adapter =
             wgpu::util::initialize_adapter_from_env_or_default(&self.instance, compatible_surface)
                 .await?;
-        let features = adapter.features();
-        let limits = Limits::default();
-        let mut maybe_features = wgpu::Features::CLEAR_TEXTURE;
+        // Constrained adapters get a device with their own limits, and the renderer picks the
+        // shader variants which fit in them.
+        let capabilities = Capabilities::detect(&adapter);
+        #[allow(unused_mut)]
+        let mut features = capabilities.features;
         #[cfg(feature = "wgpu-profiler")]
         {
-            maybe_features |= wgpu_profiler::GpuProfiler::ALL_WGPU_TIMER_FEATURES;
+            features |= adapter.features() & wgpu_profiler::GpuProfiler::ALL_WGPU_TIMER_FEATURES;
         };
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
-                    features: features & maybe_features,
-                    limits,
+                    features,
+                    limits: capabilities.limits,
                 },
                 None,
             )
@@ -163,6 +196,45 @@ impl RenderContext {
     }
 }
 
+impl DeviceHandle {
+    /// Returns the capabilities of the device, which the renderer chooses shader variants to
+    /// fit.
+    pub fn capabilities(&self) -> Capabilities {
+        Capabilities::from_device(&self.device)
+    }
+
+    /// Returns whether the single pass path tag scan can be enabled for this device.
+    pub fn supports_single_pass_scan(&self) -> bool {
+        supports_single_pass_scan(&self.adapter.get_info())
+    }
+}
+
+/// Returns whether an adapter is known to guarantee the forward progress the single pass
+/// path tag scan relies on, see [`RendererOptions::single_pass_scan`].
+///
+/// Workgroups waiting on each other can hang on adapters which don't, such as tiled mobile
+/// GPUs and some software rasterizers, so only discrete NVIDIA and AMD GPUs on Vulkan and
+/// DirectX 12 are allowed for now.
+///
+/// [`RendererOptions::single_pass_scan`]: crate::RendererOptions::single_pass_scan
+pub fn supports_single_pass_scan(info: &wgpu::AdapterInfo) -> bool {
+    const VENDOR_NVIDIA: u32 = 0x10de;
+    const VENDOR_AMD: u32 = 0x1002;
+    matches!(info.vendor, VENDOR_NVIDIA | VENDOR_AMD)
+        && info.device_type == wgpu::DeviceType::DiscreteGpu
+        && matches!(info.backend, wgpu::Backend::Vulkan | wgpu::Backend::Dx12)
+}
+
+/// Returns whether compute shaders can run on `device`.
+///
+/// The [`Renderer`](crate::Renderer) runs every stage on the CPU on devices which can't, such
+/// as those of WebGL2, and only uses the GPU to present the result.
+pub fn supports_compute(device: &Device) -> bool {
+    let limits = device.limits();
+    limits.max_compute_workgroups_per_dimension != 0
+        && limits.max_storage_buffers_per_shader_stage != 0
+}
+
 /// Combination of surface and its configuration.
 #[derive(Debug)]
 pub struct RenderSurface {
@@ -195,3 +267,145 @@ pub fn block_on_wgpu<F: Future>(device: &Device, mut fut: F) -> F::Output {
         }
     }
 }
+
+/// Waker which unparks the thread blocked on a future.
+#[cfg(feature = "headless")]
+struct ThreadWake(std::thread::Thread);
+
+#[cfg(feature = "headless")]
+impl std::task::Wake for ThreadWake {
+    fn wake(self: std::sync::Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Block on a future which doesn't need the device to be polled, such as a device request.
+#[cfg(feature = "headless")]
+fn block_on<F: Future>(mut fut: F) -> F::Output {
+    let waker = std::task::Waker::from(std::sync::Arc::new(ThreadWake(std::thread::current())));
+    let mut context = std::task::Context::from_waker(&waker);
+    // Same logic as `pin_mut!` macro from `pin_utils`.
+    let mut fut = unsafe { std::pin::Pin::new_unchecked(&mut fut) };
+    loop {
+        match fut.as_mut().poll(&mut context) {
+            std::task::Poll::Pending => std::thread::park(),
+            std::task::Poll::Ready(item) => break item,
+        }
+    }
+}
+
+/// Renders a scene offscreen on the default adapter and returns the result as an RGBA8
+/// image with separate alpha, blocking until the GPU is done.
+///
+/// This creates a device and renderer for each call, which is fine for command line tools and
+/// thumbnailers. Programs which render many scenes should keep a [`crate::Renderer`] and read
+/// its target back with [`read_texture`] instead.
+#[cfg(feature = "headless")]
+pub fn render_to_image(
+    scene: &crate::Scene,
+    params: &crate::RenderParams,
+) -> Result<peniko::Image> {
+    let mut context = RenderContext::new()?;
+    let device_id = block_on(context.device(None)).ok_or("No compatible device found")?;
+    let DeviceHandle { device, queue, .. } = &context.devices[device_id];
+    let mut renderer = crate::Renderer::new(
+        device,
+        &crate::RendererOptions {
+            surface_format: None,
+            timestamp_period: queue.get_timestamp_period(),
+            use_cpu: false,
+            num_init_threads: None,
+            memory_budget_bytes: None,
+            compact_tiles: false,
+            antialiasing_method: crate::AaConfig::Area,
+            linear_blending: false,
+            dithering: false,
+            output_format: TextureFormat::Rgba8Unorm,
+            split_submissions: false,
+            single_pass_scan: false,
+            shader_source: None,
+        },
+    )?;
+    let target = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Target texture"),
+        size: wgpu::Extent3d {
+            width: params.width,
+            height: params.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: TextureFormat::Rgba8Unorm,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = target.create_view(&wgpu::TextureViewDescriptor::default());
+    renderer.render_to_texture(device, queue, scene, &view, params)?;
+    let data = read_texture(device, queue, &target)?;
+    Ok(peniko::Image::new(
+        peniko::Blob::new(std::sync::Arc::new(data)),
+        peniko::Format::Rgba8,
+        params.width,
+        params.height,
+    ))
+}
+
+/// Renders a scene offscreen like [`render_to_image`] and writes the result as a PNG.
+#[cfg(feature = "headless")]
+pub fn render_to_png(
+    scene: &crate::Scene,
+    params: &crate::RenderParams,
+    writer: impl std::io::Write,
+) -> Result<()> {
+    let image = render_to_image(scene, params)?;
+    let mut encoder = png::Encoder::new(writer, image.width, image.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(image.data.data())?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Reads back the contents of an RGBA8 texture with the [wgpu::TextureUsages::COPY_SRC]
+/// usage, as tightly packed rows, blocking until the GPU is done.
+#[cfg(feature = "headless")]
+pub fn read_texture(device: &Device, queue: &Queue, texture: &wgpu::Texture) -> Result<Vec<u8>> {
+    let (width, height) = (texture.width(), texture.height());
+    let byte_width = width * 4;
+    let alignment = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let padded_byte_width = (byte_width + alignment - 1) / alignment * alignment;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("readback"),
+        size: padded_byte_width as u64 * height as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Copy out buffer"),
+    });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_byte_width),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit([encoder.finish()]);
+    let buf_slice = buffer.slice(..);
+    let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+    buf_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+    block_on_wgpu(device, receiver.receive()).ok_or("channel was closed")??;
+    let data = buf_slice.get_mapped_range();
+    let mut result = Vec::with_capacity((byte_width * height) as usize);
+    for row in data.chunks_exact(padded_byte_width as usize) {
+        result.extend_from_slice(&row[..byte_width as usize]);
+    }
+    Ok(result)
+}
@@ -4,18 +4,26 @@
 use std::{
     borrow::Cow,
     cell::RefCell,
-    collections::{hash_map::Entry, HashMap, HashSet},
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    num::NonZeroUsize,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
 use wgpu::{
     BindGroup, BindGroupLayout, Buffer, BufferUsages, CommandEncoder, CommandEncoderDescriptor,
-    ComputePipeline, Device, Queue, Texture, TextureAspect, TextureUsages, TextureView,
-    TextureViewDimension,
+    ComputePipeline, Device, PipelineLayout, Queue, ShaderModule, Texture, TextureAspect,
+    TextureUsages, TextureView, TextureViewDimension,
 };
 
 use crate::{
-    cpu_dispatch::CpuBinding,
-    engine::{BindType, Error},
+    cpu_dispatch::{CpuBinding, CpuTexture},
+    debug_log::{self, DEBUG_LOG_SIZE},
+    engine::{BindType, Error, MemoryBudgetError, ShaderCompileError},
+    shaders::SourceMap,
     BufProxy, Command, Id, ImageProxy, Recording, ResourceProxy, ShaderId,
 };
 
@@ -25,13 +33,50 @@ pub struct WgpuEngine {
     pool: ResourcePool,
     bind_map: BindMap,
     downloads: HashMap<Id, Buffer>,
+    /// Buffers of [`Command::UploadRetained`] kept between recordings, by name.
+    retained_bufs: HashMap<&'static str, RetainedBuf>,
+    /// Retained buffers bound in the current recording, with the data uploaded to them.
+    retained_in_use: HashMap<Id, (&'static str, Vec<u8>)>,
+    bind_group_cache: BindGroupCache,
+    /// Pipelines whose creation has been deferred so that they can be compiled in parallel
+    /// by [`WgpuEngine::build_shaders_if_needed`]. `None` if shaders are created eagerly.
+    shaders_to_initialise: Option<Vec<UninitialisedShader>>,
+    /// Maximum number of bytes of GPU memory a single recording may use.
+    memory_budget: Option<u64>,
+    /// Whether recordings are split into several queue submissions.
+    split_submissions: bool,
+    /// Log that shaders built with the `debug_log` feature record into, once one has been
+    /// dispatched.
+    debug_log: Option<BufProxy>,
+    /// Whether every shader runs on the CPU, for devices without compute shaders.
+    cpu_only: bool,
 }
 
 struct Shader {
-    pipeline: ComputePipeline,
-    bind_group_layout: BindGroupLayout,
+    /// The compute pipeline, which is `None` until deferred initialisation has run.
+    pipeline: Option<ComputePipeline>,
+    /// The bind group layout, which is `None` if the engine only runs CPU shaders.
+    bind_group_layout: Option<BindGroupLayout>,
     label: &'static str,
     cpu_shader: Option<fn(u32, &[CpuBinding])>,
+    /// Source map of shaders which record into the debug log, for printing the records.
+    debug_log: Option<SourceMap>,
+}
+
+/// A shader whose module and bind group layout have been created, but not its pipeline.
+struct UninitialisedShader {
+    shader_module: ShaderModule,
+    label: &'static str,
+    shader_index: usize,
+}
+
+/// Compute pipelines being created on background threads, see
+/// [`WgpuEngine::build_shaders_in_background`].
+#[cfg(not(target_arch = "wasm32"))]
+pub struct ShaderBuild {
+    pipelines: std::sync::mpsc::Receiver<(ComputePipeline, usize)>,
+    /// Number of pipelines which haven't been installed yet.
+    remaining: usize,
 }
 
 pub enum ExternalResource<'a> {
@@ -40,6 +85,12 @@ pub enum ExternalResource<'a> {
     Image(ImageProxy, &'a TextureView),
 }
 
+/// A buffer kept between recordings, along with the data it holds.
+struct RetainedBuf {
+    buffer: Buffer,
+    data: Vec<u8>,
+}
+
 /// A buffer can exist either on the GPU or on CPU.
 enum MaterializedBuffer {
     Gpu(Buffer),
@@ -56,6 +107,8 @@ struct BindMapBuffer {
 struct BindMap {
     buf_map: HashMap<Id, BindMapBuffer>,
     image_map: HashMap<Id, (Texture, TextureView)>,
+    /// Images bound to CPU shaders.
+    cpu_image_map: HashMap<Id, RefCell<CpuTexture>>,
     pending_clears: HashSet<Id>,
 }
 
@@ -69,9 +122,60 @@ struct BufferProperties {
 
 #[derive(Default)]
 struct ResourcePool {
-    bufs: HashMap<BufferProperties, Vec<Buffer>>,
+    bufs: HashMap<BufferProperties, PoolEntry>,
+    /// Buffers freed by recordings the GPU may still be executing, oldest first.
+    in_flight: VecDeque<InFlightSubmission>,
+    /// Number of recordings run since the pool was last trimmed.
+    recordings_since_trim: u32,
 }
 
+/// The buffers freed by one submitted recording.
+///
+/// They only return to the free lists once the submission has completed, so that the next
+/// frame is encoded into different buffers while the GPU is still working on this one, rather
+/// than having to wait for it.
+struct InFlightSubmission {
+    /// Set by the queue once the submission has completed.
+    done: Arc<AtomicBool>,
+    bufs: Vec<(BufferProperties, Buffer)>,
+}
+
+/// The free buffers of one size class and usage, along with how many are needed.
+#[derive(Default)]
+struct PoolEntry {
+    free: Vec<Buffer>,
+    /// Number of buffers requested during the current recording.
+    demand: usize,
+    /// The largest `demand` of any recording since the pool was last trimmed.
+    high_water: usize,
+}
+
+/// Bind groups from previous dispatches, keyed by shader and the bound resources.
+///
+/// Buffers are recycled through the [`ResourcePool`], so consecutive frames of a static
+/// scene usually bind exactly the same GPU resources to each shader.
+#[derive(Default)]
+struct BindGroupCache {
+    groups: HashMap<(ShaderId, Vec<BindingId>), CachedBindGroup>,
+    /// Number of recordings run so far, used to evict stale bind groups.
+    recording: u64,
+}
+
+struct CachedBindGroup {
+    bind_group: BindGroup,
+    last_used: u64,
+}
+
+#[derive(Hash, PartialEq, Eq)]
+enum BindingId {
+    Buffer(wgpu::Id<Buffer>),
+    TextureView(wgpu::Id<TextureView>),
+}
+
+/// Number of recordings after which an unused bind group is dropped from the cache. This also
+/// bounds how long a cached bind group keeps otherwise freed resources alive.
+const BIND_GROUP_CACHE_LIFETIME: u64 = 8;
+
 /// The transient bind map contains short-lifetime resources.
 ///
 /// In particular, it has resources scoped to a single call of
@@ -90,8 +194,172 @@ enum TransientBuf<'a> {
 }
 
 impl WgpuEngine {
-    pub fn new() -> WgpuEngine {
-        Default::default()
+    /// Creates a new engine.
+    ///
+    /// If `use_parallel_initialisation` is set, compute pipelines aren't created by
+    /// [`WgpuEngine::add_shader`]. Instead, [`WgpuEngine::build_shaders_if_needed`] must be
+    /// called once all shaders have been added, which compiles them on multiple threads.
+    pub fn new(use_parallel_initialisation: bool) -> WgpuEngine {
+        Self {
+            shaders_to_initialise: use_parallel_initialisation.then(Vec::new),
+            ..Default::default()
+        }
+    }
+
+    /// Creates an engine which runs every shader on the CPU.
+    ///
+    /// This is for devices without compute shaders, such as WebGL2. Shaders are still added
+    /// with [`WgpuEngine::add_shader`], which only records their labels, and each must be given
+    /// a CPU implementation with [`WgpuEngine::set_cpu_shader`] before it is dispatched. Images
+    /// are kept in memory; the results are read with [`WgpuEngine::take_cpu_image`].
+    pub fn new_cpu_only() -> WgpuEngine {
+        Self {
+            cpu_only: true,
+            ..Default::default()
+        }
+    }
+
+    /// Limits the GPU memory used by the resources of each recording.
+    ///
+    /// [`WgpuEngine::run_recording`] returns a [`MemoryBudgetError`] instead of allocating any
+    /// resources if a recording needs more than `budget` bytes, counting buffers at the size
+    /// they are pooled at. The resources the recording would have freed are still freed, so
+    /// that those bound by earlier recordings of the same render don't leak.
+    pub fn set_memory_budget(&mut self, budget: Option<u64>) {
+        self.memory_budget = budget;
+    }
+
+    /// Splits the work of large recordings into several queue submissions.
+    ///
+    /// A submission is ended after dispatches totalling [`SUBMISSION_WORKGROUP_LIMIT`]
+    /// workgroups, and after every indirect dispatch, as those are sized by the scene. This keeps
+    /// very heavy frames from running long enough in a single submission to trigger the GPU
+    /// watchdog of the operating system, at the cost of some submission overhead.
+    pub fn set_split_submissions(&mut self, split: bool) {
+        self.split_submissions = split;
+    }
+
+    /// Creates the compute pipelines of all shaders added since the engine was created.
+    ///
+    /// Pipeline creation is the dominant startup cost on many drivers, and wgpu allows it to
+    /// happen concurrently. The work is spread over `num_threads` threads, or a number chosen
+    /// based on the available parallelism if `None`. This does nothing if the engine wasn't
+    /// created with parallel initialisation.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn build_shaders_if_needed(&mut self, device: &Device, num_threads: Option<NonZeroUsize>) {
+        let Some(new_shaders) = self.shaders_to_initialise.take() else {
+            return;
+        };
+        let (tx, rx) = std::sync::mpsc::channel::<(ComputePipeline, usize)>();
+        std::thread::scope(|scope| {
+            for chunk in split_for_threads(new_shaders, num_threads) {
+                let tx = tx.clone();
+                let shaders = &self.shaders;
+                scope.spawn(move || {
+                    for shader in chunk {
+                        let bind_group_layout = shaders[shader.shader_index]
+                            .bind_group_layout
+                            .as_ref()
+                            .expect("deferred shaders have a bind group layout");
+                        let pipeline = create_compute_pipeline(
+                            device,
+                            shader.label,
+                            &shader.shader_module,
+                            bind_group_layout,
+                        );
+                        tx.send((pipeline, shader.shader_index)).unwrap();
+                    }
+                });
+            }
+            drop(tx);
+        });
+        for (pipeline, index) in rx {
+            self.shaders[index].pipeline = Some(pipeline);
+        }
+    }
+
+    /// Starts creating the compute pipelines of all shaders added since the engine was created
+    /// on background threads, and returns without waiting for them.
+    ///
+    /// The threads are chosen as in [`WgpuEngine::build_shaders_if_needed`]. The pipelines are
+    /// installed by [`WgpuEngine::poll_shader_build`] as they finish, and recordings which
+    /// dispatch a shader without a pipeline fail until then.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn build_shaders_in_background(
+        &mut self,
+        device: &Arc<Device>,
+        num_threads: Option<NonZeroUsize>,
+    ) -> ShaderBuild {
+        let new_shaders = self.shaders_to_initialise.take().unwrap_or_default();
+        let remaining = new_shaders.len();
+        // The threads outlive this call, so they can't borrow the bind group layouts.
+        let jobs = new_shaders
+            .into_iter()
+            .map(|shader| {
+                let bind_group_layout = self.shaders[shader.shader_index]
+                    .bind_group_layout
+                    .as_ref()
+                    .expect("deferred shaders have a bind group layout");
+                let pipeline_layout = create_pipeline_layout(device, bind_group_layout);
+                (shader, pipeline_layout)
+            })
+            .collect();
+        let (tx, rx) = std::sync::mpsc::channel();
+        for chunk in split_for_threads(jobs, num_threads) {
+            let tx = tx.clone();
+            let device = device.clone();
+            std::thread::spawn(move || {
+                for (shader, pipeline_layout) in chunk {
+                    let pipeline =
+                        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                            label: Some(shader.label),
+                            layout: Some(&pipeline_layout),
+                            module: &shader.shader_module,
+                            entry_point: "main",
+                        });
+                    // The build may have been dropped, in which case the pipeline isn't needed.
+                    let _ = tx.send((pipeline, shader.shader_index));
+                }
+            });
+        }
+        ShaderBuild {
+            pipelines: rx,
+            remaining,
+        }
+    }
+
+    /// Installs the pipelines of `build` which have been created so far, and returns whether
+    /// all of them have.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll_shader_build(&mut self, build: &mut ShaderBuild) -> bool {
+        while let Ok((pipeline, index)) = build.pipelines.try_recv() {
+            self.shaders[index].pipeline = Some(pipeline);
+            build.remaining -= 1;
+        }
+        build.remaining == 0
+    }
+
+    /// Creates the compute pipelines of all shaders added since the engine was created.
+    ///
+    /// Threads aren't available on the web, so this compiles all deferred pipelines serially.
+    #[cfg(target_arch = "wasm32")]
+    pub fn build_shaders_if_needed(&mut self, device: &Device, _: Option<NonZeroUsize>) {
+        let Some(new_shaders) = self.shaders_to_initialise.take() else {
+            return;
+        };
+        for shader in new_shaders {
+            let bind_group_layout = self.shaders[shader.shader_index]
+                .bind_group_layout
+                .as_ref()
+                .expect("deferred shaders have a bind group layout");
+            let pipeline = create_compute_pipeline(
+                device,
+                shader.label,
+                &shader.shader_module,
+                bind_group_layout,
+            );
+            self.shaders[shader.shader_index].pipeline = Some(pipeline);
+        }
     }
 
     /// Add a shader.
@@ -101,17 +369,43 @@ impl WgpuEngine {
     ///
     /// Maybe should do template instantiation here? But shader compilation pipeline feels maybe
     /// a bit separate.
+    ///
+    /// If a `source_map` is provided, compile errors are reported as a [`ShaderCompileError`]
+    /// pointing at the original (unpreprocessed) shader file and line. Errors can only be
+    /// captured synchronously on native targets; on the web they are still reported through the
+    /// device's uncaptured error handler.
     pub fn add_shader(
         &mut self,
         device: &Device,
         label: &'static str,
         wgsl: Cow<'static, str>,
+        source_map: Option<&SourceMap>,
         layout: &[BindType],
     ) -> Result<ShaderId, Error> {
+        let id = self.shaders.len();
+        if self.cpu_only {
+            // The device may not support compute shaders at all, so none are created.
+            self.shaders.push(Shader {
+                pipeline: None,
+                bind_group_layout: None,
+                label,
+                cpu_shader: None,
+                debug_log: None,
+            });
+            return Ok(ShaderId(id));
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
         let shader_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
             label: Some(label),
             source: wgpu::ShaderSource::Wgsl(wgsl),
         });
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(error) = crate::util::block_on_wgpu(device, device.pop_error_scope()) {
+            return Err(shader_compile_error(label, source_map, error).into());
+        }
+        #[cfg(target_arch = "wasm32")]
+        let _ = source_map;
         let entries = layout
             .iter()
             .enumerate()
@@ -164,34 +458,106 @@ impl WgpuEngine {
             label: None,
             entries: &entries,
         });
-        let compute_pipeline_layout =
-            device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: &[&bind_group_layout],
-                push_constant_ranges: &[],
+        let pipeline = if let Some(uninit) = self.shaders_to_initialise.as_mut() {
+            uninit.push(UninitialisedShader {
+                shader_module,
+                label,
+                shader_index: id,
             });
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-            label: Some(label),
-            layout: Some(&compute_pipeline_layout),
-            module: &shader_module,
-            entry_point: "main",
-        });
+            None
+        } else {
+            Some(create_compute_pipeline(
+                device,
+                label,
+                &shader_module,
+                &bind_group_layout,
+            ))
+        };
         let cpu_shader = None;
         let shader = Shader {
             pipeline,
-            bind_group_layout,
+            bind_group_layout: Some(bind_group_layout),
             label,
             cpu_shader,
+            debug_log: None,
         };
-        let id = self.shaders.len();
         self.shaders.push(shader);
         Ok(ShaderId(id))
     }
 
+    /// Checks that a shader compiles, reporting errors like [`WgpuEngine::add_shader`], without
+    /// adding it to an engine or creating a pipeline.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn validate_shader(
+        device: &Device,
+        label: &'static str,
+        wgsl: Cow<'static, str>,
+        source_map: Option<&SourceMap>,
+    ) -> Result<(), Error> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(wgsl),
+        });
+        match crate::util::block_on_wgpu(device, device.pop_error_scope()) {
+            Some(error) => Err(shader_compile_error(label, source_map, error).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Adds a shader which records into the debug log with the helpers of
+    /// `shader/shared/debug.wgsl`.
+    ///
+    /// The log is bound after the bindings of `layout`, and its records are printed after each
+    /// recording which dispatches the shader.
+    #[cfg(feature = "debug_log")]
+    pub fn add_debug_log_shader(
+        &mut self,
+        device: &Device,
+        label: &'static str,
+        wgsl: Cow<'static, str>,
+        source_map: Option<&SourceMap>,
+        layout: &[BindType],
+    ) -> Result<ShaderId, Error> {
+        let wgsl = wgsl
+            .replace("DEBUG_LOG_BINDING", &layout.len().to_string())
+            .replace("DEBUG_LOG_SHADER", &format!("{}u", self.shaders.len()));
+        let mut layout = layout.to_vec();
+        layout.push(BindType::Buffer);
+        let id = self.add_shader(device, label, wgsl.into(), source_map, &layout)?;
+        self.shaders[id.0].debug_log = Some(source_map.cloned().unwrap_or_default());
+        Ok(id)
+    }
+
+    /// Returns the label the shader was added with.
+    pub fn shader_label(&self, id: ShaderId) -> &'static str {
+        self.shaders[id.0].label
+    }
+
+    /// Replace all shaders with the ones compiled into `other`.
+    ///
+    /// This is used for hot reloading and for changing workgroup sizes. Shader ids handed out
+    /// by `other` remain valid, while the resources owned by this engine are kept.
+    pub fn replace_shaders(&mut self, other: WgpuEngine) {
+        self.shaders = other.shaders;
+        // Cached bind groups were created with the old bind group layouts.
+        self.bind_group_cache = BindGroupCache::default();
+    }
+
     pub fn set_cpu_shader(&mut self, id: ShaderId, f: fn(u32, &[CpuBinding])) {
         self.shaders[id.0].cpu_shader = Some(f);
     }
 
+    /// Removes an image written by CPU shaders from the engine and returns it.
+    ///
+    /// This returns `None` if the image was never bound, or was bound on the GPU.
+    pub fn take_cpu_image(&mut self, proxy: ImageProxy) -> Option<CpuTexture> {
+        self.bind_map
+            .cpu_image_map
+            .remove(&proxy.id)
+            .map(RefCell::into_inner)
+    }
+
     pub fn run_recording(
         &mut self,
         device: &Device,
@@ -201,6 +567,41 @@ impl WgpuEngine {
         label: &'static str,
         #[cfg(feature = "wgpu-profiler")] profiler: &mut wgpu_profiler::GpuProfiler,
     ) -> Result<(), Error> {
+        if let Some(budget) = self.memory_budget {
+            let external: Vec<Id> = external_resources
+                .iter()
+                .map(|resource| match resource {
+                    ExternalResource::Buf(proxy, _) => proxy.id,
+                    ExternalResource::Image(proxy, _) => proxy.id,
+                })
+                .collect();
+            let required = recording.memory_footprint(&external, |size| {
+                ResourcePool::size_class(size, SIZE_CLASS_BITS)
+            });
+            if required > budget {
+                // Earlier recordings of the same render may have left resources bound for this
+                // one to free, which would otherwise leak.
+                let free_bufs = recording
+                    .commands
+                    .iter()
+                    .filter_map(|command| match command {
+                        Command::FreeBuf(proxy) => Some(proxy.id),
+                        _ => None,
+                    })
+                    .collect();
+                let free_images = recording
+                    .commands
+                    .iter()
+                    .filter_map(|command| match command {
+                        Command::FreeImage(proxy) => Some(proxy.id),
+                        _ => None,
+                    })
+                    .collect();
+                self.free_resources(queue, free_bufs, free_images);
+                return Err(MemoryBudgetError { required, budget }.into());
+            }
+        }
+        self.pool.reclaim();
         let mut free_bufs: HashSet<Id> = Default::default();
         let mut free_images: HashSet<Id> = Default::default();
         let mut transient_map = TransientBindMap::new(external_resources);
@@ -209,8 +610,37 @@ impl WgpuEngine {
             device.create_command_encoder(&CommandEncoderDescriptor { label: Some(label) });
         #[cfg(feature = "wgpu-profiler")]
         profiler.begin_scope(label, &mut encoder, device);
+        let debug_log = self.prepare_debug_log(recording, &mut encoder);
+        // Workgroups dispatched since the last submission, when splitting submissions.
+        let mut submission_workgroups = 0;
         for command in &recording.commands {
             match command {
+                Command::Upload(buf_proxy, bytes)
+                | Command::UploadRetained(buf_proxy, bytes)
+                | Command::UploadUniform(buf_proxy, bytes)
+                    if self.cpu_only =>
+                {
+                    transient_map
+                        .bufs
+                        .insert(buf_proxy.id, TransientBuf::Cpu(bytes));
+                }
+                Command::UploadImage(image_proxy, bytes) if self.cpu_only => {
+                    let mut texture =
+                        CpuTexture::new(image_proxy.width as usize, image_proxy.height as usize);
+                    texture.write(0, 0, image_proxy.width as usize, bytes);
+                    self.bind_map
+                        .cpu_image_map
+                        .insert(image_proxy.id, RefCell::new(texture));
+                }
+                Command::WriteImage(proxy, [x, y, width, _], data) if self.cpu_only => {
+                    self.bind_map
+                        .get_or_create_cpu_image(*proxy)
+                        .get_mut()
+                        .write(*x as usize, *y as usize, *width as usize, data);
+                }
+                Command::Download(_) if self.cpu_only => {
+                    return Err("buffers can't be downloaded from CPU shaders".into());
+                }
                 Command::Upload(buf_proxy, bytes) => {
                     transient_map
                         .bufs
@@ -225,6 +655,38 @@ impl WgpuEngine {
                     queue.write_buffer(&buf, 0, bytes);
                     self.bind_map.insert_buf(buf_proxy, buf);
                 }
+                Command::UploadRetained(buf_proxy, bytes) => {
+                    transient_map
+                        .bufs
+                        .insert(buf_proxy.id, TransientBuf::Cpu(bytes));
+                    let retained = self
+                        .retained_bufs
+                        .remove(buf_proxy.name)
+                        .filter(|retained| retained.buffer.size() >= buf_proxy.size);
+                    let buf = if let Some(retained) = retained {
+                        // Only write the chunks that changed since the last upload, and
+                        // whatever goes past its end.
+                        let common = retained.data.len().min(bytes.len());
+                        for range in dirty_ranges(&retained.data[..common], &bytes[..common]) {
+                            queue.write_buffer(&retained.buffer, range.start as u64, &bytes[range]);
+                        }
+                        if common < bytes.len() {
+                            queue.write_buffer(&retained.buffer, common as u64, &bytes[common..]);
+                        }
+                        retained.buffer
+                    } else {
+                        let usage =
+                            BufferUsages::COPY_SRC | BufferUsages::COPY_DST | BufferUsages::STORAGE;
+                        let buf = self
+                            .pool
+                            .get_buf(buf_proxy.size, buf_proxy.name, usage, device);
+                        queue.write_buffer(&buf, 0, bytes);
+                        buf
+                    };
+                    self.retained_in_use
+                        .insert(buf_proxy.id, (buf_proxy.name, bytes.clone()));
+                    self.bind_map.insert_buf(buf_proxy, buf);
+                }
                 Command::UploadUniform(buf_proxy, bytes) => {
                     transient_map
                         .bufs
@@ -331,23 +793,49 @@ impl WgpuEngine {
                             transient_map.create_cpu_resources(&mut self.bind_map, bindings);
                         cpu_shader(wg_size.0, &resources);
                     } else {
+                        let layout = shader
+                            .bind_group_layout
+                            .as_ref()
+                            .ok_or_else(|| format!("{} has no CPU implementation", shader.label))?;
                         let bind_group = transient_map.create_bind_group(
                             &mut self.bind_map,
                             &mut self.pool,
+                            &mut self.bind_group_cache,
                             device,
                             queue,
                             &mut encoder,
-                            &shader.bind_group_layout,
-                            bindings,
+                            *shader_id,
+                            layout,
+                            &debug_log_bindings(shader, bindings, debug_log),
                         )?;
+                        let pipeline = shader
+                            .pipeline
+                            .as_ref()
+                            .ok_or("shader pipelines have not been built")?;
                         let mut cpass = encoder.begin_compute_pass(&Default::default());
                         #[cfg(feature = "wgpu-profiler")]
                         profiler.begin_scope(shader.label, &mut cpass, device);
-                        cpass.set_pipeline(&shader.pipeline);
-                        cpass.set_bind_group(0, &bind_group, &[]);
+                        cpass.set_pipeline(pipeline);
+                        cpass.set_bind_group(0, bind_group, &[]);
                         cpass.dispatch_workgroups(wg_size.0, wg_size.1, wg_size.2);
                         #[cfg(feature = "wgpu-profiler")]
                         profiler.end_scope(&mut cpass);
+                        drop(cpass);
+                        submission_workgroups +=
+                            wg_size.0 as u64 * wg_size.1 as u64 * wg_size.2 as u64;
+                        if self.split_submissions
+                            && submission_workgroups >= SUBMISSION_WORKGROUP_LIMIT
+                        {
+                            submission_workgroups = 0;
+                            split_submission(
+                                device,
+                                queue,
+                                &mut encoder,
+                                label,
+                                #[cfg(feature = "wgpu-profiler")]
+                                profiler,
+                            );
+                        }
                     }
                 }
                 Command::DispatchIndirect(shader_id, proxy, offset, bindings) => {
@@ -358,7 +846,7 @@ impl WgpuEngine {
                         if let CpuBinding::BufferRW(b) = self.bind_map.get_cpu_buf(proxy.id) {
                             let slice = b.borrow();
                             let indirect: &[u32] = bytemuck::cast_slice(&slice);
-                            n_wg = indirect[0];
+                            n_wg = indirect[*offset as usize / 4];
                         } else {
                             panic!("indirect buffer missing from bind map");
                         }
@@ -366,14 +854,20 @@ impl WgpuEngine {
                             transient_map.create_cpu_resources(&mut self.bind_map, bindings);
                         cpu_shader(n_wg, &resources);
                     } else {
+                        let layout = shader
+                            .bind_group_layout
+                            .as_ref()
+                            .ok_or_else(|| format!("{} has no CPU implementation", shader.label))?;
                         let bind_group = transient_map.create_bind_group(
                             &mut self.bind_map,
                             &mut self.pool,
+                            &mut self.bind_group_cache,
                             device,
                             queue,
                             &mut encoder,
-                            &shader.bind_group_layout,
-                            bindings,
+                            *shader_id,
+                            layout,
+                            &debug_log_bindings(shader, bindings, debug_log),
                         )?;
                         transient_map.materialize_gpu_buf_for_indirect(
                             &mut self.bind_map,
@@ -382,11 +876,15 @@ impl WgpuEngine {
                             queue,
                             proxy,
                         );
+                        let pipeline = shader
+                            .pipeline
+                            .as_ref()
+                            .ok_or("shader pipelines have not been built")?;
                         let mut cpass = encoder.begin_compute_pass(&Default::default());
                         #[cfg(feature = "wgpu-profiler")]
                         profiler.begin_scope(shader.label, &mut cpass, device);
-                        cpass.set_pipeline(&shader.pipeline);
-                        cpass.set_bind_group(0, &bind_group, &[]);
+                        cpass.set_pipeline(pipeline);
+                        cpass.set_bind_group(0, bind_group, &[]);
                         let buf = self
                             .bind_map
                             .get_gpu_buf(proxy.id)
@@ -394,6 +892,18 @@ impl WgpuEngine {
                         cpass.dispatch_workgroups_indirect(buf, *offset);
                         #[cfg(feature = "wgpu-profiler")]
                         profiler.end_scope(&mut cpass);
+                        drop(cpass);
+                        if self.split_submissions {
+                            submission_workgroups = 0;
+                            split_submission(
+                                device,
+                                queue,
+                                &mut encoder,
+                                label,
+                                #[cfg(feature = "wgpu-profiler")]
+                                profiler,
+                            );
+                        }
                     }
                 }
                 Command::Download(proxy) => {
@@ -432,39 +942,309 @@ impl WgpuEngine {
         }
         #[cfg(feature = "wgpu-profiler")]
         profiler.end_scope(&mut encoder);
+        let debug_log_readback = debug_log.and_then(|log| {
+            let src_buf = self.bind_map.get_gpu_buf(log.id)?;
+            let usage = BufferUsages::MAP_READ | BufferUsages::COPY_DST;
+            let buf = self
+                .pool
+                .get_buf(DEBUG_LOG_SIZE, "debug_log", usage, device);
+            encoder.copy_buffer_to_buffer(src_buf, 0, &buf, 0, DEBUG_LOG_SIZE);
+            Some(buf)
+        });
         queue.submit(Some(encoder.finish()));
+        if let Some(buf) = debug_log_readback {
+            self.print_debug_log(device, buf);
+        }
+        self.free_resources(queue, free_bufs, free_images);
+        self.pool.end_recording();
+        self.bind_group_cache.end_recording();
+        Ok(())
+    }
+
+    /// Releases bound buffers and images, once the work submitted so far no longer uses them.
+    fn free_resources(&mut self, queue: &Queue, free_bufs: HashSet<Id>, free_images: HashSet<Id>) {
+        let mut submission = InFlightSubmission {
+            done: Arc::new(AtomicBool::new(false)),
+            bufs: vec![],
+        };
         for id in free_bufs {
             if let Some(buf) = self.bind_map.buf_map.remove(&id) {
                 if let MaterializedBuffer::Gpu(gpu_buf) = buf.buffer {
+                    // Retained buffers are kept for the next upload with the same name, rather
+                    // than returned to the pool. Writes to them are ordered after this
+                    // submission by the queue.
+                    if let Some((name, data)) = self.retained_in_use.remove(&id) {
+                        let retained = RetainedBuf {
+                            buffer: gpu_buf,
+                            data,
+                        };
+                        self.retained_bufs.insert(name, retained);
+                        continue;
+                    }
                     let props = BufferProperties {
                         size: gpu_buf.size(),
                         usages: gpu_buf.usage(),
                         #[cfg(feature = "buffer_labels")]
                         name: buf.label,
                     };
-                    self.pool.bufs.entry(props).or_default().push(gpu_buf);
+                    submission.bufs.push((props, gpu_buf));
                 }
             }
         }
+        if !submission.bufs.is_empty() {
+            let done = submission.done.clone();
+            queue.on_submitted_work_done(move || done.store(true, Ordering::Release));
+            self.pool.in_flight.push_back(submission);
+        }
         for id in free_images {
             if let Some((texture, view)) = self.bind_map.image_map.remove(&id) {
                 // TODO: have a pool to avoid needless re-allocation
                 drop(texture);
                 drop(view);
             }
+            self.bind_map.cpu_image_map.remove(&id);
         }
-        Ok(())
     }
 
-    pub fn get_download(&self, buf: BufProxy) -> Option<&Buffer> {
-        self.downloads.get(&buf.id)
+    /// Clears the debug log if `recording` dispatches a shader which records into it, returning
+    /// the log.
+    fn prepare_debug_log(
+        &mut self,
+        recording: &Recording,
+        encoder: &mut CommandEncoder,
+    ) -> Option<BufProxy> {
+        let uses_log = recording.commands.iter().any(|command| match command {
+            Command::Dispatch(id, ..) | Command::DispatchIndirect(id, ..) => {
+                let shader = &self.shaders[id.0];
+                shader.debug_log.is_some() && shader.cpu_shader.is_none()
+            }
+            _ => false,
+        });
+        if !uses_log {
+            return None;
+        }
+        let log = *self
+            .debug_log
+            .get_or_insert_with(|| BufProxy::new(DEBUG_LOG_SIZE, "debug_log"));
+        // The log is never freed, so it stays in the bind map once it has been materialized.
+        match self.bind_map.get_gpu_buf(log.id) {
+            Some(buf) => encoder.clear_buffer(buf, 0, None),
+            None => {
+                self.bind_map.pending_clears.insert(log.id);
+            }
+        }
+        Some(log)
+    }
+
+    /// Waits for the copy of the debug log in `buf` and prints its records.
+    ///
+    /// The web can't block on the map, so the log isn't printed there.
+    fn print_debug_log(&mut self, #[allow(unused)] device: &Device, buf: Buffer) {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let slice = buf.slice(..);
+            let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+            slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+            if let Some(Ok(())) = crate::util::block_on_wgpu(device, receiver.receive()) {
+                // Pooled buffers are rounded up to their size class.
+                let log: Vec<u32> =
+                    bytemuck::cast_slice(&slice.get_mapped_range()[..DEBUG_LOG_SIZE as usize])
+                        .to_vec();
+                debug_log::print(&log, |shader, line| {
+                    let Some(shader) = self.shaders.get(shader as usize) else {
+                        return format!("<unknown shader {shader}>:{line}");
+                    };
+                    match shader
+                        .debug_log
+                        .as_ref()
+                        .and_then(|map| map.get(line as usize))
+                    {
+                        Some(location) => format!("{}.wgsl:{}", location.file, location.line),
+                        None => format!("{}:{line}", shader.label),
+                    }
+                });
+                buf.unmap();
+            }
+        }
+        let props = BufferProperties {
+            size: buf.size(),
+            usages: buf.usage(),
+            #[cfg(feature = "buffer_labels")]
+            name: "debug_log",
+        };
+        self.pool.bufs.entry(props).or_default().free.push(buf);
+    }
+
+    /// Reads back the contents of a buffer prepared with [`Recording::download`].
+    ///
+    /// The recording containing the download must already have been run. The staging buffer is
+    /// unmapped and returned to the pool once its contents have been copied out.
+    pub async fn read_download(&mut self, buf: BufProxy) -> Result<Vec<u8>, Error> {
+        let staging = self
+            .downloads
+            .remove(&buf.id)
+            .ok_or("buffer has not been downloaded")?;
+        let slice = staging.slice(..);
+        let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
+        slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
+        receiver.receive().await.ok_or("channel was closed")??;
+        // Pooled buffers are rounded up to their size class.
+        let data = slice.get_mapped_range()[..buf.size as usize].to_vec();
+        staging.unmap();
+        let props = BufferProperties {
+            size: staging.size(),
+            usages: staging.usage(),
+            #[cfg(feature = "buffer_labels")]
+            name: "download",
+        };
+        self.pool.bufs.entry(props).or_default().free.push(staging);
+        Ok(data)
+    }
+}
+
+/// Appends the debug log to `bindings` if `shader` records into it.
+fn debug_log_bindings<'a>(
+    shader: &Shader,
+    bindings: &'a [ResourceProxy],
+    debug_log: Option<BufProxy>,
+) -> Cow<'a, [ResourceProxy]> {
+    match debug_log.filter(|_| shader.debug_log.is_some()) {
+        Some(log) => {
+            let mut bindings = bindings.to_vec();
+            bindings.push(ResourceProxy::Buf(log));
+            Cow::Owned(bindings)
+        }
+        None => Cow::Borrowed(bindings),
+    }
+}
+
+/// Number of workgroups after which a submission is ended when splitting submissions.
+const SUBMISSION_WORKGROUP_LIMIT: u64 = 1 << 16;
+
+/// Submits the commands encoded so far and replaces `encoder` with a fresh one.
+fn split_submission(
+    device: &Device,
+    queue: &Queue,
+    encoder: &mut CommandEncoder,
+    label: &'static str,
+    #[cfg(feature = "wgpu-profiler")] profiler: &mut wgpu_profiler::GpuProfiler,
+) {
+    let next = device.create_command_encoder(&CommandEncoderDescriptor { label: Some(label) });
+    #[cfg_attr(not(feature = "wgpu-profiler"), allow(unused_mut))]
+    let mut finished = std::mem::replace(encoder, next);
+    // Profiler scopes can't span command encoders, so the scope of the recording is split too.
+    #[cfg(feature = "wgpu-profiler")]
+    profiler.end_scope(&mut finished);
+    queue.submit(Some(finished.finish()));
+    #[cfg(feature = "wgpu-profiler")]
+    profiler.begin_scope(label, encoder, device);
+}
+
+// TODO: persist compiled pipelines across runs. wgpu 0.17 doesn't expose a pipeline cache
+// (`wgpu::PipelineCache` only arrived in later releases), so once we upgrade this is the place
+// to thread one through, keyed by adapter info and a hash of the preprocessed shader source.
+fn create_compute_pipeline(
+    device: &Device,
+    label: &'static str,
+    shader_module: &ShaderModule,
+    bind_group_layout: &BindGroupLayout,
+) -> ComputePipeline {
+    let compute_pipeline_layout = create_pipeline_layout(device, bind_group_layout);
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(label),
+        layout: Some(&compute_pipeline_layout),
+        module: shader_module,
+        entry_point: "main",
+    })
+}
+
+fn create_pipeline_layout(device: &Device, bind_group_layout: &BindGroupLayout) -> PipelineLayout {
+    device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        label: None,
+        bind_group_layouts: &[bind_group_layout],
+        push_constant_ranges: &[],
+    })
+}
+
+/// Splits the deferred pipelines into a chunk per initialisation thread, using `num_threads`
+/// threads or a number based on the available parallelism if `None`.
+#[cfg(not(target_arch = "wasm32"))]
+fn split_for_threads<T>(mut items: Vec<T>, num_threads: Option<NonZeroUsize>) -> Vec<Vec<T>> {
+    let num_threads = num_threads.map(NonZeroUsize::get).unwrap_or_else(|| {
+        // Leave a couple of cores for the rest of the application, but always use a few
+        // threads since pipeline creation frequently blocks on the driver.
+        std::thread::available_parallelism().map_or(2, |it| it.get().max(4) - 2)
+    });
+    let mut chunks = Vec::with_capacity(num_threads);
+    let mut remaining = items.len();
+    for thread_ix in (1..=num_threads).rev() {
+        let chunk = items.split_off(items.len() - remaining / thread_ix);
+        remaining -= chunk.len();
+        chunks.push(chunk);
     }
+    chunks
+}
 
-    pub fn free_download(&mut self, buf: BufProxy) {
-        self.downloads.remove(&buf.id);
+/// Converts a shader module creation error into a [`ShaderCompileError`], using the source map
+/// to translate the reported line back to the original shader sources.
+#[cfg(not(target_arch = "wasm32"))]
+fn shader_compile_error(
+    label: &str,
+    source_map: Option<&SourceMap>,
+    error: wgpu::Error,
+) -> ShaderCompileError {
+    let message = error.to_string();
+    // Diagnostics are rendered by codespan as `┌─ <name>:<line>:<column>`, where line numbers
+    // refer to the preprocessed source.
+    let line = message.split("┌─").nth(1).and_then(|location| {
+        let location = location.split_whitespace().next()?;
+        let mut parts = location.rsplit(':');
+        let _column = parts.next()?;
+        parts.next()?.parse::<usize>().ok()
+    });
+    match (line, source_map) {
+        (Some(line), Some(source_map)) => match source_map.get(line) {
+            Some(location) => ShaderCompileError {
+                file: Some(location.file.clone()),
+                line: Some(location.line),
+                message,
+            },
+            None => ShaderCompileError {
+                file: Some(label.into()),
+                line: None,
+                message,
+            },
+        },
+        (line, _) => ShaderCompileError {
+            file: Some(label.into()),
+            line,
+            message,
+        },
     }
 }
 
+/// Size of the chunks in which retained buffers are compared and written, in bytes.
+const DIRTY_CHUNK_SIZE: usize = 256;
+
+/// Returns the byte ranges, in whole chunks, in which `new` differs from `old`.
+///
+/// Both slices have the same length, which is a multiple of 4 like the ranges.
+fn dirty_ranges<'a>(old: &'a [u8], new: &'a [u8]) -> impl Iterator<Item = Range<usize>> + 'a {
+    let mut chunks = old
+        .chunks(DIRTY_CHUNK_SIZE)
+        .zip(new.chunks(DIRTY_CHUNK_SIZE))
+        .enumerate()
+        .peekable();
+    std::iter::from_fn(move || {
+        let (start, _) = chunks.find(|(_, (old, new))| old != new)?;
+        let mut end = start + 1;
+        while chunks.next_if(|(_, (old, new))| old != new).is_some() {
+            end += 1;
+        }
+        Some(start * DIRTY_CHUNK_SIZE..(end * DIRTY_CHUNK_SIZE).min(new.len()))
+    })
+}
+
 impl BindMap {
     fn insert_buf(&mut self, proxy: &BufProxy, buffer: Buffer) {
         self.buf_map.insert(
@@ -513,6 +1293,12 @@ impl BindMap {
         self.buf_map.get(&proxy.id)
     }
 
+    fn get_or_create_cpu_image(&mut self, proxy: ImageProxy) -> &mut RefCell<CpuTexture> {
+        self.cpu_image_map.entry(proxy.id).or_insert_with(|| {
+            RefCell::new(CpuTexture::new(proxy.width as usize, proxy.height as usize))
+        })
+    }
+
     fn get_or_create_image(
         &mut self,
         proxy: ImageProxy,
@@ -532,7 +1318,9 @@ impl BindMap {
                     mip_level_count: 1,
                     sample_count: 1,
                     dimension: wgpu::TextureDimension::D2,
-                    usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                    usage: TextureUsages::TEXTURE_BINDING
+                        | TextureUsages::STORAGE_BINDING
+                        | TextureUsages::COPY_DST,
                     format,
                     view_formats: &[],
                 });
@@ -554,6 +1342,16 @@ impl BindMap {
 
 const SIZE_CLASS_BITS: u32 = 1;
 
+/// Maximum number of submissions whose freed buffers are held back while the GPU may still be
+/// using them. This allows for about three frames in flight, as a frame is made up of one to
+/// three recordings. Beyond this, the oldest are recycled anyway, which is still correct as
+/// wgpu orders the accesses, but may make the GPU wait for the earlier submission.
+const MAX_SUBMISSIONS_IN_FLIGHT: usize = 8;
+
+/// Number of recordings over which the high water mark of buffer usage is tracked before the
+/// pool is trimmed to it.
+const POOL_TRIM_INTERVAL: u32 = 64;
+
 impl ResourcePool {
     /// Get a buffer from the pool or create one.
     fn get_buf(
@@ -570,10 +1368,10 @@ impl ResourcePool {
             #[cfg(feature = "buffer_labels")]
             name,
         };
-        if let Some(buf_vec) = self.bufs.get_mut(&props) {
-            if let Some(buf) = buf_vec.pop() {
-                return buf;
-            }
+        let entry = self.bufs.entry(props).or_default();
+        entry.demand += 1;
+        if let Some(buf) = entry.free.pop() {
+            return buf;
         }
         device.create_buffer(&wgpu::BufferDescriptor {
             #[cfg(feature = "buffer_labels")]
@@ -586,6 +1384,45 @@ impl ResourcePool {
         })
     }
 
+    /// Return the buffers of completed submissions to the free lists.
+    ///
+    /// Completion is signalled when the device is polled, which applications are expected to
+    /// do regularly.
+    fn reclaim(&mut self) {
+        while let Some(submission) = self.in_flight.front() {
+            if self.in_flight.len() <= MAX_SUBMISSIONS_IN_FLIGHT
+                && !submission.done.load(Ordering::Acquire)
+            {
+                break;
+            }
+            let submission = self.in_flight.pop_front().unwrap();
+            for (props, buf) in submission.bufs {
+                self.bufs.entry(props).or_default().free.push(buf);
+            }
+        }
+    }
+
+    /// Update usage statistics at the end of a recording, trimming the pool if due.
+    ///
+    /// Free buffers beyond the most that any single recording needed in the last
+    /// [`POOL_TRIM_INTERVAL`] recordings are released, so that a spike in usage (for example
+    /// a single very complex frame) doesn't hold on to memory indefinitely.
+    fn end_recording(&mut self) {
+        for entry in self.bufs.values_mut() {
+            entry.high_water = entry.high_water.max(entry.demand);
+            entry.demand = 0;
+        }
+        self.recordings_since_trim += 1;
+        if self.recordings_since_trim >= POOL_TRIM_INTERVAL {
+            self.recordings_since_trim = 0;
+            self.bufs.retain(|_, entry| {
+                entry.free.truncate(entry.high_water);
+                entry.high_water = 0;
+                !entry.free.is_empty()
+            });
+        }
+    }
+
     /// Quantize a size up to the nearest size class.
     fn size_class(x: u64, bits: u32) -> u64 {
         if x > 1 << bits {
@@ -598,6 +1435,15 @@ impl ResourcePool {
     }
 }
 
+impl BindGroupCache {
+    fn end_recording(&mut self) {
+        let recording = self.recording;
+        self.groups
+            .retain(|_, group| recording - group.last_used < BIND_GROUP_CACHE_LIFETIME);
+        self.recording += 1;
+    }
+}
+
 impl BindMapBuffer {
     // Upload a buffer from CPU to GPU if needed.
     //
@@ -657,16 +1503,18 @@ impl<'a> TransientBindMap<'a> {
     }
 
     #[allow(clippy::too_many_arguments)]
-    fn create_bind_group(
+    fn create_bind_group<'c>(
         &mut self,
         bind_map: &mut BindMap,
         pool: &mut ResourcePool,
+        cache: &'c mut BindGroupCache,
         device: &Device,
         queue: &Queue,
         encoder: &mut CommandEncoder,
+        shader_id: ShaderId,
         layout: &BindGroupLayout,
         bindings: &[ResourceProxy],
-    ) -> Result<BindGroup, Error> {
+    ) -> Result<&'c BindGroup, Error> {
         for proxy in bindings {
             match proxy {
                 ResourceProxy::Buf(proxy) => {
@@ -710,7 +1558,10 @@ impl<'a> TransientBindMap<'a> {
                             mip_level_count: 1,
                             sample_count: 1,
                             dimension: wgpu::TextureDimension::D2,
-                            usage: TextureUsages::TEXTURE_BINDING | TextureUsages::COPY_DST,
+                            // Images bound to shaders may be written as storage textures.
+                            usage: TextureUsages::TEXTURE_BINDING
+                                | TextureUsages::STORAGE_BINDING
+                                | TextureUsages::COPY_DST,
                             format,
                             view_formats: &[],
                         });
@@ -729,19 +1580,15 @@ impl<'a> TransientBindMap<'a> {
                 }
             }
         }
-        let entries = bindings
+        let resources = bindings
             .iter()
-            .enumerate()
-            .map(|(i, proxy)| match proxy {
+            .map(|proxy| match proxy {
                 ResourceProxy::Buf(proxy) => {
                     let buf = match self.bufs.get(&proxy.id) {
                         Some(TransientBuf::Gpu(b)) => b,
                         _ => bind_map.get_gpu_buf(proxy.id).unwrap(),
                     };
-                    Ok(wgpu::BindGroupEntry {
-                        binding: i as u32,
-                        resource: buf.as_entire_binding(),
-                    })
+                    wgpu::BindingResource::Buffer(buf.as_entire_buffer_binding())
                 }
                 ResourceProxy::Image(proxy) => {
                     let view = self
@@ -750,19 +1597,44 @@ impl<'a> TransientBindMap<'a> {
                         .copied()
                         .or_else(|| bind_map.image_map.get(&proxy.id).map(|v| &v.1))
                         .unwrap();
-                    Ok(wgpu::BindGroupEntry {
-                        binding: i as u32,
-                        resource: wgpu::BindingResource::TextureView(view),
-                    })
+                    wgpu::BindingResource::TextureView(view)
                 }
             })
-            .collect::<Result<Vec<_>, Error>>()?;
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: None,
-            layout,
-            entries: &entries,
+            .collect::<Vec<_>>();
+        let key = resources
+            .iter()
+            .map(|resource| match resource {
+                wgpu::BindingResource::TextureView(view) => {
+                    BindingId::TextureView(view.global_id())
+                }
+                wgpu::BindingResource::Buffer(binding) => {
+                    BindingId::Buffer(binding.buffer.global_id())
+                }
+                _ => unreachable!(),
+            })
+            .collect();
+        let recording = cache.recording;
+        let cached = cache.groups.entry((shader_id, key)).or_insert_with(|| {
+            let entries = resources
+                .into_iter()
+                .enumerate()
+                .map(|(i, resource)| wgpu::BindGroupEntry {
+                    binding: i as u32,
+                    resource,
+                })
+                .collect::<Vec<_>>();
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: None,
+                layout,
+                entries: &entries,
+            });
+            CachedBindGroup {
+                bind_group,
+                last_used: recording,
+            }
         });
-        Ok(bind_group)
+        cached.last_used = recording;
+        Ok(&cached.bind_group)
     }
 
     fn create_cpu_resources(
@@ -778,7 +1650,12 @@ impl<'a> TransientBindMap<'a> {
                     Some(TransientBuf::Gpu(_)) => panic!("buffer was already materialized on GPU"),
                     _ => bind_map.materialize_cpu_buf(buf),
                 },
-                ResourceProxy::Image(_) => todo!(),
+                ResourceProxy::Image(image) => {
+                    if self.images.contains_key(&image.id) {
+                        panic!("image was already materialized on GPU");
+                    }
+                    bind_map.get_or_create_cpu_image(*image);
+                }
             };
         }
         // Second pass takes immutable references
@@ -789,7 +1666,9 @@ impl<'a> TransientBindMap<'a> {
                     Some(TransientBuf::Cpu(b)) => CpuBinding::Buffer(b),
                     _ => bind_map.get_cpu_buf(buf.id),
                 },
-                ResourceProxy::Image(_) => todo!(),
+                ResourceProxy::Image(image) => {
+                    CpuBinding::Texture(&bind_map.cpu_image_map[&image.id])
+                }
             })
             .collect()
     }